@@ -0,0 +1,516 @@
+// Authentication of CPEs on the CWMP endpoint (TR-069 Amendment 6, section 3.4.4).
+//
+// A CPE authenticates with the ManagementServer.Username/Password the ACS set on
+// it, or with a credential shared by the whole fleet, which devices with their
+// own credentials cannot use. Only the POST opening a
+// session is checked; the session stays authenticated until it ends. Digest
+// (RFC 7616, SHA-256 and MD5 with `qop=auth`) is offered first, Basic last.
+//
+// In auto-registration mode devices the ACS does not know yet are let in with
+// anything, so they can bootstrap. Credentials are still bound to the device:
+// nobody can Inform as a known device without its own or the shared credentials.
+use crate::connection_request::{auth_params, Credentials};
+use crate::device::{DeviceId, DeviceRegistry};
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
+use md5::Md5;
+use sha2::{Digest, Sha256};
+use std::{
+    collections::{hash_map::RandomState, HashMap},
+    fmt,
+    hash::BuildHasher,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+pub const DEFAULT_REALM: &str = "tr069-acs";
+/// A Digest nonce is accepted for this long after it was issued.
+const NONCE_LIFETIME: Duration = Duration::from_secs(300);
+/// Outstanding Digest nonces kept at most, the oldest makes room for a new one.
+const MAX_NONCES: usize = 10_000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Algorithm {
+    Md5,
+    Sha256,
+}
+
+impl Algorithm {
+    // RFC 7616 defaults to MD5 when the client names no algorithm.
+    fn parse(name: Option<&str>) -> Option<Self> {
+        match name {
+            None => Some(Algorithm::Md5),
+            Some(name) if name.eq_ignore_ascii_case("md5") => Some(Algorithm::Md5),
+            Some(name) if name.eq_ignore_ascii_case("sha-256") => Some(Algorithm::Sha256),
+            Some(_) => None,
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            Algorithm::Md5 => "MD5",
+            Algorithm::Sha256 => "SHA-256",
+        }
+    }
+
+    fn hash(self, input: &str) -> String {
+        match self {
+            Algorithm::Md5 => format!("{:x}", Md5::digest(input.as_bytes())),
+            Algorithm::Sha256 => format!("{:x}", Sha256::digest(input.as_bytes())),
+        }
+    }
+}
+
+/// Whom a CPE authenticated as.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CpeIdentity {
+    /// The credentials of this device.
+    Device(DeviceId),
+    /// The fallback credential shared by the fleet.
+    Shared,
+    /// A device without credentials, let in by auto-registration.
+    Unregistered,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AuthError {
+    /// No Authorization header, or one with a scheme the ACS does not offer.
+    Missing,
+    /// A Digest answer to a nonce that expired or was never issued.
+    StaleNonce,
+    /// Unknown username or wrong password.
+    InvalidCredentials,
+    /// The credentials belong to another device than the one that Informed.
+    WrongDevice,
+}
+
+impl fmt::Display for AuthError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AuthError::Missing => write!(f, "no usable Authorization header"),
+            AuthError::StaleNonce => write!(f, "stale Digest nonce"),
+            AuthError::InvalidCredentials => write!(f, "invalid credentials"),
+            AuthError::WrongDevice => write!(f, "credentials of another device"),
+        }
+    }
+}
+
+impl std::error::Error for AuthError {}
+
+/// Compare a secret without revealing through timing how much of `given` was right.
+pub fn secret_matches(expected: &str, given: &str) -> bool {
+    expected.len() == given.len()
+        && expected
+            .bytes()
+            .zip(given.bytes())
+            .fold(0, |diff, (a, b)| diff | (a ^ b))
+            == 0
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Nonce {
+    issued: Instant,
+    /// Highest `nc` a CPE authenticated with, an answer must count past it.
+    last_count: u32,
+}
+
+#[derive(Debug, Clone)]
+pub struct CpeAuthenticator {
+    realm: String,
+    allow_basic: bool,
+    fallback: Option<Credentials>,
+    auto_register: bool,
+    /// Makes nonces unpredictable across restarts.
+    secret: u64,
+    nonces: Arc<Mutex<HashMap<String, Nonce>>>,
+    next_nonce: Arc<AtomicU64>,
+}
+
+impl Default for CpeAuthenticator {
+    fn default() -> Self {
+        Self::new(DEFAULT_REALM)
+    }
+}
+
+impl CpeAuthenticator {
+    pub fn new(realm: impl Into<String>) -> Self {
+        Self {
+            realm: realm.into(),
+            allow_basic: true,
+            fallback: None,
+            auto_register: false,
+            secret: RandomState::new().hash_one(std::process::id()),
+            nonces: Arc::default(),
+            next_nonce: Arc::default(),
+        }
+    }
+
+    /// Offer Basic as well as Digest. On by default, turn it off unless the
+    /// endpoint is only reachable over TLS.
+    pub fn with_basic(mut self, allow_basic: bool) -> Self {
+        self.allow_basic = allow_basic;
+        self
+    }
+
+    /// Accept `credentials` from any device without credentials of its own.
+    pub fn with_fallback(mut self, credentials: Credentials) -> Self {
+        self.fallback = Some(credentials);
+        self
+    }
+
+    pub fn with_auto_register(mut self, auto_register: bool) -> Self {
+        self.auto_register = auto_register;
+        self
+    }
+
+    fn issue_nonce(&self) -> String {
+        let count = self.next_nonce.fetch_add(1, Ordering::Relaxed);
+        let nanos = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_nanos();
+        let nonce = Algorithm::Sha256.hash(&format!("{}:{count}:{nanos}", self.secret));
+        let mut nonces = self.nonces.lock().unwrap();
+        let now = Instant::now();
+        nonces.retain(|_, nonce| now.duration_since(nonce.issued) < NONCE_LIFETIME);
+        while nonces.len() >= MAX_NONCES {
+            let oldest = nonces
+                .iter()
+                .min_by_key(|(_, nonce)| nonce.issued)
+                .map(|(nonce, _)| nonce.clone());
+            if let Some(oldest) = oldest {
+                nonces.remove(&oldest);
+            }
+        }
+        nonces.insert(
+            nonce.clone(),
+            Nonce {
+                issued: now,
+                last_count: 0,
+            },
+        );
+        nonce
+    }
+
+    fn nonce_is_valid(&self, nonce: &str) -> bool {
+        self.nonces
+            .lock()
+            .unwrap()
+            .get(nonce)
+            .is_some_and(|nonce| nonce.issued.elapsed() < NONCE_LIFETIME)
+    }
+
+    // Record the `nc` of an accepted answer. A count not past the last one is a
+    // replay of an earlier answer.
+    fn count_nonce(&self, nonce: &str, count: u32) -> Result<(), AuthError> {
+        let mut nonces = self.nonces.lock().unwrap();
+        match nonces.get_mut(nonce) {
+            Some(nonce) if count > nonce.last_count => {
+                nonce.last_count = count;
+                Ok(())
+            }
+            _ => Err(AuthError::StaleNonce),
+        }
+    }
+
+    /// `WWW-Authenticate` values for a 401 answer, strongest scheme first.
+    pub fn challenges(&self, stale: bool) -> Vec<String> {
+        let nonce = self.issue_nonce();
+        let stale = if stale { ", stale=true" } else { "" };
+        let mut challenges: Vec<String> = [Algorithm::Sha256, Algorithm::Md5]
+            .into_iter()
+            .map(|algorithm| {
+                format!(
+                    r#"Digest realm="{}", qop="auth", algorithm={}, nonce="{nonce}"{stale}"#,
+                    self.realm,
+                    algorithm.name()
+                )
+            })
+            .collect();
+        if self.allow_basic {
+            challenges.push(format!(r#"Basic realm="{}""#, self.realm));
+        }
+        challenges
+    }
+
+    /// Check the Authorization header of the POST to `uri` opening a session.
+    /// `inform` is the device the POST's Inform identifies.
+    pub fn authenticate(
+        &self,
+        authorization: Option<&str>,
+        uri: &str,
+        devices: &DeviceRegistry,
+        inform: Option<&DeviceId>,
+    ) -> Result<CpeIdentity, AuthError> {
+        let authorization = authorization.ok_or(AuthError::Missing)?.trim();
+        let (scheme, params) = authorization.split_once(' ').unwrap_or((authorization, ""));
+        let identity = if scheme.eq_ignore_ascii_case("basic") && self.allow_basic {
+            let decoded = BASE64
+                .decode(params.trim())
+                .ok()
+                .and_then(|decoded| String::from_utf8(decoded).ok())
+                .ok_or(AuthError::Missing)?;
+            let (username, password) = decoded.split_once(':').ok_or(AuthError::Missing)?;
+            self.identify(username, devices, |expected| {
+                secret_matches(expected, password)
+            })?
+        } else if scheme.eq_ignore_ascii_case("digest") {
+            self.verify_digest(&auth_params(params), uri, devices)?
+        } else {
+            return Err(AuthError::Missing);
+        };
+
+        match (&identity, inform) {
+            (CpeIdentity::Device(id), Some(inform)) if id != inform => Err(AuthError::WrongDevice),
+            (CpeIdentity::Shared, Some(inform)) if has_password(devices, inform) => {
+                Err(AuthError::WrongDevice)
+            }
+            (CpeIdentity::Unregistered, Some(inform)) if devices.get(inform).is_some() => {
+                Err(AuthError::WrongDevice)
+            }
+            _ => Ok(identity),
+        }
+    }
+
+    fn verify_digest(
+        &self,
+        params: &HashMap<String, String>,
+        uri: &str,
+        devices: &DeviceRegistry,
+    ) -> Result<CpeIdentity, AuthError> {
+        let algorithm = Algorithm::parse(params.get("algorithm").map(String::as_str))
+            .ok_or(AuthError::Missing)?;
+        let field = |name: &str| {
+            params
+                .get(name)
+                .map(String::as_str)
+                .ok_or(AuthError::Missing)
+        };
+        let (username, nonce, response) = (field("username")?, field("nonce")?, field("response")?);
+        if field("uri")? != uri || field("realm")? != self.realm {
+            return Err(AuthError::InvalidCredentials);
+        }
+        if !self.nonce_is_valid(nonce) {
+            return Err(AuthError::StaleNonce);
+        }
+        let ha2 = algorithm.hash(&format!("POST:{uri}"));
+        let (tail, count) = match params.get("qop").map(String::as_str) {
+            Some("auth") => {
+                let nc = field("nc")?;
+                let count = u32::from_str_radix(nc, 16).map_err(|_| AuthError::Missing)?;
+                (format!("{nc}:{}:auth:{ha2}", field("cnonce")?), count)
+            }
+            Some(_) => return Err(AuthError::Missing),
+            // RFC 2069 compatibility, without a count the nonce is good for one answer.
+            None => (ha2, 1),
+        };
+        let response = response.to_ascii_lowercase();
+        let identity = self.identify(username, devices, |password| {
+            let ha1 = algorithm.hash(&format!("{username}:{}:{password}", self.realm));
+            secret_matches(&algorithm.hash(&format!("{ha1}:{nonce}:{tail}")), &response)
+        })?;
+        self.count_nonce(nonce, count)?;
+        Ok(identity)
+    }
+
+    // Match `username` and the password `check` accepts to a device's own
+    // credentials, then to the fallback.
+    fn identify(
+        &self,
+        username: &str,
+        devices: &DeviceRegistry,
+        check: impl Fn(&str) -> bool,
+    ) -> Result<CpeIdentity, AuthError> {
+        let device = devices
+            .find_by_cwmp_username(username)
+            .and_then(|id| devices.get(&id))
+            .and_then(|device| Some((device.id, device.credentials?)));
+        if let Some((id, credentials)) = device {
+            return if check(&credentials.password) {
+                Ok(CpeIdentity::Device(id))
+            } else {
+                Err(AuthError::InvalidCredentials)
+            };
+        }
+        if let Some(fallback) = &self.fallback {
+            if fallback.username == username && check(&fallback.password) {
+                return Ok(CpeIdentity::Shared);
+            }
+        }
+        if self.auto_register {
+            Ok(CpeIdentity::Unregistered)
+        } else {
+            Err(AuthError::InvalidCredentials)
+        }
+    }
+}
+
+fn has_password(devices: &DeviceRegistry, id: &DeviceId) -> bool {
+    devices
+        .get(id)
+        .is_some_and(|device| device.credentials.is_some())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::device::{CachedParameter, Device};
+    use crate::storage::{MemoryStorage, Storage};
+    use chrono::Utc;
+
+    // A registry with SN1, which has credentials cpe1/pw1, and SN2, which has none
+    // but reported a Username/Password of its own.
+    fn registry() -> DeviceRegistry {
+        let storage = MemoryStorage::new();
+        let now = Utc::now();
+        for serial in ["SN1", "SN2"] {
+            let mut device = Device::new(format!("001A2B-RouterX-{serial}").parse().unwrap(), now);
+            if serial == "SN1" {
+                device.credentials = Some(Credentials {
+                    username: String::from("cpe1"),
+                    password: String::from("pw1"),
+                });
+            }
+            storage.save_device(&device).unwrap();
+        }
+        let parameter = |value: &str| CachedParameter {
+            xsi_type: Some(String::from("xsd:string")),
+            value: Some(value.to_string()),
+            writable: Some(true),
+            notification: None,
+            access_list: Vec::new(),
+            updated: now,
+        };
+        let (username, password) = (parameter("cpe2"), parameter("pw2"));
+        storage
+            .save_parameters(
+                &"001A2B-RouterX-SN2".parse().unwrap(),
+                &[
+                    ("Device.ManagementServer.Username", &username),
+                    ("Device.ManagementServer.Password", &password),
+                ],
+            )
+            .unwrap();
+        DeviceRegistry::load(Arc::new(storage)).unwrap()
+    }
+
+    fn basic(username: &str, password: &str) -> String {
+        format!("Basic {}", BASE64.encode(format!("{username}:{password}")))
+    }
+
+    #[test]
+    fn test_basic_authentication() {
+        let devices = registry();
+        let sn1: DeviceId = "001A2B-RouterX-SN1".parse().unwrap();
+        let sn2: DeviceId = "001A2B-RouterX-SN2".parse().unwrap();
+        let auth = CpeAuthenticator::default();
+        let check = |authorization: &str, inform: &DeviceId| {
+            auth.authenticate(Some(authorization), "/", &devices, Some(inform))
+        };
+
+        assert_eq!(
+            check(&basic("cpe1", "pw1"), &sn1),
+            Ok(CpeIdentity::Device(sn1.clone()))
+        );
+        assert_eq!(
+            check(&basic("cpe1", "wrong"), &sn1),
+            Err(AuthError::InvalidCredentials)
+        );
+        assert_eq!(
+            check(&basic("cpe1", "pw1"), &sn2),
+            Err(AuthError::WrongDevice)
+        );
+        assert_eq!(
+            auth.authenticate(None, "/", &devices, Some(&sn1)),
+            Err(AuthError::Missing)
+        );
+        assert_eq!(
+            check(&basic("fleet", "shared"), &sn2),
+            Err(AuthError::InvalidCredentials)
+        );
+        // What the CPE reports is no credential.
+        assert_eq!(
+            check(&basic("cpe2", "pw2"), &sn2),
+            Err(AuthError::InvalidCredentials)
+        );
+
+        let auth = CpeAuthenticator::default()
+            .with_fallback(Credentials {
+                username: String::from("fleet"),
+                password: String::from("shared"),
+            })
+            .with_auto_register(true);
+        let check = |authorization: &str, inform: &DeviceId| {
+            auth.authenticate(Some(authorization), "/", &devices, Some(inform))
+        };
+        assert_eq!(
+            check(&basic("fleet", "shared"), &sn2),
+            Ok(CpeIdentity::Shared)
+        );
+        let new: DeviceId = "001A2B-RouterX-SN3".parse().unwrap();
+        assert_eq!(
+            check(&basic("anyone", "anything"), &new),
+            Ok(CpeIdentity::Unregistered)
+        );
+        assert_eq!(
+            check(&basic("fleet", "shared"), &sn1),
+            Err(AuthError::WrongDevice)
+        );
+        for known in [&sn1, &sn2] {
+            assert_eq!(
+                check(&basic("anyone", "anything"), known),
+                Err(AuthError::WrongDevice)
+            );
+        }
+    }
+
+    #[test]
+    fn test_digest_authentication() {
+        let devices = registry();
+        let sn1: DeviceId = "001A2B-RouterX-SN1".parse().unwrap();
+        let auth = CpeAuthenticator::new("acs").with_basic(false);
+        let challenges = auth.challenges(false);
+        assert_eq!(challenges.len(), 2);
+        assert!(challenges[0].contains("algorithm=SHA-256"));
+        let nonce = auth_params(challenges[0].trim_start_matches("Digest "))["nonce"].clone();
+
+        let answer = |algorithm: Algorithm, password: &str, nonce: &str, nc: u32| {
+            let ha1 = algorithm.hash(&format!("cpe1:acs:{password}"));
+            let ha2 = algorithm.hash("POST:/");
+            let response = algorithm.hash(&format!("{ha1}:{nonce}:{nc:08x}:abcd:auth:{ha2}"));
+            format!(
+                r#"Digest username="cpe1", realm="acs", nonce="{nonce}", uri="/", qop=auth, nc={nc:08x}, cnonce="abcd", response="{response}", algorithm={}"#,
+                algorithm.name()
+            )
+        };
+        let check =
+            |authorization: &str| auth.authenticate(Some(authorization), "/", &devices, Some(&sn1));
+
+        for (nc, algorithm) in [(1, Algorithm::Sha256), (2, Algorithm::Md5)] {
+            assert_eq!(
+                check(&answer(algorithm, "pw1", &nonce, nc)),
+                Ok(CpeIdentity::Device(sn1.clone()))
+            );
+        }
+        assert_eq!(
+            check(&answer(Algorithm::Sha256, "wrong", &nonce, 3)),
+            Err(AuthError::InvalidCredentials)
+        );
+        assert_eq!(
+            check(&answer(Algorithm::Sha256, "pw1", "forged", 1)),
+            Err(AuthError::StaleNonce)
+        );
+        // A replayed answer does not count past the last one.
+        assert_eq!(
+            check(&answer(Algorithm::Md5, "pw1", &nonce, 2)),
+            Err(AuthError::StaleNonce)
+        );
+        assert_eq!(
+            check(&answer(Algorithm::Sha256, "pw1", &nonce, 3)),
+            Ok(CpeIdentity::Device(sn1.clone()))
+        );
+        assert_eq!(check(&basic("cpe1", "pw1")), Err(AuthError::Missing));
+    }
+}
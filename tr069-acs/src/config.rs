@@ -0,0 +1,732 @@
+// ACS configuration.
+//
+// Settings come from a TOML file, `tr069-acs.toml` in the working directory or the
+// file named by TR069_ACS_CONFIG, with TR069_ACS_* environment variables taking
+// precedence over it. Everything has a default, so the ACS also runs with neither.
+// The result is checked once at startup, before anything is bound or opened.
+use crate::{
+    auth::DEFAULT_REALM,
+    connection_request::{XmppConfig, DEFAULT_MIN_INTERVAL, DEFAULT_TIMEOUT},
+    cwmp_msg::session::{DEFAULT_MAX_SESSIONS, DEFAULT_SESSION_TIMEOUT},
+    events::EventKind,
+    task::{DEFAULT_MAX_ATTEMPTS, DEFAULT_RETENTION},
+    tls::TlsSettings,
+    webhook::WebhookConfig,
+};
+use chrono::TimeDelta;
+use serde::Deserialize;
+use std::{
+    fmt, io,
+    net::{IpAddr, Ipv4Addr, SocketAddr},
+    path::{Path, PathBuf},
+    str::FromStr,
+    time::Duration,
+};
+use tracing::Level;
+
+/// Read when TR069_ACS_CONFIG is not set, if it exists.
+pub const DEFAULT_CONFIG_FILE: &str = "tr069-acs.toml";
+
+#[derive(Debug)]
+pub enum ConfigError {
+    /// The configuration file could not be read.
+    Read { path: PathBuf, error: io::Error },
+    /// The configuration file is not valid TOML or has unknown keys.
+    Parse {
+        path: PathBuf,
+        error: toml::de::Error,
+    },
+    /// A setting has a value the ACS cannot run with. `key` is the TOML key, or
+    /// the environment variable the value came from.
+    Invalid { key: String, reason: String },
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConfigError::Read { path, error } => write!(f, "{}: {error}", path.display()),
+            ConfigError::Parse { path, error } => write!(f, "{}: {error}", path.display()),
+            ConfigError::Invalid { key, reason } => write!(f, "invalid {key}: {reason}"),
+        }
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+fn invalid(key: impl Into<String>, reason: impl Into<String>) -> ConfigError {
+    ConfigError::Invalid {
+        key: key.into(),
+        reason: reason.into(),
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct CwmpConfig {
+    pub address: IpAddr,
+    pub port: u16,
+    /// Seconds a session waits for a CPE whose Inform has no SessionTimeout header.
+    pub session_timeout: u64,
+    /// Open sessions kept at most.
+    pub max_sessions: usize,
+    /// Seconds open sessions get to finish at shutdown before they are aborted.
+    pub shutdown_timeout: u64,
+    /// Ask CPEs with pending tasks to hold their own requests (HoldRequests) so
+    /// the tasks run first.
+    pub hold_requests: bool,
+}
+
+impl Default for CwmpConfig {
+    fn default() -> Self {
+        Self {
+            address: IpAddr::V4(Ipv4Addr::LOCALHOST),
+            port: 7777,
+            session_timeout: DEFAULT_SESSION_TIMEOUT.as_secs(),
+            max_sessions: DEFAULT_MAX_SESSIONS,
+            shutdown_timeout: 30,
+            hold_requests: false,
+        }
+    }
+}
+
+/// The web UI and the northbound interface.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct UiConfig {
+    pub address: IpAddr,
+    pub port: u16,
+    /// Bearer token clients of the northbound API must send. The API refuses
+    /// every request while none is configured.
+    pub api_token: Option<String>,
+}
+
+impl Default for UiConfig {
+    fn default() -> Self {
+        Self {
+            address: IpAddr::V4(Ipv4Addr::LOCALHOST),
+            port: 8081,
+            api_token: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct StunConfig {
+    pub enabled: bool,
+    /// UDP port, on the CWMP address.
+    pub port: u16,
+}
+
+impl Default for StunConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            port: 3478,
+        }
+    }
+}
+
+/// How CPEs authenticate on the CWMP endpoint, see `CpeAuthenticator`.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct AuthConfig {
+    pub realm: String,
+    pub allow_basic: bool,
+    /// Fleet-wide credentials for CPEs without their own.
+    pub username: Option<String>,
+    pub password: Option<String>,
+    /// Let devices the ACS has not seen before in with any credentials.
+    pub auto_register: bool,
+}
+
+impl Default for AuthConfig {
+    fn default() -> Self {
+        Self {
+            realm: String::from(DEFAULT_REALM),
+            allow_basic: true,
+            username: None,
+            password: None,
+            auto_register: false,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ConnectionRequestConfig {
+    /// Seconds to wait for a CPE to answer.
+    pub timeout: u64,
+    /// Minimum seconds between two Connection Requests to the same CPE.
+    pub min_interval: u64,
+    /// Fleet-wide ConnectionRequestUsername/Password for CPEs not reporting theirs.
+    pub username: Option<String>,
+    pub password: Option<String>,
+}
+
+impl Default for ConnectionRequestConfig {
+    fn default() -> Self {
+        Self {
+            timeout: DEFAULT_TIMEOUT.as_secs(),
+            min_interval: DEFAULT_MIN_INTERVAL.as_secs(),
+            username: None,
+            password: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct TaskConfig {
+    /// Sessions a task is sent in before it faults as unanswered.
+    pub max_attempts: u32,
+    /// Days finished tasks are kept for, forever when 0.
+    pub retention_days: u32,
+}
+
+impl Default for TaskConfig {
+    fn default() -> Self {
+        Self {
+            max_attempts: DEFAULT_MAX_ATTEMPTS,
+            retention_days: DEFAULT_RETENTION.num_days() as u32,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct StorageConfig {
+    /// SQLite database holding devices, tasks and faults.
+    pub path: PathBuf,
+}
+
+impl Default for StorageConfig {
+    fn default() -> Self {
+        Self {
+            path: PathBuf::from("tr069-acs.db"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct LogConfig {
+    /// `error`, `warn`, `info`, `debug` or `trace`.
+    pub level: String,
+}
+
+impl Default for LogConfig {
+    fn default() -> Self {
+        Self {
+            level: String::from("info"),
+        }
+    }
+}
+
+impl LogConfig {
+    pub fn level(&self) -> Level {
+        Level::from_str(&self.level).unwrap_or(Level::INFO)
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub cwmp: CwmpConfig,
+    pub ui: UiConfig,
+    pub stun: StunConfig,
+    /// CWMP is served over TLS when set.
+    pub tls: Option<TlsSettings>,
+    pub auth: AuthConfig,
+    /// XMPP Connection Requests are sent when set.
+    pub xmpp: Option<XmppConfig>,
+    pub connection_request: ConnectionRequestConfig,
+    pub tasks: TaskConfig,
+    pub storage: StorageConfig,
+    pub log: LogConfig,
+    /// `[[webhooks]]` entries, events are POSTed to each.
+    pub webhooks: Vec<WebhookConfig>,
+}
+
+impl Config {
+    /// Read the configuration file if there is one, apply the environment and the
+    /// `dx serve` UI address, and check the result.
+    pub fn load() -> Result<Self, ConfigError> {
+        let mut config = match std::env::var_os("TR069_ACS_CONFIG") {
+            Some(path) => Self::from_file(Path::new(&path))?,
+            None if Path::new(DEFAULT_CONFIG_FILE).exists() => {
+                Self::from_file(Path::new(DEFAULT_CONFIG_FILE))?
+            }
+            None => Self::default(),
+        };
+        // `dx serve` proxies the UI, so it decides where the UI listens.
+        if let Some(address) = dioxus::cli_config::server_ip() {
+            config.ui.address = address;
+        }
+        if let Some(port) = dioxus::cli_config::server_port() {
+            config.ui.port = port;
+        }
+        config.apply_env(|name| std::env::var(name).ok())?;
+        config.validate()?;
+        Ok(config)
+    }
+
+    pub fn from_file(path: &Path) -> Result<Self, ConfigError> {
+        let text = std::fs::read_to_string(path).map_err(|error| ConfigError::Read {
+            path: path.to_path_buf(),
+            error,
+        })?;
+        toml::from_str(&text).map_err(|error| ConfigError::Parse {
+            path: path.to_path_buf(),
+            error,
+        })
+    }
+
+    /// Override settings with the TR069_ACS_* variables `var` returns.
+    pub fn apply_env(&mut self, var: impl Fn(&str) -> Option<String>) -> Result<(), ConfigError> {
+        fn parse<T: FromStr>(name: &str, value: String) -> Result<T, ConfigError> {
+            value
+                .parse()
+                .map_err(|_| invalid(name, format!("cannot parse {value:?}")))
+        }
+        fn flag(name: &str, value: String) -> Result<bool, ConfigError> {
+            match value.as_str() {
+                "1" | "true" => Ok(true),
+                "0" | "false" => Ok(false),
+                _ => Err(invalid(
+                    name,
+                    format!("expected true or false, not {value:?}"),
+                )),
+            }
+        }
+        let env = |name: &'static str| var(name).map(|value| (name, value));
+
+        if let Some((name, value)) = env("TR069_ACS_CWMP_ADDRESS") {
+            self.cwmp.address = parse(name, value)?;
+        }
+        if let Some((name, value)) = env("TR069_ACS_CWMP_PORT") {
+            self.cwmp.port = parse(name, value)?;
+        }
+        if let Some((name, value)) = env("TR069_ACS_SESSION_TIMEOUT") {
+            self.cwmp.session_timeout = parse(name, value)?;
+        }
+        if let Some((name, value)) = env("TR069_ACS_MAX_SESSIONS") {
+            self.cwmp.max_sessions = parse(name, value)?;
+        }
+        if let Some((name, value)) = env("TR069_ACS_SHUTDOWN_TIMEOUT") {
+            self.cwmp.shutdown_timeout = parse(name, value)?;
+        }
+        if let Some((name, value)) = env("TR069_ACS_HOLD_REQUESTS") {
+            self.cwmp.hold_requests = flag(name, value)?;
+        }
+        if let Some((name, value)) = env("TR069_ACS_UI_ADDRESS") {
+            self.ui.address = parse(name, value)?;
+        }
+        if let Some((name, value)) = env("TR069_ACS_UI_PORT") {
+            self.ui.port = parse(name, value)?;
+        }
+        if let Some((_, value)) = env("TR069_ACS_API_TOKEN") {
+            self.ui.api_token = Some(value);
+        }
+        if let Some((name, value)) = env("TR069_ACS_STUN_PORT") {
+            self.stun.port = parse(name, value)?;
+        }
+        if let Some((name, value)) = env("TR069_ACS_STUN") {
+            self.stun.enabled = flag(name, value)?;
+        }
+        if let Some((name, value)) = env("TR069_ACS_TASK_MAX_ATTEMPTS") {
+            self.tasks.max_attempts = parse(name, value)?;
+        }
+        if let Some((name, value)) = env("TR069_ACS_TASK_RETENTION_DAYS") {
+            self.tasks.retention_days = parse(name, value)?;
+        }
+        if let Some((_, value)) = env("TR069_ACS_DB") {
+            self.storage.path = PathBuf::from(value);
+        }
+        if let Some((_, value)) = env("TR069_ACS_LOG_LEVEL") {
+            self.log.level = value;
+        }
+
+        if let Some((_, value)) = env("TR069_ACS_TLS_CERT") {
+            self.tls_mut().cert = PathBuf::from(value);
+        }
+        if let Some((_, value)) = env("TR069_ACS_TLS_KEY") {
+            self.tls_mut().key = PathBuf::from(value);
+        }
+        if let Some((_, value)) = env("TR069_ACS_TLS_CLIENT_CA") {
+            self.tls_mut().client_ca = Some(PathBuf::from(value));
+        }
+        if let Some((name, value)) = env("TR069_ACS_TLS_REQUIRE_CLIENT_CERT") {
+            self.tls_mut().require_client_cert = flag(name, value)?;
+        }
+
+        if let Some((_, value)) = env("TR069_ACS_AUTH_REALM") {
+            self.auth.realm = value;
+        }
+        if let Some((name, value)) = env("TR069_ACS_AUTH_BASIC") {
+            self.auth.allow_basic = flag(name, value)?;
+        }
+        if let Some((_, value)) = env("TR069_ACS_CPE_USERNAME") {
+            self.auth.username = Some(value);
+        }
+        if let Some((_, value)) = env("TR069_ACS_CPE_PASSWORD") {
+            self.auth.password = Some(value);
+        }
+        if let Some((_, value)) = env("TR069_ACS_CONNECTION_REQUEST_USERNAME") {
+            self.connection_request.username = Some(value);
+        }
+        if let Some((_, value)) = env("TR069_ACS_CONNECTION_REQUEST_PASSWORD") {
+            self.connection_request.password = Some(value);
+        }
+        if let Some((name, value)) = env("TR069_ACS_AUTO_REGISTER") {
+            self.auth.auto_register = flag(name, value)?;
+        }
+
+        if let Some((_, value)) = env("TR069_ACS_XMPP_JID") {
+            self.xmpp_mut().jid = value;
+        }
+        if let Some((_, value)) = env("TR069_ACS_XMPP_PASSWORD") {
+            self.xmpp_mut().password = value;
+        }
+        if let Some((_, value)) = env("TR069_ACS_XMPP_SERVER") {
+            self.xmpp_mut().server = Some(value);
+        }
+        if let Some((_, value)) = env("TR069_ACS_XMPP_CA") {
+            self.xmpp_mut().ca = PathBuf::from(value);
+        }
+        Ok(())
+    }
+
+    // The TLS settings, started empty by the first TLS variable.
+    fn tls_mut(&mut self) -> &mut TlsSettings {
+        self.tls.get_or_insert_with(|| TlsSettings {
+            cert: PathBuf::new(),
+            key: PathBuf::new(),
+            client_ca: None,
+            require_client_cert: false,
+        })
+    }
+
+    fn xmpp_mut(&mut self) -> &mut XmppConfig {
+        self.xmpp.get_or_insert_with(|| XmppConfig {
+            jid: String::new(),
+            password: String::new(),
+            server: None,
+            ca: PathBuf::new(),
+        })
+    }
+
+    /// Check the settings fit together, and that the files they name exist.
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        let (cwmp, ui) = (self.cwmp_addr(), self.ui_addr());
+        let overlap =
+            cwmp.ip() == ui.ip() || cwmp.ip().is_unspecified() || ui.ip().is_unspecified();
+        if cwmp.port() == ui.port() && cwmp.port() != 0 && overlap {
+            return Err(invalid(
+                "cwmp.port",
+                format!("CWMP and UI would both listen on port {}", cwmp.port()),
+            ));
+        }
+        if self.cwmp.session_timeout == 0 {
+            return Err(invalid("cwmp.session_timeout", "must be at least 1 second"));
+        }
+        if self.cwmp.max_sessions == 0 {
+            return Err(invalid("cwmp.max_sessions", "must be at least 1"));
+        }
+        if self.connection_request.timeout == 0 {
+            return Err(invalid(
+                "connection_request.timeout",
+                "must be at least 1 second",
+            ));
+        }
+        if self.ui.api_token.as_deref() == Some("") {
+            return Err(invalid("ui.api_token", "must not be empty"));
+        }
+        if self.tasks.max_attempts == 0 {
+            return Err(invalid("tasks.max_attempts", "must be at least 1"));
+        }
+        if Level::from_str(&self.log.level).is_err() {
+            return Err(invalid(
+                "log.level",
+                format!("unknown level {:?}", self.log.level),
+            ));
+        }
+        if self.storage.path.as_os_str().is_empty() {
+            return Err(invalid("storage.path", "must not be empty"));
+        }
+
+        if let Some(tls) = &self.tls {
+            let file = |key: &str, path: &Path| {
+                if path.as_os_str().is_empty() {
+                    Err(invalid(key, "required for TLS"))
+                } else if !path.is_file() {
+                    Err(invalid(key, format!("{} does not exist", path.display())))
+                } else {
+                    Ok(())
+                }
+            };
+            file("tls.cert", &tls.cert)?;
+            file("tls.key", &tls.key)?;
+            match &tls.client_ca {
+                Some(client_ca) => file("tls.client_ca", client_ca)?,
+                None if tls.require_client_cert => {
+                    return Err(invalid(
+                        "tls.require_client_cert",
+                        "client certificates need tls.client_ca",
+                    ))
+                }
+                None => {}
+            }
+        }
+
+        if self.auth.username.is_some() != self.auth.password.is_some() {
+            return Err(invalid(
+                "auth.username",
+                "username and password must be given together",
+            ));
+        }
+        let connection_request = &self.connection_request;
+        if connection_request.username.is_some() != connection_request.password.is_some() {
+            return Err(invalid(
+                "connection_request.username",
+                "username and password must be given together",
+            ));
+        }
+        if let Some(xmpp) = &self.xmpp {
+            if !xmpp.jid.contains('@') {
+                return Err(invalid(
+                    "xmpp.jid",
+                    format!("{:?} is not user@domain", xmpp.jid),
+                ));
+            }
+            if xmpp.password.is_empty() {
+                return Err(invalid("xmpp.password", "required with xmpp.jid"));
+            }
+            // SASL PLAIN is only sent over TLS.
+            if xmpp.ca.as_os_str().is_empty() {
+                return Err(invalid("xmpp.ca", "required with xmpp.jid"));
+            } else if !xmpp.ca.is_file() {
+                return Err(invalid(
+                    "xmpp.ca",
+                    format!("{} does not exist", xmpp.ca.display()),
+                ));
+            }
+        }
+
+        for (index, webhook) in self.webhooks.iter().enumerate() {
+            let key = |field: &str| format!("webhooks[{index}].{field}");
+            if webhook.name.is_empty() {
+                return Err(invalid(key("name"), "must not be empty"));
+            }
+            if self.webhooks[..index]
+                .iter()
+                .any(|other| other.name == webhook.name)
+            {
+                return Err(invalid(
+                    key("name"),
+                    format!("{:?} names another webhook too", webhook.name),
+                ));
+            }
+            if !["http://", "https://"]
+                .iter()
+                .any(|scheme| webhook.url.starts_with(scheme))
+            {
+                return Err(invalid(
+                    key("url"),
+                    format!("{:?} is not an http or https URL", webhook.url),
+                ));
+            }
+            if let Some(unknown) = webhook
+                .types
+                .iter()
+                .find(|name| !EventKind::TYPES.contains(&name.as_str()))
+            {
+                return Err(invalid(
+                    key("types"),
+                    format!("unknown event type {unknown:?}"),
+                ));
+            }
+            if webhook.max_attempts == 0 {
+                return Err(invalid(key("max_attempts"), "must be at least 1"));
+            }
+            if webhook.retry_interval == 0 {
+                return Err(invalid(key("retry_interval"), "must be at least 1 second"));
+            }
+            if webhook.timeout == 0 {
+                return Err(invalid(key("timeout"), "must be at least 1 second"));
+            }
+        }
+        Ok(())
+    }
+
+    pub fn cwmp_addr(&self) -> SocketAddr {
+        SocketAddr::new(self.cwmp.address, self.cwmp.port)
+    }
+
+    pub fn ui_addr(&self) -> SocketAddr {
+        SocketAddr::new(self.ui.address, self.ui.port)
+    }
+
+    pub fn stun_addr(&self) -> SocketAddr {
+        SocketAddr::new(self.cwmp.address, self.stun.port)
+    }
+
+    pub fn session_timeout(&self) -> Duration {
+        Duration::from_secs(self.cwmp.session_timeout)
+    }
+
+    pub fn shutdown_timeout(&self) -> Duration {
+        Duration::from_secs(self.cwmp.shutdown_timeout)
+    }
+
+    /// How long finished tasks are kept, `None` for forever.
+    pub fn task_retention(&self) -> Option<TimeDelta> {
+        let days = self.tasks.retention_days;
+        (days > 0).then(|| TimeDelta::days(days.into()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    #[test]
+    fn test_parse_and_override() {
+        let mut config: Config = toml::from_str(
+            r#"
+            [cwmp]
+            address = "0.0.0.0"
+            port = 7547
+            session_timeout = 60
+
+            [auth]
+            username = "fleet"
+            password = "secret"
+
+            [xmpp]
+            jid = "acs@xmpp.example.com"
+            password = "xmpp-secret"
+            ca = "/etc/tr069-acs/xmpp-ca.pem"
+
+            [storage]
+            path = "/var/lib/tr069-acs/acs.db"
+
+            [[webhooks]]
+            name = "oss"
+            url = "https://oss.example.com/hooks/acs"
+            secret = "hook-secret"
+            types = ["device_informed", "fault_raised"]
+            event_codes = ["0 BOOTSTRAP"]
+            "#,
+        )
+        .unwrap();
+        assert_eq!(config.cwmp_addr(), "0.0.0.0:7547".parse().unwrap());
+        assert_eq!(config.session_timeout(), Duration::from_secs(60));
+        assert_eq!(config.ui, UiConfig::default());
+        assert_eq!(config.xmpp.as_ref().unwrap().server, None);
+        assert_eq!(config.webhooks[0].max_attempts, 8);
+        assert_eq!(config.webhooks[0].filter().event_codes, ["0 BOOTSTRAP"]);
+        assert!(
+            matches!(config.validate(), Err(ConfigError::Invalid { key, .. }) if key == "xmpp.ca")
+        );
+        config.xmpp = None;
+        config.validate().unwrap();
+
+        let env = HashMap::from([
+            ("TR069_ACS_CWMP_PORT", "7548"),
+            ("TR069_ACS_DB", "acs.db"),
+            ("TR069_ACS_AUTO_REGISTER", "true"),
+            ("TR069_ACS_LOG_LEVEL", "debug"),
+        ]);
+        config
+            .apply_env(|name| env.get(name).map(|value| value.to_string()))
+            .unwrap();
+        assert_eq!(config.cwmp.port, 7548);
+        assert_eq!(config.storage.path, PathBuf::from("acs.db"));
+        assert!(config.auth.auto_register);
+        assert_eq!(config.log.level(), Level::DEBUG);
+        assert_eq!(config.auth.username.as_deref(), Some("fleet"));
+
+        let err = config
+            .apply_env(|name| (name == "TR069_ACS_UI_PORT").then(|| String::from("http")))
+            .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "invalid TR069_ACS_UI_PORT: cannot parse \"http\""
+        );
+    }
+
+    #[test]
+    fn test_validate() {
+        assert!(toml::from_str::<Config>("[cwmp]\nport_number = 7547").is_err());
+        Config::default().validate().unwrap();
+
+        // The same port on the wildcard address collides with any other address.
+        let mut config = Config::default();
+        config.cwmp.address = IpAddr::V4(Ipv4Addr::UNSPECIFIED);
+        config.cwmp.port = config.ui.port;
+        assert!(
+            matches!(config.validate(), Err(ConfigError::Invalid { key, .. }) if key == "cwmp.port")
+        );
+
+        let mut config = Config::default();
+        config.auth.username = Some(String::from("fleet"));
+        assert!(config.validate().is_err());
+
+        let mut config = Config::default();
+        config.connection_request.password = Some(String::from("secret"));
+        assert!(
+            matches!(config.validate(), Err(ConfigError::Invalid { key, .. }) if key == "connection_request.username")
+        );
+
+        let mut config = Config::default();
+        config.log.level = String::from("verbose");
+        assert!(config.validate().is_err());
+
+        let mut config = Config::default();
+        assert_eq!(config.task_retention(), Some(DEFAULT_RETENTION));
+        config.tasks.retention_days = 0;
+        assert_eq!(config.task_retention(), None);
+        config.tasks.max_attempts = 0;
+        assert!(config.validate().is_err());
+
+        let webhook: WebhookConfig =
+            toml::from_str("name = \"oss\"\nurl = \"https://oss.example.com/\"").unwrap();
+        let mut config = Config {
+            webhooks: vec![webhook.clone(), webhook],
+            ..Config::default()
+        };
+        assert!(
+            matches!(config.validate(), Err(ConfigError::Invalid { key, .. }) if key == "webhooks[1].name")
+        );
+        config.webhooks[1].name = String::from("noc");
+        config.webhooks[1].types = vec![String::from("reboot")];
+        assert!(config.validate().is_err());
+        config.webhooks[1].types.clear();
+        config.validate().unwrap();
+
+        let dir = std::env::temp_dir().join(format!("tr069-acs-config-{}", std::process::id()));
+        let mut settings = crate::tls::tests::write_certificates(&dir);
+        let mut config = Config {
+            tls: Some(settings.clone()),
+            ..Config::default()
+        };
+        config.validate().unwrap();
+        config.xmpp = Some(XmppConfig {
+            jid: String::from("acs@xmpp.example.com"),
+            password: String::from("xmpp-secret"),
+            server: None,
+            ca: dir.join("ca.pem"),
+        });
+        config.validate().unwrap();
+        settings.client_ca = None;
+        settings.require_client_cert = true;
+        config.tls = Some(settings);
+        assert!(config.validate().is_err());
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert!(
+            matches!(config.validate(), Err(ConfigError::Invalid { key, .. }) if key == "tls.cert")
+        );
+    }
+}
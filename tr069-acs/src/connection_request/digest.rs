@@ -0,0 +1,163 @@
+// HTTP authentication of Connection Requests (TR-069 Amendment 6, section 3.2.2).
+//
+// CPEs challenge with Digest (RFC 7616, MD5 and `qop=auth`), some older ones with
+// Basic. Only what a CPE can ask for is implemented: no `auth-int`, no SHA-256.
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
+use md5::{Digest, Md5};
+use std::{
+    collections::HashMap,
+    sync::atomic::{AtomicU64, Ordering},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+fn md5_hex(input: &str) -> String {
+    format!("{:x}", Md5::digest(input.as_bytes()))
+}
+
+/// A parsed `WWW-Authenticate` challenge.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Challenge {
+    Basic,
+    Digest {
+        realm: String,
+        nonce: String,
+        opaque: Option<String>,
+        /// The server offered `qop=auth`.
+        qop_auth: bool,
+    },
+}
+
+// `key=value` and `key="quoted, value"` pairs of an auth-param list.
+pub(crate) fn auth_params(params: &str) -> HashMap<String, String> {
+    let mut result = HashMap::new();
+    let mut rest = params.trim();
+    while !rest.is_empty() {
+        let Some((key, after)) = rest.split_once('=') else {
+            break;
+        };
+        let key = key.trim().to_ascii_lowercase();
+        let after = after.trim_start();
+        let (value, remaining) = match after.strip_prefix('"') {
+            Some(quoted) => {
+                let end = quoted.find('"').unwrap_or(quoted.len());
+                (&quoted[..end], quoted.get(end + 1..).unwrap_or_default())
+            }
+            None => after.split_once(',').unwrap_or((after, "")),
+        };
+        result.insert(key, value.trim().to_string());
+        rest = remaining.trim_start_matches([',', ' ']);
+    }
+    result
+}
+
+impl Challenge {
+    pub fn parse(header: &str) -> Option<Self> {
+        let (scheme, params) = header.trim().split_once(' ').unwrap_or((header.trim(), ""));
+        if scheme.eq_ignore_ascii_case("basic") {
+            return Some(Challenge::Basic);
+        }
+        if !scheme.eq_ignore_ascii_case("digest") {
+            return None;
+        }
+        let mut params = auth_params(params);
+        if params
+            .get("algorithm")
+            .is_some_and(|algorithm| !algorithm.eq_ignore_ascii_case("md5"))
+        {
+            return None;
+        }
+        Some(Challenge::Digest {
+            realm: params.remove("realm").unwrap_or_default(),
+            nonce: params.remove("nonce")?,
+            opaque: params.remove("opaque"),
+            qop_auth: params
+                .get("qop")
+                .is_some_and(|qop| qop.split(',').any(|qop| qop.trim() == "auth")),
+        })
+    }
+
+    /// `Authorization` header value answering this challenge for a GET of `uri`.
+    pub fn authorization(&self, username: &str, password: &str, uri: &str) -> String {
+        match self {
+            Challenge::Basic => {
+                format!("Basic {}", BASE64.encode(format!("{username}:{password}")))
+            }
+            Challenge::Digest {
+                realm,
+                nonce,
+                opaque,
+                qop_auth,
+            } => {
+                let ha1 = md5_hex(&format!("{username}:{realm}:{password}"));
+                let ha2 = md5_hex(&format!("GET:{uri}"));
+                let mut header = format!(
+                    r#"Digest username="{username}", realm="{realm}", nonce="{nonce}", uri="{uri}""#
+                );
+                if *qop_auth {
+                    let cnonce = cnonce();
+                    let response = md5_hex(&format!("{ha1}:{nonce}:00000001:{cnonce}:auth:{ha2}"));
+                    header.push_str(&format!(
+                        r#", qop=auth, nc=00000001, cnonce="{cnonce}", response="{response}""#
+                    ));
+                } else {
+                    let response = md5_hex(&format!("{ha1}:{nonce}:{ha2}"));
+                    header.push_str(&format!(r#", response="{response}""#));
+                }
+                if let Some(opaque) = opaque {
+                    header.push_str(&format!(r#", opaque="{opaque}""#));
+                }
+                header.push_str(", algorithm=MD5");
+                header
+            }
+        }
+    }
+}
+
+// Client nonce, only needs to be unpredictable enough not to repeat.
+pub(super) fn cnonce() -> String {
+    static COUNTER: AtomicU64 = AtomicU64::new(0);
+    let nanos = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_nanos();
+    let count = COUNTER.fetch_add(1, Ordering::Relaxed);
+    md5_hex(&format!("{nanos}:{count}:{}", std::process::id()))[..16].to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_digest_authorization() {
+        // Credentials of the RFC 2617 section 3.5 example, without qop.
+        let challenge = Challenge::parse(
+            r#"Digest realm="testrealm@host.com", nonce="dcd98b7102dd2f0e8b11d0f600bfb0c093", opaque="5ccc069c403ebaf9f0171e9517f40e41""#,
+        )
+        .unwrap();
+        let header = challenge.authorization("Mufasa", "Circle Of Life", "/dir/index.html");
+        assert!(
+            header.contains(r#"response="670fd8c2df070c60b045671b8b24ff02""#),
+            "{header}"
+        );
+        assert!(header.contains(r#"opaque="5ccc069c403ebaf9f0171e9517f40e41""#));
+
+        let challenge =
+            Challenge::parse(r#"Digest realm="cpe", nonce="abc", qop="auth,auth-int""#).unwrap();
+        assert!(matches!(
+            challenge,
+            Challenge::Digest { qop_auth: true, .. }
+        ));
+        assert!(challenge
+            .authorization("acs", "secret", "/cr")
+            .contains("qop=auth, nc=00000001"));
+
+        assert_eq!(
+            Challenge::parse(r#"Basic realm="cpe""#)
+                .unwrap()
+                .authorization("acs", "secret", "/"),
+            "Basic YWNzOnNlY3JldA=="
+        );
+        assert_eq!(Challenge::parse("Bearer"), None);
+    }
+}
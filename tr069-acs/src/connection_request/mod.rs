@@ -0,0 +1,353 @@
+// Connection Requests: asking a CPE to open a CWMP session now instead of at its
+// next periodic Inform (TR-069 Amendment 6, section 3.2.2).
+//
+// The ACS sends an HTTP GET to the ConnectionRequestURL the CPE reported in its
+// Inform, authenticating with ConnectionRequestUsername/Password. The CPE answers
+// 200 or 204 and then Informs with the "6 CONNECTION REQUEST" event. CPEs behind a
+// NAT are reached over UDP instead, at the binding they report through STUN, or
+// through an XMPP server both sides are connected to.
+mod digest;
+mod stun;
+mod udp;
+mod xmpp;
+
+pub(crate) use digest::auth_params;
+pub use digest::Challenge;
+pub use stun::StunServer;
+pub use udp::UdpConnectionRequestSender;
+pub use xmpp::{XmppConfig, XmppConnectionRequestSender, XmppError};
+
+use crate::device::{Device, DeviceId};
+use reqwest::{header, StatusCode};
+use std::{
+    collections::HashMap,
+    fmt, io,
+    net::SocketAddr,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+/// Give up on a CPE that does not answer within this time.
+pub const DEFAULT_TIMEOUT: Duration = Duration::from_secs(10);
+/// CPEs may ignore Connection Requests arriving faster than this (section 3.2.2.1).
+pub const DEFAULT_MIN_INTERVAL: Duration = Duration::from_secs(10);
+
+#[derive(Debug)]
+pub enum ConnectionRequestError {
+    /// The CPE has reported no ConnectionRequestURL, UDP binding or Jabber ID.
+    NoUrl,
+    /// A Connection Request was sent to this CPE less than the minimum interval ago.
+    RateLimited {
+        retry_in: Duration,
+    },
+    /// The CPE asked for credentials and none are known, or it rejected them.
+    Unauthorized,
+    /// The CPE answered with an unexpected status.
+    Status(StatusCode),
+    Http(reqwest::Error),
+    Udp(io::Error),
+    Xmpp(XmppError),
+}
+
+impl fmt::Display for ConnectionRequestError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConnectionRequestError::NoUrl => write!(f, "no ConnectionRequestURL known"),
+            ConnectionRequestError::RateLimited { retry_in } => {
+                write!(f, "rate limited, retry in {}s", retry_in.as_secs())
+            }
+            ConnectionRequestError::Unauthorized => write!(f, "CPE rejected the credentials"),
+            ConnectionRequestError::Status(status) => write!(f, "CPE answered {status}"),
+            ConnectionRequestError::Http(err) => write!(f, "HTTP error: {err}"),
+            ConnectionRequestError::Udp(err) => write!(f, "UDP error: {err}"),
+            ConnectionRequestError::Xmpp(err) => write!(f, "XMPP error: {err}"),
+        }
+    }
+}
+
+impl std::error::Error for ConnectionRequestError {}
+
+impl From<reqwest::Error> for ConnectionRequestError {
+    fn from(err: reqwest::Error) -> Self {
+        ConnectionRequestError::Http(err)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Credentials {
+    pub username: String,
+    pub password: String,
+}
+
+impl Credentials {
+    /// Credentials the ACS configured on the device, taken from its cached
+    /// `ManagementServer.ConnectionRequestUsername` and `...Password` Parameters.
+    pub fn of_device(device: &Device) -> Option<Self> {
+        Some(Self {
+            username: device
+                .management_server("ConnectionRequestUsername")?
+                .to_string(),
+            password: device
+                .management_server("ConnectionRequestPassword")?
+                .to_string(),
+        })
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct ConnectionRequestClient {
+    http: reqwest::Client,
+    /// Used for devices whose credentials are not in the Parameter cache.
+    default_credentials: Option<Credentials>,
+    min_interval: Duration,
+    last_sent: Arc<Mutex<HashMap<DeviceId, Instant>>>,
+    udp: Option<UdpConnectionRequestSender>,
+    xmpp: Option<XmppConnectionRequestSender>,
+}
+
+impl Default for ConnectionRequestClient {
+    fn default() -> Self {
+        Self::new(DEFAULT_TIMEOUT, DEFAULT_MIN_INTERVAL)
+    }
+}
+
+impl ConnectionRequestClient {
+    pub fn new(timeout: Duration, min_interval: Duration) -> Self {
+        let http = reqwest::Client::builder()
+            .timeout(timeout)
+            .build()
+            .expect("default TLS backend is available");
+        Self {
+            http,
+            default_credentials: None,
+            min_interval,
+            last_sent: Arc::default(),
+            udp: None,
+            xmpp: None,
+        }
+    }
+
+    pub fn with_default_credentials(mut self, credentials: Credentials) -> Self {
+        self.default_credentials = Some(credentials);
+        self
+    }
+
+    /// Reach CPEs that reported a UDP binding through `sender`.
+    pub fn with_udp(mut self, sender: UdpConnectionRequestSender) -> Self {
+        self.udp = Some(sender);
+        self
+    }
+
+    /// Reach CPEs that reported a ConnReqJabberID through `sender`.
+    pub fn with_xmpp(mut self, sender: XmppConnectionRequestSender) -> Self {
+        self.xmpp = Some(sender);
+        self
+    }
+
+    // Reserve the slot for `id`, or say how long to wait for the next one.
+    fn rate_limit(&self, id: &DeviceId) -> Result<(), ConnectionRequestError> {
+        let mut last_sent = self.last_sent.lock().unwrap();
+        let now = Instant::now();
+        if let Some(last) = last_sent.get(id) {
+            let elapsed = now.duration_since(*last);
+            if elapsed < self.min_interval {
+                return Err(ConnectionRequestError::RateLimited {
+                    retry_in: self.min_interval - elapsed,
+                });
+            }
+        }
+        last_sent.insert(id.clone(), now);
+        Ok(())
+    }
+
+    /// Ask `device` to open a session with the ACS: over HTTP when it reported a
+    /// ConnectionRequestURL, then over UDP and XMPP when HTTP fails or the device
+    /// is only reachable there. A UDP request is not acknowledged, once sent it
+    /// counts as delivered.
+    pub async fn send(&self, device: &Device) -> Result<(), ConnectionRequestError> {
+        let url = device
+            .connection_request_url
+            .as_deref()
+            .filter(|url| !url.is_empty());
+        let udp = self.udp.as_ref().zip(
+            device
+                .udp_connection_request_address
+                .as_deref()
+                .and_then(|address| address.parse::<SocketAddr>().ok()),
+        );
+        let xmpp = self.xmpp.as_ref().zip(
+            device
+                .connection_request_jabber_id
+                .as_deref()
+                .filter(|jid| !jid.is_empty()),
+        );
+        if url.is_none() && udp.is_none() && xmpp.is_none() {
+            return Err(ConnectionRequestError::NoUrl);
+        }
+        self.rate_limit(&device.id)?;
+        let credentials =
+            Credentials::of_device(device).or_else(|| self.default_credentials.clone());
+
+        let mut result = Err(ConnectionRequestError::NoUrl);
+        if let Some(url) = url {
+            tracing::info!("Connection Request to {} at {url}", device.id);
+            result = self.get(url, credentials.as_ref()).await;
+        }
+        if let (Err(err), Some((sender, addr))) = (&result, udp) {
+            tracing::info!("UDP Connection Request to {} at {addr} ({err})", device.id);
+            result = match &credentials {
+                Some(credentials) => sender
+                    .send(addr, credentials)
+                    .await
+                    .map_err(ConnectionRequestError::Udp),
+                None => Err(ConnectionRequestError::Unauthorized),
+            };
+        }
+        if let (Err(err), Some((sender, jid))) = (&result, xmpp) {
+            tracing::info!("XMPP Connection Request to {} at {jid} ({err})", device.id);
+            result = match &credentials {
+                Some(credentials) => sender
+                    .send(jid, credentials)
+                    .await
+                    .map_err(ConnectionRequestError::Xmpp),
+                None => Err(ConnectionRequestError::Unauthorized),
+            };
+        }
+        result
+    }
+
+    async fn get(
+        &self,
+        url: &str,
+        credentials: Option<&Credentials>,
+    ) -> Result<(), ConnectionRequestError> {
+        let response = self.http.get(url).send().await?;
+        if response.status() != StatusCode::UNAUTHORIZED {
+            return check_status(response.status());
+        }
+
+        // Prefer Digest when the CPE offers several schemes.
+        let mut challenges: Vec<Challenge> = response
+            .headers()
+            .get_all(header::WWW_AUTHENTICATE)
+            .iter()
+            .filter_map(|value| Challenge::parse(value.to_str().ok()?))
+            .collect();
+        challenges.sort_by_key(|challenge| matches!(challenge, Challenge::Basic));
+        let (Some(challenge), Some(credentials)) = (challenges.first(), credentials) else {
+            return Err(ConnectionRequestError::Unauthorized);
+        };
+
+        let uri = match response.url().query() {
+            Some(query) => format!("{}?{query}", response.url().path()),
+            None => response.url().path().to_string(),
+        };
+        let authorization =
+            challenge.authorization(&credentials.username, &credentials.password, &uri);
+        let response = self
+            .http
+            .get(url)
+            .header(header::AUTHORIZATION, authorization)
+            .send()
+            .await?;
+        check_status(response.status())
+    }
+}
+
+fn check_status(status: StatusCode) -> Result<(), ConnectionRequestError> {
+    match status {
+        StatusCode::OK | StatusCode::NO_CONTENT => Ok(()),
+        StatusCode::UNAUTHORIZED => Err(ConnectionRequestError::Unauthorized),
+        status => Err(ConnectionRequestError::Status(status)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{http::HeaderMap, response::IntoResponse, routing::get, Router};
+    use chrono::Utc;
+    use md5::{Digest, Md5};
+
+    const CHALLENGE: &str = r#"Digest realm="cpe", nonce="0123456789abcdef", qop="auth""#;
+
+    // A CPE that checks the Digest answer of user "acs" with password "secret".
+    async fn cpe(headers: HeaderMap) -> axum::response::Response {
+        let authorized = headers
+            .get(header::AUTHORIZATION)
+            .and_then(|value| value.to_str().ok()?.strip_prefix("Digest "))
+            .map(auth_params)
+            .is_some_and(|params| {
+                let field = |name: &str| params.get(name).map(String::as_str).unwrap_or_default();
+                let md5 = |input: String| format!("{:x}", Md5::digest(input.as_bytes()));
+                let ha1 = md5(format!("acs:{}:secret", field("realm")));
+                let ha2 = md5(format!("GET:{}", field("uri")));
+                let expected = md5(format!(
+                    "{ha1}:{}:{}:{}:auth:{ha2}",
+                    field("nonce"),
+                    field("nc"),
+                    field("cnonce")
+                ));
+                field("username") == "acs"
+                    && field("realm") == "cpe"
+                    && field("nonce") == "0123456789abcdef"
+                    && field("uri") == "/cr"
+                    && field("qop") == "auth"
+                    && field("response") == expected
+            });
+        if authorized {
+            StatusCode::NO_CONTENT.into_response()
+        } else {
+            (
+                StatusCode::UNAUTHORIZED,
+                [(header::WWW_AUTHENTICATE, CHALLENGE)],
+            )
+                .into_response()
+        }
+    }
+
+    #[tokio::test]
+    async fn test_connection_request_digest() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        tokio::spawn(async move {
+            axum::serve(listener, Router::new().route("/cr", get(cpe)))
+                .await
+                .unwrap()
+        });
+
+        let mut device = Device::new("001A2B-RouterX-SN1".parse().unwrap(), Utc::now());
+        let client = ConnectionRequestClient::default();
+        assert!(matches!(
+            client.send(&device).await,
+            Err(ConnectionRequestError::NoUrl)
+        ));
+
+        device.connection_request_url = Some(format!("http://127.0.0.1:{port}/cr"));
+        assert!(matches!(
+            client.send(&device).await,
+            Err(ConnectionRequestError::Unauthorized)
+        ));
+        assert!(matches!(
+            client.send(&device).await,
+            Err(ConnectionRequestError::RateLimited { .. })
+        ));
+
+        let client = ConnectionRequestClient::new(DEFAULT_TIMEOUT, Duration::ZERO)
+            .with_default_credentials(Credentials {
+                username: String::from("acs"),
+                password: String::from("wrong"),
+            });
+        assert!(matches!(
+            client.send(&device).await,
+            Err(ConnectionRequestError::Unauthorized)
+        ));
+
+        let client = ConnectionRequestClient::new(DEFAULT_TIMEOUT, Duration::ZERO)
+            .with_default_credentials(Credentials {
+                username: String::from("acs"),
+                password: String::from("secret"),
+            });
+        client.send(&device).await.unwrap();
+    }
+}
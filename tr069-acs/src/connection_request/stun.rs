@@ -0,0 +1,470 @@
+// STUN server for CPEs behind NAT (TR-111 Part 2, RFC 3489 and RFC 5389).
+//
+// A CPE with STUNEnable keeps a NAT binding open by sending Binding Requests to the
+// ACS. Requests carrying CONNECTION-REQUEST-BINDING tell the ACS which public
+// address reaches the CPE's UDP Connection Request port; BINDING-CHANGE marks a
+// new binding. The USERNAME alone proves nothing: a binding is only recorded
+// with a MESSAGE-INTEGRITY keyed by the STUNPassword the ACS set, or, for devices
+// without one, when no address is known yet.
+use super::udp::UdpConnectionRequestSender;
+use crate::device::DeviceRegistry;
+use hmac::{Hmac, Mac};
+use sha1::Sha1;
+use std::{
+    io,
+    net::{IpAddr, SocketAddr},
+    sync::Arc,
+};
+use tokio::net::UdpSocket;
+
+const BINDING_REQUEST: u16 = 0x0001;
+const BINDING_RESPONSE: u16 = 0x0101;
+const MAGIC_COOKIE: u32 = 0x2112_A442;
+const HEADER_LEN: usize = 20;
+
+const MAPPED_ADDRESS: u16 = 0x0001;
+const USERNAME: u16 = 0x0006;
+const MESSAGE_INTEGRITY: u16 = 0x0008;
+const INTEGRITY_LEN: usize = 20;
+const XOR_MAPPED_ADDRESS: u16 = 0x0020;
+// TR-111 attributes.
+const CONNECTION_REQUEST_BINDING: u16 = 0xC001;
+const BINDING_CHANGE: u16 = 0xC002;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StunMessage {
+    pub message_type: u16,
+    /// Magic cookie followed by the 96 bit transaction id; the whole 128 bit
+    /// transaction id of RFC 3489 clients.
+    pub transaction_id: [u8; 16],
+    pub attributes: Vec<(u16, Vec<u8>)>,
+}
+
+impl StunMessage {
+    pub fn parse(packet: &[u8]) -> Option<Self> {
+        if packet.len() < HEADER_LEN || packet[0] & 0xC0 != 0 {
+            return None;
+        }
+        let message_type = u16::from_be_bytes([packet[0], packet[1]]);
+        let length = u16::from_be_bytes([packet[2], packet[3]]) as usize;
+        let body = packet.get(HEADER_LEN..HEADER_LEN + length)?;
+        let mut transaction_id = [0; 16];
+        transaction_id.copy_from_slice(&packet[4..HEADER_LEN]);
+
+        let mut attributes = Vec::new();
+        let mut rest = body;
+        while rest.len() >= 4 {
+            let kind = u16::from_be_bytes([rest[0], rest[1]]);
+            let len = u16::from_be_bytes([rest[2], rest[3]]) as usize;
+            let value = rest.get(4..4 + len)?;
+            attributes.push((kind, value.to_vec()));
+            // Values are padded to 4 bytes.
+            let padded = (4 + len).next_multiple_of(4);
+            rest = rest.get(padded..).unwrap_or_default();
+        }
+        Some(Self {
+            message_type,
+            transaction_id,
+            attributes,
+        })
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut body = Vec::new();
+        for (kind, value) in &self.attributes {
+            body.extend_from_slice(&kind.to_be_bytes());
+            body.extend_from_slice(&(value.len() as u16).to_be_bytes());
+            body.extend_from_slice(value);
+            body.resize(body.len().next_multiple_of(4), 0);
+        }
+        let mut packet = Vec::with_capacity(HEADER_LEN + body.len());
+        packet.extend_from_slice(&self.message_type.to_be_bytes());
+        packet.extend_from_slice(&(body.len() as u16).to_be_bytes());
+        packet.extend_from_slice(&self.transaction_id);
+        packet.extend_from_slice(&body);
+        packet
+    }
+
+    pub fn attribute(&self, kind: u16) -> Option<&[u8]> {
+        self.attributes
+            .iter()
+            .find(|(found, _)| *found == kind)
+            .map(|(_, value)| value.as_slice())
+    }
+
+    /// RFC 5389 clients set the magic cookie, RFC 3489 ones do not.
+    fn has_magic_cookie(&self) -> bool {
+        self.transaction_id[..4] == MAGIC_COOKIE.to_be_bytes()
+    }
+
+    pub fn username(&self) -> Option<String> {
+        let username = self.attribute(USERNAME)?;
+        // RFC 3489 usernames are padded with NULs to a multiple of 4 bytes.
+        let username = String::from_utf8_lossy(username);
+        Some(username.trim_end_matches('\0').to_string())
+    }
+
+    /// The Binding Response sent back to `source`.
+    pub fn binding_response(&self, source: SocketAddr) -> Self {
+        let mut attributes = vec![(MAPPED_ADDRESS, address_value(source, None))];
+        if self.has_magic_cookie() {
+            let xor = address_value(source, Some(&self.transaction_id));
+            attributes.push((XOR_MAPPED_ADDRESS, xor));
+        }
+        Self {
+            message_type: BINDING_RESPONSE,
+            transaction_id: self.transaction_id,
+            attributes,
+        }
+    }
+}
+
+// HMAC-SHA1 over the message up to its MESSAGE-INTEGRITY attribute, `prefix`,
+// with the header length covering that attribute (RFC 5389 15.4). RFC 3489 pads
+// the text with zeros to a multiple of 64 bytes instead.
+fn integrity(prefix: &[u8], password: &str, rfc3489: bool) -> [u8; INTEGRITY_LEN] {
+    let mut text = prefix.to_vec();
+    let length = (text.len() - HEADER_LEN + 4 + INTEGRITY_LEN) as u16;
+    text[2..4].copy_from_slice(&length.to_be_bytes());
+    if rfc3489 {
+        text.resize(text.len().next_multiple_of(64), 0);
+    }
+    let mut mac =
+        Hmac::<Sha1>::new_from_slice(password.as_bytes()).expect("HMAC accepts any key length");
+    mac.update(&text);
+    mac.finalize().into_bytes().into()
+}
+
+/// Whether `packet` carries a MESSAGE-INTEGRITY keyed by `password`.
+fn verify_integrity(packet: &[u8], password: &str) -> bool {
+    let Some(message) = StunMessage::parse(packet) else {
+        return false;
+    };
+    // Attributes start after the header and are padded to 4 bytes.
+    let mut offset = HEADER_LEN;
+    for (kind, value) in &message.attributes {
+        if *kind == MESSAGE_INTEGRITY {
+            let expected = integrity(&packet[..offset], password, !message.has_magic_cookie());
+            return value.len() == INTEGRITY_LEN
+                && expected
+                    .iter()
+                    .zip(value)
+                    .fold(0, |diff, (a, b)| diff | (a ^ b))
+                    == 0;
+        }
+        offset += (4 + value.len()).next_multiple_of(4);
+    }
+    false
+}
+
+// MAPPED-ADDRESS value, XORed with the magic cookie and transaction id when given.
+fn address_value(address: SocketAddr, xor: Option<&[u8; 16]>) -> Vec<u8> {
+    let (family, ip) = match address.ip() {
+        IpAddr::V4(ip) => (0x01, ip.octets().to_vec()),
+        IpAddr::V6(ip) => (0x02, ip.octets().to_vec()),
+    };
+    let mut port = address.port().to_be_bytes();
+    let mut ip = ip;
+    if let Some(key) = xor {
+        port.iter_mut()
+            .zip(key)
+            .for_each(|(byte, key)| *byte ^= key);
+        ip.iter_mut().zip(key).for_each(|(byte, key)| *byte ^= key);
+    }
+    let mut value = vec![0, family];
+    value.extend_from_slice(&port);
+    value.extend_from_slice(&ip);
+    value
+}
+
+/// Answers Binding Requests and records the CPEs' UDP Connection Request bindings.
+#[derive(Debug, Clone)]
+pub struct StunServer {
+    socket: Arc<UdpSocket>,
+    devices: DeviceRegistry,
+}
+
+impl StunServer {
+    pub async fn bind(addr: SocketAddr, devices: DeviceRegistry) -> io::Result<Self> {
+        let socket = UdpSocket::bind(addr).await?;
+        Ok(Self {
+            socket: Arc::new(socket),
+            devices,
+        })
+    }
+
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.socket.local_addr()
+    }
+
+    /// Sends UDP Connection Requests from the STUN port, the only source address
+    /// a restricted cone NAT lets through to the CPE.
+    pub fn sender(&self) -> UdpConnectionRequestSender {
+        UdpConnectionRequestSender::new(self.socket.clone())
+    }
+
+    pub async fn run(self) {
+        let mut buffer = [0; 1500];
+        loop {
+            let (len, source) = match self.socket.recv_from(&mut buffer).await {
+                Ok(received) => received,
+                Err(err) => {
+                    tracing::warn!("STUN receive failed: {err}");
+                    continue;
+                }
+            };
+            let Some(request) = StunMessage::parse(&buffer[..len]) else {
+                continue;
+            };
+            if request.message_type != BINDING_REQUEST {
+                continue;
+            }
+            self.record_binding(&request, &buffer[..len], source);
+            let response = request.binding_response(source).to_bytes();
+            if let Err(err) = self.socket.send_to(&response, source).await {
+                tracing::warn!("STUN response to {source} failed: {err}");
+            }
+        }
+    }
+
+    fn record_binding(&self, request: &StunMessage, packet: &[u8], source: SocketAddr) {
+        if request.attribute(CONNECTION_REQUEST_BINDING).is_none() {
+            return;
+        }
+        let Some(username) = request.username() else {
+            return;
+        };
+        let Some(id) = self.devices.find_by_stun_username(&username) else {
+            tracing::debug!("STUN binding from unknown device {username:?}");
+            return;
+        };
+        let Some(device) = self.devices.get(&id) else {
+            return;
+        };
+        let known = device.udp_connection_request_address;
+        match device.stun_password {
+            Some(password) if !verify_integrity(packet, &password) => {
+                tracing::warn!("STUN binding of {id} from {source} failed MESSAGE-INTEGRITY");
+                return;
+            }
+            Some(_) => {}
+            None if known.is_some() => {
+                tracing::debug!("Ignoring unauthenticated STUN binding of {id} from {source}");
+                return;
+            }
+            None => {}
+        }
+        let address = source.to_string();
+        if request.attribute(BINDING_CHANGE).is_some() || known.as_ref() != Some(&address) {
+            tracing::info!("UDP Connection Request address of {id} is {address}");
+            self.devices
+                .set_udp_connection_request_address(&id, address);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        connection_request::{
+            udp::signature, ConnectionRequestClient, Credentials, DEFAULT_TIMEOUT,
+        },
+        cwmp_msg::ParameterValueStruct,
+        device::{Device, DeviceId},
+        storage::{MemoryStorage, Storage},
+    };
+    use chrono::Utc;
+    use std::time::Duration;
+
+    // Parse a MAPPED-ADDRESS or XOR-MAPPED-ADDRESS value.
+    fn parse_address(value: &[u8], xor: Option<&[u8; 16]>) -> Option<SocketAddr> {
+        let mut port = [*value.get(2)?, *value.get(3)?];
+        let mut ip = value.get(4..)?.to_vec();
+        if let Some(key) = xor {
+            port.iter_mut()
+                .zip(key)
+                .for_each(|(byte, key)| *byte ^= key);
+            ip.iter_mut().zip(key).for_each(|(byte, key)| *byte ^= key);
+        }
+        let ip: IpAddr = match (value.get(1)?, ip.len()) {
+            (0x01, 4) => <[u8; 4]>::try_from(ip).ok()?.into(),
+            (0x02, 16) => <[u8; 16]>::try_from(ip).ok()?.into(),
+            _ => return None,
+        };
+        Some(SocketAddr::new(ip, u16::from_be_bytes(port)))
+    }
+
+    // A TR-111 Binding Request as a CPE sends it.
+    fn binding_request(username: &str, binding_change: bool) -> StunMessage {
+        let mut transaction_id = [7; 16];
+        transaction_id[..4].copy_from_slice(&MAGIC_COOKIE.to_be_bytes());
+        let mut attributes = vec![
+            (USERNAME, username.as_bytes().to_vec()),
+            (CONNECTION_REQUEST_BINDING, b"dslforum.org/TR-111 ".to_vec()),
+        ];
+        if binding_change {
+            attributes.push((BINDING_CHANGE, Vec::new()));
+        }
+        StunMessage {
+            message_type: BINDING_REQUEST,
+            transaction_id,
+            attributes,
+        }
+    }
+
+    // `message` with a MESSAGE-INTEGRITY keyed by `password` appended.
+    fn signed(message: &StunMessage, password: &str) -> Vec<u8> {
+        let packet = message.to_bytes();
+        let mac = integrity(&packet, password, !message.has_magic_cookie());
+        let mut message = message.clone();
+        message.attributes.push((MESSAGE_INTEGRITY, mac.to_vec()));
+        message.to_bytes()
+    }
+
+    #[test]
+    fn test_message_integrity() {
+        let request = binding_request("001A2B-SN1", true);
+        let packet = signed(&request, "stun1");
+        assert!(verify_integrity(&packet, "stun1"));
+        assert!(!verify_integrity(&packet, "stun2"));
+        assert!(!verify_integrity(&request.to_bytes(), "stun1"));
+        let mut tampered = packet.clone();
+        tampered[HEADER_LEN + 4] ^= 1;
+        assert!(!verify_integrity(&tampered, "stun1"));
+
+        // RFC 3489 clients have no magic cookie.
+        let mut request = request;
+        request.transaction_id = [7; 16];
+        assert!(verify_integrity(&signed(&request, "stun1"), "stun1"));
+    }
+
+    #[tokio::test]
+    async fn test_binding_authentication() {
+        let id: DeviceId = "001A2B-RouterX-SN1".parse().unwrap();
+        let storage = MemoryStorage::new();
+        storage
+            .save_device(&Device::new(id.clone(), Utc::now()))
+            .unwrap();
+        let devices = DeviceRegistry::load(Arc::new(storage)).unwrap();
+        let server = StunServer::bind("127.0.0.1:0".parse().unwrap(), devices.clone())
+            .await
+            .unwrap();
+        let address =
+            |devices: &DeviceRegistry| devices.get(&id).unwrap().udp_connection_request_address;
+        let request = binding_request("001A2B-SN1", true);
+        let first: SocketAddr = "203.0.113.7:40001".parse().unwrap();
+        let second: SocketAddr = "203.0.113.8:40001".parse().unwrap();
+
+        // Without a STUNPassword only the first binding is taken.
+        server.record_binding(&request, &request.to_bytes(), first);
+        assert_eq!(address(&devices), Some(first.to_string()));
+        server.record_binding(&request, &request.to_bytes(), second);
+        assert_eq!(address(&devices), Some(first.to_string()));
+
+        let stun = [ParameterValueStruct::new(
+            "Device.ManagementServer.STUNPassword",
+            "xsd:string",
+            "stun1",
+        )];
+        devices.update_credentials(&id, &stun);
+        server.record_binding(&request, &request.to_bytes(), second);
+        assert_eq!(address(&devices), Some(first.to_string()));
+        server.record_binding(&request, &signed(&request, "stun2"), second);
+        assert_eq!(address(&devices), Some(first.to_string()));
+        server.record_binding(&request, &signed(&request, "stun1"), second);
+        assert_eq!(address(&devices), Some(second.to_string()));
+    }
+
+    #[test]
+    fn test_stun_message_round_trip() {
+        let request = binding_request("001A2B-SN1", true);
+        let parsed = StunMessage::parse(&request.to_bytes()).unwrap();
+        assert_eq!(parsed, request);
+        assert_eq!(parsed.username().as_deref(), Some("001A2B-SN1"));
+
+        let source: SocketAddr = "203.0.113.7:40001".parse().unwrap();
+        let response = StunMessage::parse(&request.binding_response(source).to_bytes()).unwrap();
+        assert_eq!(response.message_type, BINDING_RESPONSE);
+        assert_eq!(
+            parse_address(response.attribute(MAPPED_ADDRESS).unwrap(), None),
+            Some(source)
+        );
+        assert_eq!(
+            parse_address(
+                response.attribute(XOR_MAPPED_ADDRESS).unwrap(),
+                Some(&response.transaction_id)
+            ),
+            Some(source)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_udp_connection_request_through_stun() {
+        let id: DeviceId = "001A2B-RouterX-SN1".parse().unwrap();
+        let storage = MemoryStorage::new();
+        storage
+            .save_device(&Device::new(id.clone(), Utc::now()))
+            .unwrap();
+        let devices = DeviceRegistry::load(Arc::new(storage)).unwrap();
+
+        let server = StunServer::bind("127.0.0.1:0".parse().unwrap(), devices.clone())
+            .await
+            .unwrap();
+        let server_addr = server.local_addr().unwrap();
+        let client = ConnectionRequestClient::new(DEFAULT_TIMEOUT, Duration::ZERO)
+            .with_default_credentials(Credentials {
+                username: String::from("acs"),
+                password: String::from("secret"),
+            })
+            .with_udp(server.sender());
+        tokio::spawn(server.run());
+
+        // The CPE learns its public address and advertises the binding.
+        let cpe = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let cpe_addr = cpe.local_addr().unwrap();
+        let request = binding_request("001A2B-SN1", true);
+        cpe.send_to(&request.to_bytes(), server_addr).await.unwrap();
+        let mut buffer = [0; 1500];
+        let len = cpe.recv(&mut buffer).await.unwrap();
+        let response = StunMessage::parse(&buffer[..len]).unwrap();
+        assert_eq!(response.transaction_id, request.transaction_id);
+        assert_eq!(
+            parse_address(response.attribute(MAPPED_ADDRESS).unwrap(), None),
+            Some(cpe_addr)
+        );
+        let device = devices.get(&id).unwrap();
+        assert_eq!(
+            device.udp_connection_request_address,
+            Some(cpe_addr.to_string())
+        );
+
+        // Without a ConnectionRequestURL the request goes over UDP.
+        client.send(&device).await.unwrap();
+        let len = cpe.recv(&mut buffer).await.unwrap();
+        let message = std::str::from_utf8(&buffer[..len]).unwrap();
+        let (line, host) = message.split_once("\r\n").unwrap();
+        assert_eq!(host, format!("Host: {cpe_addr}\r\n\r\n"));
+        let query = line
+            .strip_prefix(&format!("GET http://{cpe_addr}?"))
+            .and_then(|rest| rest.strip_suffix(" HTTP/1.1"))
+            .unwrap();
+        let field = |key: &str| {
+            query
+                .split('&')
+                .find_map(|pair| pair.strip_prefix(&format!("{key}=")))
+                .unwrap()
+        };
+        assert_eq!(field("un"), "acs");
+        assert_eq!(
+            field("sig"),
+            signature(
+                "secret",
+                field("ts").parse().unwrap(),
+                field("id").parse().unwrap(),
+                "acs",
+                field("cn")
+            )
+        );
+    }
+}
@@ -0,0 +1,89 @@
+// UDP Connection Requests (TR-111 Part 2, TR-069 Annex G).
+//
+// A CPE behind a NAT is reached through the binding it advertised to the STUN
+// server. The request is an HTTP GET line in a single datagram, authenticated by an
+// HMAC-SHA1 signature over the timestamp, message id, username and cnonce, keyed
+// with the ConnectionRequestPassword. UDP is lossy, so it is sent a few times with
+// the same message id; the CPE ignores the duplicates.
+use super::{digest::cnonce, Credentials};
+use hmac::{Hmac, Mac};
+use sha1::Sha1;
+use std::{
+    io,
+    net::SocketAddr,
+    sync::{
+        atomic::{AtomicU32, Ordering},
+        Arc,
+    },
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+use tokio::net::UdpSocket;
+
+/// Copies of each request sent, and the delay between them.
+const RETRANSMITS: u32 = 3;
+const RETRANSMIT_INTERVAL: Duration = Duration::from_millis(500);
+
+/// `sig` value of a UDP Connection Request: uppercase hex HMAC-SHA1 of
+/// `ts`, `id`, `un` and `cn` concatenated.
+pub fn signature(password: &str, ts: u64, id: u32, username: &str, cnonce: &str) -> String {
+    let mut mac =
+        Hmac::<Sha1>::new_from_slice(password.as_bytes()).expect("HMAC accepts any key length");
+    mac.update(format!("{ts}{id}{username}{cnonce}").as_bytes());
+    mac.finalize()
+        .into_bytes()
+        .iter()
+        .map(|byte| format!("{byte:02X}"))
+        .collect()
+}
+
+/// Sends UDP Connection Requests, from the STUN server's socket.
+#[derive(Debug, Clone)]
+pub struct UdpConnectionRequestSender {
+    socket: Arc<UdpSocket>,
+    next_id: Arc<AtomicU32>,
+}
+
+impl UdpConnectionRequestSender {
+    pub fn new(socket: Arc<UdpSocket>) -> Self {
+        Self {
+            socket,
+            next_id: Arc::new(AtomicU32::new(1)),
+        }
+    }
+
+    /// Send a UDP Connection Request to the CPE's binding `addr`.
+    pub async fn send(&self, addr: SocketAddr, credentials: &Credentials) -> io::Result<()> {
+        let ts = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs();
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        let cn = cnonce();
+        let sig = signature(&credentials.password, ts, id, &credentials.username, &cn);
+        let request = format!(
+            "GET http://{addr}?ts={ts}&id={id}&un={}&cn={cn}&sig={sig} HTTP/1.1\r\nHost: {addr}\r\n\r\n",
+            credentials.username
+        );
+        for attempt in 0..RETRANSMITS {
+            if attempt > 0 {
+                tokio::time::sleep(RETRANSMIT_INTERVAL).await;
+            }
+            self.socket.send_to(request.as_bytes(), addr).await?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_signature() {
+        // Parameters of the TR-111 example message, with password "secret".
+        assert_eq!(
+            signature("secret", 1120673700, 1234, "CPE057", "XTGRWIPC6D3IPXS3"),
+            "7D91AC724599EE846F3356C8FC279ED0A0026A24"
+        );
+    }
+}
@@ -0,0 +1,664 @@
+// XMPP Connection Requests (TR-069 Amendment 6, Annex K).
+//
+// The ACS logs in to an XMPP server and sends a `connectionRequest` IQ to the full
+// JID the CPE reported in ManagementServer.ConnReqJabberID. The IQ carries the
+// ConnectionRequestUsername/Password; the CPE answers with an IQ result, or an IQ
+// error when it rejects the credentials or is not allowed to take requests from
+// the ACS. Each request uses its own connection: STARTTLS, SASL PLAIN over the
+// TLS stream, resource binding, the IQ, then the stream is closed. A server that
+// does not offer STARTTLS never sees the ACS password.
+use super::Credentials;
+use crate::tls::{certificates, TlsError};
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
+use quick_xml::{escape::escape, events::Event, Reader};
+use rustls::{crypto::ring, pki_types::ServerName, ClientConfig, RootCertStore};
+use serde::Deserialize;
+use std::{
+    fmt, io,
+    path::PathBuf,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+use tokio::{
+    io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt},
+    net::TcpStream,
+};
+use tokio_rustls::TlsConnector;
+
+pub const CONNECTION_REQUEST_NS: &str = "urn:broadband-forum-org:cwmp:xmppConnReq-1-0";
+const TLS_NS: &str = "urn:ietf:params:xml:ns:xmpp-tls";
+const SASL_NS: &str = "urn:ietf:params:xml:ns:xmpp-sasl";
+const BIND_NS: &str = "urn:ietf:params:xml:ns:xmpp-bind";
+
+/// Give up on an attempt that does not complete within this time.
+pub const DEFAULT_XMPP_TIMEOUT: Duration = Duration::from_secs(10);
+/// Attempts after the first one, when the connection or the server failed.
+pub const DEFAULT_XMPP_RETRIES: u32 = 2;
+const DEFAULT_RETRY_INTERVAL: Duration = Duration::from_secs(2);
+
+#[derive(Debug)]
+pub enum XmppError {
+    Io(io::Error),
+    Timeout,
+    /// The server sent something this client does not understand.
+    Protocol(String),
+    /// The server rejected the ACS account.
+    AuthenticationFailed,
+    /// The CPE, or the server on its behalf, answered with this IQ error condition.
+    Rejected(String),
+}
+
+impl fmt::Display for XmppError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            XmppError::Io(err) => write!(f, "I/O error: {err}"),
+            XmppError::Timeout => write!(f, "timed out"),
+            XmppError::Protocol(err) => write!(f, "protocol error: {err}"),
+            XmppError::AuthenticationFailed => write!(f, "XMPP server rejected the ACS account"),
+            XmppError::Rejected(condition) => write!(f, "CPE answered {condition}"),
+        }
+    }
+}
+
+impl std::error::Error for XmppError {}
+
+impl XmppError {
+    // Worth another connection: the CPE's answer and the login failure are final.
+    fn is_transient(&self) -> bool {
+        matches!(
+            self,
+            XmppError::Io(_) | XmppError::Timeout | XmppError::Protocol(_)
+        )
+    }
+}
+
+impl From<io::Error> for XmppError {
+    fn from(err: io::Error) -> Self {
+        XmppError::Io(err)
+    }
+}
+
+/// The ACS account on the XMPP server.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct XmppConfig {
+    /// `user@domain` or `user@domain/resource`.
+    pub jid: String,
+    pub password: String,
+    /// `host:port` of the server, the JID's domain on port 5222 if not given.
+    pub server: Option<String>,
+    /// PEM CA certificates the server's certificate is checked against. The
+    /// certificate must be issued to the JID's domain.
+    pub ca: PathBuf,
+}
+
+// Local part, domain and resource of a JID.
+fn split_jid(jid: &str) -> Option<(&str, &str, Option<&str>)> {
+    let (bare, resource) = match jid.split_once('/') {
+        Some((bare, resource)) => (bare, Some(resource)),
+        None => (jid, None),
+    };
+    let (local, domain) = bare.split_once('@')?;
+    (!local.is_empty() && !domain.is_empty()).then_some((local, domain, resource))
+}
+
+/// A parsed stanza, or the stream header without its children.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+struct Element {
+    /// Local name, namespace prefixes are dropped.
+    name: String,
+    attributes: Vec<(String, String)>,
+    children: Vec<Element>,
+    text: String,
+}
+
+impl Element {
+    fn from_start(start: &quick_xml::events::BytesStart) -> Self {
+        let attributes = start
+            .attributes()
+            .flatten()
+            .map(|attribute| {
+                let key = String::from_utf8_lossy(attribute.key.as_ref()).into_owned();
+                let value = attribute
+                    .unescape_value()
+                    .map(|value| value.into_owned())
+                    .unwrap_or_default();
+                (key, value)
+            })
+            .collect();
+        Self {
+            name: String::from_utf8_lossy(start.local_name().as_ref()).into_owned(),
+            attributes,
+            ..Default::default()
+        }
+    }
+
+    fn attribute(&self, name: &str) -> Option<&str> {
+        self.attributes
+            .iter()
+            .find(|(key, _)| key == name)
+            .map(|(_, value)| value.as_str())
+    }
+
+    fn child(&self, name: &str) -> Option<&Element> {
+        self.children.iter().find(|child| child.name == name)
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+enum Frame {
+    StreamStart(Element),
+    Stanza(Element),
+    StreamEnd,
+}
+
+// The first complete frame in `buffer` and the bytes it used, `None` until enough
+// data has arrived.
+fn parse_frame(buffer: &[u8]) -> Option<(Frame, usize)> {
+    let mut reader = Reader::from_reader(buffer);
+    // The stream header was consumed by an earlier call.
+    reader.config_mut().allow_unmatched_ends = true;
+
+    let mut stack: Vec<Element> = Vec::new();
+    // Whitespace-only text is kept back until we know it is not just the
+    // indentation between two elements.
+    let mut whitespace = String::new();
+    let frame = loop {
+        match reader.read_event().ok()? {
+            Event::Start(start) => {
+                whitespace.clear();
+                let element = Element::from_start(&start);
+                if stack.is_empty() && element.name == "stream" {
+                    break Frame::StreamStart(element);
+                }
+                stack.push(element);
+            }
+            Event::Empty(start) => {
+                whitespace.clear();
+                let element = Element::from_start(&start);
+                match stack.last_mut() {
+                    Some(parent) => parent.children.push(element),
+                    None => break Frame::Stanza(element),
+                }
+            }
+            Event::Text(text) => {
+                let text = text.decode().ok()?;
+                if text.trim().is_empty() {
+                    whitespace.push_str(&text);
+                } else if let Some(element) = stack.last_mut() {
+                    element.text.push_str(&whitespace);
+                    element.text.push_str(&text);
+                    whitespace.clear();
+                }
+            }
+            Event::GeneralRef(reference) => {
+                if let Some(element) = stack.last_mut() {
+                    element.text.push_str(&whitespace);
+                    whitespace.clear();
+                    let raw = format!("&{};", reference.decode().ok()?);
+                    element
+                        .text
+                        .push_str(&quick_xml::escape::unescape(&raw).ok()?);
+                }
+            }
+            Event::End(_) => match stack.pop() {
+                None => break Frame::StreamEnd,
+                Some(mut element) => {
+                    // Trailing whitespace belongs to the text of a leaf.
+                    if element.children.is_empty() && !element.text.is_empty() {
+                        element.text.push_str(&whitespace);
+                    }
+                    whitespace.clear();
+                    match stack.last_mut() {
+                        Some(parent) => parent.children.push(element),
+                        None => break Frame::Stanza(element),
+                    }
+                }
+            },
+            Event::Eof => return None,
+            _ => {}
+        }
+    };
+    Some((frame, reader.buffer_position() as usize))
+}
+
+fn stream_header(attributes: &str) -> String {
+    format!(
+        "<?xml version='1.0'?><stream:stream {attributes} version='1.0' xmlns='jabber:client' xmlns:stream='http://etherx.jabber.org/streams'>"
+    )
+}
+
+// One end of an XMPP stream, over TCP or TLS.
+struct XmppStream<S> {
+    socket: S,
+    buffer: Vec<u8>,
+}
+
+impl<S: AsyncRead + AsyncWrite + Unpin> XmppStream<S> {
+    fn new(socket: S) -> Self {
+        Self {
+            socket,
+            buffer: Vec::new(),
+        }
+    }
+
+    async fn write(&mut self, data: &str) -> Result<(), XmppError> {
+        self.socket.write_all(data.as_bytes()).await?;
+        Ok(())
+    }
+
+    async fn read_frame(&mut self) -> Result<Frame, XmppError> {
+        loop {
+            if let Some((frame, used)) = parse_frame(&self.buffer) {
+                self.buffer.drain(..used);
+                return Ok(frame);
+            }
+            if self.socket.read_buf(&mut self.buffer).await? == 0 {
+                return Err(XmppError::Protocol(String::from("connection closed")));
+            }
+        }
+    }
+
+    async fn read_stanza(&mut self) -> Result<Element, XmppError> {
+        match self.read_frame().await? {
+            Frame::Stanza(element) => Ok(element),
+            Frame::StreamStart(_) => Err(XmppError::Protocol(String::from(
+                "unexpected stream header",
+            ))),
+            Frame::StreamEnd => Err(XmppError::Protocol(String::from("stream closed"))),
+        }
+    }
+
+    // Open a client stream to `domain` and return the server's stream features.
+    async fn open(&mut self, domain: &str) -> Result<Element, XmppError> {
+        self.write(&stream_header(&format!("to='{}'", escape(domain))))
+            .await?;
+        let Frame::StreamStart(_) = self.read_frame().await? else {
+            return Err(XmppError::Protocol(String::from(
+                "expected a stream header",
+            )));
+        };
+        let features = self.read_stanza().await?;
+        if features.name != "features" {
+            return Err(XmppError::Protocol(format!(
+                "expected stream features, got {}",
+                features.name
+            )));
+        }
+        Ok(features)
+    }
+
+    // Ask for STARTTLS and return the socket to run the handshake on.
+    async fn start_tls(mut self, features: &Element) -> Result<S, XmppError> {
+        if features.child("starttls").is_none() {
+            return Err(XmppError::Protocol(String::from(
+                "server does not offer STARTTLS",
+            )));
+        }
+        self.write(&format!("<starttls xmlns='{TLS_NS}'/>")).await?;
+        match self.read_stanza().await?.name.as_str() {
+            "proceed" if self.buffer.is_empty() => Ok(self.socket),
+            "proceed" => Err(XmppError::Protocol(String::from(
+                "data before the TLS handshake",
+            ))),
+            name => Err(XmppError::Protocol(format!(
+                "unexpected {name} during STARTTLS"
+            ))),
+        }
+    }
+}
+
+/// Sends Connection Requests to CPEs through the ACS's XMPP account.
+#[derive(Debug, Clone)]
+pub struct XmppConnectionRequestSender {
+    config: XmppConfig,
+    tls: Arc<ClientConfig>,
+    timeout: Duration,
+    retries: u32,
+    retry_interval: Duration,
+    next_id: Arc<AtomicU64>,
+}
+
+impl XmppConnectionRequestSender {
+    /// Fails when the CA certificates cannot be read.
+    pub fn new(config: XmppConfig) -> Result<Self, TlsError> {
+        let mut roots = RootCertStore::empty();
+        for certificate in certificates(&config.ca)? {
+            roots.add(certificate)?;
+        }
+        let tls = ClientConfig::builder_with_provider(Arc::new(ring::default_provider()))
+            .with_safe_default_protocol_versions()?
+            .with_root_certificates(roots)
+            .with_no_client_auth();
+        Ok(Self {
+            config,
+            tls: Arc::new(tls),
+            timeout: DEFAULT_XMPP_TIMEOUT,
+            retries: DEFAULT_XMPP_RETRIES,
+            retry_interval: DEFAULT_RETRY_INTERVAL,
+            next_id: Arc::new(AtomicU64::new(1)),
+        })
+    }
+
+    /// Send a `connectionRequest` IQ to the CPE's full JID. Connection and server
+    /// failures are retried; an authentication failure or the CPE's answer is final.
+    pub async fn send(&self, cpe_jid: &str, credentials: &Credentials) -> Result<(), XmppError> {
+        let mut attempt = 0;
+        loop {
+            let result = tokio::time::timeout(self.timeout, self.attempt(cpe_jid, credentials))
+                .await
+                .unwrap_or(Err(XmppError::Timeout));
+            match result {
+                Err(err) if err.is_transient() && attempt < self.retries => {
+                    attempt += 1;
+                    tracing::info!(
+                        "XMPP Connection Request to {cpe_jid} failed, retry {attempt}: {err}"
+                    );
+                    tokio::time::sleep(self.retry_interval).await;
+                }
+                result => return result,
+            }
+        }
+    }
+
+    async fn attempt(&self, cpe_jid: &str, credentials: &Credentials) -> Result<(), XmppError> {
+        let (local, domain, resource) = split_jid(&self.config.jid)
+            .ok_or_else(|| XmppError::Protocol(format!("invalid JID {}", self.config.jid)))?;
+        let server = match &self.config.server {
+            Some(server) => server.clone(),
+            None => format!("{domain}:5222"),
+        };
+        let server_name = ServerName::try_from(domain.to_string())
+            .map_err(|_| XmppError::Protocol(format!("invalid domain {domain}")))?;
+        let mut stream = XmppStream::new(TcpStream::connect(server).await?);
+        let features = stream.open(domain).await?;
+        let socket = stream.start_tls(&features).await?;
+
+        // TLS restarts the stream.
+        let mut stream = XmppStream::new(
+            TlsConnector::from(self.tls.clone())
+                .connect(server_name, socket)
+                .await?,
+        );
+        let features = stream.open(domain).await?;
+        let plain = features.child("mechanisms").is_some_and(|mechanisms| {
+            mechanisms
+                .children
+                .iter()
+                .any(|mechanism| mechanism.text == "PLAIN")
+        });
+        if !plain {
+            return Err(XmppError::Protocol(String::from(
+                "server does not offer SASL PLAIN",
+            )));
+        }
+        let token = BASE64.encode(format!("\0{local}\0{}", self.config.password));
+        stream
+            .write(&format!(
+                "<auth xmlns='{SASL_NS}' mechanism='PLAIN'>{token}</auth>"
+            ))
+            .await?;
+        match stream.read_stanza().await?.name.as_str() {
+            "success" => {}
+            "failure" => return Err(XmppError::AuthenticationFailed),
+            name => {
+                return Err(XmppError::Protocol(format!(
+                    "unexpected {name} during SASL"
+                )))
+            }
+        }
+
+        // Authentication restarts the stream.
+        stream.open(domain).await?;
+        let resource = match resource {
+            Some(resource) => format!("<resource>{}</resource>", escape(resource)),
+            None => String::new(),
+        };
+        stream
+            .write(&format!(
+                "<iq type='set' id='bind'><bind xmlns='{BIND_NS}'>{resource}</bind></iq>"
+            ))
+            .await?;
+        let bound = stream.read_stanza().await?;
+        if bound.attribute("type") != Some("result") {
+            return Err(XmppError::Protocol(String::from("resource binding failed")));
+        }
+
+        let id = format!("cr{}", self.next_id.fetch_add(1, Ordering::Relaxed));
+        stream
+            .write(&format!(
+                "<iq to='{}' type='get' id='{id}'><connectionRequest xmlns='{CONNECTION_REQUEST_NS}'><username>{}</username><password>{}</password></connectionRequest></iq>",
+                escape(cpe_jid),
+                escape(&credentials.username),
+                escape(&credentials.password)
+            ))
+            .await?;
+        // Presence and other stanzas may arrive before the answer.
+        let answer = loop {
+            let stanza = stream.read_stanza().await?;
+            if stanza.name == "iq" && stanza.attribute("id") == Some(id.as_str()) {
+                break stanza;
+            }
+        };
+        stream.write("</stream:stream>").await.ok();
+
+        match answer.attribute("type") {
+            Some("result") => Ok(()),
+            Some("error") => {
+                let condition = answer
+                    .child("error")
+                    .and_then(|error| error.children.first())
+                    .map(|condition| condition.name.clone())
+                    .unwrap_or_else(|| String::from("error"));
+                Err(XmppError::Rejected(condition))
+            }
+            kind => Err(XmppError::Protocol(format!("unexpected IQ type {kind:?}"))),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tls::ReloadableTlsConfig;
+    use tokio::net::TcpListener;
+    use tokio_rustls::TlsAcceptor;
+
+    const FEATURES_TLS: &str = "<stream:features><starttls xmlns='urn:ietf:params:xml:ns:xmpp-tls'><required/></starttls></stream:features>";
+    const FEATURES_SASL: &str = "<stream:features><mechanisms xmlns='urn:ietf:params:xml:ns:xmpp-sasl'><mechanism>PLAIN</mechanism></mechanisms></stream:features>";
+    const FEATURES_BIND: &str =
+        "<stream:features><bind xmlns='urn:ietf:params:xml:ns:xmpp-bind'/></stream:features>";
+
+    // A stand-in XMPP server for localhost with the account acs@localhost/secret,
+    // delivering IQs to a CPE that accepts the Connection Request credentials
+    // cr/cr-secret. Without an acceptor it offers SASL PLAIN over plain TCP.
+    async fn serve(socket: TcpStream, tls: Option<TlsAcceptor>) -> Result<(), XmppError> {
+        let header = stream_header("from='localhost' id='s1'");
+        let mut stream = XmppStream::new(socket);
+        stream.read_frame().await?;
+        stream.write(&header).await?;
+        let Some(tls) = tls else {
+            stream.write(FEATURES_SASL).await?;
+            let stanza = stream.read_stanza().await?;
+            return Err(XmppError::Protocol(format!("client sent {}", stanza.name)));
+        };
+        stream.write(FEATURES_TLS).await?;
+        if stream.read_stanza().await?.name != "starttls" {
+            return Err(XmppError::Protocol(String::from("expected starttls")));
+        }
+        stream
+            .write(&format!("<proceed xmlns='{TLS_NS}'/>"))
+            .await?;
+        let mut stream = XmppStream::new(tls.accept(stream.socket).await?);
+
+        stream.read_frame().await?;
+        stream.write(&header).await?;
+        stream.write(FEATURES_SASL).await?;
+        let auth = stream.read_stanza().await?;
+        if BASE64.decode(&auth.text).ok().as_deref() != Some(b"\0acs\0secret".as_slice()) {
+            return stream
+                .write(&format!(
+                    "<failure xmlns='{SASL_NS}'><not-authorized/></failure>"
+                ))
+                .await;
+        }
+        stream
+            .write(&format!("<success xmlns='{SASL_NS}'/>"))
+            .await?;
+
+        stream.read_frame().await?;
+        stream.write(&header).await?;
+        stream.write(FEATURES_BIND).await?;
+        let bind = stream.read_stanza().await?;
+        stream
+            .write(&format!(
+                "<iq type='result' id='{}'><bind xmlns='{BIND_NS}'><jid>acs@acs.example/acs</jid></bind></iq>",
+                bind.attribute("id").unwrap_or_default()
+            ))
+            .await?;
+        stream
+            .write("<presence from='someone@acs.example'/>")
+            .await?;
+
+        let iq = stream.read_stanza().await?;
+        let id = iq.attribute("id").unwrap_or_default().to_string();
+        let request = iq.child("connectionRequest");
+        let field = |name: &str| {
+            request
+                .and_then(|request| request.child(name))
+                .map(|field| field.text.as_str())
+        };
+        let accepted = iq.attribute("to") == Some("cpe@acs.example/cwmp")
+            && request.and_then(|request| request.attribute("xmlns"))
+                == Some(CONNECTION_REQUEST_NS)
+            && field("username") == Some("cr")
+            && field("password") == Some("cr-secret");
+        if accepted {
+            stream
+                .write(&format!(
+                    "<iq type='result' id='{id}' from='cpe@acs.example/cwmp'/>"
+                ))
+                .await
+        } else {
+            stream
+                .write(&format!(
+                    "<iq type='error' id='{id}'><error type='cancel'><not-authorized xmlns='urn:ietf:params:xml:ns:xmpp-stanzas'/></error></iq>"
+                ))
+                .await
+        }
+    }
+
+    #[test]
+    fn test_parse_frame() {
+        let data =
+            b"<?xml version='1.0'?><stream:stream from='x'><iq id='1'><a>1 &amp; 2</a></iq><iq";
+        let (frame, used) = parse_frame(data).unwrap();
+        assert!(
+            matches!(frame, Frame::StreamStart(ref header) if header.attribute("from") == Some("x"))
+        );
+        let (frame, used_iq) = parse_frame(&data[used..]).unwrap();
+        let Frame::Stanza(iq) = frame else {
+            panic!("expected a stanza");
+        };
+        assert_eq!(iq.child("a").unwrap().text, "1 & 2");
+        assert_eq!(parse_frame(&data[used + used_iq..]), None);
+        let (frame, _) = parse_frame(b"<iq>\n  <a>&lt; &gt; </a>\n</iq>").unwrap();
+        let Frame::Stanza(iq) = frame else {
+            panic!("expected a stanza");
+        };
+        assert_eq!(iq.text, "");
+        assert_eq!(iq.child("a").unwrap().text, "< > ");
+        assert_eq!(
+            parse_frame(b" </stream:stream>"),
+            Some((Frame::StreamEnd, 17))
+        );
+    }
+
+    #[tokio::test]
+    async fn test_xmpp_connection_request() {
+        let dir = std::env::temp_dir().join(format!("tr069-acs-xmpp-{}", std::process::id()));
+        let settings = crate::tls::tests::write_certificates(&dir);
+        let tls = ReloadableTlsConfig::load(crate::tls::TlsSettings {
+            client_ca: None,
+            ..settings
+        })
+        .unwrap();
+        let listen = |tls: Option<ReloadableTlsConfig>| async move {
+            let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+            let server = listener.local_addr().unwrap().to_string();
+            tokio::spawn(async move {
+                while let Ok((socket, _)) = listener.accept().await {
+                    let acceptor = tls.as_ref().map(ReloadableTlsConfig::acceptor);
+                    tokio::spawn(serve(socket, acceptor));
+                }
+            });
+            server
+        };
+
+        let config = XmppConfig {
+            jid: String::from("acs@localhost/acs"),
+            password: String::from("secret"),
+            server: Some(listen(Some(tls)).await),
+            ca: dir.join("ca.pem"),
+        };
+        let credentials = Credentials {
+            username: String::from("cr"),
+            password: String::from("cr-secret"),
+        };
+        let sender = XmppConnectionRequestSender::new(config.clone()).unwrap();
+        sender
+            .send("cpe@acs.example/cwmp", &credentials)
+            .await
+            .unwrap();
+
+        let wrong = Credentials {
+            password: String::from("wrong"),
+            ..credentials.clone()
+        };
+        assert!(matches!(
+            sender.send("cpe@acs.example/cwmp", &wrong).await,
+            Err(XmppError::Rejected(condition)) if condition == "not-authorized"
+        ));
+
+        let sender = XmppConnectionRequestSender::new(XmppConfig {
+            password: String::from("wrong"),
+            ..config.clone()
+        })
+        .unwrap();
+        assert!(matches!(
+            sender.send("cpe@acs.example/cwmp", &credentials).await,
+            Err(XmppError::AuthenticationFailed)
+        ));
+
+        // The password is not sent to a server without STARTTLS.
+        let mut sender = XmppConnectionRequestSender::new(XmppConfig {
+            server: Some(listen(None).await),
+            ..config.clone()
+        })
+        .unwrap();
+        sender.retries = 0;
+        sender.retry_interval = Duration::ZERO;
+        assert!(matches!(
+            sender.send("cpe@acs.example/cwmp", &credentials).await,
+            Err(XmppError::Protocol(err)) if err == "server does not offer STARTTLS"
+        ));
+
+        // Nothing listens there: every retry fails to connect.
+        let mut sender = XmppConnectionRequestSender::new(XmppConfig {
+            jid: String::from("acs@localhost"),
+            server: Some(String::from("127.0.0.1:1")),
+            ..config
+        })
+        .unwrap();
+        sender.retries = 1;
+        sender.retry_interval = Duration::ZERO;
+        assert!(matches!(
+            sender.send("cpe@acs.example/cwmp", &credentials).await,
+            Err(XmppError::Io(_))
+        ));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,376 @@
+// ACS initiated RPC requests (TR-069 Amendment 6, Annex A.3.2 and A.4.1).
+//
+// Each struct is the payload of the matching `CWMPMsg` variant and serializes to the
+// children of the `cwmp:<Method>` element inside `soap-env:Body`, e.g.
+// `Envelope::new(CWMPMsg::Reboot(Reboot::new("maintenance")))`.
+use super::{array_type, empty_string, ParameterList, ParameterValueStruct};
+use yaserde_derive::{YaDeserialize, YaSerialize};
+
+/// `string[]` array of Parameter names or partial paths.
+#[derive(Debug, Clone, Default, YaSerialize, YaDeserialize)]
+pub struct ParameterNames {
+    #[yaserde(attribute = true, prefix = "soap-enc", rename = "arrayType")]
+    pub array_type: Option<String>,
+
+    #[yaserde(rename = "string")]
+    pub names: Vec<String>,
+}
+
+impl ParameterNames {
+    pub fn new(names: Vec<String>) -> Self {
+        Self {
+            array_type: array_type("xsd:string", names.len()),
+            names,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, YaSerialize, YaDeserialize)]
+pub struct GetRPCMethods {}
+
+#[derive(Debug, Clone, Default, YaSerialize, YaDeserialize)]
+pub struct GetParameterValues {
+    #[yaserde(rename = "ParameterNames")]
+    pub parameter_names: ParameterNames,
+}
+
+impl GetParameterValues {
+    pub fn new(names: Vec<String>) -> Self {
+        Self {
+            parameter_names: ParameterNames::new(names),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, YaSerialize, YaDeserialize)]
+pub struct SetParameterValues {
+    #[yaserde(rename = "ParameterList")]
+    pub parameter_list: ParameterList,
+
+    // Value the CPE stores in ManagementServer.ParameterKey once the set is applied.
+    #[yaserde(rename = "ParameterKey", default = "empty_string")]
+    pub parameter_key: String,
+}
+
+impl SetParameterValues {
+    pub fn new(parameters: Vec<ParameterValueStruct>, parameter_key: impl Into<String>) -> Self {
+        Self {
+            parameter_list: ParameterList::new(parameters),
+            parameter_key: parameter_key.into(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, YaSerialize, YaDeserialize)]
+pub struct GetParameterNames {
+    // A full Parameter name, or a partial path ending with a dot.
+    #[yaserde(rename = "ParameterPath", default = "empty_string")]
+    pub parameter_path: String,
+
+    // Only return the immediate children of ParameterPath when true.
+    #[yaserde(rename = "NextLevel")]
+    pub next_level: bool,
+}
+
+impl GetParameterNames {
+    pub fn new(parameter_path: impl Into<String>, next_level: bool) -> Self {
+        Self {
+            parameter_path: parameter_path.into(),
+            next_level,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, YaSerialize, YaDeserialize)]
+pub struct SetParameterAttributesStruct {
+    #[yaserde(rename = "Name", default = "empty_string")]
+    pub name: String,
+
+    #[yaserde(rename = "NotificationChange")]
+    pub notification_change: bool,
+
+    // 0 = off, 1 = passive, 2 = active notification.
+    #[yaserde(rename = "Notification")]
+    pub notification: i32,
+
+    #[yaserde(rename = "AccessListChange")]
+    pub access_list_change: bool,
+
+    #[yaserde(rename = "AccessList")]
+    pub access_list: ParameterNames,
+}
+
+#[derive(Debug, Clone, Default, YaSerialize, YaDeserialize)]
+pub struct SetParameterAttributesList {
+    #[yaserde(attribute = true, prefix = "soap-enc", rename = "arrayType")]
+    pub array_type: Option<String>,
+
+    #[yaserde(rename = "SetParameterAttributesStruct")]
+    pub attributes: Vec<SetParameterAttributesStruct>,
+}
+
+#[derive(Debug, Clone, Default, YaSerialize, YaDeserialize)]
+pub struct SetParameterAttributes {
+    #[yaserde(rename = "ParameterList")]
+    pub parameter_list: SetParameterAttributesList,
+}
+
+impl SetParameterAttributes {
+    pub fn new(attributes: Vec<SetParameterAttributesStruct>) -> Self {
+        Self {
+            parameter_list: SetParameterAttributesList {
+                array_type: array_type("cwmp:SetParameterAttributesStruct", attributes.len()),
+                attributes,
+            },
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, YaSerialize, YaDeserialize)]
+pub struct GetParameterAttributes {
+    #[yaserde(rename = "ParameterNames")]
+    pub parameter_names: ParameterNames,
+}
+
+impl GetParameterAttributes {
+    pub fn new(names: Vec<String>) -> Self {
+        Self {
+            parameter_names: ParameterNames::new(names),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, YaSerialize, YaDeserialize)]
+pub struct AddObject {
+    // Path of a multi-instance object, ending with a dot.
+    #[yaserde(rename = "ObjectName", default = "empty_string")]
+    pub object_name: String,
+
+    #[yaserde(rename = "ParameterKey", default = "empty_string")]
+    pub parameter_key: String,
+}
+
+impl AddObject {
+    pub fn new(object_name: impl Into<String>, parameter_key: impl Into<String>) -> Self {
+        Self {
+            object_name: object_name.into(),
+            parameter_key: parameter_key.into(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, YaSerialize, YaDeserialize)]
+pub struct DeleteObject {
+    // Path of the instance to delete, ending with its instance number and a dot.
+    #[yaserde(rename = "ObjectName", default = "empty_string")]
+    pub object_name: String,
+
+    #[yaserde(rename = "ParameterKey", default = "empty_string")]
+    pub parameter_key: String,
+}
+
+impl DeleteObject {
+    pub fn new(object_name: impl Into<String>, parameter_key: impl Into<String>) -> Self {
+        Self {
+            object_name: object_name.into(),
+            parameter_key: parameter_key.into(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, YaSerialize, YaDeserialize)]
+pub struct Reboot {
+    #[yaserde(rename = "CommandKey", default = "empty_string")]
+    pub command_key: String,
+}
+
+impl Reboot {
+    pub fn new(command_key: impl Into<String>) -> Self {
+        Self {
+            command_key: command_key.into(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, YaSerialize, YaDeserialize)]
+pub struct Download {
+    #[yaserde(rename = "CommandKey", default = "empty_string")]
+    pub command_key: String,
+
+    #[yaserde(rename = "FileType", default = "empty_string")]
+    pub file_type: String,
+
+    #[yaserde(rename = "URL", default = "empty_string")]
+    pub url: String,
+
+    #[yaserde(rename = "Username", default = "empty_string")]
+    pub username: String,
+
+    #[yaserde(rename = "Password", default = "empty_string")]
+    pub password: String,
+
+    #[yaserde(rename = "FileSize")]
+    pub file_size: u32,
+
+    #[yaserde(rename = "TargetFileName", default = "empty_string")]
+    pub target_file_name: String,
+
+    #[yaserde(rename = "DelaySeconds")]
+    pub delay_seconds: u32,
+
+    #[yaserde(rename = "SuccessURL", default = "empty_string")]
+    pub success_url: String,
+
+    #[yaserde(rename = "FailureURL", default = "empty_string")]
+    pub failure_url: String,
+}
+
+impl Download {
+    pub fn new(
+        command_key: impl Into<String>,
+        file_type: impl Into<String>,
+        url: impl Into<String>,
+    ) -> Self {
+        Self {
+            command_key: command_key.into(),
+            file_type: file_type.into(),
+            url: url.into(),
+            ..Default::default()
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, YaSerialize, YaDeserialize)]
+pub struct Upload {
+    #[yaserde(rename = "CommandKey", default = "empty_string")]
+    pub command_key: String,
+
+    #[yaserde(rename = "FileType", default = "empty_string")]
+    pub file_type: String,
+
+    #[yaserde(rename = "URL", default = "empty_string")]
+    pub url: String,
+
+    #[yaserde(rename = "Username", default = "empty_string")]
+    pub username: String,
+
+    #[yaserde(rename = "Password", default = "empty_string")]
+    pub password: String,
+
+    #[yaserde(rename = "DelaySeconds")]
+    pub delay_seconds: u32,
+}
+
+impl Upload {
+    pub fn new(
+        command_key: impl Into<String>,
+        file_type: impl Into<String>,
+        url: impl Into<String>,
+    ) -> Self {
+        Self {
+            command_key: command_key.into(),
+            file_type: file_type.into(),
+            url: url.into(),
+            ..Default::default()
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, YaSerialize, YaDeserialize)]
+pub struct FactoryReset {}
+
+#[derive(Debug, Clone, Default, YaSerialize, YaDeserialize)]
+pub struct ScheduleInform {
+    #[yaserde(rename = "DelaySeconds")]
+    pub delay_seconds: u32,
+
+    #[yaserde(rename = "CommandKey", default = "empty_string")]
+    pub command_key: String,
+}
+
+impl ScheduleInform {
+    pub fn new(delay_seconds: u32, command_key: impl Into<String>) -> Self {
+        Self {
+            delay_seconds,
+            command_key: command_key.into(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, YaSerialize, YaDeserialize)]
+pub struct TimeWindowStruct {
+    // Seconds from the reception of ScheduleDownload.
+    #[yaserde(rename = "WindowStart")]
+    pub window_start: u32,
+
+    #[yaserde(rename = "WindowEnd")]
+    pub window_end: u32,
+
+    // "1 At Any Time", "2 Immediately", "3 When Idle" or "4 Confirmation Needed".
+    #[yaserde(rename = "WindowMode", default = "empty_string")]
+    pub window_mode: String,
+
+    #[yaserde(rename = "UserMessage", default = "empty_string")]
+    pub user_message: String,
+
+    // -1 lets the CPE pick its own retry policy.
+    #[yaserde(rename = "MaxRetries")]
+    pub max_retries: i32,
+}
+
+#[derive(Debug, Clone, Default, YaSerialize, YaDeserialize)]
+pub struct TimeWindowList {
+    #[yaserde(attribute = true, prefix = "soap-enc", rename = "arrayType")]
+    pub array_type: Option<String>,
+
+    #[yaserde(rename = "TimeWindowStruct")]
+    pub windows: Vec<TimeWindowStruct>,
+}
+
+#[derive(Debug, Clone, Default, YaSerialize, YaDeserialize)]
+pub struct ScheduleDownload {
+    #[yaserde(rename = "CommandKey", default = "empty_string")]
+    pub command_key: String,
+
+    #[yaserde(rename = "FileType", default = "empty_string")]
+    pub file_type: String,
+
+    #[yaserde(rename = "URL", default = "empty_string")]
+    pub url: String,
+
+    #[yaserde(rename = "Username", default = "empty_string")]
+    pub username: String,
+
+    #[yaserde(rename = "Password", default = "empty_string")]
+    pub password: String,
+
+    #[yaserde(rename = "FileSize")]
+    pub file_size: u32,
+
+    #[yaserde(rename = "TargetFileName", default = "empty_string")]
+    pub target_file_name: String,
+
+    // One or two time windows, in increasing order of WindowStart.
+    #[yaserde(rename = "TimeWindowList")]
+    pub time_window_list: TimeWindowList,
+}
+
+impl ScheduleDownload {
+    pub fn new(
+        command_key: impl Into<String>,
+        file_type: impl Into<String>,
+        url: impl Into<String>,
+        windows: Vec<TimeWindowStruct>,
+    ) -> Self {
+        Self {
+            command_key: command_key.into(),
+            file_type: file_type.into(),
+            url: url.into(),
+            time_window_list: TimeWindowList {
+                array_type: array_type("cwmp:TimeWindowStruct", windows.len()),
+                windows,
+            },
+            ..Default::default()
+        }
+    }
+}
@@ -1,9 +1,42 @@
 pub const ENC_NP: &str = "soap-enc";
 pub const ENV_NP: &str = "soap-env";
 pub const CWMP_NP: &str = "cwmp";
+pub const XSD_NP: &str = "xsd";
+pub const XSI_NP: &str = "xsi";
 
 pub const SOAP_ENV_NP: &str = r#"http://schemas.xmlsoap.org/soap/envelope/"#;
 pub const SOAP_ENC_NP: &str = r#"http://schemas.xmlsoap.org/soap/encoding/"#;
 pub const SOAP_CWMP_NP: &str = r#"urn:dslforum-org:cwmp-1-0"#;
+pub const SOAP_CWMP_1_1_NP: &str = r#"urn:dslforum-org:cwmp-1-1"#;
+pub const SOAP_CWMP_1_2_NP: &str = r#"urn:dslforum-org:cwmp-1-2"#;
+pub const SOAP_CWMP_1_3_NP: &str = r#"urn:dslforum-org:cwmp-1-3"#;
+pub const SOAP_CWMP_1_4_NP: &str = r#"urn:dslforum-org:cwmp-1-4"#;
+/// CWMP namespaces the ACS accepts, oldest first.
+pub const CWMP_NAMESPACES: [&str; 5] = [
+    SOAP_CWMP_NP,
+    SOAP_CWMP_1_1_NP,
+    SOAP_CWMP_1_2_NP,
+    SOAP_CWMP_1_3_NP,
+    SOAP_CWMP_1_4_NP,
+];
 pub const SOAP_XSD_NP: &str = r#"http://www.w3.org/2001/XMLSchema"#;
 pub const SOAP_XSI_NP: &str = r#"http://www.w3.org/2001/XMLSchema-instance"#;
+
+/// CWMP versions the ACS speaks, and the namespace each one uses.
+pub const CWMP_VERSIONS: [(&str, &str); 5] = [
+    ("1.0", SOAP_CWMP_NP),
+    ("1.1", SOAP_CWMP_1_1_NP),
+    ("1.2", SOAP_CWMP_1_2_NP),
+    ("1.3", SOAP_CWMP_1_3_NP),
+    ("1.4", SOAP_CWMP_1_4_NP),
+];
+
+/// `cwmp:` header elements the ACS understands.
+pub const KNOWN_HEADERS: [&str; 6] = [
+    "ID",
+    "HoldRequests",
+    "NoMoreRequests",
+    "SessionTimeout",
+    "SupportedCWMPVersions",
+    "UseCWMPVersion",
+];
@@ -0,0 +1,289 @@
+// CPE responses to ACS requests and CPE initiated requests (TR-069 Amendment 6,
+// Annex A.3.2, A.3.3 and A.4.1).
+//
+// Array payloads reuse the SOAP array layout of `ParameterList`: a
+// `soap-enc:arrayType` attribute followed by one element per item.
+use super::{empty_string, ParameterList, ParameterNames};
+use yaserde_derive::{YaDeserialize, YaSerialize};
+
+#[derive(Debug, Clone, Default, YaSerialize, YaDeserialize)]
+pub struct GetRPCMethodsResponse {
+    #[yaserde(rename = "MethodList")]
+    pub method_list: ParameterNames,
+}
+
+#[derive(Debug, Clone, Default, YaSerialize, YaDeserialize)]
+pub struct SetParameterValuesResponse {
+    // 0 = applied, 1 = applied and committed later (e.g. after a reboot).
+    #[yaserde(rename = "Status")]
+    pub status: i32,
+}
+
+#[derive(Debug, Clone, Default, YaSerialize, YaDeserialize)]
+pub struct GetParameterValuesResponse {
+    #[yaserde(rename = "ParameterList")]
+    pub parameter_list: ParameterList,
+}
+
+#[derive(Debug, Clone, Default, YaSerialize, YaDeserialize)]
+pub struct ParameterInfoStruct {
+    // Full Parameter name, or a partial path ending with a dot for objects.
+    #[yaserde(rename = "Name", default = "empty_string")]
+    pub name: String,
+
+    // xsd:boolean, CPEs send either "0"/"1" or "false"/"true".
+    #[yaserde(rename = "Writable", default = "empty_string")]
+    pub writable: String,
+}
+
+impl ParameterInfoStruct {
+    pub fn is_writable(&self) -> bool {
+        matches!(self.writable.trim(), "1" | "true")
+    }
+}
+
+#[derive(Debug, Clone, Default, YaSerialize, YaDeserialize)]
+pub struct ParameterInfoList {
+    #[yaserde(attribute = true, prefix = "soap-enc", rename = "arrayType")]
+    pub array_type: Option<String>,
+
+    #[yaserde(rename = "ParameterInfoStruct")]
+    pub parameters: Vec<ParameterInfoStruct>,
+}
+
+#[derive(Debug, Clone, Default, YaSerialize, YaDeserialize)]
+pub struct GetParameterNamesResponse {
+    #[yaserde(rename = "ParameterList")]
+    pub parameter_list: ParameterInfoList,
+}
+
+#[derive(Debug, Clone, Default, YaSerialize, YaDeserialize)]
+pub struct SetParameterAttributesResponse {}
+
+#[derive(Debug, Clone, Default, YaSerialize, YaDeserialize)]
+pub struct ParameterAttributeStruct {
+    #[yaserde(rename = "Name", default = "empty_string")]
+    pub name: String,
+
+    // 0 = off, 1 = passive, 2 = active notification.
+    #[yaserde(rename = "Notification")]
+    pub notification: i32,
+
+    #[yaserde(rename = "AccessList")]
+    pub access_list: ParameterNames,
+}
+
+#[derive(Debug, Clone, Default, YaSerialize, YaDeserialize)]
+pub struct ParameterAttributeList {
+    #[yaserde(attribute = true, prefix = "soap-enc", rename = "arrayType")]
+    pub array_type: Option<String>,
+
+    #[yaserde(rename = "ParameterAttributeStruct")]
+    pub parameters: Vec<ParameterAttributeStruct>,
+}
+
+#[derive(Debug, Clone, Default, YaSerialize, YaDeserialize)]
+pub struct GetParameterAttributesResponse {
+    #[yaserde(rename = "ParameterList")]
+    pub parameter_list: ParameterAttributeList,
+}
+
+#[derive(Debug, Clone, Default, YaSerialize, YaDeserialize)]
+pub struct AddObjectResponse {
+    #[yaserde(rename = "InstanceNumber")]
+    pub instance_number: u32,
+
+    // 0 = object created, 1 = created and committed later.
+    #[yaserde(rename = "Status")]
+    pub status: i32,
+}
+
+#[derive(Debug, Clone, Default, YaSerialize, YaDeserialize)]
+pub struct DeleteObjectResponse {
+    #[yaserde(rename = "Status")]
+    pub status: i32,
+}
+
+#[derive(Debug, Clone, Default, YaSerialize, YaDeserialize)]
+pub struct RebootResponse {}
+
+#[derive(Debug, Clone, Default, YaSerialize, YaDeserialize)]
+pub struct DownloadResponse {
+    // 0 = download completed, 1 = not yet completed, a TransferComplete follows.
+    #[yaserde(rename = "Status")]
+    pub status: i32,
+
+    #[yaserde(rename = "StartTime", default = "empty_string")]
+    pub start_time: String,
+
+    #[yaserde(rename = "CompleteTime", default = "empty_string")]
+    pub complete_time: String,
+}
+
+#[derive(Debug, Clone, Default, YaSerialize, YaDeserialize)]
+pub struct ScheduleDownloadResponse {}
+
+#[derive(Debug, Clone, Default, YaSerialize, YaDeserialize)]
+pub struct UploadResponse {
+    #[yaserde(rename = "Status")]
+    pub status: i32,
+
+    #[yaserde(rename = "StartTime", default = "empty_string")]
+    pub start_time: String,
+
+    #[yaserde(rename = "CompleteTime", default = "empty_string")]
+    pub complete_time: String,
+}
+
+#[derive(Debug, Clone, Default, YaSerialize, YaDeserialize)]
+pub struct FactoryResetResponse {}
+
+#[derive(Debug, Clone, Default, YaSerialize, YaDeserialize)]
+pub struct ScheduleInformResponse {}
+
+#[derive(Debug, Clone, Default, YaSerialize, YaDeserialize)]
+pub struct QueuedTransferStruct {
+    #[yaserde(rename = "CommandKey", default = "empty_string")]
+    pub command_key: String,
+
+    // 1 = not yet started, 2 = in progress, 3 = completed.
+    #[yaserde(rename = "State")]
+    pub state: i32,
+}
+
+#[derive(Debug, Clone, Default, YaSerialize, YaDeserialize)]
+pub struct TransferList {
+    #[yaserde(attribute = true, prefix = "soap-enc", rename = "arrayType")]
+    pub array_type: Option<String>,
+
+    #[yaserde(rename = "QueuedTransferStruct")]
+    pub transfers: Vec<QueuedTransferStruct>,
+}
+
+#[derive(Debug, Clone, Default, YaSerialize, YaDeserialize)]
+pub struct GetQueuedTransfersResponse {
+    #[yaserde(rename = "TransferList")]
+    pub transfer_list: TransferList,
+}
+
+#[derive(Debug, Clone, Default, YaSerialize, YaDeserialize)]
+pub struct SetVouchersResponse {}
+
+#[derive(Debug, Clone, Default, YaSerialize, YaDeserialize)]
+pub struct OptionStruct {
+    #[yaserde(rename = "OptionName", default = "empty_string")]
+    pub option_name: String,
+
+    #[yaserde(rename = "VoucherSN")]
+    pub voucher_sn: u32,
+
+    #[yaserde(rename = "State")]
+    pub state: u32,
+
+    #[yaserde(rename = "Mode")]
+    pub mode: i32,
+
+    #[yaserde(rename = "StartDate", default = "empty_string")]
+    pub start_date: String,
+
+    #[yaserde(rename = "ExpirationDate", default = "empty_string")]
+    pub expiration_date: String,
+
+    #[yaserde(rename = "IsTransferable")]
+    pub is_transferable: bool,
+}
+
+#[derive(Debug, Clone, Default, YaSerialize, YaDeserialize)]
+pub struct OptionList {
+    #[yaserde(attribute = true, prefix = "soap-enc", rename = "arrayType")]
+    pub array_type: Option<String>,
+
+    #[yaserde(rename = "OptionStruct")]
+    pub options: Vec<OptionStruct>,
+}
+
+#[derive(Debug, Clone, Default, YaSerialize, YaDeserialize)]
+pub struct GetOptionsResponse {
+    #[yaserde(rename = "OptionList")]
+    pub option_list: OptionList,
+}
+
+#[derive(Debug, Clone, Default, YaSerialize, YaDeserialize)]
+pub struct QueuedEventStruct {
+    #[yaserde(rename = "EventCode", default = "empty_string")]
+    pub event_code: String,
+
+    #[yaserde(rename = "CommandKey", default = "empty_string")]
+    pub command_key: String,
+}
+
+#[derive(Debug, Clone, Default, YaSerialize, YaDeserialize)]
+pub struct QueuedEventList {
+    #[yaserde(attribute = true, prefix = "soap-enc", rename = "arrayType")]
+    pub array_type: Option<String>,
+
+    #[yaserde(rename = "EventStruct")]
+    pub events: Vec<QueuedEventStruct>,
+}
+
+#[derive(Debug, Clone, Default, YaSerialize, YaDeserialize)]
+pub struct GetAllQueuedEventsResponse {
+    #[yaserde(rename = "EventList")]
+    pub event_list: QueuedEventList,
+}
+
+/// Outcome of a transfer, FaultCode 0 means success.
+#[derive(Debug, Clone, Default, YaSerialize, YaDeserialize)]
+pub struct FaultStruct {
+    #[yaserde(rename = "FaultCode")]
+    pub fault_code: u32,
+
+    #[yaserde(rename = "FaultString", default = "empty_string")]
+    pub fault_string: String,
+}
+
+#[derive(Debug, Clone, Default, YaSerialize, YaDeserialize)]
+pub struct TransferComplete {
+    #[yaserde(rename = "CommandKey", default = "empty_string")]
+    pub command_key: String,
+
+    #[yaserde(rename = "FaultStruct")]
+    pub fault_struct: FaultStruct,
+
+    #[yaserde(rename = "StartTime", default = "empty_string")]
+    pub start_time: String,
+
+    #[yaserde(rename = "CompleteTime", default = "empty_string")]
+    pub complete_time: String,
+}
+
+#[derive(Debug, Clone, Default, YaSerialize, YaDeserialize)]
+pub struct AutonomousTransferComplete {
+    #[yaserde(rename = "AnnounceURL", default = "empty_string")]
+    pub announce_url: String,
+
+    #[yaserde(rename = "TransferURL", default = "empty_string")]
+    pub transfer_url: String,
+
+    // true for a download, false for an upload.
+    #[yaserde(rename = "IsDownload")]
+    pub is_download: bool,
+
+    #[yaserde(rename = "FileType", default = "empty_string")]
+    pub file_type: String,
+
+    #[yaserde(rename = "FileSize")]
+    pub file_size: u32,
+
+    #[yaserde(rename = "TargetFileName", default = "empty_string")]
+    pub target_file_name: String,
+
+    #[yaserde(rename = "FaultStruct")]
+    pub fault_struct: FaultStruct,
+
+    #[yaserde(rename = "StartTime", default = "empty_string")]
+    pub start_time: String,
+
+    #[yaserde(rename = "CompleteTime", default = "empty_string")]
+    pub complete_time: String,
+}
@@ -0,0 +1,112 @@
+// Inform EventCodes (TR-069 Amendment 6, section 3.7.1.5 and Table 7).
+//
+// Codes are carried as plain strings inside `EventStruct`; parsing then formatting
+// an EventCode gives back the original string, including vendor and unknown codes.
+use std::{convert::Infallible, fmt, str::FromStr};
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum EventCode {
+    Bootstrap,
+    Boot,
+    Periodic,
+    Scheduled,
+    ValueChange,
+    Kicked,
+    ConnectionRequest,
+    TransferComplete,
+    DiagnosticsComplete,
+    RequestDownload,
+    AutonomousTransferComplete,
+    DuStateChangeComplete,
+    AutonomousDuStateChangeComplete,
+    Wakeup,
+    Heartbeat,
+    MReboot,
+    MScheduleInform,
+    MDownload,
+    MScheduleDownload,
+    MUpload,
+    MChangeDuState,
+    /// "M X_<VENDOR>_<Method>", a completed vendor specific method.
+    MVendorMethod(String),
+    /// "X <OUI> <event>", a vendor specific event.
+    Vendor {
+        oui: String,
+        event: String,
+    },
+    /// Anything else, kept verbatim.
+    Unknown(String),
+}
+
+const SINGLE_EVENTS: &[(EventCode, &str)] = &[
+    (EventCode::Bootstrap, "0 BOOTSTRAP"),
+    (EventCode::Boot, "1 BOOT"),
+    (EventCode::Periodic, "2 PERIODIC"),
+    (EventCode::Scheduled, "3 SCHEDULED"),
+    (EventCode::ValueChange, "4 VALUE CHANGE"),
+    (EventCode::Kicked, "5 KICKED"),
+    (EventCode::ConnectionRequest, "6 CONNECTION REQUEST"),
+    (EventCode::TransferComplete, "7 TRANSFER COMPLETE"),
+    (EventCode::DiagnosticsComplete, "8 DIAGNOSTICS COMPLETE"),
+    (EventCode::RequestDownload, "9 REQUEST DOWNLOAD"),
+    (
+        EventCode::AutonomousTransferComplete,
+        "10 AUTONOMOUS TRANSFER COMPLETE",
+    ),
+    (
+        EventCode::DuStateChangeComplete,
+        "11 DU STATE CHANGE COMPLETE",
+    ),
+    (
+        EventCode::AutonomousDuStateChangeComplete,
+        "12 AUTONOMOUS DU STATE CHANGE COMPLETE",
+    ),
+    (EventCode::Wakeup, "13 WAKEUP"),
+    (EventCode::Heartbeat, "14 HEARTBEAT"),
+    (EventCode::MReboot, "M Reboot"),
+    (EventCode::MScheduleInform, "M ScheduleInform"),
+    (EventCode::MDownload, "M Download"),
+    (EventCode::MScheduleDownload, "M ScheduleDownload"),
+    (EventCode::MUpload, "M Upload"),
+    (EventCode::MChangeDuState, "M ChangeDUState"),
+];
+
+impl FromStr for EventCode {
+    type Err = Infallible;
+
+    fn from_str(code: &str) -> Result<Self, Self::Err> {
+        if let Some((event, _)) = SINGLE_EVENTS.iter().find(|(_, name)| *name == code) {
+            return Ok(event.clone());
+        }
+        if let Some(method) = code.strip_prefix("M X_") {
+            return Ok(EventCode::MVendorMethod(format!("X_{method}")));
+        }
+        if let Some((oui, event)) = code
+            .strip_prefix("X ")
+            .and_then(|vendor| vendor.split_once(' '))
+        {
+            return Ok(EventCode::Vendor {
+                oui: oui.to_string(),
+                event: event.to_string(),
+            });
+        }
+        Ok(EventCode::Unknown(code.to_string()))
+    }
+}
+
+impl fmt::Display for EventCode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            EventCode::MVendorMethod(method) => write!(f, "M {method}"),
+            EventCode::Vendor { oui, event } => write!(f, "X {oui} {event}"),
+            EventCode::Unknown(code) => f.write_str(code),
+            event => {
+                let (_, name) = SINGLE_EVENTS
+                    .iter()
+                    .find(|(known, _)| known == event)
+                    .expect("every single event is listed in SINGLE_EVENTS");
+                f.write_str(name)
+            }
+        }
+    }
+}
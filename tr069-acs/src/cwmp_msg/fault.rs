@@ -0,0 +1,443 @@
+// SOAP Fault carrying a CWMP Fault in its detail element (TR-069 Amendment 6,
+// section 3.4.7 and Annex A.5):
+//
+// <soap-env:Fault>
+//   <faultcode>Client</faultcode>
+//   <faultstring>CWMP fault</faultstring>
+//   <detail>
+//     <cwmp:Fault>
+//       <FaultCode>9003</FaultCode>
+//       <FaultString>Invalid arguments</FaultString>
+//       <SetParameterValuesFault>...</SetParameterValuesFault>
+//     </cwmp:Fault>
+//   </detail>
+// </soap-env:Fault>
+use super::empty_string;
+use std::fmt;
+use yaserde_derive::{YaDeserialize, YaSerialize};
+
+/// Fault codes the ACS returns to the CPE (8000 series).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AcsFaultCode {
+    MethodNotSupported,
+    RequestDenied,
+    InternalError,
+    InvalidArguments,
+    ResourcesExceeded,
+    RetryRequest,
+    AcsVersionIncompatible,
+    /// 8800 - 8899
+    Vendor(u32),
+}
+
+/// Fault codes the CPE returns to the ACS (9000 series).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CpeFaultCode {
+    MethodNotSupported,
+    RequestDenied,
+    InternalError,
+    InvalidArguments,
+    ResourcesExceeded,
+    InvalidParameterName,
+    InvalidParameterType,
+    InvalidParameterValue,
+    NonWritableParameter,
+    NotificationRequestRejected,
+    DownloadFailure,
+    UploadFailure,
+    FileTransferAuthenticationFailure,
+    UnsupportedTransferProtocol,
+    MulticastJoinFailure,
+    FileServerUnreachable,
+    FileAccessFailure,
+    DownloadIncomplete,
+    FileCorrupted,
+    FileAuthenticationFailure,
+    TimeWindowExpired,
+    CancelNotPermitted,
+    InvalidUuidFormat,
+    UnknownExecutionEnvironment,
+    DisabledExecutionEnvironment,
+    DeploymentUnitMismatch,
+    DuplicateDeploymentUnit,
+    SystemResourcesExceeded,
+    UnknownDeploymentUnit,
+    InvalidDeploymentUnitState,
+    DowngradeNotPermitted,
+    VersionNotSpecified,
+    VersionAlreadyExists,
+    /// 9800 - 9899
+    Vendor(u32),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FaultCode {
+    Acs(AcsFaultCode),
+    Cpe(CpeFaultCode),
+    /// A code outside the ranges defined by TR-069, kept as received.
+    Unknown(u32),
+}
+
+impl AcsFaultCode {
+    fn from_code(code: u32) -> Option<Self> {
+        Some(match code {
+            8000 => Self::MethodNotSupported,
+            8001 => Self::RequestDenied,
+            8002 => Self::InternalError,
+            8003 => Self::InvalidArguments,
+            8004 => Self::ResourcesExceeded,
+            8005 => Self::RetryRequest,
+            8006 => Self::AcsVersionIncompatible,
+            8800..=8899 => Self::Vendor(code),
+            _ => return None,
+        })
+    }
+
+    pub fn code(self) -> u32 {
+        match self {
+            Self::MethodNotSupported => 8000,
+            Self::RequestDenied => 8001,
+            Self::InternalError => 8002,
+            Self::InvalidArguments => 8003,
+            Self::ResourcesExceeded => 8004,
+            Self::RetryRequest => 8005,
+            Self::AcsVersionIncompatible => 8006,
+            Self::Vendor(code) => code,
+        }
+    }
+
+    pub fn description(self) -> &'static str {
+        match self {
+            Self::MethodNotSupported => "Method not supported",
+            Self::RequestDenied => "Request denied (no reason specified)",
+            Self::InternalError => "Internal error",
+            Self::InvalidArguments => "Invalid arguments",
+            Self::ResourcesExceeded => "Resources exceeded",
+            Self::RetryRequest => "Retry request",
+            Self::AcsVersionIncompatible => "ACS version incompatible with CPE",
+            Self::Vendor(_) => "Vendor defined fault",
+        }
+    }
+}
+
+impl CpeFaultCode {
+    fn from_code(code: u32) -> Option<Self> {
+        Some(match code {
+            9000 => Self::MethodNotSupported,
+            9001 => Self::RequestDenied,
+            9002 => Self::InternalError,
+            9003 => Self::InvalidArguments,
+            9004 => Self::ResourcesExceeded,
+            9005 => Self::InvalidParameterName,
+            9006 => Self::InvalidParameterType,
+            9007 => Self::InvalidParameterValue,
+            9008 => Self::NonWritableParameter,
+            9009 => Self::NotificationRequestRejected,
+            9010 => Self::DownloadFailure,
+            9011 => Self::UploadFailure,
+            9012 => Self::FileTransferAuthenticationFailure,
+            9013 => Self::UnsupportedTransferProtocol,
+            9014 => Self::MulticastJoinFailure,
+            9015 => Self::FileServerUnreachable,
+            9016 => Self::FileAccessFailure,
+            9017 => Self::DownloadIncomplete,
+            9018 => Self::FileCorrupted,
+            9019 => Self::FileAuthenticationFailure,
+            9020 => Self::TimeWindowExpired,
+            9021 => Self::CancelNotPermitted,
+            9022 => Self::InvalidUuidFormat,
+            9023 => Self::UnknownExecutionEnvironment,
+            9024 => Self::DisabledExecutionEnvironment,
+            9025 => Self::DeploymentUnitMismatch,
+            9026 => Self::DuplicateDeploymentUnit,
+            9027 => Self::SystemResourcesExceeded,
+            9028 => Self::UnknownDeploymentUnit,
+            9029 => Self::InvalidDeploymentUnitState,
+            9030 => Self::DowngradeNotPermitted,
+            9031 => Self::VersionNotSpecified,
+            9032 => Self::VersionAlreadyExists,
+            9800..=9899 => Self::Vendor(code),
+            _ => return None,
+        })
+    }
+
+    pub fn code(self) -> u32 {
+        match self {
+            Self::MethodNotSupported => 9000,
+            Self::RequestDenied => 9001,
+            Self::InternalError => 9002,
+            Self::InvalidArguments => 9003,
+            Self::ResourcesExceeded => 9004,
+            Self::InvalidParameterName => 9005,
+            Self::InvalidParameterType => 9006,
+            Self::InvalidParameterValue => 9007,
+            Self::NonWritableParameter => 9008,
+            Self::NotificationRequestRejected => 9009,
+            Self::DownloadFailure => 9010,
+            Self::UploadFailure => 9011,
+            Self::FileTransferAuthenticationFailure => 9012,
+            Self::UnsupportedTransferProtocol => 9013,
+            Self::MulticastJoinFailure => 9014,
+            Self::FileServerUnreachable => 9015,
+            Self::FileAccessFailure => 9016,
+            Self::DownloadIncomplete => 9017,
+            Self::FileCorrupted => 9018,
+            Self::FileAuthenticationFailure => 9019,
+            Self::TimeWindowExpired => 9020,
+            Self::CancelNotPermitted => 9021,
+            Self::InvalidUuidFormat => 9022,
+            Self::UnknownExecutionEnvironment => 9023,
+            Self::DisabledExecutionEnvironment => 9024,
+            Self::DeploymentUnitMismatch => 9025,
+            Self::DuplicateDeploymentUnit => 9026,
+            Self::SystemResourcesExceeded => 9027,
+            Self::UnknownDeploymentUnit => 9028,
+            Self::InvalidDeploymentUnitState => 9029,
+            Self::DowngradeNotPermitted => 9030,
+            Self::VersionNotSpecified => 9031,
+            Self::VersionAlreadyExists => 9032,
+            Self::Vendor(code) => code,
+        }
+    }
+
+    pub fn description(self) -> &'static str {
+        match self {
+            Self::MethodNotSupported => "Method not supported",
+            Self::RequestDenied => "Request denied (no reason specified)",
+            Self::InternalError => "Internal error",
+            Self::InvalidArguments => "Invalid arguments",
+            Self::ResourcesExceeded => "Resources exceeded",
+            Self::InvalidParameterName => "Invalid parameter name",
+            Self::InvalidParameterType => "Invalid parameter type",
+            Self::InvalidParameterValue => "Invalid parameter value",
+            Self::NonWritableParameter => "Attempt to set a non-writable parameter",
+            Self::NotificationRequestRejected => "Notification request rejected",
+            Self::DownloadFailure => "File transfer failure",
+            Self::UploadFailure => "Upload failure",
+            Self::FileTransferAuthenticationFailure => {
+                "File transfer server authentication failure"
+            }
+            Self::UnsupportedTransferProtocol => "Unsupported protocol for file transfer",
+            Self::MulticastJoinFailure => "File transfer failure: unable to join multicast group",
+            Self::FileServerUnreachable => "File transfer failure: unable to contact file server",
+            Self::FileAccessFailure => "File transfer failure: unable to access file",
+            Self::DownloadIncomplete => "File transfer failure: unable to complete download",
+            Self::FileCorrupted => "File transfer failure: file corrupted or otherwise unusable",
+            Self::FileAuthenticationFailure => "File transfer failure: file authentication failure",
+            Self::TimeWindowExpired => {
+                "File transfer failure: unable to complete download within specified time windows"
+            }
+            Self::CancelNotPermitted => {
+                "Cancelation of file transfer not permitted in current transfer state"
+            }
+            Self::InvalidUuidFormat => "Invalid UUID format",
+            Self::UnknownExecutionEnvironment => "Unknown execution environment",
+            Self::DisabledExecutionEnvironment => "Disabled execution environment",
+            Self::DeploymentUnitMismatch => "Deployment unit to execution environment mismatch",
+            Self::DuplicateDeploymentUnit => "Duplicate deployment unit",
+            Self::SystemResourcesExceeded => "System resources exceeded",
+            Self::UnknownDeploymentUnit => "Unknown deployment unit",
+            Self::InvalidDeploymentUnitState => "Invalid deployment unit state",
+            Self::DowngradeNotPermitted => {
+                "Invalid deployment unit update: downgrade not permitted"
+            }
+            Self::VersionNotSpecified => "Invalid deployment unit update: version not specified",
+            Self::VersionAlreadyExists => "Invalid deployment unit update: version already exists",
+            Self::Vendor(_) => "Vendor defined fault",
+        }
+    }
+}
+
+impl FaultCode {
+    pub fn from_code(code: u32) -> Self {
+        AcsFaultCode::from_code(code)
+            .map(FaultCode::Acs)
+            .or_else(|| CpeFaultCode::from_code(code).map(FaultCode::Cpe))
+            .unwrap_or(FaultCode::Unknown(code))
+    }
+
+    pub fn code(self) -> u32 {
+        match self {
+            FaultCode::Acs(code) => code.code(),
+            FaultCode::Cpe(code) => code.code(),
+            FaultCode::Unknown(code) => code,
+        }
+    }
+
+    pub fn description(self) -> &'static str {
+        match self {
+            FaultCode::Acs(code) => code.description(),
+            FaultCode::Cpe(code) => code.description(),
+            FaultCode::Unknown(_) => "Unknown fault",
+        }
+    }
+
+    // SOAP faultcode: "Client" when the request was at fault, "Server" otherwise.
+    fn soap_fault_code(self) -> &'static str {
+        match self {
+            FaultCode::Acs(AcsFaultCode::InternalError | AcsFaultCode::RetryRequest)
+            | FaultCode::Cpe(CpeFaultCode::InternalError) => "Server",
+            _ => "Client",
+        }
+    }
+}
+
+impl fmt::Display for FaultCode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {}", self.code(), self.description())
+    }
+}
+
+#[derive(Debug, Clone, Default, YaSerialize, YaDeserialize)]
+pub struct SetParameterValuesFault {
+    #[yaserde(rename = "ParameterName", default = "empty_string")]
+    pub parameter_name: String,
+
+    #[yaserde(rename = "FaultCode")]
+    pub fault_code: u32,
+
+    #[yaserde(rename = "FaultString", default = "empty_string")]
+    pub fault_string: String,
+}
+
+#[derive(Debug, Clone, Default, YaSerialize, YaDeserialize)]
+pub struct CwmpFaultStruct {
+    #[yaserde(rename = "FaultCode")]
+    pub fault_code: u32,
+
+    #[yaserde(rename = "FaultString", default = "empty_string")]
+    pub fault_string: String,
+
+    // Only present in a fault answering SetParameterValues, one per rejected Parameter.
+    #[yaserde(rename = "SetParameterValuesFault")]
+    pub set_parameter_values_fault: Vec<SetParameterValuesFault>,
+}
+
+#[derive(Debug, Clone, Default, YaSerialize, YaDeserialize)]
+#[yaserde(namespaces = { "cwmp" = "urn:dslforum-org:cwmp-1-0" })]
+pub struct FaultDetail {
+    #[yaserde(rename = "Fault", prefix = "cwmp")]
+    pub fault: CwmpFaultStruct,
+}
+
+/// Payload of `CWMPMsg::Fault`, the `soap-env:Fault` element.
+#[derive(Debug, Clone, Default, YaSerialize, YaDeserialize)]
+pub struct SoapFault {
+    #[yaserde(rename = "faultcode", default = "empty_string")]
+    pub faultcode: String,
+
+    #[yaserde(rename = "faultstring", default = "empty_string")]
+    pub faultstring: String,
+
+    // Absent for SOAP level faults such as MustUnderstand.
+    #[yaserde(rename = "detail")]
+    pub detail: Option<FaultDetail>,
+}
+
+impl SoapFault {
+    /// SOAP 1.1 MustUnderstand fault for a mandatory header the ACS does not know.
+    pub fn must_understand(header: &str) -> Self {
+        Self {
+            faultcode: String::from("soap-env:MustUnderstand"),
+            faultstring: format!("Header {header} not understood"),
+            detail: None,
+        }
+    }
+}
+
+/// A rejected Parameter of a SetParameterValues fault.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParameterFault {
+    pub name: String,
+    pub code: FaultCode,
+    pub message: String,
+}
+
+/// CWMP fault, either received from the CPE or returned by the ACS.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CwmpFault {
+    pub code: FaultCode,
+    pub message: String,
+    pub parameter_faults: Vec<ParameterFault>,
+}
+
+impl CwmpFault {
+    /// An ACS fault using the standard description as FaultString.
+    pub fn acs(code: AcsFaultCode) -> Self {
+        Self {
+            code: FaultCode::Acs(code),
+            message: code.description().to_string(),
+            parameter_faults: Vec::new(),
+        }
+    }
+
+    pub fn with_message(mut self, message: impl Into<String>) -> Self {
+        self.message = message.into();
+        self
+    }
+
+    pub fn to_soap_fault(&self) -> SoapFault {
+        SoapFault {
+            faultcode: self.code.soap_fault_code().to_string(),
+            faultstring: String::from("CWMP fault"),
+            detail: Some(FaultDetail {
+                fault: CwmpFaultStruct {
+                    fault_code: self.code.code(),
+                    fault_string: self.message.clone(),
+                    set_parameter_values_fault: self
+                        .parameter_faults
+                        .iter()
+                        .map(|fault| SetParameterValuesFault {
+                            parameter_name: fault.name.clone(),
+                            fault_code: fault.code.code(),
+                            fault_string: fault.message.clone(),
+                        })
+                        .collect(),
+                },
+            }),
+        }
+    }
+}
+
+impl From<&SoapFault> for CwmpFault {
+    fn from(fault: &SoapFault) -> Self {
+        let Some(FaultDetail { fault: detail }) = &fault.detail else {
+            return Self {
+                code: FaultCode::Unknown(0),
+                message: fault.faultstring.clone(),
+                parameter_faults: Vec::new(),
+            };
+        };
+        Self {
+            code: FaultCode::from_code(detail.fault_code),
+            message: detail.fault_string.clone(),
+            parameter_faults: detail
+                .set_parameter_values_fault
+                .iter()
+                .map(|fault| ParameterFault {
+                    name: fault.parameter_name.clone(),
+                    code: FaultCode::from_code(fault.fault_code),
+                    message: fault.fault_string.clone(),
+                })
+                .collect(),
+        }
+    }
+}
+
+impl fmt::Display for CwmpFault {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "CWMP fault {}: {}", self.code.code(), self.message)?;
+        for fault in &self.parameter_faults {
+            write!(
+                f,
+                "; {} {}: {}",
+                fault.name,
+                fault.code.code(),
+                fault.message
+            )?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for CwmpFault {}
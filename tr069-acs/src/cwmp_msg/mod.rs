@@ -1,106 +1,66 @@
+mod acs_rpc;
 pub mod consts;
+mod cpe_rpc;
+mod event;
+mod fault;
+mod namespace;
 pub mod session;
+mod value;
 
-use crate::{
-    cwmp_msg::consts::{SOAP_CWMP_NP, SOAP_ENC_NP, SOAP_XSD_NP, SOAP_XSI_NP},
-    telemetry::{get_subscriber, init_subscriber},
-};
-use std::{collections::HashMap, sync::OnceLock};
-use tracing::level_filters::LevelFilter;
+pub use acs_rpc::*;
+pub use cpe_rpc::*;
+pub use event::EventCode;
+pub use fault::*;
+pub use value::ValueError;
+
+use crate::cwmp_msg::consts::{SOAP_CWMP_NP, SOAP_ENC_NP, SOAP_ENV_NP, SOAP_XSD_NP, SOAP_XSI_NP};
+use std::fmt;
 // use yaserde::{YaDeserialize, YaSerialize};
 use yaserde_derive::{YaDeserialize, YaSerialize};
 
-#[derive(Debug)]
-pub enum CpeRPC {
-    Inform,
-    GetRPCMethodsResponse,
-    SetParameterValuesResponse,
-    GetParameterValuesResponse,
-    GetParameterNamesResponse,
-    SetParameterAttributesResponse,
-    GetParameterAttributesResponse,
-    AddObjectResponse,
-    DeleteObjectResponse,
-    RebootResponse,
-    DownloadResponse,
-    ScheduleDownloadResponse,
-    UploadResponse,
-    FactoryResetResponse,
-    TransferComplete,
-    AutonomousTransferComplete,
-    RequestDownload,
-    DUStateChangeComplete,
-    GetQueuedTransfersResponse,
-    SetVouchersResponse,
-    GetOptionsResponse,
-    ScheduleInformResponse,
-    GetAllQueuedEventsResponse,
-}
-
-// #[derive(YaSerialize, YaDeserialize)]
-// enum EventCode {
-//     Event0BootStrap,
-//     Event1Boot,
-//     Event2Periodic,
-//     Event3Schedule,
-//     Event4ValueChange,
-//     Event5Kicked,
-//     Event6ConnectionRequest,
-//     Event7TransferComplete,
-//     Event8DiagnosticComplete,
-//     Event9RequestDownload,
-//     Event10AutonomousTransferComplete,
-//     Event11DUStateChangeComplete,
-//     Event12AutonomousDUStateChangeComplete,
-//     Event13Wakeup,
-//     Event14Heartbeat,
-//     EventMReboot,
-//     EventMScheduleInform,
-//     EventMDownload,
-//     EventMScheduleDownload,
-//     EventMUpload,
-//     EventMChangeDUState,
-//     EventMVendorMethod,
-//     EventMVendorEvent,
-// }
-
-#[derive(Debug, YaSerialize, YaDeserialize)]
-struct CommandKey {
-    value: Option<String>,
-}
-
 #[derive(Debug, YaSerialize, YaDeserialize, Default)]
 struct EventStruct {
     // #[yaserde(rename = "@arrayType")]
     // nb_of_event: Option<String>,
+    // Kept as sent on the wire, read it through `EventStruct::event_code`.
     #[yaserde(rename = "EventCode")]
     event_code: Option<String>,
     #[yaserde(rename = "CommandKey")]
     command_key: Option<String>,
 }
 
+impl EventStruct {
+    pub fn event_code(&self) -> EventCode {
+        let code = self.event_code.as_deref().unwrap_or_default().trim();
+        match code.parse() {
+            Ok(event_code) => event_code,
+            Err(never) => match never {},
+        }
+    }
+}
+
 #[derive(Debug, YaSerialize, YaDeserialize, Default)]
 struct EventList {
-    #[yaserde(rename = "@arrayType")]
+    #[yaserde(attribute = true, prefix = "soap-enc", rename = "arrayType")]
     nb_of_event: Option<String>,
 
     #[yaserde(rename = "EventStruct")]
     event_struct: Vec<EventStruct>,
 }
 
-#[derive(Debug, Default, YaSerialize, YaDeserialize)]
-struct DeviceIDStruct {
+#[derive(Debug, Clone, Default, YaSerialize, YaDeserialize)]
+pub struct DeviceIDStruct {
     #[yaserde(rename = "Manufacturer")]
-    manufacturer: Option<String>,
+    pub manufacturer: Option<String>,
 
     #[yaserde(rename = "OUI")]
-    oui: Option<String>,
+    pub oui: Option<String>,
 
     #[yaserde(rename = "ProductClass")]
-    product_class: Option<String>,
+    pub product_class: Option<String>,
 
     #[yaserde(rename = "SerialNumber")]
-    serial_number: Option<String>,
+    pub serial_number: Option<String>,
 }
 
 //The value of an element defined to be of type “anySimpleType” MAY be of any simple data type,
@@ -112,36 +72,72 @@ struct DeviceIDStruct {
 //  <Value xsi:type="xsd:string">code12345</Value>
 // </ParameterValueStruct>
 // The namespaces xsi and xsd used above are as defined in [12].
-#[derive(Debug, YaSerialize, YaDeserialize, Default)]
-struct AnySimpleType {
-    #[yaserde(rename = "@type")]
-    xsi_type: Option<String>,
+#[derive(Debug, Clone, YaSerialize, YaDeserialize, Default)]
+pub struct AnySimpleType {
+    #[yaserde(attribute = true, prefix = "xsi", rename = "type")]
+    pub xsi_type: Option<String>,
 
-    #[yaserde(rename = "$text")]
-    value: Option<String>,
+    #[yaserde(text = true)]
+    pub value: Option<String>,
 }
 
-#[derive(Debug, YaSerialize, YaDeserialize, Default)]
-struct ParameterValueStruct {
+#[derive(Debug, Clone, YaSerialize, YaDeserialize, Default)]
+pub struct ParameterValueStruct {
     #[yaserde(rename = "Name")]
-    name: Option<String>,
+    pub name: Option<String>,
     //This is the value the Parameter is to be set. The CPE
     //MUST treat string-valued Parameter values as casesensitive.
     #[yaserde(rename = "Value")]
-    value: Option<AnySimpleType>,
+    pub value: Option<AnySimpleType>,
 }
 
-#[derive(Debug, YaSerialize, YaDeserialize, Default)]
-struct ParameterList {
+#[derive(Debug, Clone, YaSerialize, YaDeserialize, Default)]
+pub struct ParameterList {
     #[yaserde(rename = "ParameterValueStruct")]
-    parameter_struct: Vec<ParameterValueStruct>,
+    pub parameter_struct: Vec<ParameterValueStruct>,
+
+    #[yaserde(attribute = true, prefix = "soap-enc", rename = "arrayType")]
+    pub nb_of_parameter: Option<String>,
+}
+
+impl ParameterValueStruct {
+    pub fn new(
+        name: impl Into<String>,
+        xsi_type: impl Into<String>,
+        value: impl Into<String>,
+    ) -> Self {
+        Self {
+            name: Some(name.into()),
+            value: Some(AnySimpleType {
+                xsi_type: Some(xsi_type.into()),
+                value: Some(value.into()),
+            }),
+        }
+    }
+}
 
-    #[yaserde(rename = "@arrayType")]
-    nb_of_parameter: Option<String>,
+impl ParameterList {
+    pub fn new(parameter_struct: Vec<ParameterValueStruct>) -> Self {
+        Self {
+            nb_of_parameter: array_type("cwmp:ParameterValueStruct", parameter_struct.len()),
+            parameter_struct,
+        }
+    }
+}
+
+// Default of string elements sent empty, such as `<CommandKey/>`: yaserde finds no
+// text in them and would otherwise reject the message.
+fn empty_string() -> String {
+    String::new()
+}
+
+/// `soap-enc:arrayType` attribute value of a SOAP array, e.g. `xsd:string[3]`.
+fn array_type(item_type: &str, len: usize) -> Option<String> {
+    Some(format!("{item_type}[{len}]"))
 }
 
 #[derive(Debug, YaSerialize, YaDeserialize, Default)]
-struct Inform {
+pub struct Inform {
     #[yaserde(rename = "DeviceId")]
     device_id: DeviceIDStruct,
 
@@ -151,7 +147,7 @@ struct Inform {
     #[yaserde(rename = "MaxEnvelopes")]
     max_envelopes: u32,
 
-    #[yaserde(rename = "CurrentTime")]
+    #[yaserde(rename = "CurrentTime", default = "empty_string")]
     current_time: String,
 
     #[yaserde(rename = "RetryCount")]
@@ -161,85 +157,166 @@ struct Inform {
     parameter_list: Vec<ParameterList>,
 }
 
+impl Inform {
+    pub fn device_id(&self) -> &DeviceIDStruct {
+        &self.device_id
+    }
+
+    /// Parameters the CPE reports in every Inform (Forced Inform and changed values).
+    pub fn parameters(&self) -> impl Iterator<Item = &ParameterValueStruct> {
+        self.parameter_list
+            .iter()
+            .flat_map(|list| list.parameter_struct.iter())
+    }
+
+    pub fn events(&self) -> impl Iterator<Item = EventCode> + '_ {
+        self.event.event_struct.iter().map(EventStruct::event_code)
+    }
+
+    pub fn has_event(&self, event_code: &EventCode) -> bool {
+        self.events().any(|event| &event == event_code)
+    }
+}
+
 #[derive(Debug, YaSerialize, YaDeserialize)]
 pub struct InformResponse {
     #[yaserde(rename = "MaxEnvelopes")]
     max_envelopes: u32,
 }
 
-#[derive(YaSerialize, Debug, YaDeserialize)]
-#[yaserde(rename = "cwmp:ID")]
-struct ID {
-    #[yaserde(rename = "@mustUnderstand")]
+/// A `cwmp:` SOAP header element (TR-069 Amendment 6, section 3.4.5, Table 5).
+#[derive(YaSerialize, Debug, YaDeserialize, Clone, Default, PartialEq)]
+struct HeaderField {
+    #[yaserde(attribute = true, prefix = "soap-env", rename = "mustUnderstand")]
     must_understand: Option<String>,
-    #[yaserde(rename = "$text")]
+    #[yaserde(text = true)]
     text: Option<String>,
 }
 
-#[derive(YaSerialize, Debug, YaDeserialize)]
+impl HeaderField {
+    fn new(text: impl Into<String>, must_understand: bool) -> Self {
+        Self {
+            must_understand: Some(String::from(if must_understand { "1" } else { "0" })),
+            text: Some(text.into()),
+        }
+    }
+}
+
+#[derive(YaSerialize, Debug, YaDeserialize, Clone)]
+#[yaserde(namespaces = { "cwmp" = "urn:dslforum-org:cwmp-1-0" })]
 struct Header {
-    #[yaserde(rename = "ID")]
-    id: ID,
+    #[yaserde(rename = "ID", prefix = "cwmp")]
+    id: HeaderField,
+
+    // ACS to CPE: the CPE must not send further requests while true.
+    #[yaserde(rename = "HoldRequests", prefix = "cwmp")]
+    hold_requests: Option<HeaderField>,
+
+    // Deprecated, the sender has no more requests for this session.
+    #[yaserde(rename = "NoMoreRequests", prefix = "cwmp")]
+    no_more_requests: Option<HeaderField>,
+
+    // CPE to ACS in the Inform: seconds the CPE waits for an ACS response.
+    #[yaserde(rename = "SessionTimeout", prefix = "cwmp")]
+    session_timeout: Option<HeaderField>,
+
+    // CPE to ACS in the Inform, e.g. "1.0,1.1,1.2,1.3,1.4".
+    #[yaserde(rename = "SupportedCWMPVersions", prefix = "cwmp")]
+    supported_cwmp_versions: Option<HeaderField>,
+
+    // ACS to CPE in the InformResponse, the version used for the rest of the session.
+    #[yaserde(rename = "UseCWMPVersion", prefix = "cwmp")]
+    use_cwmp_version: Option<HeaderField>,
 }
 
 #[derive(YaSerialize, Default, YaDeserialize, Debug)]
+#[yaserde(
+    prefix = "soap-env",
+    namespaces = { "soap-env" = "http://schemas.xmlsoap.org/soap/envelope/" }
+)]
 pub enum CWMPMsg {
     #[default]
     DefaultMsg,
     Inform(Inform),
     InformResponse(InformResponse),
-    GetRPCMethodsResponse,
-    SetParameterValuesResponse,
-    GetParameterValuesResponse,
-    GetParameterNamesResponse,
-    SetParameterAttributesResponse,
-    GetParameterAttributesResponse,
-    AddObjectResponse,
-    DeleteObjectResponse,
-    RebootResponse,
-    DownloadResponse,
-    ScheduleDownloadResponse,
-    UploadResponse,
-    FactoryResetResponse,
-    TransferComplete,
-    AutonomousTransferComplete,
+    // ACS initiated requests
+    GetRPCMethods(GetRPCMethods),
+    GetParameterValues(GetParameterValues),
+    SetParameterValues(SetParameterValues),
+    GetParameterNames(GetParameterNames),
+    SetParameterAttributes(SetParameterAttributes),
+    GetParameterAttributes(GetParameterAttributes),
+    AddObject(AddObject),
+    DeleteObject(DeleteObject),
+    Reboot(Reboot),
+    Download(Download),
+    Upload(Upload),
+    FactoryReset(FactoryReset),
+    ScheduleInform(ScheduleInform),
+    ScheduleDownload(ScheduleDownload),
+    // CPE responses and CPE initiated requests
+    GetRPCMethodsResponse(GetRPCMethodsResponse),
+    SetParameterValuesResponse(SetParameterValuesResponse),
+    GetParameterValuesResponse(GetParameterValuesResponse),
+    GetParameterNamesResponse(GetParameterNamesResponse),
+    SetParameterAttributesResponse(SetParameterAttributesResponse),
+    GetParameterAttributesResponse(GetParameterAttributesResponse),
+    AddObjectResponse(AddObjectResponse),
+    DeleteObjectResponse(DeleteObjectResponse),
+    RebootResponse(RebootResponse),
+    DownloadResponse(DownloadResponse),
+    ScheduleDownloadResponse(ScheduleDownloadResponse),
+    UploadResponse(UploadResponse),
+    FactoryResetResponse(FactoryResetResponse),
+    TransferComplete(TransferComplete),
+    AutonomousTransferComplete(AutonomousTransferComplete),
     RequestDownload,
     DUStateChangeComplete,
-    GetQueuedTransfersResponse,
-    SetVouchersResponse,
-    GetOptionsResponse,
-    ScheduleInformResponse,
-    GetAllQueuedEventsResponse,
+    GetQueuedTransfersResponse(GetQueuedTransfersResponse),
+    SetVouchersResponse(SetVouchersResponse),
+    GetOptionsResponse(GetOptionsResponse),
+    ScheduleInformResponse(ScheduleInformResponse),
+    GetAllQueuedEventsResponse(GetAllQueuedEventsResponse),
+    // soap-env:Fault, sent by either side in place of a response
+    Fault(SoapFault),
+    TransferCompleteResponse,
+    AutonomousTransferCompleteResponse,
+    DUStateChangeCompleteResponse,
+    RequestDownloadResponse,
 }
 
 #[derive(YaSerialize, Debug, YaDeserialize)]
 struct Body {
-    #[yaserde(rename = "$value")]
+    #[yaserde(flatten = true)]
     msg_type: CWMPMsg,
 }
 
 #[derive(YaSerialize, Debug, YaDeserialize)]
-#[yaserde(rename = "soap-env:Envelope")]
+#[yaserde(
+    rename = "Envelope",
+    prefix = "soap-env",
+    namespaces = { "soap-env" = "http://schemas.xmlsoap.org/soap/envelope/" }
+)]
 pub struct Envelope {
-    #[yaserde(rename = "@xmlns:soap-enc")]
+    #[yaserde(attribute = true, prefix = "xmlns", rename = "soap-enc")]
     soap_enc: Option<String>,
 
-    #[yaserde[rename = "@xmlns:soap-env"]]
+    #[yaserde(attribute = true, prefix = "xmlns", rename = "soap-env")]
     soap_env: Option<String>,
 
-    #[yaserde[rename = "@xmlns:xsd"]]
+    #[yaserde(attribute = true, prefix = "xmlns", rename = "xsd")]
     xsd: Option<String>,
 
-    #[yaserde(rename = "@xmlns:xsi")]
+    #[yaserde(attribute = true, prefix = "xmlns", rename = "xsi")]
     xsi: Option<String>,
 
-    #[yaserde(rename = "@xmlns:cwmp")]
+    #[yaserde(attribute = true, prefix = "xmlns", rename = "cwmp")]
     cwmp: Option<String>,
 
-    #[yaserde(rename = "Header")]
+    #[yaserde(rename = "Header", prefix = "soap-env")]
     header: Option<Header>,
 
-    #[yaserde(rename = "Body")]
+    #[yaserde(rename = "Body", prefix = "soap-env")]
     body: Option<Body>,
     // #[yaserde(flatten)]
     // pub attrs: std::collections::HashMap<String, String>,
@@ -252,66 +329,244 @@ impl Body {
 }
 impl Default for Header {
     fn default() -> Self {
-        let default_id = ID {
+        let default_id = HeaderField {
             must_understand: Some(String::from("1")),
             text: None,
         };
-        Header { id: default_id }
+        Header {
+            id: default_id,
+            hold_requests: None,
+            no_more_requests: None,
+            session_timeout: None,
+            supported_cwmp_versions: None,
+            use_cwmp_version: None,
+        }
+    }
+}
+/// Why an incoming envelope was rejected.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum EnvelopeError {
+    /// Not well formed XML, or not a CWMP envelope.
+    Malformed(String),
+    /// A header marked `mustUnderstand="1"` the ACS does not implement.
+    MustUnderstand(String),
+}
+
+impl fmt::Display for EnvelopeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            EnvelopeError::Malformed(err) => write!(f, "malformed envelope: {err}"),
+            EnvelopeError::MustUnderstand(header) => write!(f, "header {header} not understood"),
+        }
+    }
+}
+
+impl std::error::Error for EnvelopeError {}
+
+// "1" and "true" are both valid xsd:boolean true values.
+fn header_flag(text: Option<&str>) -> bool {
+    matches!(text, Some("1" | "true"))
+}
+
+impl Envelope {
+    /// Value of the `cwmp:ID` header, used to correlate a response with its request.
+    pub fn id(&self) -> Option<&str> {
+        self.header
+            .as_ref()
+            .and_then(|header| header.id.text.as_deref())
+    }
+
+    pub fn set_id(&mut self, id: Option<String>) {
+        self.header.get_or_insert_with(Header::default).id.text = id;
+    }
+
+    pub fn msg(&self) -> Option<&CWMPMsg> {
+        self.body.as_ref().map(|body| &body.msg_type)
+    }
+
+    pub fn into_msg(self) -> Option<CWMPMsg> {
+        self.body.map(|body| body.msg_type)
+    }
+
+    /// Parse an envelope whatever prefixes and CWMP version (cwmp-1-0 to cwmp-1-4) it uses.
+    pub fn parse(xml: &str) -> Result<Self, EnvelopeError> {
+        let normalized = namespace::normalize(xml).map_err(EnvelopeError::Malformed)?;
+        if let Some(header) = normalized.not_understood {
+            return Err(EnvelopeError::MustUnderstand(header));
+        }
+        let envelope: Envelope =
+            yaserde::de::from_str(&normalized.xml).map_err(EnvelopeError::Malformed)?;
+        // The reader takes the declarations as namespace bindings rather than
+        // attributes; the normalized root declares the canonical ones.
+        Ok(Envelope {
+            cwmp: Some(normalized.cwmp_namespace),
+            header: envelope.header,
+            body: envelope.body,
+            ..Envelope::new(CWMPMsg::default())
+        })
+    }
+
+    fn header_field(&self, field: impl Fn(&Header) -> Option<&HeaderField>) -> Option<&str> {
+        self.header
+            .as_ref()
+            .and_then(field)
+            .and_then(|field| field.text.as_deref())
+            .map(str::trim)
+    }
+
+    /// The sender has no request of its own left in this session.
+    pub fn no_more_requests(&self) -> bool {
+        header_flag(self.header_field(|h| h.no_more_requests.as_ref()))
+    }
+
+    /// Seconds the CPE waits for the ACS between messages, from its Inform.
+    pub fn session_timeout(&self) -> Option<u32> {
+        self.header_field(|h| h.session_timeout.as_ref())?
+            .parse()
+            .ok()
+    }
+
+    /// Versions listed in `SupportedCWMPVersions`, e.g. `["1.0", "1.4"]`.
+    pub fn supported_cwmp_versions(&self) -> Vec<&str> {
+        self.header_field(|h| h.supported_cwmp_versions.as_ref())
+            .map(|versions| {
+                versions
+                    .split(',')
+                    .map(str::trim)
+                    .filter(|version| !version.is_empty())
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
+    /// The CWMP namespace URI of this envelope, cwmp-1-0 when none is declared.
+    pub fn cwmp_namespace(&self) -> &str {
+        self.cwmp.as_deref().unwrap_or(SOAP_CWMP_NP)
+    }
+
+    pub fn set_cwmp_namespace(&mut self, namespace: impl Into<String>) {
+        self.cwmp = Some(namespace.into());
+    }
+
+    /// Envelope carrying `fault` in a `soap-env:Fault`, answering the request with `id`.
+    pub fn fault(fault: &CwmpFault, id: Option<String>) -> Self {
+        let mut envelope = Self::new(CWMPMsg::Fault(fault.to_soap_fault()));
+        envelope.set_id(id);
+        envelope
     }
 }
-// impl Envelope {
-//     pub fn get_rpc_type(self) -> CWMPMsg {
-//         self.body.msg_type
-//     }
-// }
 
 impl Envelope {
     pub fn new(msg_body: CWMPMsg) -> Self {
         Self {
             cwmp: Some(String::from(SOAP_CWMP_NP)),
             soap_enc: Some(String::from(SOAP_ENC_NP)),
-            xsi: Some(String::from(SOAP_ENC_NP)),
+            xsi: Some(String::from(SOAP_XSI_NP)),
             xsd: Some(String::from(SOAP_XSD_NP)),
-            soap_env: Some(String::from(SOAP_XSI_NP)),
+            soap_env: Some(String::from(SOAP_ENV_NP)),
             header: Some(Header::default()),
-            body: Some(Body { msg_type: msg_body }),
+            body: Some(Body::new(msg_body)),
             // attrs: HashMap::new(),
         }
     }
-}
-impl Default for InformResponse {
-    fn default() -> Self {
-        Self { max_envelopes: 1 }
+
+    pub fn builder(msg_body: CWMPMsg) -> EnvelopeBuilder {
+        EnvelopeBuilder {
+            envelope: Self::new(msg_body),
+        }
+    }
+
+    /// Serialize for the wire, with the RPC element and header fields in the `cwmp`
+    /// namespace and the envelope structure in `soap-env`.
+    pub fn to_xml(&self) -> Result<String, String> {
+        namespace::qualify(&yaserde::ser::to_string(self)?)
     }
 }
 
-static TRACING: OnceLock<()> = OnceLock::new();
+/// Builds the envelopes the ACS sends, with the optional CWMP header fields.
+#[derive(Debug)]
+pub struct EnvelopeBuilder {
+    envelope: Envelope,
+}
 
-fn spawn_log() {
-    if TRACING.get().is_none() {
-        let _tracing = TRACING.get_or_init(|| {
-            let test_sub = get_subscriber("tr069-server-test".into(), LevelFilter::TRACE.into());
-            init_subscriber(test_sub);
-        });
+impl EnvelopeBuilder {
+    fn header(&mut self) -> &mut Header {
+        self.envelope.header.get_or_insert_with(Header::default)
+    }
+
+    pub fn id(mut self, id: impl Into<String>) -> Self {
+        self.header().id.text = Some(id.into());
+        self
+    }
+
+    pub fn hold_requests(mut self, hold: bool) -> Self {
+        self.header().hold_requests = Some(HeaderField::new(if hold { "1" } else { "0" }, true));
+        self
     }
-}
 
-pub trait HandleCwmpMessage {
-    fn parse(xml: &str) -> Self;
+    pub fn use_cwmp_version(mut self, version: impl Into<String>) -> Self {
+        self.header().use_cwmp_version = Some(HeaderField::new(version, true));
+        self
+    }
+
+    pub fn cwmp_namespace(mut self, namespace: impl Into<String>) -> Self {
+        self.envelope.set_cwmp_namespace(namespace);
+        self
+    }
+
+    pub fn build(self) -> Envelope {
+        self.envelope
+    }
+}
+impl Default for InformResponse {
+    fn default() -> Self {
+        Self { max_envelopes: 1 }
+    }
 }
 
-// impl HandleCwmpMessage for Inform {
-//     fn parse(xml: &str) -> Self {
-//         Form
-//     }
-// }
+/// A BOOT Inform from the CPE 001A2B-RouterX-`serial`, reporting `parameters` as
+/// (name, type, value).
+#[cfg(test)]
+pub(crate) fn boot_inform(serial: &str, parameters: &[(&str, &str, &str)]) -> String {
+    let parameters: String = parameters
+        .iter()
+        .map(|(name, xsi_type, value)| {
+            format!(
+                r#"<ParameterValueStruct><Name>{name}</Name><Value xsi:type="{xsi_type}">{value}</Value></ParameterValueStruct>"#
+            )
+        })
+        .collect();
+    format!(
+        r#"<soap:Envelope xmlns:soap="http://schemas.xmlsoap.org/soap/envelope/"
+                   xmlns:cwmp="urn:dslforum-org:cwmp-1-0"
+                   xmlns:xsd="http://www.w3.org/2001/XMLSchema"
+                   xmlns:xsi="http://www.w3.org/2001/XMLSchema-instance">
+          <soap:Header><cwmp:ID soap:mustUnderstand="1">1</cwmp:ID></soap:Header>
+          <soap:Body>
+            <cwmp:Inform>
+              <DeviceId>
+                <Manufacturer>ExampleCo</Manufacturer>
+                <OUI>001A2B</OUI>
+                <ProductClass>RouterX</ProductClass>
+                <SerialNumber>{serial}</SerialNumber>
+              </DeviceId>
+              <Event><EventStruct><EventCode>1 BOOT</EventCode><CommandKey/></EventStruct></Event>
+              <MaxEnvelopes>1</MaxEnvelopes>
+              <CurrentTime>2025-10-07T10:00:00Z</CurrentTime>
+              <RetryCount>0</RetryCount>
+              <ParameterList>{parameters}</ParameterList>
+            </cwmp:Inform>
+          </soap:Body>
+        </soap:Envelope>"#
+    )
+}
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use value::XsdValue;
     #[test]
     fn test_deserialize_soap_xml() {
-        // spawn_log();
         let xml = r#"
                                     <soap-env:Envelope xmlns:soap-env="http://schemas.xmlsoap.org/soap/envelope/"
                                                        xmlns:soap-enc="http://schemas.xmlsoap.org/soap/encoding/"
@@ -355,5 +610,468 @@ mod tests {
 
         let soap_env: Envelope = yaserde::de::from_str(xml).unwrap();
         // trace!("soap evelope test {:?}", soap_env);
+        let Some(CWMPMsg::Inform(inform)) = soap_env.msg() else {
+            panic!("unexpected {soap_env:?}");
+        };
+        assert!(inform.has_event(&EventCode::Bootstrap));
+        assert_eq!(
+            inform.event.event_struct[0].command_key.as_deref(),
+            Some("Darwin command")
+        );
+    }
+
+    #[test]
+    fn test_parse_foreign_prefixes() {
+        let xml = r#"<?xml version="1.0" encoding="UTF-8"?>
+            <SOAP-ENV:Envelope xmlns:SOAP-ENV="http://schemas.xmlsoap.org/soap/envelope/"
+                               xmlns:SOAP-ENC="http://schemas.xmlsoap.org/soap/encoding/"
+                               xmlns:xs="http://www.w3.org/2001/XMLSchema"
+                               xmlns:i="http://www.w3.org/2001/XMLSchema-instance">
+              <SOAP-ENV:Header>
+                <ns1:ID xmlns:ns1="urn:dslforum-org:cwmp-1-2" SOAP-ENV:mustUnderstand="1">77</ns1:ID>
+              </SOAP-ENV:Header>
+              <SOAP-ENV:Body>
+                <Inform xmlns="urn:dslforum-org:cwmp-1-2">
+                  <DeviceId>
+                    <Manufacturer>ExampleCorp</Manufacturer>
+                    <OUI>001A2B</OUI>
+                    <ProductClass>RouterX100</ProductClass>
+                    <SerialNumber>SN1</SerialNumber>
+                  </DeviceId>
+                  <Event SOAP-ENC:arrayType="ns1:EventStruct[1]"
+                         xmlns:ns1="urn:dslforum-org:cwmp-1-2">
+                    <EventStruct>
+                      <EventCode>1 BOOT</EventCode>
+                      <CommandKey/>
+                    </EventStruct>
+                  </Event>
+                  <MaxEnvelopes>1</MaxEnvelopes>
+                  <CurrentTime>2025-10-01T05:00:00Z</CurrentTime>
+                  <RetryCount>0</RetryCount>
+                  <ParameterList SOAP-ENC:arrayType="ns1:ParameterValueStruct[1]"
+                                 xmlns:ns1="urn:dslforum-org:cwmp-1-2">
+                    <ParameterValueStruct>
+                      <Name>Device.DeviceInfo.UpTime</Name>
+                      <Value i:type="xs:unsignedInt">42</Value>
+                    </ParameterValueStruct>
+                  </ParameterList>
+                </Inform>
+              </SOAP-ENV:Body>
+            </SOAP-ENV:Envelope>"#;
+
+        let envelope = Envelope::parse(xml).unwrap();
+        assert_eq!(envelope.cwmp_namespace(), consts::SOAP_CWMP_1_2_NP);
+        assert_eq!(envelope.id(), Some("77"));
+        let Some(CWMPMsg::Inform(inform)) = envelope.msg() else {
+            panic!("unexpected {envelope:?}");
+        };
+        assert!(inform.has_event(&EventCode::Boot));
+        let parameter = &inform.parameter_list[0].parameter_struct[0];
+        assert_eq!(parameter.typed_value().unwrap(), XsdValue::UnsignedInt(42));
+    }
+
+    #[test]
+    fn test_typed_values() {
+        let cases = [
+            (
+                "xsd:string",
+                " code 12345 ",
+                XsdValue::String(" code 12345 ".into()),
+            ),
+            ("xsd:int", "-5", XsdValue::Int(-5)),
+            ("xsd:unsignedInt", "300", XsdValue::UnsignedInt(300)),
+            ("xsd:long", "-9000000000", XsdValue::Long(-9_000_000_000)),
+            (
+                "xsd:unsignedLong",
+                "18000000000",
+                XsdValue::UnsignedLong(18_000_000_000),
+            ),
+            ("xsd:boolean", "1", XsdValue::Boolean(true)),
+            ("xsd:base64", "AQID", XsdValue::Base64(vec![1, 2, 3])),
+            ("xsd:hexBinary", "0a0B", XsdValue::HexBinary(vec![10, 11])),
+        ];
+        for (xsi_type, text, expected) in cases {
+            let value = XsdValue::parse(xsi_type, text).unwrap();
+            assert_eq!(value, expected);
+            let parameter = ParameterValueStruct {
+                name: Some(String::from("Device.Test")),
+                value: Some(AnySimpleType::from(&value)),
+            };
+            assert_eq!(parameter.typed_value().unwrap(), expected);
+        }
+
+        let time = XsdValue::parse("xsd:dateTime", "2025-11-18T14:19:09.541Z").unwrap();
+        assert_eq!(time.to_string(), "2025-11-18T14:19:09.541Z");
+        assert!(XsdValue::parse("xsd:dateTime", "2025-11-18T14:19:09").is_ok());
+
+        assert!(matches!(
+            XsdValue::parse("xsd:unsignedInt", "-1"),
+            Err(ValueError::Invalid { .. })
+        ));
+        assert!(matches!(
+            XsdValue::parse("xsd:float", "1.0"),
+            Err(ValueError::UnknownType(_))
+        ));
+        assert!(matches!(
+            XsdValue::Int(1).ensure_type("xsd:unsignedInt"),
+            Err(ValueError::Mismatch { .. })
+        ));
+        assert!(XsdValue::UnsignedInt(1)
+            .ensure_type("xs:unsignedInt")
+            .is_ok());
+        assert!(ParameterValueStruct::checked("Device.Test", "xsd:boolean", "yes").is_err());
+    }
+
+    #[test]
+    fn test_event_code_round_trip() {
+        for code in [
+            "0 BOOTSTRAP",
+            "4 VALUE CHANGE",
+            "7 TRANSFER COMPLETE",
+            "14 HEARTBEAT",
+            "M Download",
+            "M X_00D09E_Restore",
+            "X 00D09E CONFIG_RESTORED",
+            "15 SOMETHING NEW",
+        ] {
+            let event: EventCode = code.parse().unwrap();
+            assert_eq!(event.to_string(), code);
+        }
+        assert_eq!(
+            "X 00D09E CONFIG_RESTORED".parse::<EventCode>().unwrap(),
+            EventCode::Vendor {
+                oui: "00D09E".into(),
+                event: "CONFIG_RESTORED".into()
+            }
+        );
+        assert_eq!(
+            "M X_00D09E_Restore".parse::<EventCode>().unwrap(),
+            EventCode::MVendorMethod("X_00D09E_Restore".into())
+        );
+        assert_eq!(
+            "15 SOMETHING NEW".parse::<EventCode>().unwrap(),
+            EventCode::Unknown("15 SOMETHING NEW".into())
+        );
+    }
+
+    // Parse a captured CPE response body, then check it survives serialize + parse.
+    fn round_trip(body: &str) -> CWMPMsg {
+        let xml = format!(
+            r#"<soap-env:Envelope xmlns:soap-env="http://schemas.xmlsoap.org/soap/envelope/"
+                                  xmlns:soap-enc="http://schemas.xmlsoap.org/soap/encoding/"
+                                  xmlns:xsd="http://www.w3.org/2001/XMLSchema"
+                                  xmlns:xsi="http://www.w3.org/2001/XMLSchema-instance"
+                                  xmlns:cwmp="urn:dslforum-org:cwmp-1-0">
+                 <soap-env:Header>
+                   <cwmp:ID soap-env:mustUnderstand="1">42</cwmp:ID>
+                 </soap-env:Header>
+                 <soap-env:Body>{body}</soap-env:Body>
+               </soap-env:Envelope>"#
+        );
+        let envelope = Envelope::parse(&xml).unwrap();
+        let reparsed = Envelope::parse(&envelope.to_xml().unwrap()).unwrap();
+        assert_eq!(
+            format!("{:?}", envelope.msg()),
+            format!("{:?}", reparsed.msg())
+        );
+        reparsed.into_msg().unwrap()
+    }
+
+    #[test]
+    fn test_get_parameter_values_response() {
+        let msg = round_trip(
+            r#"<cwmp:GetParameterValuesResponse>
+                 <ParameterList soap-enc:arrayType="cwmp:ParameterValueStruct[2]">
+                   <ParameterValueStruct>
+                     <Name>InternetGatewayDevice.DeviceInfo.SoftwareVersion</Name>
+                     <Value xsi:type="xsd:string">V100R001IRQC56B017</Value>
+                   </ParameterValueStruct>
+                   <ParameterValueStruct>
+                     <Name>InternetGatewayDevice.ManagementServer.PeriodicInformInterval</Name>
+                     <Value xsi:type="xsd:unsignedInt">300</Value>
+                   </ParameterValueStruct>
+                 </ParameterList>
+               </cwmp:GetParameterValuesResponse>"#,
+        );
+        let CWMPMsg::GetParameterValuesResponse(response) = msg else {
+            panic!("unexpected {msg:?}");
+        };
+        let parameters = &response.parameter_list.parameter_struct;
+        assert_eq!(parameters.len(), 2);
+        let value = parameters[1].value.as_ref().unwrap();
+        assert_eq!(value.xsi_type.as_deref(), Some("xsd:unsignedInt"));
+        assert_eq!(value.value.as_deref(), Some("300"));
+    }
+
+    #[test]
+    fn test_add_object_response() {
+        let msg = round_trip(
+            r#"<cwmp:AddObjectResponse>
+                 <InstanceNumber>3</InstanceNumber>
+                 <Status>0</Status>
+               </cwmp:AddObjectResponse>"#,
+        );
+        let CWMPMsg::AddObjectResponse(response) = msg else {
+            panic!("unexpected {msg:?}");
+        };
+        assert_eq!(response.instance_number, 3);
+        assert_eq!(response.status, 0);
+    }
+
+    #[test]
+    fn test_get_parameter_names_and_attributes_response() {
+        let msg = round_trip(
+            r#"<cwmp:GetParameterNamesResponse>
+                 <ParameterList soap-enc:arrayType="cwmp:ParameterInfoStruct[2]">
+                   <ParameterInfoStruct>
+                     <Name>InternetGatewayDevice.LANDevice.</Name>
+                     <Writable>0</Writable>
+                   </ParameterInfoStruct>
+                   <ParameterInfoStruct>
+                     <Name>InternetGatewayDevice.LANDevice.1.</Name>
+                     <Writable>true</Writable>
+                   </ParameterInfoStruct>
+                 </ParameterList>
+               </cwmp:GetParameterNamesResponse>"#,
+        );
+        let CWMPMsg::GetParameterNamesResponse(response) = msg else {
+            panic!("unexpected {msg:?}");
+        };
+        let parameters = &response.parameter_list.parameters;
+        assert_eq!(parameters[1].name, "InternetGatewayDevice.LANDevice.1.");
+        assert!(!parameters[0].is_writable());
+        assert!(parameters[1].is_writable());
+
+        let msg = round_trip(
+            r#"<cwmp:GetParameterAttributesResponse>
+                 <ParameterList soap-enc:arrayType="cwmp:ParameterAttributeStruct[1]">
+                   <ParameterAttributeStruct>
+                     <Name>InternetGatewayDevice.DeviceInfo.SoftwareVersion</Name>
+                     <Notification>2</Notification>
+                     <AccessList soap-enc:arrayType="xsd:string[1]">
+                       <string>Subscriber</string>
+                     </AccessList>
+                   </ParameterAttributeStruct>
+                 </ParameterList>
+               </cwmp:GetParameterAttributesResponse>"#,
+        );
+        let CWMPMsg::GetParameterAttributesResponse(response) = msg else {
+            panic!("unexpected {msg:?}");
+        };
+        let attribute = &response.parameter_list.parameters[0];
+        assert_eq!(attribute.notification, 2);
+        assert_eq!(attribute.access_list.names, vec!["Subscriber".to_string()]);
+    }
+
+    #[test]
+    fn test_cpe_fault() {
+        let msg = round_trip(
+            r#"<soap-env:Fault>
+                 <faultcode>Client</faultcode>
+                 <faultstring>CWMP fault</faultstring>
+                 <detail>
+                   <cwmp:Fault>
+                     <FaultCode>9003</FaultCode>
+                     <FaultString>Invalid arguments</FaultString>
+                     <SetParameterValuesFault>
+                       <ParameterName>InternetGatewayDevice.Time.Enable</ParameterName>
+                       <FaultCode>9008</FaultCode>
+                       <FaultString>Attempt to set a non-writable parameter</FaultString>
+                     </SetParameterValuesFault>
+                   </cwmp:Fault>
+                 </detail>
+               </soap-env:Fault>"#,
+        );
+        let CWMPMsg::Fault(fault) = msg else {
+            panic!("unexpected {msg:?}");
+        };
+        let fault = CwmpFault::from(&fault);
+        assert_eq!(fault.code, FaultCode::Cpe(CpeFaultCode::InvalidArguments));
+        assert_eq!(fault.parameter_faults.len(), 1);
+        assert_eq!(
+            fault.parameter_faults[0].code,
+            FaultCode::Cpe(CpeFaultCode::NonWritableParameter)
+        );
+        assert_eq!(
+            FaultCode::from_code(9850),
+            FaultCode::Cpe(CpeFaultCode::Vendor(9850))
+        );
+        assert_eq!(FaultCode::from_code(1234), FaultCode::Unknown(1234));
+    }
+
+    #[test]
+    fn test_acs_fault() {
+        let fault = CwmpFault::acs(AcsFaultCode::MethodNotSupported);
+        let xml = Envelope::fault(&fault, Some("7".into())).to_xml().unwrap();
+        assert!(xml.contains("<faultcode>Client</faultcode>"), "{xml}");
+        assert!(xml.contains("<FaultCode>8000</FaultCode>"), "{xml}");
+        assert!(
+            xml.contains("<FaultString>Method not supported</FaultString>"),
+            "{xml}"
+        );
+    }
+
+    #[test]
+    fn test_serialize_acs_requests() {
+        let set = SetParameterValues::new(
+            vec![ParameterValueStruct::new(
+                "Device.ManagementServer.PeriodicInformInterval",
+                "xsd:unsignedInt",
+                "300",
+            )],
+            "key-1",
+        );
+        let xml = Envelope::new(CWMPMsg::SetParameterValues(set))
+            .to_xml()
+            .unwrap();
+        assert!(xml.contains("<cwmp:SetParameterValues>"), "{xml}");
+        assert!(
+            xml.contains(r#"soap-enc:arrayType="cwmp:ParameterValueStruct[1]""#),
+            "{xml}"
+        );
+        assert!(xml.contains(r#"xsi:type="xsd:unsignedInt""#), "{xml}");
+        assert!(xml.contains("<ParameterKey>key-1</ParameterKey>"), "{xml}");
+
+        let get = GetParameterValues::new(vec!["Device.DeviceInfo.".into()]);
+        let xml = Envelope::new(CWMPMsg::GetParameterValues(get))
+            .to_xml()
+            .unwrap();
+        assert!(
+            xml.contains(r#"soap-enc:arrayType="xsd:string[1]""#),
+            "{xml}"
+        );
+        assert!(xml.contains("<string>Device.DeviceInfo.</string>"), "{xml}");
+
+        let xml = Envelope::new(CWMPMsg::Reboot(Reboot::new("rb")))
+            .to_xml()
+            .unwrap();
+        assert!(xml.contains("<CommandKey>rb</CommandKey>"), "{xml}");
+    }
+
+    // Outgoing envelopes must declare the right namespaces and parse back unchanged.
+    fn assert_round_trip(envelope: Envelope) -> String {
+        let xml = envelope.to_xml().unwrap();
+        for declaration in [
+            r#"xmlns:soap-env="http://schemas.xmlsoap.org/soap/envelope/""#,
+            r#"xmlns:soap-enc="http://schemas.xmlsoap.org/soap/encoding/""#,
+            r#"xmlns:xsd="http://www.w3.org/2001/XMLSchema""#,
+            r#"xmlns:xsi="http://www.w3.org/2001/XMLSchema-instance""#,
+        ] {
+            assert!(xml.contains(declaration), "{declaration} missing in {xml}");
+        }
+        for prefix in ["soap-env", "soap-enc", "xsd", "xsi", "cwmp"] {
+            let declarations = xml.matches(&format!("xmlns:{prefix}=")).count();
+            assert_eq!(
+                declarations, 1,
+                "{prefix} declared {declarations} times in {xml}"
+            );
+        }
+        assert!(xml.contains("<soap-env:Envelope"), "{xml}");
+        assert!(xml.contains("<soap-env:Header>"), "{xml}");
+        assert!(xml.contains("<soap-env:Body>"), "{xml}");
+
+        let reparsed = Envelope::parse(&xml).unwrap();
+        assert_eq!(format!("{envelope:?}"), format!("{reparsed:?}"), "{xml}");
+        xml
+    }
+
+    #[test]
+    fn test_envelope_builder_round_trip() {
+        let xml = assert_round_trip(
+            Envelope::builder(CWMPMsg::InformResponse(InformResponse::default()))
+                .id("1234")
+                .use_cwmp_version("1.4")
+                .cwmp_namespace(consts::SOAP_CWMP_1_4_NP)
+                .build(),
+        );
+        assert!(
+            xml.contains(r#"xmlns:cwmp="urn:dslforum-org:cwmp-1-4""#),
+            "{xml}"
+        );
+        assert!(
+            xml.contains(r#"<cwmp:ID soap-env:mustUnderstand="1">1234</cwmp:ID>"#),
+            "{xml}"
+        );
+        assert!(xml.contains("<cwmp:InformResponse>"), "{xml}");
+        assert!(
+            xml.contains(
+                r#"<cwmp:UseCWMPVersion soap-env:mustUnderstand="1">1.4</cwmp:UseCWMPVersion>"#
+            ),
+            "{xml}"
+        );
+
+        let xml = assert_round_trip(
+            Envelope::builder(CWMPMsg::GetParameterNames(GetParameterNames::new(
+                "Device.", true,
+            )))
+            .id("2")
+            .hold_requests(true)
+            .build(),
+        );
+        assert!(
+            xml.contains(r#"<cwmp:HoldRequests soap-env:mustUnderstand="1">1</cwmp:HoldRequests>"#),
+            "{xml}"
+        );
+
+        assert_round_trip(
+            Envelope::builder(CWMPMsg::Download(Download::new(
+                "fw-1",
+                "1 Firmware Upgrade Image",
+                "http://files.example.com/fw.bin",
+            )))
+            .id("3")
+            .build(),
+        );
+        assert_round_trip(
+            Envelope::builder(CWMPMsg::SetParameterAttributes(
+                SetParameterAttributes::new(vec![SetParameterAttributesStruct {
+                    name: "Device.DeviceInfo.SoftwareVersion".into(),
+                    notification_change: true,
+                    notification: 2,
+                    ..Default::default()
+                }]),
+            ))
+            .id("4")
+            .build(),
+        );
+        assert_round_trip(Envelope::fault(
+            &CwmpFault::acs(AcsFaultCode::RetryRequest),
+            Some("5".into()),
+        ));
+    }
+
+    // A BOOT Inform in CWMP 1.4 with the session headers, followed by `extra`.
+    fn header_inform(extra: &str) -> String {
+        boot_inform("SN1", &[]).replace("cwmp-1-0", "cwmp-1-4").replace(
+            "</cwmp:ID>",
+            &format!(
+                r#"</cwmp:ID>
+            <cwmp:SessionTimeout soap:mustUnderstand="0">60</cwmp:SessionTimeout>
+            <cwmp:SupportedCWMPVersions soap:mustUnderstand="0">1.0,1.2, 1.4</cwmp:SupportedCWMPVersions>
+            {extra}"#
+            ),
+        )
+    }
+
+    #[test]
+    fn test_header_extensions() {
+        let envelope = Envelope::parse(&header_inform("")).unwrap();
+        assert_eq!(envelope.id(), Some("1"));
+        assert_eq!(envelope.session_timeout(), Some(60));
+        assert_eq!(envelope.supported_cwmp_versions(), ["1.0", "1.2", "1.4"]);
+        assert!(!envelope.no_more_requests());
+        let no_more = "<cwmp:NoMoreRequests>1</cwmp:NoMoreRequests>";
+        assert!(Envelope::parse(&header_inform(no_more))
+            .unwrap()
+            .no_more_requests());
+
+        // Unknown optional headers are ignored, unknown mandatory ones reject the message.
+        let optional = r#"<x:Trace xmlns:x="urn:example" soap:mustUnderstand="0">1</x:Trace>"#;
+        assert!(Envelope::parse(&header_inform(optional)).is_ok());
+        let mandatory = r#"<x:Trace xmlns:x="urn:example" soap:mustUnderstand="1">1</x:Trace>"#;
+        assert_eq!(
+            Envelope::parse(&header_inform(mandatory)).unwrap_err(),
+            EnvelopeError::MustUnderstand(String::from("x:Trace"))
+        );
     }
 }
@@ -0,0 +1,309 @@
+// Namespace normalization of incoming envelopes.
+//
+// CPEs are free to pick their own prefixes (`soap:`, `SOAP-ENV:`, `cwmp:`, `ns1:`, a
+// default namespace, ...) and any of the cwmp-1-0 to cwmp-1-4 namespaces. The yaserde
+// structs expect the canonical `soap-env`, `soap-enc`, `xsi`, `xsd` and `cwmp`
+// prefixes, so element and attribute names are rewritten according to the namespace
+// URI they resolve to before deserializing. QName valued attributes (`xsi:type`,
+// `soap-enc:arrayType`) get the same treatment.
+//
+// Only the RPC elements (children of Header, Body and Fault detail) are qualified by
+// the CWMP namespace; their content is unqualified even when a CPE puts it in the
+// CWMP default namespace.
+use super::consts::{
+    CWMP_NAMESPACES, CWMP_NP, ENC_NP, ENV_NP, KNOWN_HEADERS, SOAP_CWMP_NP, SOAP_ENC_NP,
+    SOAP_ENV_NP, SOAP_XSD_NP, SOAP_XSI_NP, XSD_NP, XSI_NP,
+};
+use quick_xml::{
+    events::{attributes::Attribute, BytesEnd, BytesStart, Event},
+    name::{PrefixDeclaration, QName, ResolveResult},
+    NsReader, Reader, Writer,
+};
+use std::borrow::Cow;
+
+/// An envelope rewritten with canonical prefixes.
+#[derive(Debug)]
+pub struct Normalized {
+    pub xml: String,
+    /// The CWMP namespace the CPE used, one of `CWMP_NAMESPACES`.
+    pub cwmp_namespace: String,
+    /// First header marked `mustUnderstand="1"` that the ACS does not know.
+    pub not_understood: Option<String>,
+}
+
+fn canonical_prefix(namespace: &[u8], cwmp_namespace: &str) -> Option<&'static str> {
+    match std::str::from_utf8(namespace).ok()? {
+        SOAP_ENV_NP => Some(ENV_NP),
+        SOAP_ENC_NP => Some(ENC_NP),
+        SOAP_XSI_NP => Some(XSI_NP),
+        SOAP_XSD_NP => Some(XSD_NP),
+        uri if uri == cwmp_namespace => Some(CWMP_NP),
+        _ => None,
+    }
+}
+
+// First CWMP namespace declared anywhere in the document.
+fn find_cwmp_namespace(xml: &str) -> Result<Option<String>, String> {
+    let mut reader = NsReader::from_str(xml);
+    loop {
+        match reader.read_event().map_err(|err| err.to_string())? {
+            Event::Start(element) | Event::Empty(element) => {
+                for attribute in element.attributes().with_checks(false).flatten() {
+                    if attribute.key.as_namespace_binding().is_none() {
+                        continue;
+                    }
+                    let value = attribute.unescape_value().map_err(|err| err.to_string())?;
+                    if CWMP_NAMESPACES.contains(&value.as_ref()) {
+                        return Ok(Some(value.into_owned()));
+                    }
+                }
+            }
+            Event::Eof => return Ok(None),
+            _ => {}
+        }
+    }
+}
+
+/// Rewrite `xml` with canonical prefixes and declare them all on the root element.
+pub fn normalize(xml: &str) -> Result<Normalized, String> {
+    let cwmp_namespace = find_cwmp_namespace(xml)?.unwrap_or_else(|| String::from(SOAP_CWMP_NP));
+    let mut reader = NsReader::from_str(xml);
+    let mut writer = Writer::new(Vec::new());
+    // Names written for each open element, to close them with the same name.
+    let mut open: Vec<String> = Vec::new();
+    let mut not_understood = None;
+
+    loop {
+        let (namespace, event) = reader
+            .read_resolved_event()
+            .map_err(|err| err.to_string())?;
+        let namespace = match namespace {
+            ResolveResult::Bound(namespace) => Some(namespace.into_inner().to_vec()),
+            _ => None,
+        };
+        let event = match event {
+            Event::Start(element) => {
+                let start = rewrite_element(
+                    &reader,
+                    &element,
+                    namespace.as_deref(),
+                    &cwmp_namespace,
+                    open.last().map(String::as_str),
+                )?;
+                if open
+                    .last()
+                    .is_some_and(|parent| parent == "soap-env:Header")
+                {
+                    not_understood = not_understood.or_else(|| unknown_mandatory_header(&start));
+                }
+                open.push(String::from_utf8_lossy(start.name().as_ref()).into_owned());
+                Event::Start(start)
+            }
+            Event::Empty(element) => {
+                let start = rewrite_element(
+                    &reader,
+                    &element,
+                    namespace.as_deref(),
+                    &cwmp_namespace,
+                    open.last().map(String::as_str),
+                )?;
+                if open
+                    .last()
+                    .is_some_and(|parent| parent == "soap-env:Header")
+                {
+                    not_understood = not_understood.or_else(|| unknown_mandatory_header(&start));
+                }
+                Event::Empty(start)
+            }
+            Event::End(_) => {
+                let name = open.pop().ok_or("unbalanced end tag")?;
+                Event::End(BytesEnd::new(name))
+            }
+            Event::DocType(_) => return Err(String::from("DTDs are not allowed in SOAP messages")),
+            Event::Eof => break,
+            other => other,
+        };
+        writer.write_event(event).map_err(|err| err.to_string())?;
+    }
+
+    let xml = String::from_utf8(writer.into_inner()).map_err(|err| err.to_string())?;
+    Ok(Normalized {
+        xml,
+        cwmp_namespace,
+        not_understood,
+    })
+}
+
+// SOAP 1.1 section 4.2.3: a header entry with mustUnderstand="1" the receiver does not
+// know must fail the whole message. Expects a name already rewritten by `rewrite_element`.
+fn unknown_mandatory_header(header: &BytesStart<'_>) -> Option<String> {
+    let name = String::from_utf8_lossy(header.name().as_ref()).into_owned();
+    let known = name
+        .strip_prefix("cwmp:")
+        .is_some_and(|local| KNOWN_HEADERS.contains(&local));
+    let mandatory = header
+        .attributes()
+        .with_checks(false)
+        .flatten()
+        .any(|attribute| {
+            attribute.key.as_ref() == b"soap-env:mustUnderstand"
+                && matches!(attribute.value.as_ref(), b"1" | b"true")
+        });
+    (mandatory && !known).then_some(name)
+}
+
+fn rewrite_element<'a>(
+    reader: &NsReader<&[u8]>,
+    element: &BytesStart<'_>,
+    namespace: Option<&[u8]>,
+    cwmp_namespace: &str,
+    parent: Option<&str>,
+) -> Result<BytesStart<'a>, String> {
+    let name = element.name();
+    let local = String::from_utf8_lossy(element.local_name().as_ref()).into_owned();
+    let rpc_parent = matches!(parent, Some("soap-env:Header" | "soap-env:Body" | "detail"));
+    let mut start = match namespace.and_then(|ns| canonical_prefix(ns, cwmp_namespace)) {
+        Some(CWMP_NP) if !rpc_parent => BytesStart::new(local),
+        Some(prefix) => BytesStart::new(format!("{prefix}:{local}")),
+        // Unqualified elements (e.g. `ParameterList`) and foreign namespaces keep their name.
+        None => BytesStart::new(String::from_utf8_lossy(name.as_ref()).into_owned()),
+    };
+
+    if parent.is_none() {
+        for (prefix, uri) in [
+            (ENV_NP, SOAP_ENV_NP),
+            (ENC_NP, SOAP_ENC_NP),
+            (XSD_NP, SOAP_XSD_NP),
+            (XSI_NP, SOAP_XSI_NP),
+            // The yaserde structs only know cwmp-1-0, `Normalized` keeps the real one.
+            (CWMP_NP, SOAP_CWMP_NP),
+        ] {
+            start.push_attribute((format!("xmlns:{prefix}").as_str(), uri));
+        }
+    }
+
+    for attribute in element.attributes().with_checks(false) {
+        let attribute = attribute.map_err(|err| err.to_string())?;
+        let value = attribute.unescape_value().map_err(|err| err.to_string())?;
+        if attribute.key.as_namespace_binding().is_some() {
+            // Known namespaces are declared once on the root with canonical prefixes.
+            if canonical_prefix(value.as_bytes(), cwmp_namespace).is_some() {
+                continue;
+            }
+            start.push_attribute((
+                String::from_utf8_lossy(attribute.key.as_ref()).as_ref(),
+                value.as_ref(),
+            ));
+            continue;
+        }
+        let (attr_namespace, attr_local) = reader.resolve_attribute(attribute.key);
+        let attr_local = String::from_utf8_lossy(attr_local.as_ref()).into_owned();
+        let prefix = match attr_namespace {
+            ResolveResult::Bound(ns) => canonical_prefix(ns.into_inner(), cwmp_namespace),
+            _ => None,
+        };
+        let key = match prefix {
+            Some(prefix) => format!("{prefix}:{attr_local}"),
+            None => String::from_utf8_lossy(attribute.key.as_ref()).into_owned(),
+        };
+        let value = match attr_local.as_str() {
+            "type" | "arrayType" => rewrite_qname_value(reader, &value, cwmp_namespace),
+            _ => value,
+        };
+        start.push_attribute((key.as_str(), value.as_ref()));
+    }
+    Ok(start)
+}
+
+// `xs:string` -> `xsd:string`, `ns0:ParameterValueStruct[2]` -> `cwmp:ParameterValueStruct[2]`
+fn rewrite_qname_value<'v>(
+    reader: &NsReader<&[u8]>,
+    value: &Cow<'v, str>,
+    cwmp_namespace: &str,
+) -> Cow<'v, str> {
+    let Some((prefix, local)) = value.split_once(':') else {
+        return value.clone();
+    };
+    let qname = QName(value.as_bytes());
+    let canonical = match reader.resolver().resolve_prefix(qname.prefix(), false) {
+        ResolveResult::Bound(ns) => canonical_prefix(ns.into_inner(), cwmp_namespace),
+        // Many CPEs use `xsd:` without declaring it, keep the value as sent.
+        _ => None,
+    };
+    match canonical {
+        Some(canonical) if canonical != prefix => Cow::Owned(format!("{canonical}:{local}")),
+        _ => value.clone(),
+    }
+}
+
+// Prefix an unqualified element name as it must appear on the wire, given its parent.
+fn qualified_name(parent: Option<&str>, name: &str) -> Option<String> {
+    if name.contains(':') {
+        return None;
+    }
+    let prefix = match (parent, name) {
+        (None, _) => ENV_NP,
+        (Some(parent), "Header" | "Body") if parent.ends_with("Envelope") => ENV_NP,
+        (Some("soap-env:Body"), "Fault") => ENV_NP,
+        (Some("soap-env:Header" | "soap-env:Body" | "detail"), _) => CWMP_NP,
+        _ => return None,
+    };
+    Some(format!("{prefix}:{name}"))
+}
+
+/// Qualify the structural elements of a serialized envelope: `soap-env:` for
+/// Envelope, Header, Body and Fault, `cwmp:` for header fields and RPC elements.
+pub fn qualify(xml: &str) -> Result<String, String> {
+    let mut reader = Reader::from_str(xml);
+    let mut writer = Writer::new(Vec::new());
+    let mut open: Vec<String> = Vec::new();
+
+    loop {
+        let event = match reader.read_event().map_err(|err| err.to_string())? {
+            Event::Start(element) => {
+                let start = qualify_element(&element, open.last().map(String::as_str));
+                open.push(String::from_utf8_lossy(start.name().as_ref()).into_owned());
+                Event::Start(start)
+            }
+            Event::Empty(element) => {
+                Event::Empty(qualify_element(&element, open.last().map(String::as_str)))
+            }
+            Event::End(_) => {
+                let name = open.pop().ok_or("unbalanced end tag")?;
+                Event::End(BytesEnd::new(name))
+            }
+            Event::Eof => break,
+            other => other,
+        };
+        writer.write_event(event).map_err(|err| err.to_string())?;
+    }
+    String::from_utf8(writer.into_inner()).map_err(|err| err.to_string())
+}
+
+// yaserde declares the fixed namespaces of the structs it serializes besides the
+// ones the envelope carries: the envelope's own declaration wins on the root, which
+// holds the negotiated CWMP namespace, and canonical prefixes are not declared again
+// below it.
+fn qualify_element(element: &BytesStart<'_>, parent: Option<&str>) -> BytesStart<'static> {
+    let name = String::from_utf8_lossy(element.name().as_ref()).into_owned();
+    let mut start = BytesStart::new(qualified_name(parent, &name).unwrap_or(name));
+    let attributes: Vec<Attribute<'_>> =
+        element.attributes().with_checks(false).flatten().collect();
+    for (i, attribute) in attributes.iter().enumerate() {
+        if let Some(PrefixDeclaration::Named(prefix)) = attribute.key.as_namespace_binding() {
+            let redeclared = match parent {
+                None => attributes[i + 1..]
+                    .iter()
+                    .any(|later| later.key == attribute.key),
+                Some(_) => [ENV_NP, ENC_NP, XSD_NP, XSI_NP, CWMP_NP]
+                    .iter()
+                    .any(|canonical| canonical.as_bytes() == prefix),
+            };
+            if redeclared {
+                continue;
+            }
+        }
+        start.push_attribute(attribute.clone());
+    }
+    start
+}
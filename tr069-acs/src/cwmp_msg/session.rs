@@ -1,149 +1,874 @@
-use std::sync::OnceLock;
-use tokio;
-use tracing::{info, level_filters::LevelFilter};
+use sha2::{Digest, Sha256};
+use std::collections::{hash_map::RandomState, HashMap, VecDeque};
+use std::fmt;
+use std::hash::BuildHasher;
+use std::net::SocketAddr;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 
 use crate::{
-    startup::run,
-    telemetry::{get_subscriber, init_subscriber},
+    cwmp_msg::{
+        consts::{CWMP_VERSIONS, SOAP_CWMP_NP},
+        AcsFaultCode, CWMPMsg, CwmpFault, Envelope, EnvelopeBuilder, EventCode, InformResponse,
+    },
+    device::DeviceId,
 };
-use axum::{
-    body::{Body, Bytes},
-    extract::Request,
-    http::StatusCode,
-    middleware::{self, Next},
-    response::{IntoResponse, Response},
-    routing::post,
-    Router,
-};
-use http_body_util::BodyExt;
 
-static TRACING: OnceLock<()> = OnceLock::new();
+// A CWMP session as seen by the ACS (TR-069 Amendment 6, section 3.7.1):
+//
+//   CPE                                ACS
+//    | --- Inform ----------------------> |   AwaitingInform
+//    | <-- InformResponse --------------- |
+//    | --- CPE requests (TransferComplete, ...) -> CpeRequests
+//    | <-- matching responses ----------- |
+//    | --- empty POST ------------------> |
+//    | <-- ACS request ------------------ |   AcsRequests
+//    | --- response --------------------> |
+//    | <-- next ACS request / HTTP 204 -- |   Closed
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SessionState {
+    AwaitingInform,
+    CpeRequests,
+    AcsRequests,
+    Closed,
+}
 
-fn spawn_log() {
-    if TRACING.get().is_none() {
-        let _tracing = TRACING.get_or_init(|| {
-            let test_sub = get_subscriber("tr069-server-test".into(), LevelFilter::INFO.into());
-            init_subscriber(test_sub);
-        });
+/// What the HTTP layer should send back for one POST of the session.
+#[derive(Debug)]
+pub enum SessionReply {
+    Message(Envelope),
+    /// A SOAP Fault answering a CPE request, sent with HTTP 500.
+    Fault(Envelope),
+    /// HTTP 204 No Content, the ACS has nothing left to send and the session ends.
+    NoContent,
+}
+
+#[derive(Debug)]
+pub enum SessionError {
+    /// The first message of a session was not an Inform.
+    InformExpected,
+    /// The CPE sent a message that is not valid in the current state.
+    Unexpected { state: SessionState, msg: String },
+    /// The session already ended with HTTP 204.
+    Closed,
+}
+
+impl fmt::Display for SessionError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SessionError::InformExpected => write!(f, "session must start with an Inform"),
+            SessionError::Unexpected { state, msg } => {
+                write!(f, "unexpected {msg} in session state {state:?}")
+            }
+            SessionError::Closed => write!(f, "session is already closed"),
+        }
+    }
+}
+
+impl std::error::Error for SessionError {}
+
+impl SessionError {
+    /// ACS fault sent back to the CPE before the session is dropped.
+    pub fn fault(&self) -> CwmpFault {
+        let code = match self {
+            SessionError::InformExpected => AcsFaultCode::RequestDenied,
+            SessionError::Unexpected { .. } => AcsFaultCode::MethodNotSupported,
+            SessionError::Closed => AcsFaultCode::RetryRequest,
+        };
+        CwmpFault::acs(code).with_message(self.to_string())
     }
 }
 
-// use http_body_util::BodyExt;
-pub async fn cwmp_session_handle(msg: String) {
-    info!("{}", msg);
+/// Per-CPE session state, kept by the HTTP layer across the POSTs of one session.
+#[derive(Debug)]
+pub struct CwmpSession {
+    state: SessionState,
+    /// ACS initiated requests waiting to be sent once the CPE is done, with the
+    /// task each one carries out.
+    pending: VecDeque<(u64, CWMPMsg)>,
+    /// `cwmp:ID` of the ACS request the CPE is currently answering.
+    in_flight: Option<String>,
+    in_flight_task: Option<u64>,
+    next_id: u32,
+    /// CWMP namespace of the CPE's Inform, echoed in every envelope the ACS sends.
+    cwmp_namespace: String,
+    /// Version sent in `UseCWMPVersion` when the CPE listed `SupportedCWMPVersions`.
+    cwmp_version: Option<&'static str>,
+    /// Ask the CPE to hold its own requests while ACS requests are queued.
+    hold_cpe_requests: bool,
+    /// The CPE sent NoMoreRequests: it has no request of its own left, so it is
+    /// not asked to hold any and a further CPE request ends the session.
+    no_more_cpe_requests: bool,
+    timeout: Duration,
+    last_activity: Instant,
+    /// The CPE, known once its Inform has been handled.
+    device_id: Option<DeviceId>,
 }
 
-async fn contruct_test_acs() -> u16 {
-    // let app = Router::new()
-    //     .route("/", post(cwmp_session_handle))
-    //     // .route("/", post(cwmp_session_handle))
-    //     .layer(middleware::from_fn(print_request_response));
-    //
-    // // run it
-    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
-    let port = listener.local_addr().unwrap().port();
-    info!("listening on {}", port);
-    let server = run(listener);
-    tokio::spawn(server);
-    port
+/// Session timeout when the CPE's Inform has no SessionTimeout header (section 3.4.5).
+pub const DEFAULT_SESSION_TIMEOUT: Duration = Duration::from_secs(30);
+
+impl Default for CwmpSession {
+    fn default() -> Self {
+        Self {
+            state: SessionState::AwaitingInform,
+            pending: VecDeque::new(),
+            in_flight: None,
+            in_flight_task: None,
+            next_id: 1,
+            cwmp_namespace: String::from(SOAP_CWMP_NP),
+            cwmp_version: None,
+            hold_cpe_requests: false,
+            no_more_cpe_requests: false,
+            timeout: DEFAULT_SESSION_TIMEOUT,
+            last_activity: Instant::now(),
+            device_id: None,
+        }
+    }
+}
+
+// Highest version both sides support, versions the ACS does not know are ignored.
+fn negotiate_version(supported: &[&str]) -> Option<(&'static str, &'static str)> {
+    CWMP_VERSIONS
+        .iter()
+        .rev()
+        .find(|(version, _)| supported.contains(version))
+        .copied()
 }
 
-pub async fn print_request_response(
-    req: Request,
-    next: Next,
-) -> Result<impl IntoResponse, (StatusCode, String)> {
-    let (parts, body) = req.into_parts();
-    let bytes = buffer_and_print("request", body).await?;
-    let req = Request::from_parts(parts, Body::from(bytes));
+impl CwmpSession {
+    /// A session waiting `timeout` for CPEs whose Inform has no SessionTimeout header.
+    pub fn with_timeout(timeout: Duration) -> Self {
+        Self {
+            timeout,
+            ..Self::default()
+        }
+    }
+
+    pub fn state(&self) -> SessionState {
+        self.state
+    }
+
+    pub fn is_closed(&self) -> bool {
+        self.state == SessionState::Closed
+    }
+
+    pub fn device_id(&self) -> Option<&DeviceId> {
+        self.device_id.as_ref()
+    }
+
+    /// True once the CPE has been silent for longer than its session timeout.
+    pub fn is_expired(&self) -> bool {
+        self.last_activity.elapsed() > self.timeout
+    }
+
+    /// Send `HoldRequests` so the CPE moves on to the ACS requests as soon as possible.
+    pub fn hold_cpe_requests(&mut self, hold: bool) {
+        self.hold_cpe_requests = hold;
+    }
 
-    let res = next.run(req).await;
+    /// Queue the request of task `task_id`, sent after the CPE signals it has
+    /// nothing more to send, see `in_flight_task`.
+    pub fn enqueue_task(&mut self, task_id: u64, msg: CWMPMsg) {
+        self.pending.push_back((task_id, msg));
+    }
 
-    let (parts, body) = res.into_parts();
-    let bytes = buffer_and_print("response", body).await?;
-    let res = Response::from_parts(parts, Body::from(bytes));
+    /// Task whose request the CPE is currently answering.
+    pub fn in_flight_task(&self) -> Option<u64> {
+        self.in_flight_task
+    }
 
-    info!("Print request in in middleware response {:?}", res);
+    /// Drive the state machine with one HTTP POST. `None` is an empty POST.
+    pub fn handle(&mut self, envelope: Option<Envelope>) -> Result<SessionReply, SessionError> {
+        self.last_activity = Instant::now();
+        match (self.state, envelope) {
+            (SessionState::Closed, _) => Err(SessionError::Closed),
+            (SessionState::AwaitingInform, Some(envelope)) => self.handle_inform(envelope),
+            (SessionState::AwaitingInform, None) => Err(SessionError::InformExpected),
+            (SessionState::CpeRequests, Some(envelope)) => self.handle_cpe_request(envelope),
+            (SessionState::CpeRequests, None) => {
+                self.state = SessionState::AcsRequests;
+                Ok(self.next_acs_request())
+            }
+            (SessionState::AcsRequests, Some(envelope)) => self.handle_cpe_response(envelope),
+            (SessionState::AcsRequests, None) => Ok(self.close()),
+        }
+    }
 
-    Ok(res)
+    fn handle_inform(&mut self, envelope: Envelope) -> Result<SessionReply, SessionError> {
+        let id = envelope.id().map(String::from);
+        self.cwmp_namespace = envelope.cwmp_namespace().to_string();
+        if let Some(timeout) = envelope.session_timeout() {
+            self.timeout = Duration::from_secs(timeout.into());
+        }
+        let negotiated = negotiate_version(&envelope.supported_cwmp_versions());
+        self.no_more_cpe_requests = envelope.no_more_requests();
+        match envelope.into_msg() {
+            Some(CWMPMsg::Inform(inform)) => {
+                self.device_id = DeviceId::from_device_id_struct(inform.device_id());
+                if inform.has_event(&EventCode::Bootstrap) {
+                    tracing::info!("CPE {:?} bootstrapped", inform.device_id());
+                }
+                self.state = SessionState::CpeRequests;
+                let reply = CWMPMsg::InformResponse(InformResponse::default());
+                // The InformResponse still uses the Inform's namespace, the rest of
+                // the session uses the namespace of the negotiated version.
+                let mut builder = self.builder(reply, id);
+                if let Some((version, namespace)) = negotiated {
+                    builder = builder.use_cwmp_version(version);
+                    self.cwmp_version = Some(version);
+                    self.cwmp_namespace = namespace.to_string();
+                }
+                Ok(SessionReply::Message(builder.build()))
+            }
+            _ => Err(SessionError::InformExpected),
+        }
+    }
+
+    fn handle_cpe_request(&mut self, envelope: Envelope) -> Result<SessionReply, SessionError> {
+        if self.no_more_cpe_requests {
+            return Err(self.unexpected(envelope.into_msg()));
+        }
+        let id = envelope.id().map(String::from);
+        self.no_more_cpe_requests = envelope.no_more_requests();
+        let response = match envelope.into_msg() {
+            Some(CWMPMsg::TransferComplete(_)) => CWMPMsg::TransferCompleteResponse,
+            Some(CWMPMsg::AutonomousTransferComplete(_)) => {
+                CWMPMsg::AutonomousTransferCompleteResponse
+            }
+            Some(CWMPMsg::DUStateChangeComplete) => CWMPMsg::DUStateChangeCompleteResponse,
+            Some(CWMPMsg::RequestDownload) => CWMPMsg::RequestDownloadResponse,
+            other => {
+                // The session goes on, the CPE may send its next request or an empty POST.
+                tracing::warn!("Unsupported CPE request {:?}", other);
+                let fault = CwmpFault::acs(AcsFaultCode::MethodNotSupported);
+                let reply = CWMPMsg::Fault(fault.to_soap_fault());
+                return Ok(SessionReply::Fault(self.builder(reply, id).build()));
+            }
+        };
+        Ok(SessionReply::Message(self.builder(response, id).build()))
+    }
+
+    fn handle_cpe_response(&mut self, envelope: Envelope) -> Result<SessionReply, SessionError> {
+        if self.in_flight.is_none() {
+            return Err(self.unexpected(envelope.into_msg()));
+        }
+        if envelope.id() != self.in_flight.as_deref() {
+            tracing::warn!(
+                "response ID {:?} does not match request ID {:?}",
+                envelope.id(),
+                self.in_flight
+            );
+        }
+        match envelope.msg() {
+            Some(CWMPMsg::Fault(fault)) => {
+                tracing::warn!("CPE rejected request: {}", CwmpFault::from(fault))
+            }
+            msg => tracing::info!("CPE answered {:?}", msg),
+        }
+        self.in_flight = None;
+        self.in_flight_task = None;
+        Ok(self.next_acs_request())
+    }
+
+    fn next_acs_request(&mut self) -> SessionReply {
+        match self.pending.pop_front() {
+            Some((task, msg)) => {
+                let id = self.next_id.to_string();
+                self.next_id += 1;
+                let request = self.builder(msg, Some(id.clone())).build();
+                self.in_flight = Some(id);
+                self.in_flight_task = Some(task);
+                SessionReply::Message(request)
+            }
+            None => self.close(),
+        }
+    }
+
+    // HoldRequests only matters while the CPE may still send requests.
+    fn builder(&self, msg: CWMPMsg, id: Option<String>) -> EnvelopeBuilder {
+        let mut builder = Envelope::builder(msg).cwmp_namespace(self.cwmp_namespace.clone());
+        if let Some(id) = id {
+            builder = builder.id(id);
+        }
+        if self.state == SessionState::CpeRequests
+            && self.hold_cpe_requests
+            && !self.no_more_cpe_requests
+            && !self.pending.is_empty()
+        {
+            builder = builder.hold_requests(true);
+        }
+        builder
+    }
+
+    fn close(&mut self) -> SessionReply {
+        self.state = SessionState::Closed;
+        SessionReply::NoContent
+    }
+
+    fn unexpected(&self, msg: Option<CWMPMsg>) -> SessionError {
+        SessionError::Unexpected {
+            state: self.state,
+            msg: format!("{msg:?}"),
+        }
+    }
+}
+
+/// Cookie the ACS sets on the first response of a session.
+pub const SESSION_COOKIE: &str = "cwmp-session";
+/// Open sessions kept at most, the longest idle one makes room for a new one.
+pub const DEFAULT_MAX_SESSIONS: usize = 10_000;
+
+/// Open sessions. A POST belongs to the session named by its session cookie, or
+/// for CPEs that ignore cookies, to the session last seen on its TCP connection.
+/// Sessions are dropped once closed or idle past their timeout.
+#[derive(Debug)]
+pub struct SessionTable {
+    sessions: HashMap<String, CwmpSession>,
+    connections: HashMap<SocketAddr, String>,
+    capacity: usize,
+    /// Makes session keys unpredictable, they are as good as a password.
+    secret: u64,
+    issued: u64,
 }
 
-async fn buffer_and_print<B>(direction: &str, body: B) -> Result<Bytes, (StatusCode, String)>
-where
-    B: axum::body::HttpBody<Data = Bytes>,
-    B::Error: std::fmt::Display,
-{
-    let bytes = match body.collect().await {
-        Ok(collected) => collected.to_bytes(),
-        Err(err) => {
-            return Err((
-                StatusCode::BAD_REQUEST,
-                format!("failed to read {direction} body: {err}"),
-            ));
+impl Default for SessionTable {
+    fn default() -> Self {
+        Self::with_capacity(DEFAULT_MAX_SESSIONS)
+    }
+}
+
+impl SessionTable {
+    pub fn with_capacity(capacity: usize) -> Self {
+        Self {
+            sessions: HashMap::new(),
+            connections: HashMap::new(),
+            capacity: capacity.max(1),
+            secret: RandomState::new().hash_one(std::process::id()),
+            issued: 0,
         }
-    };
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.sessions.is_empty()
+    }
+
+    /// Key of the session a POST from `peer` with session cookie `cookie` belongs
+    /// to. A CPE that reconnected keeps its session on the new connection.
+    pub fn find(&mut self, cookie: Option<&str>, peer: SocketAddr) -> Option<String> {
+        self.remove_expired();
+        let key = match cookie.filter(|cookie| self.sessions.contains_key(*cookie)) {
+            Some(cookie) => cookie.to_string(),
+            None => self.connections.get(&peer)?.clone(),
+        };
+        self.connections.insert(peer, key.clone());
+        Some(key)
+    }
+
+    pub fn get_mut(&mut self, key: &str) -> Option<&mut CwmpSession> {
+        self.sessions.get_mut(key)
+    }
 
-    if let Ok(body) = std::str::from_utf8(&bytes) {
-        tracing::debug!("{direction} body = {body:?}");
+    /// Track `session`, opened by `peer`, and return its key.
+    pub fn insert(&mut self, peer: SocketAddr, session: CwmpSession) -> String {
+        self.remove_expired();
+        while self.sessions.len() >= self.capacity {
+            let idlest = self
+                .sessions
+                .iter()
+                .min_by_key(|(_, session)| session.last_activity)
+                .map(|(key, _)| key.clone());
+            if let Some(key) = idlest {
+                tracing::warn!("Session table full, dropping the longest idle session");
+                self.remove(&key);
+            }
+        }
+        let key = self.new_key();
+        self.connections.insert(peer, key.clone());
+        self.sessions.insert(key.clone(), session);
+        key
     }
 
-    Ok(bytes)
+    pub fn remove(&mut self, key: &str) -> Option<CwmpSession> {
+        self.connections.retain(|_, session| session != key);
+        self.sessions.remove(key)
+    }
+
+    /// Drop sessions whose CPE stayed silent past their timeout, returning them.
+    pub fn remove_expired(&mut self) -> Vec<CwmpSession> {
+        let expired: Vec<String> = self
+            .sessions
+            .iter()
+            .filter(|(_, session)| session.is_expired())
+            .map(|(key, _)| key.clone())
+            .collect();
+        expired.iter().filter_map(|key| self.remove(key)).collect()
+    }
+
+    /// Remove every session, e.g. to abort them at shutdown.
+    pub fn drain(&mut self) -> Vec<CwmpSession> {
+        self.connections.clear();
+        self.sessions.drain().map(|(_, session)| session).collect()
+    }
+
+    fn new_key(&mut self) -> String {
+        self.issued += 1;
+        let nanos = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_nanos();
+        Sha256::digest(format!("{}:{}:{nanos}", self.secret, self.issued))[..16]
+            .iter()
+            .map(|byte| format!("{byte:02x}"))
+            .collect()
+    }
 }
 
-#[tokio::test]
-async fn test_send_inform_message() {
-    spawn_log();
-    let port = contruct_test_acs().await;
-    const INFORM_MESSAGE: &str = r#"
-                                    <soap:Envelope xmlns:soap="http://schemas.xmlsoap.org/soap/envelope/"
-                                                   xmlns:cwmp="urn:dslforum-org:cwmp-1-0">
-                                      <soap:Header>
-                                        <cwmp:ID soap:mustUnderstand="true">1</cwmp:ID>
-                                      </soap:Header>
-                                      <soap:Body>
-                                        <cwmp:Inform>
-                                          <DeviceId>
-                                            <Manufacturer>ExampleCo</Manufacturer>
-                                            <OUI>001A2B</OUI>
-                                            <ProductClass>RouterX</ProductClass>
-                                            <SerialNumber>123456789</SerialNumber>
-                                          </DeviceId>
-                                          <Event>
-                                            <EventStruct>
-                                              <EventCode>2 PERIODIC</EventCode>
-                                              <CommandKey></CommandKey>
-                                            </EventStruct>
-                                          </Event>
-                                          <MaxEnvelopes>1</MaxEnvelopes>
-                                          <CurrentTime>2025-10-07T10:00:00Z</CurrentTime>
-                                          <RetryCount>0</RetryCount>
-                                          <ParameterList soap:arrayType="cwmp:ParameterValueStruct[1]">
-                                            <ParameterValueStruct>
-                                              <Name>Device.ManagementServer.URL</Name>
-                                              <Value xsi:type="xsd:string"
-                                                     xmlns:xsd="http://www.w3.org/2001/XMLSchema"
-                                                     xmlns:xsi="http://www.w3.org/2001/XMLSchema-instance">
-                                                http://acs.example.com/acs
-                                              </Value>
-                                            </ParameterValueStruct>
-                                          </ParameterList>
-                                        </cwmp:Inform>
-                                      </soap:Body>
-                                    </soap:Envelope>
-                                    "#;
-    //ACS Endpoint
-    let url = format!("http://127.0.0.1:{}", port);
-
-    // Build http client
-    let client = reqwest::Client::new();
-
-    let res = client
-        .post(url)
-        .header("Content-Type", "text/xml; charset=utf-8")
-        .header("SOAPAction", "\"\"")
-        .body(INFORM_MESSAGE)
-        .send()
-        .await
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        auth::CpeAuthenticator,
+        connection_request::Credentials,
+        cwmp_msg::boot_inform,
+        startup::{run, run_tls, AcsState},
+        telemetry::{get_subscriber, init_subscriber},
+        tls::ReloadableTlsConfig,
+    };
+    use axum::http::StatusCode;
+    use std::sync::OnceLock;
+    use tracing::{info, level_filters::LevelFilter};
+
+    static TRACING: OnceLock<()> = OnceLock::new();
+
+    fn spawn_log() {
+        if TRACING.get().is_none() {
+            let _tracing = TRACING.get_or_init(|| {
+                let test_sub = get_subscriber("tr069-server-test".into(), LevelFilter::INFO.into());
+                init_subscriber(test_sub);
+            });
+        }
+    }
+
+    async fn contruct_test_acs() -> u16 {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        info!("listening on {}", port);
+        let server = run(listener, AcsState::default());
+        tokio::spawn(server);
+        port
+    }
+
+    #[tokio::test]
+    async fn test_send_inform_message() {
+        spawn_log();
+        let port = contruct_test_acs().await;
+        let inform = boot_inform(
+            "123456789",
+            &[(
+                "Device.ManagementServer.URL",
+                "xsd:string",
+                "http://acs.example.com/acs",
+            )],
+        )
+        .replace("1 BOOT", "2 PERIODIC");
+        //ACS Endpoint
+        let url = format!("http://127.0.0.1:{}", port);
+
+        // Build http client
+        let client = reqwest::Client::new();
+
+        let res = client
+            .post(&url)
+            .header("Content-Type", "text/xml; charset=utf-8")
+            .header("SOAPAction", "\"\"")
+            .body(inform)
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+        let body = res.text().await.unwrap();
+        assert!(body.contains("InformResponse"), "{body}");
+
+        // Nothing is queued for this CPE, the empty POST ends the session.
+        let res = client.post(&url).send().await.unwrap();
+        assert_eq!(res.status(), StatusCode::NO_CONTENT);
+    }
+
+    // Whether the ACS asked the CPE to hold its own requests.
+    fn holds_requests(envelope: &Envelope) -> bool {
+        envelope.header_field(|h| h.hold_requests.as_ref()) == Some("1")
+    }
+
+    #[tokio::test]
+    async fn test_inform_requires_authentication() {
+        let inform = boot_inform("123456789", &[]);
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let auth = CpeAuthenticator::default().with_fallback(Credentials {
+            username: String::from("fleet"),
+            password: String::from("shared"),
+        });
+        tokio::spawn(run(listener, AcsState::default().with_cpe_auth(auth)));
+
+        let client = reqwest::Client::new();
+        let res = client.post(&url).body(inform.clone()).send().await.unwrap();
+        assert_eq!(res.status(), StatusCode::UNAUTHORIZED);
+        let challenge = res.headers()[axum::http::header::WWW_AUTHENTICATE]
+            .to_str()
+            .unwrap();
+        assert!(challenge.starts_with("Digest"), "{challenge}");
+
+        let res = client
+            .post(&url)
+            .basic_auth("fleet", Some("wrong"))
+            .body(inform.clone())
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(res.status(), StatusCode::UNAUTHORIZED);
+
+        let res = client
+            .post(&url)
+            .basic_auth("fleet", Some("shared"))
+            .body(inform.clone())
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+        assert!(res.text().await.unwrap().contains("InformResponse"));
+
+        // The session is authenticated, the empty POST needs no credentials.
+        let res = client.post(&url).send().await.unwrap();
+        assert_eq!(res.status(), StatusCode::NO_CONTENT);
+    }
+
+    #[tokio::test]
+    async fn test_inform_over_mutual_tls() {
+        use rustls::pki_types::{pem::PemObject, CertificateDer, PrivateKeyDer, ServerName};
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        let dir = std::env::temp_dir().join(format!("tr069-acs-mtls-{}", std::process::id()));
+        let settings = crate::tls::tests::write_certificates(&dir);
+        let tls = ReloadableTlsConfig::load(settings).unwrap();
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        // HTTP authentication would fail, the certificate identifies the CPE instead.
+        let state = AcsState::default().with_cpe_auth(CpeAuthenticator::default());
+        tokio::spawn(run_tls(listener, state, tls));
+
+        let mut roots = rustls::RootCertStore::empty();
+        roots
+            .add(CertificateDer::from_pem_file(dir.join("ca.pem")).unwrap())
+            .unwrap();
+        let chain = vec![CertificateDer::from_pem_file(dir.join("client.pem")).unwrap()];
+        let key = PrivateKeyDer::from_pem_file(dir.join("client.key")).unwrap();
+        let config = rustls::ClientConfig::builder_with_provider(std::sync::Arc::new(
+            rustls::crypto::ring::default_provider(),
+        ))
+        .with_safe_default_protocol_versions()
+        .unwrap()
+        .with_root_certificates(roots)
+        .with_client_auth_cert(chain, key)
         .unwrap();
+        let connector = tokio_rustls::TlsConnector::from(std::sync::Arc::new(config));
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        // One connection per Inform; returns the start of the response.
+        let post = |body: String| {
+            let connector = connector.clone();
+            async move {
+                let socket = tokio::net::TcpStream::connect(addr).await.unwrap();
+                let server_name = ServerName::try_from("localhost").unwrap();
+                let mut stream = connector.connect(server_name, socket).await.unwrap();
+                let request = format!(
+                "POST / HTTP/1.1\r\nHost: localhost\r\nContent-Type: text/xml\r\nContent-Length: {}\r\n\r\n{body}",
+                body.len()
+            );
+                stream.write_all(request.as_bytes()).await.unwrap();
+                let mut response = vec![0; 4096];
+                let len = stream.read(&mut response).await.unwrap();
+                String::from_utf8_lossy(&response[..len]).into_owned()
+            }
+        };
+
+        let response = post(boot_inform("123456789", &[])).await;
+        assert!(response.starts_with("HTTP/1.1 200"), "{response}");
+        let response = post(boot_inform("987654321", &[])).await;
+        assert!(response.starts_with("HTTP/1.1 403"), "{response}");
+    }
+
+    #[tokio::test]
+    async fn test_session_resumes_with_cookie() {
+        use axum::http::header::{COOKIE, SET_COOKIE};
 
-    // assert!();
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        tokio::spawn(run(listener, AcsState::default()));
+
+        let res = reqwest::Client::new()
+            .post(&url)
+            .body(boot_inform("123456789", &[]))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+        let cookie = res.headers()[SET_COOKIE].to_str().unwrap();
+        let cookie = cookie.split(';').next().unwrap().to_string();
+        assert!(cookie.starts_with(SESSION_COOKIE), "{cookie}");
+
+        // A new connection without the cookie is not part of the session.
+        let res = reqwest::Client::new().post(&url).send().await.unwrap();
+        assert_eq!(res.status(), StatusCode::INTERNAL_SERVER_ERROR);
+
+        // With it, the empty POST ends the session and clears the cookie.
+        let res = reqwest::Client::new()
+            .post(&url)
+            .header(COOKIE, &cookie)
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(res.status(), StatusCode::NO_CONTENT);
+        let cleared = res.headers()[SET_COOKIE].to_str().unwrap();
+        assert!(cleared.contains("Max-Age=0"), "{cleared}");
+    }
+
+    #[tokio::test]
+    async fn test_pending_tasks_hold_cpe_requests() {
+        use crate::task::Operation;
+        use axum::http::header::{COOKIE, SET_COOKIE};
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let state = AcsState::default().with_hold_requests(true);
+        tokio::spawn(run(listener, state.clone()));
+        let client = reqwest::Client::new();
+        let inform = || async {
+            let res = client
+                .post(&url)
+                .body(boot_inform("123456789", &[]))
+                .send()
+                .await
+                .unwrap();
+            let cookie = res.headers()[SET_COOKIE].to_str().unwrap();
+            let cookie = cookie.split(';').next().unwrap().to_string();
+            let reply = Envelope::parse(&res.text().await.unwrap()).unwrap();
+            (reply, cookie)
+        };
+
+        // Nothing to hold for without tasks.
+        let (reply, cookie) = inform().await;
+        assert!(!holds_requests(&reply));
+        let res = client
+            .post(&url)
+            .header(COOKIE, &cookie)
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(res.status(), StatusCode::NO_CONTENT);
+
+        let id = "001A2B-RouterX-123456789".parse().unwrap();
+        let reboot = Operation::Reboot {
+            command_key: String::from("hold"),
+        };
+        state.tasks.enqueue(id, reboot, None).unwrap();
+        let (reply, _) = inform().await;
+        assert!(holds_requests(&reply));
+    }
+
+    #[tokio::test]
+    async fn test_drain_lets_open_sessions_finish() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let state = AcsState::default();
+        tokio::spawn(run(listener, state.clone()));
+
+        let open = reqwest::Client::new();
+        let res = open
+            .post(&url)
+            .body(boot_inform("123456789", &[]))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+
+        let drain = tokio::spawn(state.drain(Duration::from_secs(5)));
+        let res = reqwest::Client::new()
+            .post(&url)
+            .body(boot_inform("987654321", &[]))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(res.status(), StatusCode::SERVICE_UNAVAILABLE);
+        assert!(res.headers().contains_key(axum::http::header::RETRY_AFTER));
+
+        let res = open.post(&url).send().await.unwrap();
+        assert_eq!(res.status(), StatusCode::NO_CONTENT);
+        assert_eq!(drain.await.unwrap(), 0);
+
+        // Past the deadline, the session is aborted.
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let state = AcsState::default();
+        tokio::spawn(run(listener, state.clone()));
+        let res = open
+            .post(&url)
+            .body(boot_inform("123456789", &[]))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+        assert_eq!(state.drain(Duration::from_millis(200)).await, 1);
+    }
+
+    #[tokio::test]
+    async fn test_session_publishes_events() {
+        use crate::events::{EventKind, SessionEnd};
+        use axum::http::header::{COOKIE, SET_COOKIE};
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let state = AcsState::default();
+        let mut events = state.events.subscribe();
+        tokio::spawn(run(listener, state.clone()));
+
+        let client = reqwest::Client::new();
+        let res = client
+            .post(&url)
+            .body(boot_inform("123456789", &[]))
+            .send()
+            .await
+            .unwrap();
+        let cookie = res.headers()[SET_COOKIE].to_str().unwrap();
+        let cookie = cookie.split(';').next().unwrap().to_string();
+        let res = client
+            .post(&url)
+            .header(COOKIE, &cookie)
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(res.status(), StatusCode::NO_CONTENT);
+
+        let informed = events.recv().await.unwrap();
+        assert_eq!(informed.device_id.to_string(), "001A2B-RouterX-123456789");
+        assert_eq!(
+            informed.kind,
+            EventKind::DeviceInformed {
+                events: vec![String::from("1 BOOT")]
+            }
+        );
+        assert_eq!(events.recv().await.unwrap().kind, EventKind::SessionStarted);
+        assert_eq!(
+            events.recv().await.unwrap().kind,
+            EventKind::SessionEnded {
+                reason: SessionEnd::Closed
+            }
+        );
+        assert!(events.try_recv().is_err());
+    }
+
+    #[test]
+    fn test_session_table() {
+        let (first, second): (SocketAddr, SocketAddr) = (
+            "192.0.2.1:4000".parse().unwrap(),
+            "192.0.2.1:4001".parse().unwrap(),
+        );
+        let mut table = SessionTable::with_capacity(2);
+        let key = table.insert(first, CwmpSession::default());
+        assert_eq!(table.find(None, first).as_ref(), Some(&key));
+        assert_eq!(table.find(Some("unknown"), second), None);
+
+        // The CPE reconnected, the cookie moves the session to the new connection.
+        assert_eq!(table.find(Some(&key), second).as_ref(), Some(&key));
+        assert_eq!(table.find(None, second).as_ref(), Some(&key));
+
+        // Full, the longest idle session makes room.
+        table.get_mut(&key).unwrap().last_activity -= Duration::from_secs(1);
+        let other = table.insert("192.0.2.2:4000".parse().unwrap(), CwmpSession::default());
+        let third = table.insert("192.0.2.3:4000".parse().unwrap(), CwmpSession::default());
+        assert_eq!(table.sessions.len(), 2);
+        assert!(table.get_mut(&key).is_none());
+        assert_eq!(table.find(None, first), None);
+        assert_ne!(other, third);
+
+        table.remove(&other);
+        assert_eq!(table.find(None, "192.0.2.2:4000".parse().unwrap()), None);
+
+        let mut session = CwmpSession::with_timeout(Duration::ZERO);
+        session.last_activity -= Duration::from_millis(1);
+        let expired = table.insert(first, session);
+        assert_eq!(table.find(Some(&expired), first), None);
+        assert_eq!(table.sessions.len(), 1);
+    }
+
+    #[test]
+    fn test_session_negotiates_version_and_holds_requests() {
+        let inform = boot_inform("123456789", &[])
+            .replace("cwmp-1-0", "cwmp-1-2")
+            .replace(
+                "</cwmp:ID>",
+                "</cwmp:ID>
+                 <cwmp:SessionTimeout>45</cwmp:SessionTimeout>
+                 <cwmp:SupportedCWMPVersions>1.0,1.2,1.4,1.9</cwmp:SupportedCWMPVersions>",
+            );
+
+        let mut session = CwmpSession::default();
+        session.hold_cpe_requests(true);
+        session.enqueue_task(1, CWMPMsg::Reboot(crate::cwmp_msg::Reboot::new("reboot-1")));
+
+        let inform = Envelope::parse(&inform).unwrap();
+        let Ok(SessionReply::Message(reply)) = session.handle(Some(inform)) else {
+            panic!("expected an InformResponse");
+        };
+        assert_eq!(session.cwmp_version, Some("1.4"));
+        assert_eq!(session.timeout, Duration::from_secs(45));
+        // The InformResponse answers in the Inform's namespace.
+        assert_eq!(
+            reply.cwmp_namespace(),
+            crate::cwmp_msg::consts::SOAP_CWMP_1_2_NP
+        );
+        assert_eq!(
+            reply.header_field(|h| h.use_cwmp_version.as_ref()),
+            Some("1.4")
+        );
+        assert!(holds_requests(&reply));
+
+        let Ok(SessionReply::Message(request)) = session.handle(None) else {
+            panic!("expected the queued Reboot");
+        };
+        assert_eq!(
+            request.cwmp_namespace(),
+            crate::cwmp_msg::consts::SOAP_CWMP_1_4_NP
+        );
+        assert!(!holds_requests(&request));
+        assert!(matches!(request.msg(), Some(CWMPMsg::Reboot(_))));
+    }
+
+    #[test]
+    fn test_session_honors_no_more_requests() {
+        let inform = boot_inform("123456789", &[]).replace(
+            "</cwmp:ID>",
+            "</cwmp:ID><cwmp:NoMoreRequests>1</cwmp:NoMoreRequests>",
+        );
+        let mut session = CwmpSession::default();
+        session.hold_cpe_requests(true);
+        session.enqueue_task(1, CWMPMsg::Reboot(crate::cwmp_msg::Reboot::new("reboot-1")));
+
+        // Nothing left for the CPE to hold.
+        let Ok(SessionReply::Message(reply)) =
+            session.handle(Some(Envelope::parse(&inform).unwrap()))
+        else {
+            panic!("expected an InformResponse");
+        };
+        assert!(!holds_requests(&reply));
+
+        // A CPE request after NoMoreRequests ends the session.
+        let transfer = Envelope::new(CWMPMsg::TransferComplete(Default::default()));
+        assert!(matches!(
+            session.handle(Some(transfer)),
+            Err(SessionError::Unexpected {
+                state: SessionState::CpeRequests,
+                ..
+            })
+        ));
+    }
 }
@@ -0,0 +1,201 @@
+// Typed values of `anySimpleType` elements (TR-069 Amendment 6, Table 4).
+//
+// The `xsi:type` attribute names the XML Schema type of a Parameter value. Some CPEs
+// reject a SetParameterValues whose xsi:type differs from the Parameter's type, so
+// values are validated against the declared type in both directions.
+use super::{AnySimpleType, ParameterValueStruct};
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
+use chrono::{DateTime, NaiveDateTime, SecondsFormat, Utc};
+use std::fmt;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum XsdValue {
+    String(String),
+    Int(i32),
+    UnsignedInt(u32),
+    Long(i64),
+    UnsignedLong(u64),
+    Boolean(bool),
+    DateTime(DateTime<Utc>),
+    Base64(Vec<u8>),
+    HexBinary(Vec<u8>),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ValueError {
+    /// The element has no `xsi:type` attribute.
+    MissingType,
+    /// The `xsi:type` is not one of the types used by TR-069 data models.
+    UnknownType(String),
+    /// The text is not a valid lexical value of the declared type.
+    Invalid { xsi_type: String, value: String },
+    /// The value has a different type than the one the Parameter is declared with.
+    Mismatch { expected: String, found: String },
+}
+
+impl fmt::Display for ValueError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ValueError::MissingType => write!(f, "value has no xsi:type attribute"),
+            ValueError::UnknownType(xsi_type) => write!(f, "unsupported xsi:type {xsi_type:?}"),
+            ValueError::Invalid { xsi_type, value } => {
+                write!(f, "{value:?} is not a valid {xsi_type}")
+            }
+            ValueError::Mismatch { expected, found } => {
+                write!(f, "expected a value of type {expected}, found {found}")
+            }
+        }
+    }
+}
+
+impl std::error::Error for ValueError {}
+
+// The xsd prefix is whatever the sender bound to the XML Schema namespace, only the
+// local part of the type name matters.
+fn local_type(xsi_type: &str) -> &str {
+    xsi_type
+        .rsplit_once(':')
+        .map_or(xsi_type, |(_, local)| local)
+        .trim()
+}
+
+impl XsdValue {
+    /// Parse `text` as a value of `xsi_type`, e.g. `("xsd:unsignedInt", "300")`.
+    pub fn parse(xsi_type: &str, text: &str) -> Result<Self, ValueError> {
+        let invalid = || ValueError::Invalid {
+            xsi_type: xsi_type.to_string(),
+            value: text.to_string(),
+        };
+        let trimmed = text.trim();
+        let value = match local_type(xsi_type) {
+            // Strings keep their whitespace, CPEs treat them as case and space sensitive.
+            "string" => XsdValue::String(text.to_string()),
+            "int" => XsdValue::Int(trimmed.parse().map_err(|_| invalid())?),
+            "unsignedInt" => XsdValue::UnsignedInt(trimmed.parse().map_err(|_| invalid())?),
+            "long" => XsdValue::Long(trimmed.parse().map_err(|_| invalid())?),
+            "unsignedLong" => XsdValue::UnsignedLong(trimmed.parse().map_err(|_| invalid())?),
+            "boolean" => XsdValue::Boolean(match trimmed {
+                "1" | "true" => true,
+                "0" | "false" => false,
+                _ => return Err(invalid()),
+            }),
+            "dateTime" => XsdValue::DateTime(parse_date_time(trimmed).ok_or_else(invalid)?),
+            "base64" | "base64Binary" => {
+                XsdValue::Base64(BASE64.decode(trimmed).map_err(|_| invalid())?)
+            }
+            "hexBinary" => XsdValue::HexBinary(decode_hex(trimmed).ok_or_else(invalid)?),
+            _ => return Err(ValueError::UnknownType(xsi_type.to_string())),
+        };
+        Ok(value)
+    }
+
+    /// The `xsi:type` attribute value this value serializes with.
+    pub fn xsi_type(&self) -> &'static str {
+        match self {
+            XsdValue::String(_) => "xsd:string",
+            XsdValue::Int(_) => "xsd:int",
+            XsdValue::UnsignedInt(_) => "xsd:unsignedInt",
+            XsdValue::Long(_) => "xsd:long",
+            XsdValue::UnsignedLong(_) => "xsd:unsignedLong",
+            XsdValue::Boolean(_) => "xsd:boolean",
+            XsdValue::DateTime(_) => "xsd:dateTime",
+            XsdValue::Base64(_) => "xsd:base64",
+            XsdValue::HexBinary(_) => "xsd:hexBinary",
+        }
+    }
+
+    /// Check the value can be sent to a Parameter declared with `xsi_type`.
+    pub fn ensure_type(&self, xsi_type: &str) -> Result<(), ValueError> {
+        let expected = local_type(xsi_type);
+        let found = local_type(self.xsi_type());
+        let same = expected == found || (expected == "base64Binary" && found == "base64");
+        if same {
+            Ok(())
+        } else {
+            Err(ValueError::Mismatch {
+                expected: xsi_type.to_string(),
+                found: self.xsi_type().to_string(),
+            })
+        }
+    }
+}
+
+impl fmt::Display for XsdValue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            XsdValue::String(value) => f.write_str(value),
+            XsdValue::Int(value) => write!(f, "{value}"),
+            XsdValue::UnsignedInt(value) => write!(f, "{value}"),
+            XsdValue::Long(value) => write!(f, "{value}"),
+            XsdValue::UnsignedLong(value) => write!(f, "{value}"),
+            XsdValue::Boolean(value) => write!(f, "{value}"),
+            XsdValue::DateTime(value) => {
+                f.write_str(&value.to_rfc3339_opts(SecondsFormat::AutoSi, true))
+            }
+            XsdValue::Base64(bytes) => f.write_str(&BASE64.encode(bytes)),
+            XsdValue::HexBinary(bytes) => bytes.iter().try_for_each(|byte| write!(f, "{byte:02X}")),
+        }
+    }
+}
+
+// TR-069 dateTime values may omit the time zone, in which case they are UTC.
+fn parse_date_time(text: &str) -> Option<DateTime<Utc>> {
+    DateTime::parse_from_rfc3339(text)
+        .map(|date_time| date_time.with_timezone(&Utc))
+        .ok()
+        .or_else(|| {
+            NaiveDateTime::parse_from_str(text, "%Y-%m-%dT%H:%M:%S%.f")
+                .ok()
+                .map(|naive| naive.and_utc())
+        })
+}
+
+fn decode_hex(text: &str) -> Option<Vec<u8>> {
+    if !text.len().is_multiple_of(2) {
+        return None;
+    }
+    (0..text.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(text.get(i..i + 2)?, 16).ok())
+        .collect()
+}
+
+impl AnySimpleType {
+    /// The value parsed according to its `xsi:type` attribute.
+    pub fn typed(&self) -> Result<XsdValue, ValueError> {
+        let xsi_type = self.xsi_type.as_deref().ok_or(ValueError::MissingType)?;
+        XsdValue::parse(xsi_type, self.value.as_deref().unwrap_or_default())
+    }
+}
+
+impl From<&XsdValue> for AnySimpleType {
+    fn from(value: &XsdValue) -> Self {
+        Self {
+            xsi_type: Some(value.xsi_type().to_string()),
+            value: Some(value.to_string()),
+        }
+    }
+}
+
+impl ParameterValueStruct {
+    /// Parse `text` as `xsi_type` before building the struct, so invalid values never
+    /// reach the CPE.
+    pub fn checked(
+        name: impl Into<String>,
+        xsi_type: &str,
+        text: &str,
+    ) -> Result<Self, ValueError> {
+        let value = XsdValue::parse(xsi_type, text)?;
+        Ok(Self {
+            name: Some(name.into()),
+            value: Some(AnySimpleType {
+                xsi_type: Some(xsi_type.to_string()),
+                value: Some(value.to_string()),
+            }),
+        })
+    }
+
+    pub fn typed_value(&self) -> Result<XsdValue, ValueError> {
+        self.value.as_ref().ok_or(ValueError::MissingType)?.typed()
+    }
+}
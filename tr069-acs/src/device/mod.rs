@@ -0,0 +1,802 @@
+// Registry of the CPEs that Informed the ACS.
+//
+// A CPE is identified by the OUI, ProductClass and SerialNumber of the DeviceIdStruct
+// in its Inform (TR-069 Amendment 6, Table 35), formatted as
+// `OUI-ProductClass-SerialNumber` like the CPE's own URN based identifiers, with
+// '-' and '%' in the fields percent-encoded as in section 3.4.4. Every
+// Inform refreshes the entry; GetParameterValues, GetParameterNames and
+// GetParameterAttributes responses fill the parameter cache.
+//
+// The ManagementServer.Username/Password a CPE authenticates with, and its
+// STUNPassword, are kept apart from that cache: whatever the CPE reports is not
+// trusted, only values the ACS set itself, with SetParameterValues or through the
+// northbound API, are.
+use crate::connection_request::Credentials;
+use crate::cwmp_msg::{
+    DeviceIDStruct, Inform, ParameterAttributeStruct, ParameterInfoStruct, ParameterValueStruct,
+};
+use crate::storage::{MemoryStorage, Storage, StorageError};
+use chrono::{DateTime, TimeDelta, Utc};
+use serde::{Deserialize, Serialize, Serializer};
+use std::{
+    collections::{BTreeMap, HashMap},
+    fmt,
+    ops::Bound,
+    str::FromStr,
+    sync::{Arc, RwLock},
+};
+use utoipa::ToSchema;
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize, ToSchema)]
+pub struct DeviceId {
+    pub oui: String,
+    /// Empty when the CPE does not report a ProductClass.
+    pub product_class: String,
+    pub serial_number: String,
+}
+
+impl DeviceId {
+    /// `None` when the CPE left OUI or SerialNumber empty, both are mandatory.
+    pub fn from_device_id_struct(device_id: &DeviceIDStruct) -> Option<Self> {
+        let field =
+            |value: &Option<String>| value.as_deref().unwrap_or_default().trim().to_string();
+        let id = Self {
+            oui: field(&device_id.oui),
+            product_class: field(&device_id.product_class),
+            serial_number: field(&device_id.serial_number),
+        };
+        (!id.oui.is_empty() && !id.serial_number.is_empty()).then_some(id)
+    }
+}
+
+// A field with the separator and the escape character percent-encoded.
+fn escape_field(field: &str) -> String {
+    let mut escaped = String::with_capacity(field.len());
+    for c in field.chars() {
+        match c {
+            '%' => escaped.push_str("%25"),
+            '-' => escaped.push_str("%2D"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+fn unescape_field(field: &str) -> Option<String> {
+    let mut bytes = Vec::with_capacity(field.len());
+    let mut rest = field.as_bytes();
+    while let Some((&byte, tail)) = rest.split_first() {
+        if byte == b'%' {
+            let hex = std::str::from_utf8(tail.get(..2)?).ok()?;
+            bytes.push(u8::from_str_radix(hex, 16).ok()?);
+            rest = &tail[2..];
+        } else {
+            bytes.push(byte);
+            rest = tail;
+        }
+    }
+    String::from_utf8(bytes).ok()
+}
+
+impl fmt::Display for DeviceId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}-{}-{}",
+            escape_field(&self.oui),
+            escape_field(&self.product_class),
+            escape_field(&self.serial_number)
+        )
+    }
+}
+
+impl FromStr for DeviceId {
+    type Err = String;
+
+    fn from_str(id: &str) -> Result<Self, Self::Err> {
+        let invalid = || format!("{id:?} is not an OUI-ProductClass-SerialNumber device id");
+        let mut fields = id.split('-').map(unescape_field);
+        let (Some(Some(oui)), Some(Some(product_class)), Some(Some(serial_number)), None) =
+            (fields.next(), fields.next(), fields.next(), fields.next())
+        else {
+            return Err(invalid());
+        };
+        if oui.is_empty() || serial_number.is_empty() {
+            return Err(invalid());
+        }
+        Ok(Self {
+            oui,
+            product_class,
+            serial_number,
+        })
+    }
+}
+
+/// Assumed PeriodicInformInterval of CPEs that have not reported theirs, in seconds.
+pub const DEFAULT_INFORM_INTERVAL: i64 = 86_400;
+
+/// Last known state of a Parameter, as the CPE reported it.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+pub struct CachedParameter {
+    pub xsi_type: Option<String>,
+    /// `None` until a GetParameterValues or Inform reports the value.
+    pub value: Option<String>,
+    /// From GetParameterNames.
+    pub writable: Option<bool>,
+    /// From GetParameterAttributes: 0 off, 1 passive, 2 active.
+    pub notification: Option<i32>,
+    pub access_list: Vec<String>,
+    /// When any of the above last changed.
+    pub updated: DateTime<Utc>,
+}
+
+/// A Parameter value that differs from the cached one.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+pub struct ParameterChange {
+    pub name: String,
+    /// `None` when the value was not cached before.
+    pub old: Option<String>,
+    pub new: String,
+}
+
+impl CachedParameter {
+    fn new(updated: DateTime<Utc>) -> Self {
+        Self {
+            xsi_type: None,
+            value: None,
+            writable: None,
+            notification: None,
+            access_list: Vec::new(),
+            updated,
+        }
+    }
+
+    /// A copy to show outside the ACS, without the value when Parameter `name`
+    /// holds a secret.
+    pub fn redacted(&self, name: &str) -> Self {
+        let mut parameter = self.clone();
+        if is_secret(name) {
+            parameter.value = None;
+        }
+        parameter
+    }
+}
+
+/// Whether Parameter `name` holds a password or key, e.g. ManagementServer.Password,
+/// ConnectionRequestPassword or a Wi-Fi KeyPassphrase.
+pub fn is_secret(name: &str) -> bool {
+    let last = name.rsplit('.').next().unwrap_or(name);
+    last.ends_with("Password") || last.ends_with("Passphrase") || last.ends_with("PreSharedKey")
+}
+
+/// What carries Parameter values out of the ACS, in the northbound API, events
+/// and webhooks: a copy without the values of secret Parameters.
+pub trait Redact {
+    fn redacted(&self) -> Self;
+}
+
+impl<T: Redact> Redact for Option<T> {
+    fn redacted(&self) -> Self {
+        self.as_ref().map(T::redacted)
+    }
+}
+
+impl<T: Redact> Redact for Vec<T> {
+    fn redacted(&self) -> Self {
+        self.iter().map(T::redacted).collect()
+    }
+}
+
+impl Redact for ParameterChange {
+    fn redacted(&self) -> Self {
+        if !is_secret(&self.name) {
+            return self.clone();
+        }
+        ParameterChange {
+            name: self.name.clone(),
+            old: self.old.as_ref().map(|_| String::new()),
+            new: String::new(),
+        }
+    }
+}
+
+/// For `serialize_with`, so every serialized copy of the field is redacted.
+pub fn serialize_redacted<T, S>(value: &T, serializer: S) -> Result<S::Ok, S::Error>
+where
+    T: Redact + Serialize,
+    S: Serializer,
+{
+    value.redacted().serialize(serializer)
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Device {
+    pub id: DeviceId,
+    pub manufacturer: Option<String>,
+    pub first_inform: DateTime<Utc>,
+    pub last_inform: DateTime<Utc>,
+    /// EventCodes of the last Inform, as sent.
+    pub last_events: Vec<String>,
+    pub connection_request_url: Option<String>,
+    /// NAT binding the CPE reported for UDP Connection Requests (TR-111), `host:port`.
+    #[serde(default)]
+    pub udp_connection_request_address: Option<String>,
+    /// Full JID taking XMPP Connection Requests (Annex K), from ConnReqJabberID.
+    #[serde(default)]
+    pub connection_request_jabber_id: Option<String>,
+    pub software_version: Option<String>,
+    /// The ManagementServer.Username/Password the ACS gave the CPE. An empty
+    /// username stands for the device id forms, see `find_by_cwmp_username`.
+    #[serde(skip)]
+    pub credentials: Option<Credentials>,
+    /// The ManagementServer.STUNPassword the ACS gave the CPE, the key of the
+    /// MESSAGE-INTEGRITY in its STUN Binding Requests.
+    #[serde(skip)]
+    pub stun_password: Option<String>,
+    /// Every Parameter seen so far, ordered by path so objects are contiguous.
+    pub parameters: BTreeMap<String, CachedParameter>,
+}
+
+impl Device {
+    pub fn new(id: DeviceId, now: DateTime<Utc>) -> Self {
+        Self {
+            id,
+            manufacturer: None,
+            first_inform: now,
+            last_inform: now,
+            last_events: Vec::new(),
+            connection_request_url: None,
+            udp_connection_request_address: None,
+            connection_request_jabber_id: None,
+            software_version: None,
+            credentials: None,
+            stun_password: None,
+            parameters: BTreeMap::new(),
+        }
+    }
+
+    /// Parameters below `path`, e.g. `Device.DeviceInfo.`.
+    pub fn subtree<'a>(
+        &'a self,
+        path: &'a str,
+    ) -> impl Iterator<Item = (&'a str, &'a CachedParameter)> {
+        self.parameters
+            .range::<str, _>((Bound::Included(path), Bound::Unbounded))
+            .take_while(move |(name, _)| name.starts_with(path))
+            .map(|(name, parameter)| (name.as_str(), parameter))
+    }
+
+    /// Whether the CPE Informed within two PeriodicInformIntervals, so it missed
+    /// at most one periodic Inform.
+    pub fn is_online(&self, now: DateTime<Utc>) -> bool {
+        let interval = self
+            .management_server("PeriodicInformInterval")
+            .and_then(|interval| interval.trim().parse().ok())
+            .filter(|interval| *interval > 0)
+            .unwrap_or(DEFAULT_INFORM_INTERVAL);
+        now - self.last_inform <= TimeDelta::seconds(interval.saturating_mul(2))
+    }
+
+    /// IP address of the CPE, from the host of its ConnectionRequestURL or else
+    /// its UDP Connection Request binding.
+    pub fn ip_address(&self) -> Option<&str> {
+        let authority = match self.connection_request_url.as_deref() {
+            Some(url) => {
+                let rest = url.split_once("://").map_or(url, |(_, rest)| rest);
+                rest.split(['/', '?']).next().unwrap_or(rest)
+            }
+            None => self.udp_connection_request_address.as_deref()?,
+        };
+        let host = match authority.strip_prefix('[') {
+            Some(ipv6) => ipv6.split(']').next().unwrap_or(ipv6),
+            None => authority.split(':').next().unwrap_or(authority),
+        };
+        (!host.is_empty()).then_some(host)
+    }
+
+    /// Cached value of `ManagementServer.<name>`, under either root object.
+    pub fn management_server(&self, name: &str) -> Option<&str> {
+        self.parameters
+            .iter()
+            .find(|(path, _)| {
+                path.split_once('.')
+                    .and_then(|(_, path)| path.strip_prefix("ManagementServer."))
+                    == Some(name)
+            })
+            .and_then(|(_, parameter)| parameter.value.as_deref())
+            .filter(|value| !value.is_empty())
+    }
+
+    fn parameter(&mut self, name: &str, now: DateTime<Utc>) -> &mut CachedParameter {
+        let parameter = self
+            .parameters
+            .entry(name.to_string())
+            .or_insert_with(|| CachedParameter::new(now));
+        parameter.updated = now;
+        parameter
+    }
+
+    // Returns the names of the Parameters that were written, and the values among
+    // them that changed.
+    fn update_values<'a>(
+        &mut self,
+        parameters: impl IntoIterator<Item = &'a ParameterValueStruct>,
+        now: DateTime<Utc>,
+    ) -> (Vec<String>, Vec<ParameterChange>) {
+        let mut updated = Vec::new();
+        let mut changes = Vec::new();
+        for parameter in parameters {
+            let Some(name) = parameter.name.as_deref().map(str::trim) else {
+                continue;
+            };
+            let value = parameter.value.clone().unwrap_or_default();
+            let text = value.value.unwrap_or_default();
+            // Both data models root the same objects under a different name.
+            match name.split_once('.').map(|(_, path)| path) {
+                Some("ManagementServer.ConnectionRequestURL") => {
+                    self.connection_request_url = Some(text.trim().to_string())
+                }
+                Some("ManagementServer.UDPConnectionRequestAddress") => {
+                    self.udp_connection_request_address = Some(text.trim().to_string())
+                }
+                Some("ManagementServer.ConnReqJabberID") => {
+                    self.connection_request_jabber_id = Some(text.trim().to_string())
+                }
+                Some("DeviceInfo.SoftwareVersion") => {
+                    self.software_version = Some(text.trim().to_string())
+                }
+                _ => {}
+            }
+            let cached = self.parameter(name, now);
+            cached.xsi_type = value.xsi_type;
+            if cached.value.as_ref() != Some(&text) {
+                changes.push(ParameterChange {
+                    name: name.to_string(),
+                    old: cached.value.replace(text.clone()),
+                    new: text,
+                });
+            }
+            updated.push(name.to_string());
+        }
+        (updated, changes)
+    }
+
+    fn update_writable(
+        &mut self,
+        infos: &[ParameterInfoStruct],
+        now: DateTime<Utc>,
+    ) -> Vec<String> {
+        infos
+            .iter()
+            .map(|info| {
+                let name = info.name.trim();
+                self.parameter(name, now).writable = Some(info.is_writable());
+                name.to_string()
+            })
+            .collect()
+    }
+
+    fn update_attributes(
+        &mut self,
+        attributes: &[ParameterAttributeStruct],
+        now: DateTime<Utc>,
+    ) -> Vec<String> {
+        attributes
+            .iter()
+            .map(|attribute| {
+                let name = attribute.name.trim();
+                let cached = self.parameter(name, now);
+                cached.notification = Some(attribute.notification);
+                cached.access_list = attribute.access_list.names.clone();
+                name.to_string()
+            })
+            .collect()
+    }
+}
+
+/// Devices known to the ACS, shared between the CWMP handler and the management side.
+///
+/// Reads are served from memory; every change is written through to the storage
+/// backend so the fleet survives a restart.
+#[derive(Debug, Clone)]
+pub struct DeviceRegistry {
+    devices: Arc<RwLock<HashMap<DeviceId, Device>>>,
+    storage: Arc<dyn Storage>,
+}
+
+impl Default for DeviceRegistry {
+    fn default() -> Self {
+        Self {
+            devices: Arc::default(),
+            storage: Arc::new(MemoryStorage::new()),
+        }
+    }
+}
+
+impl DeviceRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Registry backed by `storage`, starting with the devices it already holds.
+    pub fn load(storage: Arc<dyn Storage>) -> Result<Self, StorageError> {
+        let devices = storage
+            .load_devices()?
+            .into_iter()
+            .map(|device| (device.id.clone(), device))
+            .collect();
+        Ok(Self {
+            devices: Arc::new(RwLock::new(devices)),
+            storage,
+        })
+    }
+
+    pub fn storage(&self) -> &Arc<dyn Storage> {
+        &self.storage
+    }
+
+    // Persist the device row and the named Parameters. Storage failures are logged,
+    // the in-memory state stays authoritative until the next write succeeds.
+    fn persist(&self, device: &Device, names: &[String]) {
+        let parameters: Vec<(&str, &CachedParameter)> = names
+            .iter()
+            .filter_map(|name| Some((name.as_str(), device.parameters.get(name)?)))
+            .collect();
+        let result = self
+            .storage
+            .save_device(device)
+            .and_then(|()| self.storage.save_parameters(&device.id, &parameters));
+        if let Err(err) = result {
+            tracing::error!("Failed to persist device {}: {err}", device.id);
+        }
+    }
+
+    /// Create or refresh the device that sent `inform`, returning its id and the
+    /// Parameter values the Inform changed. `None` when the Inform does not
+    /// identify the device.
+    pub fn record_inform(
+        &self,
+        inform: &Inform,
+        now: DateTime<Utc>,
+    ) -> Option<(DeviceId, Vec<ParameterChange>)> {
+        let id = DeviceId::from_device_id_struct(inform.device_id())?;
+        let mut devices = self.devices.write().unwrap();
+        let device = devices.entry(id.clone()).or_insert_with(|| {
+            tracing::info!("New device {id}");
+            Device::new(id.clone(), now)
+        });
+        device.last_inform = now;
+        device.manufacturer = inform.device_id().manufacturer.clone();
+        device.last_events = inform.events().map(|event| event.to_string()).collect();
+        let (names, changes) = device.update_values(inform.parameters(), now);
+        self.persist(device, &names);
+        Some((id, changes))
+    }
+
+    fn update(&self, id: &DeviceId, update: impl FnOnce(&mut Device) -> Vec<String>) {
+        if let Some(device) = self.devices.write().unwrap().get_mut(id) {
+            let names = update(device);
+            self.persist(device, &names);
+        }
+    }
+
+    /// Cache the values of a GetParameterValuesResponse, returning those that changed.
+    pub fn update_parameters<'a>(
+        &self,
+        id: &DeviceId,
+        parameters: impl IntoIterator<Item = &'a ParameterValueStruct>,
+    ) -> Vec<ParameterChange> {
+        let mut devices = self.devices.write().unwrap();
+        let Some(device) = devices.get_mut(id) else {
+            return Vec::new();
+        };
+        let (names, changes) = device.update_values(parameters, Utc::now());
+        self.persist(device, &names);
+        changes
+    }
+
+    /// Cache the writability reported by a GetParameterNamesResponse.
+    pub fn update_writable(&self, id: &DeviceId, infos: &[ParameterInfoStruct]) {
+        self.update(id, |device| device.update_writable(infos, Utc::now()));
+    }
+
+    /// Cache the attributes reported by a GetParameterAttributesResponse.
+    pub fn update_attributes(&self, id: &DeviceId, attributes: &[ParameterAttributeStruct]) {
+        self.update(id, |device| {
+            device.update_attributes(attributes, Utc::now())
+        });
+    }
+
+    /// Record the UDP Connection Request address learned by the STUN server.
+    pub fn set_udp_connection_request_address(&self, id: &DeviceId, address: String) {
+        self.update(id, |device| {
+            device.udp_connection_request_address = Some(address);
+            Vec::new()
+        });
+    }
+
+    // The device whose cached `ManagementServer.<parameter>` is `username`, or
+    // whose `OUI-ProductClass-SerialNumber` or `OUI-SerialNumber` it is.
+    fn find_by_username(&self, parameter: &str, username: &str) -> Option<DeviceId> {
+        let devices = self.devices.read().unwrap();
+        devices
+            .values()
+            .find(|device| {
+                device.management_server(parameter) == Some(username)
+                    || device.id.to_string() == username
+                    || format!("{}-{}", device.id.oui, device.id.serial_number) == username
+            })
+            .map(|device| device.id.clone())
+    }
+
+    /// The device a STUN USERNAME belongs to.
+    pub fn find_by_stun_username(&self, username: &str) -> Option<DeviceId> {
+        self.find_by_username("STUNUsername", username)
+    }
+
+    /// The device with ACS-set credentials an HTTP username on the CWMP endpoint
+    /// belongs to: their username, or the device's `OUI-ProductClass-SerialNumber`
+    /// or `OUI-SerialNumber` when the ACS only set the password.
+    pub fn find_by_cwmp_username(&self, username: &str) -> Option<DeviceId> {
+        let devices = self.devices.read().unwrap();
+        devices
+            .values()
+            .find(|device| match &device.credentials {
+                Some(credentials) if credentials.username.is_empty() => {
+                    device.id.to_string() == username
+                        || format!("{}-{}", device.id.oui, device.id.serial_number) == username
+                }
+                Some(credentials) => credentials.username == username,
+                None => false,
+            })
+            .map(|device| device.id.clone())
+    }
+
+    /// Record `credentials` as the ones the CPE authenticates with. `false` when
+    /// the device is not known.
+    pub fn set_credentials(&self, id: &DeviceId, credentials: Credentials) -> bool {
+        let mut devices = self.devices.write().unwrap();
+        let Some(device) = devices.get_mut(id) else {
+            return false;
+        };
+        device.credentials = Some(credentials);
+        self.persist(device, &[]);
+        true
+    }
+
+    /// Take the ManagementServer.Username/Password among values the ACS set on
+    /// the CPE as its credentials, and the STUNPassword as the key of its STUN
+    /// Binding Requests. Setting only the password keeps the username.
+    pub fn update_credentials<'a>(
+        &self,
+        id: &DeviceId,
+        parameters: impl IntoIterator<Item = &'a ParameterValueStruct>,
+    ) {
+        let (mut username, mut password, mut stun_password) = (None, None, None);
+        for parameter in parameters {
+            let name = parameter.name.as_deref().unwrap_or_default().trim();
+            let value = parameter
+                .value
+                .as_ref()
+                .and_then(|value| value.value.clone())
+                .unwrap_or_default();
+            match name.split_once('.').map(|(_, path)| path) {
+                Some("ManagementServer.Username") => username = Some(value),
+                Some("ManagementServer.Password") => password = Some(value),
+                Some("ManagementServer.STUNPassword") => stun_password = Some(value),
+                _ => {}
+            }
+        }
+        if username.is_none() && password.is_none() && stun_password.is_none() {
+            return;
+        }
+        self.update(id, |device| {
+            if let Some(stun_password) = stun_password {
+                device.stun_password = Some(stun_password).filter(|p| !p.is_empty());
+            }
+            if username.is_none() && password.is_none() {
+                return Vec::new();
+            }
+            let current = device.credentials.take();
+            let password = password.or(current.as_ref().map(|c| c.password.clone()));
+            device.credentials = password
+                .filter(|password| !password.is_empty())
+                .map(|password| Credentials {
+                    username: username
+                        .or(current.map(|current| current.username))
+                        .unwrap_or_default(),
+                    password,
+                });
+            Vec::new()
+        });
+    }
+
+    pub fn get(&self, id: &DeviceId) -> Option<Device> {
+        self.devices.read().unwrap().get(id).cloned()
+    }
+
+    /// Every known device, ordered by id.
+    pub fn list(&self) -> Vec<Device> {
+        let mut devices: Vec<Device> = self.devices.read().unwrap().values().cloned().collect();
+        devices.sort_by(|a, b| a.id.cmp(&b.id));
+        devices
+    }
+
+    /// Forget a device, e.g. after it was decommissioned.
+    pub fn remove(&self, id: &DeviceId) -> Result<Option<Device>, StorageError> {
+        self.storage.delete_device(id)?;
+        Ok(self.devices.write().unwrap().remove(id))
+    }
+
+    pub fn len(&self) -> usize {
+        self.devices.read().unwrap().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cwmp_msg::{boot_inform, CWMPMsg, Envelope};
+
+    fn inform(serial: &str, event: &str, version: &str) -> Inform {
+        let jabber_id = format!("{serial}@xmpp.example/cwmp");
+        let xml = boot_inform(
+            serial,
+            &[
+                ("Device.DeviceInfo.SoftwareVersion", "xsd:string", version),
+                (
+                    "Device.ManagementServer.ConnectionRequestURL",
+                    "xsd:string",
+                    "http://10.0.0.2:7547/cr",
+                ),
+                (
+                    "Device.ManagementServer.ConnReqJabberID",
+                    "xsd:string",
+                    &jabber_id,
+                ),
+            ],
+        )
+        .replace("RouterX", "Router-X")
+        .replace("1 BOOT", event);
+        match Envelope::parse(&xml).unwrap().into_msg() {
+            Some(CWMPMsg::Inform(inform)) => inform,
+            other => panic!("expected an Inform, got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn test_device_id_round_trip() {
+        let id = DeviceId {
+            oui: String::from("001A2B"),
+            product_class: String::from("Router-X"),
+            serial_number: String::from("SN-1%"),
+        };
+        assert_eq!(id.to_string(), "001A2B-Router%2DX-SN%2D1%25");
+        assert_eq!(id.to_string().parse::<DeviceId>().unwrap(), id);
+        let id: DeviceId = "001A2B--SN1".parse().unwrap();
+        assert_eq!(id.product_class, "");
+        assert_eq!(id.serial_number, "SN1");
+        assert!("001A2B-Router-X-SN-1".parse::<DeviceId>().is_err());
+        assert!("001A2B-Router-SN%2".parse::<DeviceId>().is_err());
+        assert!("001A2B".parse::<DeviceId>().is_err());
+    }
+
+    #[test]
+    fn test_is_secret() {
+        assert!(is_secret(
+            "Device.ManagementServer.ConnectionRequestPassword"
+        ));
+        assert!(is_secret(
+            "Device.WiFi.AccessPoint.1.Security.KeyPassphrase"
+        ));
+        assert!(!is_secret("Device.ManagementServer.Username"));
+        assert!(!is_secret("Device.Users.User.1.Password."));
+    }
+
+    #[test]
+    fn test_update_credentials() {
+        let registry = DeviceRegistry::new();
+        let (id, _) = registry
+            .record_inform(&inform("SN1", "0 BOOTSTRAP", "1.0"), Utc::now())
+            .unwrap();
+        let reported = [
+            ParameterValueStruct::new("Device.ManagementServer.Username", "xsd:string", "cpe1"),
+            ParameterValueStruct::new("Device.ManagementServer.Password", "xsd:string", "pw1"),
+        ];
+        registry.update_parameters(&id, &reported);
+        assert_eq!(registry.get(&id).unwrap().credentials, None);
+        assert_eq!(registry.find_by_cwmp_username("cpe1"), None);
+
+        registry.update_credentials(&id, &reported[1..]);
+        assert_eq!(
+            registry.find_by_cwmp_username("001A2B-SN1"),
+            Some(id.clone())
+        );
+        registry.update_credentials(&id, &reported[..1]);
+        assert_eq!(
+            registry.get(&id).unwrap().credentials,
+            Some(Credentials {
+                username: String::from("cpe1"),
+                password: String::from("pw1"),
+            })
+        );
+        assert_eq!(registry.find_by_cwmp_username("cpe1"), Some(id.clone()));
+
+        let stun = [ParameterValueStruct::new(
+            "Device.ManagementServer.STUNPassword",
+            "xsd:string",
+            "stun1",
+        )];
+        registry.update_credentials(&id, &stun);
+        let device = registry.get(&id).unwrap();
+        assert_eq!(device.stun_password.as_deref(), Some("stun1"));
+        assert!(device.credentials.is_some());
+    }
+
+    #[test]
+    fn test_record_inform() {
+        let registry = DeviceRegistry::new();
+        let first = "2025-10-07T10:00:00Z".parse().unwrap();
+        let (id, changes) = registry
+            .record_inform(&inform("SN1", "0 BOOTSTRAP", "1.0"), first)
+            .unwrap();
+        assert_eq!(changes.len(), 3);
+        assert_eq!(id.to_string(), "001A2B-Router%2DX-SN1");
+
+        let later = "2025-10-07T11:00:00Z".parse().unwrap();
+        let (_, changes) = registry
+            .record_inform(&inform("SN1", "2 PERIODIC", "1.1"), later)
+            .unwrap();
+        assert_eq!(
+            changes,
+            [ParameterChange {
+                name: String::from("Device.DeviceInfo.SoftwareVersion"),
+                old: Some(String::from("1.0")),
+                new: String::from("1.1"),
+            }]
+        );
+        registry.record_inform(&inform("SN2", "1 BOOT", "1.0"), later);
+        assert_eq!(registry.len(), 2);
+
+        let device = registry.get(&id).unwrap();
+        assert_eq!(device.manufacturer.as_deref(), Some("ExampleCo"));
+        assert_eq!(device.first_inform, first);
+        assert_eq!(device.last_inform, later);
+        assert_eq!(device.last_events, ["2 PERIODIC"]);
+        assert_eq!(device.software_version.as_deref(), Some("1.1"));
+        assert_eq!(
+            device.connection_request_url.as_deref(),
+            Some("http://10.0.0.2:7547/cr")
+        );
+        assert_eq!(
+            device.connection_request_jabber_id.as_deref(),
+            Some("SN1@xmpp.example/cwmp")
+        );
+        assert_eq!(device.subtree("Device.DeviceInfo.").count(), 1);
+        assert_eq!(device.ip_address(), Some("10.0.0.2"));
+        assert!(device.is_online(later + TimeDelta::days(1)));
+        assert!(!device.is_online(later + TimeDelta::days(3)));
+
+        let changes = registry.update_parameters(
+            &id,
+            &[ParameterValueStruct::new(
+                "Device.DeviceInfo.UpTime",
+                "xsd:unsignedInt",
+                "42",
+            )],
+        );
+        assert_eq!(changes[0].old, None);
+        let device = registry.get(&id).unwrap();
+        assert_eq!(device.subtree("Device.DeviceInfo.").count(), 2);
+        assert_eq!(
+            device.parameters["Device.DeviceInfo.UpTime"]
+                .value
+                .as_deref(),
+            Some("42")
+        );
+    }
+}
@@ -0,0 +1,246 @@
+// Live events of the ACS, for dashboards and automation.
+//
+// The CWMP handler publishes to an `EventBus`, a broadcast channel every
+// subscriber gets its own copy of each event from. The northbound API streams
+// them to WebSocket clients at /api/v1/events, filtered by device, product class
+// or event type. Nothing is buffered for absent subscribers: a client only sees
+// the events published while it is connected, and one falling more than
+// `EVENT_CAPACITY` events behind misses the oldest ones.
+use crate::{
+    device::{serialize_redacted, DeviceId, ParameterChange},
+    task::Task,
+};
+use chrono::{DateTime, Utc};
+use serde::{Serialize, Serializer};
+use tokio::sync::broadcast;
+use utoipa::ToSchema;
+
+/// Events a subscriber may lag behind before it misses some.
+pub const EVENT_CAPACITY: usize = 1024;
+
+/// Why a CWMP session ended.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum SessionEnd {
+    /// The ACS answered with HTTP 204.
+    Closed,
+    /// The CPE broke the protocol and the ACS gave up on the session.
+    Aborted,
+    /// The CPE stayed silent past the session timeout.
+    TimedOut,
+    /// The CPE Informed again, opening a new session.
+    Replaced,
+    /// Still open when the ACS shut down.
+    Shutdown,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, ToSchema)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum EventKind {
+    /// EventCodes as sent, e.g. `1 BOOT`.
+    DeviceInformed {
+        events: Vec<String>,
+    },
+    SessionStarted,
+    SessionEnded {
+        reason: SessionEnd,
+    },
+    TaskCompleted {
+        task: Task,
+    },
+    /// The CPE answered the task with a fault, see its `result`.
+    TaskFailed {
+        task: Task,
+    },
+    /// The CPE sent a CWMP fault, whether or not it answered a task.
+    FaultRaised {
+        code: u32,
+        message: String,
+    },
+    /// Parameter values an Inform or a response changed, in one batch.
+    ParametersChanged {
+        #[serde(serialize_with = "serialize_redacted")]
+        changes: Vec<ParameterChange>,
+    },
+    /// The CPE reported the end of a Download or Upload, `fault_code` 0 on success.
+    TransferCompleted {
+        command_key: String,
+        fault_code: u32,
+        fault_string: String,
+        start_time: String,
+        complete_time: String,
+    },
+}
+
+impl EventKind {
+    /// Every `type`, as the filter of a subscriber names them.
+    pub const TYPES: [&'static str; 8] = [
+        "device_informed",
+        "session_started",
+        "session_ended",
+        "task_completed",
+        "task_failed",
+        "fault_raised",
+        "parameters_changed",
+        "transfer_completed",
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            EventKind::DeviceInformed { .. } => "device_informed",
+            EventKind::SessionStarted => "session_started",
+            EventKind::SessionEnded { .. } => "session_ended",
+            EventKind::TaskCompleted { .. } => "task_completed",
+            EventKind::TaskFailed { .. } => "task_failed",
+            EventKind::FaultRaised { .. } => "fault_raised",
+            EventKind::ParametersChanged { .. } => "parameters_changed",
+            EventKind::TransferCompleted { .. } => "transfer_completed",
+        }
+    }
+}
+
+/// Something that happened to a device, as sent to subscribers.
+#[derive(Debug, Clone, PartialEq, Serialize, ToSchema)]
+pub struct Event {
+    pub time: DateTime<Utc>,
+    /// `OUI-ProductClass-SerialNumber`.
+    #[serde(serialize_with = "serialize_device_id")]
+    #[schema(value_type = String)]
+    pub device_id: DeviceId,
+    #[serde(flatten)]
+    pub kind: EventKind,
+}
+
+fn serialize_device_id<S: Serializer>(id: &DeviceId, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.collect_str(id)
+}
+
+/// Events a subscriber wants, every event when empty.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct EventFilter {
+    pub device_id: Option<DeviceId>,
+    pub product_class: Option<String>,
+    /// Names from `EventKind::TYPES`.
+    pub types: Vec<String>,
+    /// Only `device_informed` events carrying one of these EventCodes, e.g.
+    /// `0 BOOTSTRAP`. Other types are not affected.
+    pub event_codes: Vec<String>,
+}
+
+impl EventFilter {
+    pub fn matches(&self, event: &Event) -> bool {
+        self.device_id
+            .as_ref()
+            .is_none_or(|id| &event.device_id == id)
+            && self
+                .product_class
+                .as_ref()
+                .is_none_or(|class| &event.device_id.product_class == class)
+            && (self.types.is_empty() || self.types.iter().any(|name| name == event.kind.name()))
+            && match &event.kind {
+                EventKind::DeviceInformed { events } if !self.event_codes.is_empty() => {
+                    events.iter().any(|code| self.event_codes.contains(code))
+                }
+                _ => true,
+            }
+    }
+}
+
+/// Publishes events to every current subscriber. Cloning shares the channel.
+#[derive(Debug, Clone)]
+pub struct EventBus {
+    sender: broadcast::Sender<Event>,
+}
+
+impl Default for EventBus {
+    fn default() -> Self {
+        Self::with_capacity(EVENT_CAPACITY)
+    }
+}
+
+impl EventBus {
+    pub fn with_capacity(capacity: usize) -> Self {
+        let (sender, _) = broadcast::channel(capacity);
+        Self { sender }
+    }
+
+    /// Publish an event that happened now. Dropped when nobody subscribed.
+    pub fn publish(&self, device_id: &DeviceId, kind: EventKind) {
+        let event = Event {
+            time: Utc::now(),
+            device_id: device_id.clone(),
+            kind,
+        };
+        // Only fails without subscribers.
+        let _ = self.sender.send(event);
+    }
+
+    /// Events published from now on.
+    pub fn subscribe(&self) -> broadcast::Receiver<Event> {
+        self.sender.subscribe()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[tokio::test]
+    async fn test_event_bus() {
+        let bus = EventBus::default();
+        let router: DeviceId = "001A2B-RouterX-1".parse().unwrap();
+        let modem: DeviceId = "00D09E-ModemY-2".parse().unwrap();
+        // Events without subscribers are dropped.
+        bus.publish(&router, EventKind::SessionStarted);
+
+        let mut events = bus.subscribe();
+        bus.publish(
+            &router,
+            EventKind::DeviceInformed {
+                events: vec![String::from("1 BOOT")],
+            },
+        );
+        bus.publish(
+            &modem,
+            EventKind::SessionEnded {
+                reason: SessionEnd::TimedOut,
+            },
+        );
+        let informed = events.recv().await.unwrap();
+        let ended = events.recv().await.unwrap();
+        assert!(events.try_recv().is_err());
+
+        let value = serde_json::to_value(&informed).unwrap();
+        assert_eq!(value["type"], "device_informed");
+        assert_eq!(value["device_id"], "001A2B-RouterX-1");
+        assert_eq!(value["events"], json!(["1 BOOT"]));
+        assert_eq!(serde_json::to_value(&ended).unwrap()["reason"], "timed_out");
+
+        let by_device = EventFilter {
+            device_id: Some(router.clone()),
+            ..EventFilter::default()
+        };
+        assert!(by_device.matches(&informed));
+        assert!(!by_device.matches(&ended));
+        let by_class = EventFilter {
+            product_class: Some(String::from("ModemY")),
+            ..EventFilter::default()
+        };
+        assert!(!by_class.matches(&informed));
+        assert!(by_class.matches(&ended));
+        let by_type = EventFilter {
+            types: vec![String::from("session_ended"), String::from("task_failed")],
+            ..EventFilter::default()
+        };
+        assert!(!by_type.matches(&informed));
+        assert!(by_type.matches(&ended));
+        let by_code = EventFilter {
+            event_codes: vec![String::from("0 BOOTSTRAP")],
+            ..EventFilter::default()
+        };
+        assert!(!by_code.matches(&informed));
+        assert!(by_code.matches(&ended));
+        assert!(EventFilter::default().matches(&informed));
+    }
+}
@@ -1,54 +1,198 @@
 use dioxus::prelude::*;
-// Launch axum on the server
-use axum::Router;
 #[cfg(feature = "server")]
 use tokio::runtime::Runtime;
 // use tr
 
+#[cfg(feature = "server")]
+mod auth;
+#[cfg(feature = "server")]
+mod config;
+#[cfg(feature = "server")]
+mod connection_request;
 mod cwmp_msg;
+mod device;
+#[cfg(feature = "server")]
+mod events;
+#[cfg(feature = "server")]
+mod nbi;
 mod soap_xml;
 mod startup;
+mod storage;
+mod task;
+#[cfg(test)]
 mod telemetry;
+#[cfg(feature = "server")]
+mod tls;
+mod ui;
+#[cfg(feature = "server")]
+mod webhook;
 // mod tower_test;
 
 const FAVICON: Asset = asset!("/assets/favicon.ico");
 const MAIN_CSS: Asset = asset!("/assets/main.css");
-const HEADER_SVG: Asset = asset!("/assets/header.svg");
 
 #[cfg(feature = "server")]
 async fn launch_server(component: fn() -> Element) {
+    let config = config::Config::load().unwrap_or_else(|err| {
+        eprintln!("Invalid configuration: {err}");
+        std::process::exit(1);
+    });
     // Connect dioxus's logging infrastructure
+    if let Err(err) = dioxus::logger::init(config.log.level()) {
+        eprintln!("Failed to initialize logging: {err}");
+    }
+    if let Err(err) = serve(config, component).await {
+        tracing::error!("{err}");
+        std::process::exit(1);
+    }
+}
 
-    use std::net::{IpAddr, Ipv4Addr, SocketAddr};
-
-    dioxus::logger::initialize_default();
-    let ip =
-        dioxus::cli_config::server_ip().unwrap_or_else(|| IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1)));
-    let port = dioxus::cli_config::server_port().unwrap_or(8081);
-    let server_addr = SocketAddr::new(ip, port);
+// Run the UI, rendering `component`, and the CWMP server until SIGTERM or Ctrl-C,
+// then let open CWMP sessions finish and flush the database.
+#[cfg(feature = "server")]
+async fn serve(config: config::Config, component: fn() -> Element) -> Result<(), String> {
+    use std::time::Duration;
 
-    let cwmp_port = dioxus::cli_config::server_port().unwrap_or(7777);
-    let cwmp_server_addr = SocketAddr::new(ip, cwmp_port);
+    let server_addr = config.ui_addr();
+    let cwmp_server_addr = config.cwmp_addr();
     tracing::info!("WebUI address {server_addr} - CWMP handler address {cwmp_server_addr}");
 
-    //Build a custom router
-
-    let router = axum::Router::new()
-        .serve_dioxus_application(ServeConfigBuilder::new(), App)
-        .into_make_service();
-
-    let listener = tokio::net::TcpListener::bind(server_addr).await.unwrap();
+    let listener = tokio::net::TcpListener::bind(server_addr)
+        .await
+        .map_err(|err| format!("Failed to bind the UI to {server_addr}: {err}"))?;
     let cwmp_listener = tokio::net::TcpListener::bind(cwmp_server_addr)
         .await
-        .unwrap();
+        .map_err(|err| format!("Failed to bind CWMP to {cwmp_server_addr}: {err}"))?;
+
+    // Devices, tasks and faults survive restarts in an SQLite database.
+    let db_path = config.storage.path.display();
+    let storage = storage::SqliteStorage::open(&config.storage.path)
+        .map_err(|err| format!("Failed to open database {db_path}: {err}"))?;
+    let devices = device::DeviceRegistry::load(std::sync::Arc::new(storage))
+        .map_err(|err| format!("Failed to load devices from {db_path}: {err}"))?;
+    let tasks = task::TaskQueue::load(devices.storage().clone())
+        .map_err(|err| format!("Failed to load tasks from {db_path}: {err}"))?
+        .with_max_attempts(config.tasks.max_attempts)
+        .with_retention(config.task_retention());
+    tokio::spawn(tasks.clone().run_retention());
+    if devices.is_empty() {
+        tracing::info!("No devices in {db_path} yet");
+    } else {
+        tracing::info!("Loaded {} devices from {db_path}", devices.len());
+    }
+
+    let mut connection_requests = connection_request::ConnectionRequestClient::new(
+        Duration::from_secs(config.connection_request.timeout),
+        Duration::from_secs(config.connection_request.min_interval),
+    );
+    if let (Some(username), Some(password)) = (
+        &config.connection_request.username,
+        &config.connection_request.password,
+    ) {
+        connection_requests =
+            connection_requests.with_default_credentials(connection_request::Credentials {
+                username: username.clone(),
+                password: password.clone(),
+            });
+    }
+    // CPEs behind a NAT keep their UDP Connection Request binding open through STUN.
+    if config.stun.enabled {
+        let stun_server_addr = config.stun_addr();
+        let stun_server = connection_request::StunServer::bind(stun_server_addr, devices.clone())
+            .await
+            .map_err(|err| format!("Failed to bind STUN to {stun_server_addr}: {err}"))?;
+        let stun_server_addr = stun_server
+            .local_addr()
+            .map_err(|err| format!("Failed to read the STUN address: {err}"))?;
+        tracing::info!("STUN server address {stun_server_addr}");
+        connection_requests = connection_requests.with_udp(stun_server.sender());
+        tokio::spawn(stun_server.run());
+    }
+    // XMPP Connection Requests need an ACS account on the CPEs' XMPP server.
+    if let Some(xmpp) = config.xmpp.clone() {
+        tracing::info!("XMPP Connection Requests as {}", xmpp.jid);
+        let ca = xmpp.ca.display().to_string();
+        let sender = connection_request::XmppConnectionRequestSender::new(xmpp)
+            .map_err(|err| format!("Failed to load XMPP CA certificates {ca}: {err}"))?;
+        connection_requests = connection_requests.with_xmpp(sender);
+    }
+
+    // CPEs authenticate with the ManagementServer.Username/Password the ACS set on
+    // them, or the fleet-wide fallback. Auto-registration admits devices the ACS
+    // has not seen before without either.
+    let mut cpe_auth = auth::CpeAuthenticator::new(config.auth.realm.clone())
+        .with_basic(config.auth.allow_basic)
+        .with_auto_register(config.auth.auto_register);
+    if let (Some(username), Some(password)) = (&config.auth.username, &config.auth.password) {
+        cpe_auth = cpe_auth.with_fallback(connection_request::Credentials {
+            username: username.clone(),
+            password: password.clone(),
+        });
+    }
+
+    // CWMP over TLS when a certificate is configured, reloaded on SIGHUP.
+    let tls = match config.tls.clone() {
+        Some(settings) => {
+            let tls = tls::ReloadableTlsConfig::load(settings)
+                .map_err(|err| format!("Failed to load TLS configuration: {err}"))?;
+            #[cfg(unix)]
+            tokio::spawn(tls::reload_on_sighup(tls.clone()));
+            Some(tls)
+        }
+        None => None,
+    };
+
+    // Webhook deliveries left pending by the last run are resumed.
+    let webhooks = webhook::Webhooks::load(config.webhooks.clone(), devices.storage().clone())
+        .map_err(|err| format!("Failed to load webhook deliveries from {db_path}: {err}"))?;
+
+    let state = startup::AcsState::new(devices.clone(), tasks, connection_requests)
+        .with_cpe_auth(cpe_auth)
+        .with_session_timeout(config.session_timeout())
+        .with_hold_requests(config.cwmp.hold_requests)
+        .with_max_sessions(config.cwmp.max_sessions)
+        .with_webhooks(webhooks.clone());
+    tokio::spawn(webhooks.run(state.events.subscribe()));
+    //Build a custom router, with the northbound API next to the UI
+    if config.ui.api_token.is_none() {
+        tracing::warn!("No ui.api_token configured, the northbound API refuses every request");
+    }
+    let router = nbi::router(state.clone(), config.ui.api_token.clone())
+        .serve_dioxus_application(ServeConfigBuilder::new(), component)
+        // Server functions of the UI read the state from here
+        .layer(axum::Extension(state.clone()))
+        .into_make_service();
 
-    tokio::spawn(async move {
-        startup::run(cwmp_listener).await;
+    let cwmp = tokio::spawn({
+        let state = state.clone();
+        async move {
+            match tls {
+                Some(tls) => startup::run_tls(cwmp_listener, state, tls).await,
+                None => startup::run(cwmp_listener, state).await,
+            }
+        }
+    });
+    let ui = tokio::spawn(async move {
+        if let Err(err) = axum::serve(listener, router).await {
+            tracing::error!("UI server failed: {err}");
+        }
     });
-    axum::serve(listener, router).await.unwrap();
-    // axum::serve(server_addr, router).await.unwrap();
 
-    // let cwmp_listener = tokio::net::
+    startup::shutdown_signal().await;
+    // CWMP connections stay open while draining, a CPE may reconnect to its session.
+    tracing::info!("Shutting down, waiting for open CWMP sessions");
+    let aborted = state.drain(config.shutdown_timeout()).await;
+    if aborted > 0 {
+        tracing::warn!("Aborted {aborted} CWMP sessions");
+    }
+    cwmp.abort();
+    ui.abort();
+    devices
+        .storage()
+        .flush()
+        .map_err(|err| format!("Failed to flush {db_path}: {err}"))?;
+    tracing::info!("Shut down");
+    Ok(())
 }
 
 fn main() {
@@ -71,25 +215,6 @@ fn App() -> Element {
     rsx! {
         document::Link { rel: "icon", href: FAVICON }
         document::Link { rel: "stylesheet", href: MAIN_CSS }
-        Hero {}
-    }
-}
-
-#[component]
-pub fn Hero() -> Element {
-    rsx! {
-        div { id: "hero",
-            img { src: HEADER_SVG, id: "header" }
-            div { id: "links",
-                a { href: "https://dioxuslabs.com/learn/0.6/", "📚 Learn Dioxus" }
-                a { href: "https://dioxuslabs.com/awesome", "🚀 Awesome Dioxus" }
-                a { href: "https://github.com/dioxus-community/", "📡 Community Libraries" }
-                a { href: "https://github.com/DioxusLabs/sdk", "⚙️ Dioxus Development Kit" }
-                a { href: "https://marketplace.visualstudio.com/items?itemName=DioxusLabs.dioxus",
-                    "💫 VSCode Extension"
-                }
-                a { href: "https://discord.gg/XgGxMSkvUM", "👋 Community Discord" }
-            }
-        }
+        Router::<ui::Route> {}
     }
 }
@@ -0,0 +1,272 @@
+use super::{blocking, parse_device_id, ApiError, ErrorBody};
+use crate::{
+    connection_request::Credentials,
+    device::{CachedParameter, Device},
+    startup::AcsState,
+};
+use axum::{
+    extract::{Path, Query, State},
+    http::StatusCode,
+    Json,
+};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use utoipa::{IntoParams, ToSchema};
+
+/// A device without its Parameters, see `/devices/{id}/parameters`.
+#[derive(Debug, Serialize, ToSchema)]
+pub struct DeviceSummary {
+    /// `OUI-ProductClass-SerialNumber`.
+    pub id: String,
+    pub oui: String,
+    pub product_class: String,
+    pub serial_number: String,
+    pub manufacturer: Option<String>,
+    pub software_version: Option<String>,
+    pub first_inform: DateTime<Utc>,
+    pub last_inform: DateTime<Utc>,
+    /// EventCodes of the last Inform.
+    pub last_events: Vec<String>,
+    pub connection_request_url: Option<String>,
+    /// Number of cached Parameters.
+    pub parameters: usize,
+}
+
+impl From<&Device> for DeviceSummary {
+    fn from(device: &Device) -> Self {
+        Self {
+            id: device.id.to_string(),
+            oui: device.id.oui.clone(),
+            product_class: device.id.product_class.clone(),
+            serial_number: device.id.serial_number.clone(),
+            manufacturer: device.manufacturer.clone(),
+            software_version: device.software_version.clone(),
+            first_inform: device.first_inform,
+            last_inform: device.last_inform,
+            last_events: device.last_events.clone(),
+            connection_request_url: device.connection_request_url.clone(),
+            parameters: device.parameters.len(),
+        }
+    }
+}
+
+/// One page of the devices matching a search.
+#[derive(Debug, Serialize, ToSchema)]
+pub struct DeviceList {
+    /// Matching devices, on every page.
+    pub total: usize,
+    pub devices: Vec<DeviceSummary>,
+}
+
+/// Devices listed per page when the client names no limit.
+const DEFAULT_PAGE_SIZE: usize = 100;
+/// Most devices listed per page, whatever the client asks for.
+const MAX_PAGE_SIZE: usize = 1000;
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct DeviceQuery {
+    /// Case-insensitive text in the id, manufacturer or software version.
+    q: Option<String>,
+    oui: Option<String>,
+    product_class: Option<String>,
+    software_version: Option<String>,
+    /// Only devices that Informed at or after this time.
+    informed_since: Option<DateTime<Utc>>,
+    /// Devices to skip, ordered by id.
+    #[serde(default)]
+    offset: usize,
+    /// Devices per page, 100 by default and at most 1000.
+    limit: Option<usize>,
+}
+
+impl DeviceQuery {
+    fn matches(&self, device: &Device) -> bool {
+        let text = self.q.as_deref().map(str::to_lowercase);
+        let contains = |field: Option<&str>, text: &str| {
+            field.is_some_and(|field| field.to_lowercase().contains(text))
+        };
+        text.as_deref().is_none_or(|text| {
+            contains(Some(device.id.to_string().as_str()), text)
+                || contains(device.manufacturer.as_deref(), text)
+                || contains(device.software_version.as_deref(), text)
+        }) && self.oui.as_ref().is_none_or(|oui| &device.id.oui == oui)
+            && self
+                .product_class
+                .as_ref()
+                .is_none_or(|class| &device.id.product_class == class)
+            && self
+                .software_version
+                .as_deref()
+                .is_none_or(|version| device.software_version.as_deref() == Some(version))
+            && self
+                .informed_since
+                .is_none_or(|since| device.last_inform >= since)
+    }
+}
+
+/// Search the devices.
+#[utoipa::path(
+    get,
+    path = "/api/v1/devices",
+    tag = "devices",
+    params(DeviceQuery),
+    responses((status = 200, body = DeviceList))
+)]
+pub async fn list_devices(
+    State(state): State<AcsState>,
+    Query(query): Query<DeviceQuery>,
+) -> Json<DeviceList> {
+    let matching: Vec<Device> = state
+        .devices
+        .list()
+        .into_iter()
+        .filter(|device| query.matches(device))
+        .collect();
+    let devices = matching
+        .iter()
+        .skip(query.offset)
+        .take(query.limit.unwrap_or(DEFAULT_PAGE_SIZE).min(MAX_PAGE_SIZE))
+        .map(DeviceSummary::from)
+        .collect();
+    Json(DeviceList {
+        total: matching.len(),
+        devices,
+    })
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/devices/{id}",
+    tag = "devices",
+    params(("id" = String, Path, description = "OUI-ProductClass-SerialNumber")),
+    responses(
+        (status = 200, body = DeviceSummary),
+        (status = 404, body = ErrorBody),
+    )
+)]
+pub async fn get_device(
+    State(state): State<AcsState>,
+    Path(id): Path<String>,
+) -> Result<Json<DeviceSummary>, ApiError> {
+    let id = parse_device_id(&id)?;
+    let device = state
+        .devices
+        .get(&id)
+        .ok_or_else(|| ApiError::NotFound(format!("device {id}")))?;
+    Ok(Json(DeviceSummary::from(&device)))
+}
+
+/// Forget a device, with its tasks and faults. It is added again if it Informs.
+#[utoipa::path(
+    delete,
+    path = "/api/v1/devices/{id}",
+    tag = "devices",
+    params(("id" = String, Path, description = "OUI-ProductClass-SerialNumber")),
+    responses(
+        (status = 204, description = "Deleted"),
+        (status = 404, body = ErrorBody),
+    )
+)]
+pub async fn delete_device(
+    State(state): State<AcsState>,
+    Path(id): Path<String>,
+) -> Result<StatusCode, ApiError> {
+    let id = parse_device_id(&id)?;
+    if state.devices.get(&id).is_none() {
+        return Err(ApiError::NotFound(format!("device {id}")));
+    }
+    blocking(move || {
+        for task in state.tasks.tasks_for(&id) {
+            state.tasks.cancel(task.id)?;
+        }
+        Ok(state.devices.remove(&id)?)
+    })
+    .await?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct ParameterQuery {
+    /// Only Parameters below this path, e.g. `Device.DeviceInfo.`.
+    path: Option<String>,
+}
+
+/// Cached Parameters of a device, by name. Values are as last reported by the CPE,
+/// those of passwords and keys are left out.
+#[utoipa::path(
+    get,
+    path = "/api/v1/devices/{id}/parameters",
+    tag = "devices",
+    params(("id" = String, Path, description = "OUI-ProductClass-SerialNumber"), ParameterQuery),
+    responses(
+        (status = 200, body = BTreeMap<String, CachedParameter>),
+        (status = 404, body = ErrorBody),
+    )
+)]
+pub async fn get_parameters(
+    State(state): State<AcsState>,
+    Path(id): Path<String>,
+    Query(query): Query<ParameterQuery>,
+) -> Result<Json<BTreeMap<String, CachedParameter>>, ApiError> {
+    let id = parse_device_id(&id)?;
+    let device = state
+        .devices
+        .get(&id)
+        .ok_or_else(|| ApiError::NotFound(format!("device {id}")))?;
+    let parameters = device
+        .subtree(query.path.as_deref().unwrap_or_default())
+        .map(|(name, parameter)| (name.to_string(), parameter.redacted(name)))
+        .collect();
+    Ok(Json(parameters))
+}
+
+/// ManagementServer.Username/Password the CPE was given outside of CWMP.
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct CpeCredentials {
+    /// Empty when the CPE authenticates with its device id as username.
+    #[serde(default)]
+    pub username: String,
+    pub password: String,
+}
+
+/// Set the credentials the CPE authenticates with, e.g. ones provisioned at the
+/// factory. Credentials the ACS sets with SetParameterValues replace them.
+#[utoipa::path(
+    put,
+    path = "/api/v1/devices/{id}/credentials",
+    tag = "devices",
+    params(("id" = String, Path, description = "OUI-ProductClass-SerialNumber")),
+    request_body = CpeCredentials,
+    responses(
+        (status = 204, description = "Stored"),
+        (status = 400, body = ErrorBody),
+        (status = 404, body = ErrorBody),
+    )
+)]
+pub async fn set_credentials(
+    State(state): State<AcsState>,
+    Path(id): Path<String>,
+    Json(body): Json<CpeCredentials>,
+) -> Result<StatusCode, ApiError> {
+    let id = parse_device_id(&id)?;
+    if body.password.is_empty() {
+        return Err(ApiError::BadRequest(String::from(
+            "password must not be empty",
+        )));
+    }
+    let credentials = Credentials {
+        username: body.username,
+        password: body.password,
+    };
+    blocking(move || {
+        if !state.devices.set_credentials(&id, credentials) {
+            return Err(ApiError::NotFound(format!("device {id}")));
+        }
+        Ok(())
+    })
+    .await?;
+    Ok(StatusCode::NO_CONTENT)
+}
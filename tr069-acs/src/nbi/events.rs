@@ -0,0 +1,114 @@
+use super::ApiError;
+use crate::{
+    events::{Event, EventFilter, EventKind},
+    startup::AcsState,
+};
+use axum::{
+    extract::{
+        ws::{Message, WebSocket, WebSocketUpgrade},
+        Query, State,
+    },
+    response::Response,
+};
+use serde::Deserialize;
+use serde_json::json;
+use tokio::sync::broadcast::{error::RecvError, Receiver};
+use utoipa::IntoParams;
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct EventQuery {
+    /// Only events of this device, `OUI-ProductClass-SerialNumber`.
+    device_id: Option<String>,
+    /// Only events of devices of this ProductClass.
+    product_class: Option<String>,
+    /// Comma separated event types, e.g. `session_ended,task_failed`.
+    #[serde(rename = "type")]
+    types: Option<String>,
+}
+
+impl EventQuery {
+    fn filter(self) -> Result<EventFilter, ApiError> {
+        let types: Vec<String> = self
+            .types
+            .iter()
+            .flat_map(|types| types.split(','))
+            .map(str::trim)
+            .filter(|name| !name.is_empty())
+            .map(str::to_string)
+            .collect();
+        if let Some(unknown) = types
+            .iter()
+            .find(|name| !EventKind::TYPES.contains(&name.as_str()))
+        {
+            return Err(ApiError::BadRequest(format!(
+                "unknown event type {unknown:?}, expected one of {}",
+                EventKind::TYPES.join(", ")
+            )));
+        }
+        Ok(EventFilter {
+            device_id: self
+                .device_id
+                .as_deref()
+                .map(super::parse_device_id)
+                .transpose()?,
+            product_class: self.product_class,
+            types,
+            ..EventFilter::default()
+        })
+    }
+}
+
+/// Stream events as JSON text messages over a WebSocket, from the upgrade on.
+///
+/// A client too slow to keep up misses the oldest events and is sent
+/// `{"type": "lagged", "missed": <count>}` in their place.
+#[utoipa::path(
+    get,
+    path = "/api/v1/events",
+    tag = "events",
+    params(EventQuery),
+    responses(
+        (status = 101, description = "Upgraded, one text message per event", body = Event),
+        (status = 400, body = super::ErrorBody),
+    )
+)]
+pub async fn stream_events(
+    State(state): State<AcsState>,
+    Query(query): Query<EventQuery>,
+    ws: WebSocketUpgrade,
+) -> Result<Response, ApiError> {
+    let filter = query.filter()?;
+    // Subscribed before the upgrade, so no event is lost while it completes.
+    let events = state.events.subscribe();
+    Ok(ws.on_upgrade(move |socket| forward(socket, events, filter)))
+}
+
+async fn forward(mut socket: WebSocket, mut events: Receiver<Event>, filter: EventFilter) {
+    loop {
+        let text = tokio::select! {
+            event = events.recv() => match event {
+                Ok(event) if filter.matches(&event) => match serde_json::to_string(&event) {
+                    Ok(text) => text,
+                    Err(err) => {
+                        tracing::error!("Failed to serialize event: {err}");
+                        continue;
+                    }
+                },
+                Ok(_) => continue,
+                Err(RecvError::Lagged(missed)) => {
+                    json!({ "type": "lagged", "missed": missed }).to_string()
+                }
+                Err(RecvError::Closed) => break,
+            },
+            // Clients only ever close the stream, anything else they send is ignored.
+            message = socket.recv() => match message {
+                Some(Ok(Message::Close(_))) | Some(Err(_)) | None => break,
+                Some(Ok(_)) => continue,
+            },
+        };
+        if socket.send(Message::Text(text)).await.is_err() {
+            break;
+        }
+    }
+}
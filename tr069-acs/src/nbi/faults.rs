@@ -0,0 +1,147 @@
+use super::{blocking, parse_device_id, ApiError, ErrorBody};
+use crate::{
+    startup::AcsState,
+    storage::FaultRecord,
+    task::{Task, TaskStatus},
+};
+use axum::{
+    extract::{Path, Query, State},
+    http::StatusCode,
+    Json,
+};
+use serde::Deserialize;
+use utoipa::IntoParams;
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct FaultQuery {
+    /// Only faults of this device, `OUI-ProductClass-SerialNumber`.
+    device_id: Option<String>,
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct RetryQuery {
+    /// Send a Connection Request so the CPE runs the task again now.
+    #[serde(default)]
+    connection_request: bool,
+}
+
+/// Every CWMP fault the device answered an ACS request with, oldest first, also
+/// those of tasks retried or deleted since.
+#[utoipa::path(
+    get,
+    path = "/api/v1/devices/{id}/faults",
+    tag = "faults",
+    params(("id" = String, Path, description = "OUI-ProductClass-SerialNumber")),
+    responses(
+        (status = 200, body = Vec<FaultRecord>),
+        (status = 404, body = ErrorBody),
+    )
+)]
+pub async fn list_device_faults(
+    State(state): State<AcsState>,
+    Path(id): Path<String>,
+) -> Result<Json<Vec<FaultRecord>>, ApiError> {
+    let id = parse_device_id(&id)?;
+    if state.devices.get(&id).is_none() {
+        return Err(ApiError::NotFound(format!("device {id}")));
+    }
+    let storage = state.devices.storage().clone();
+    Ok(Json(blocking(move || Ok(storage.faults(&id)?)).await?))
+}
+
+// The task `task_id`, if the CPE faulted it.
+fn faulted(state: &AcsState, task_id: u64) -> Result<Task, ApiError> {
+    let task = state
+        .tasks
+        .get(task_id)
+        .ok_or_else(|| ApiError::NotFound(format!("task {task_id}")))?;
+    if task.status != TaskStatus::Faulted {
+        return Err(ApiError::Conflict(format!("task {task_id} did not fault")));
+    }
+    Ok(task)
+}
+
+/// Faulted tasks, oldest first. The CWMP fault, or that the CPE never answered,
+/// is in their `result`.
+#[utoipa::path(
+    get,
+    path = "/api/v1/faults",
+    tag = "faults",
+    params(FaultQuery),
+    responses(
+        (status = 200, body = Vec<Task>),
+        (status = 400, body = ErrorBody),
+    )
+)]
+pub async fn list_faults(
+    State(state): State<AcsState>,
+    Query(query): Query<FaultQuery>,
+) -> Result<Json<Vec<Task>>, ApiError> {
+    let device_id = query
+        .device_id
+        .as_deref()
+        .map(parse_device_id)
+        .transpose()?;
+    let faults = state
+        .tasks
+        .list()
+        .into_iter()
+        .filter(|task| task.status == TaskStatus::Faulted)
+        .filter(|task| device_id.as_ref().is_none_or(|id| &task.device_id == id))
+        .collect();
+    Ok(Json(faults))
+}
+
+/// Queue a faulted task again, for the device's next session.
+#[utoipa::path(
+    post,
+    path = "/api/v1/faults/{task_id}/retry",
+    tag = "faults",
+    params(("task_id" = u64, Path), RetryQuery),
+    responses(
+        (status = 200, description = "Pending again", body = Task),
+        (status = 404, body = ErrorBody),
+        (status = 409, description = "The task did not fault", body = ErrorBody),
+    )
+)]
+pub async fn retry_fault(
+    State(state): State<AcsState>,
+    Path(task_id): Path<u64>,
+    Query(query): Query<RetryQuery>,
+) -> Result<Json<Task>, ApiError> {
+    faulted(&state, task_id)?;
+    let tasks = state.tasks.clone();
+    let task = blocking(move || {
+        tasks
+            .retry(task_id)
+            .ok_or_else(|| ApiError::NotFound(format!("task {task_id}")))
+    })
+    .await?;
+    if query.connection_request {
+        state.request_connection(&task.device_id);
+    }
+    Ok(Json(task))
+}
+
+/// Dismiss a fault by deleting its task.
+#[utoipa::path(
+    delete,
+    path = "/api/v1/faults/{task_id}",
+    tag = "faults",
+    params(("task_id" = u64, Path)),
+    responses(
+        (status = 204, description = "Deleted"),
+        (status = 404, body = ErrorBody),
+        (status = 409, description = "The task did not fault", body = ErrorBody),
+    )
+)]
+pub async fn delete_fault(
+    State(state): State<AcsState>,
+    Path(task_id): Path<u64>,
+) -> Result<StatusCode, ApiError> {
+    faulted(&state, task_id)?;
+    blocking(move || Ok(state.tasks.cancel(task_id)?)).await?;
+    Ok(StatusCode::NO_CONTENT)
+}
@@ -0,0 +1,657 @@
+// Northbound interface: the JSON REST API OSS/BSS systems manage the fleet with.
+//
+// Served under /api/v1 on the UI listener. Devices are addressed by their
+// `OUI-ProductClass-SerialNumber` id, '-' in a field being `%2D`; in a URL path
+// the '%' itself is escaped again, e.g. `001A2B-Router%252DX-SN1`. Tasks queue an
+// operation for the CPE's next session, optionally starting that session right
+// away with a Connection Request.
+// Faults are tasks the CPE answered with a CWMP fault; they stay until retried or
+// deleted. /api/v1/events streams live events over a WebSocket, and the webhook
+// delivery history and dead-letter queue are under /api/v1/webhooks. The OpenAPI
+// document at /api/v1/openapi.json is generated from the `utoipa::path`
+// annotations of the handlers. Every request needs the configured API token as a
+// bearer token.
+mod devices;
+mod events;
+mod faults;
+mod tasks;
+mod webhooks;
+
+use crate::{auth::secret_matches, device::DeviceId, startup::AcsState, storage::StorageError};
+use axum::{
+    extract::{Request, State},
+    http::{header, HeaderValue, StatusCode},
+    middleware::{self, Next},
+    response::{IntoResponse, Response},
+    routing::{delete, get, post, put},
+    Json, Router,
+};
+use serde::Serialize;
+use std::{fmt, sync::Arc};
+use utoipa::{
+    openapi::security::{HttpAuthScheme, HttpBuilder, SecurityScheme},
+    Modify, OpenApi, ToSchema,
+};
+
+#[derive(Debug)]
+pub enum ApiError {
+    /// No device or task has the id in the path.
+    NotFound(String),
+    BadRequest(String),
+    /// The resource is not in a state the request applies to.
+    Conflict(String),
+    /// The API token is missing or wrong.
+    Unauthorized,
+    Storage(StorageError),
+}
+
+impl fmt::Display for ApiError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ApiError::NotFound(what) => write!(f, "{what} not found"),
+            ApiError::BadRequest(reason) => write!(f, "{reason}"),
+            ApiError::Conflict(reason) => write!(f, "{reason}"),
+            ApiError::Unauthorized => write!(f, "missing or invalid API token"),
+            ApiError::Storage(err) => write!(f, "storage error: {err}"),
+        }
+    }
+}
+
+impl std::error::Error for ApiError {}
+
+impl From<StorageError> for ApiError {
+    fn from(err: StorageError) -> Self {
+        ApiError::Storage(err)
+    }
+}
+
+/// Body of every error response.
+#[derive(Debug, Serialize, ToSchema)]
+pub struct ErrorBody {
+    pub error: String,
+}
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        let status = match &self {
+            ApiError::NotFound(_) => StatusCode::NOT_FOUND,
+            ApiError::BadRequest(_) => StatusCode::BAD_REQUEST,
+            ApiError::Conflict(_) => StatusCode::CONFLICT,
+            ApiError::Unauthorized => StatusCode::UNAUTHORIZED,
+            ApiError::Storage(err) => {
+                tracing::error!("Northbound request failed: {err}");
+                StatusCode::INTERNAL_SERVER_ERROR
+            }
+        };
+        let body = ErrorBody {
+            error: self.to_string(),
+        };
+        let mut response = (status, Json(body)).into_response();
+        if status == StatusCode::UNAUTHORIZED {
+            response
+                .headers_mut()
+                .insert(header::WWW_AUTHENTICATE, HeaderValue::from_static("Bearer"));
+        }
+        response
+    }
+}
+
+// Run storage work, which blocks, off the async runtime like the CWMP handler does.
+async fn blocking<T: Send + 'static>(
+    work: impl FnOnce() -> Result<T, ApiError> + Send + 'static,
+) -> Result<T, ApiError> {
+    match tokio::task::spawn_blocking(work).await {
+        Ok(result) => result,
+        Err(err) => std::panic::resume_unwind(err.into_panic()),
+    }
+}
+
+// Let requests carrying the configured token through; there is none to match
+// when no token is configured.
+async fn require_token(
+    State(token): State<Option<Arc<str>>>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let given = request
+        .headers()
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "));
+    match (token.as_deref(), given) {
+        (Some(token), Some(given)) if secret_matches(token, given.trim()) => {
+            next.run(request).await
+        }
+        _ => ApiError::Unauthorized.into_response(),
+    }
+}
+
+// Declares the bearer token in the OpenAPI document.
+struct ApiToken;
+
+impl Modify for ApiToken {
+    fn modify(&self, openapi: &mut utoipa::openapi::OpenApi) {
+        let components = openapi.components.get_or_insert_with(Default::default);
+        components.add_security_scheme(
+            "api_token",
+            SecurityScheme::Http(HttpBuilder::new().scheme(HttpAuthScheme::Bearer).build()),
+        );
+    }
+}
+
+fn parse_device_id(id: &str) -> Result<DeviceId, ApiError> {
+    id.parse().map_err(ApiError::BadRequest)
+}
+
+#[derive(OpenApi)]
+#[openapi(
+    info(
+        title = "tr069-acs northbound API",
+        description = "Devices, their cached Parameters, tasks and faults."
+    ),
+    modifiers(&ApiToken),
+    security(("api_token" = [])),
+    paths(
+        devices::list_devices,
+        devices::get_device,
+        devices::delete_device,
+        devices::get_parameters,
+        devices::set_credentials,
+        tasks::list_device_tasks,
+        tasks::create_task,
+        tasks::get_task,
+        tasks::delete_task,
+        faults::list_faults,
+        faults::list_device_faults,
+        faults::retry_fault,
+        faults::delete_fault,
+        events::stream_events,
+        webhooks::list_deliveries,
+        webhooks::get_delivery,
+        webhooks::redeliver,
+    ),
+    tags(
+        (name = "devices", description = "CPEs that Informed the ACS"),
+        (name = "tasks", description = "Operations queued for a CPE's next session"),
+        (name = "faults", description = "CWMP faults the CPEs answered ACS requests with"),
+        (name = "events", description = "Live device, session and task events"),
+        (name = "webhooks", description = "Deliveries of events to the configured webhooks"),
+    )
+)]
+pub struct ApiDoc;
+
+async fn openapi() -> Json<utoipa::openapi::OpenApi> {
+    Json(ApiDoc::openapi())
+}
+
+/// Routes of the northbound API, to merge into the UI router. Requests must carry
+/// `api_token` as bearer token, all are refused without one.
+pub fn router(state: AcsState, api_token: Option<String>) -> Router {
+    Router::new()
+        .route("/api/v1/devices", get(devices::list_devices))
+        .route(
+            "/api/v1/devices/:id",
+            get(devices::get_device).delete(devices::delete_device),
+        )
+        .route(
+            "/api/v1/devices/:id/parameters",
+            get(devices::get_parameters),
+        )
+        .route(
+            "/api/v1/devices/:id/credentials",
+            put(devices::set_credentials),
+        )
+        .route(
+            "/api/v1/devices/:id/tasks",
+            get(tasks::list_device_tasks).post(tasks::create_task),
+        )
+        .route(
+            "/api/v1/devices/:id/faults",
+            get(faults::list_device_faults),
+        )
+        .route(
+            "/api/v1/tasks/:task_id",
+            get(tasks::get_task).delete(tasks::delete_task),
+        )
+        .route("/api/v1/faults", get(faults::list_faults))
+        .route("/api/v1/faults/:task_id", delete(faults::delete_fault))
+        .route("/api/v1/faults/:task_id/retry", post(faults::retry_fault))
+        .route("/api/v1/events", get(events::stream_events))
+        .route(
+            "/api/v1/webhooks/deliveries",
+            get(webhooks::list_deliveries),
+        )
+        .route(
+            "/api/v1/webhooks/deliveries/:delivery_id",
+            get(webhooks::get_delivery),
+        )
+        .route(
+            "/api/v1/webhooks/deliveries/:delivery_id/redeliver",
+            post(webhooks::redeliver),
+        )
+        .route("/api/v1/openapi.json", get(openapi))
+        .route_layer(middleware::from_fn_with_state(
+            api_token.map(Arc::from),
+            require_token,
+        ))
+        .with_state(state)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        connection_request::ConnectionRequestClient,
+        cwmp_msg::{boot_inform, CWMPMsg, Envelope},
+        device::DeviceRegistry,
+        events::{Event, EventKind, SessionEnd},
+        storage::FaultRecord,
+        task::{Operation, TaskQueue, TaskResult, TaskStatus},
+    };
+    use chrono::Utc;
+    use futures_util::StreamExt;
+    use serde_json::{json, Value};
+    use std::time::Duration;
+
+    const TOKEN: &str = "nbi-test-token";
+
+    // A client sending the API token.
+    fn client() -> reqwest::Client {
+        let mut headers = reqwest::header::HeaderMap::new();
+        let bearer = format!("Bearer {TOKEN}");
+        headers.insert(header::AUTHORIZATION, bearer.parse().unwrap());
+        reqwest::Client::builder()
+            .default_headers(headers)
+            .build()
+            .unwrap()
+    }
+
+    // A websocket handshake sending the API token.
+    fn ws_request(url: &str) -> tokio_tungstenite::tungstenite::handshake::client::Request {
+        use tokio_tungstenite::tungstenite::client::IntoClientRequest;
+
+        let mut request = url.into_client_request().unwrap();
+        let bearer = format!("Bearer {TOKEN}");
+        request
+            .headers_mut()
+            .insert(header::AUTHORIZATION, bearer.parse().unwrap());
+        request
+    }
+
+    // The API on a loopback port, for a fleet of one device.
+    async fn serve() -> (String, AcsState) {
+        let devices = DeviceRegistry::new();
+        let inform = boot_inform(
+            "123456789",
+            &[
+                ("Device.DeviceInfo.SoftwareVersion", "xsd:string", "1.2.3"),
+                (
+                    "Device.ManagementServer.PeriodicInformInterval",
+                    "xsd:unsignedInt",
+                    "3600",
+                ),
+                (
+                    "Device.ManagementServer.ConnectionRequestPassword",
+                    "xsd:string",
+                    "secret",
+                ),
+            ],
+        );
+        let envelope = Envelope::parse(&inform).unwrap();
+        let Some(CWMPMsg::Inform(inform)) = envelope.msg() else {
+            panic!("not an Inform");
+        };
+        devices.record_inform(inform, Utc::now()).unwrap();
+        let tasks = TaskQueue::new(devices.storage().clone());
+        let state = AcsState::new(devices, tasks, ConnectionRequestClient::default());
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/api/v1", listener.local_addr().unwrap());
+        let router = router(state.clone(), Some(String::from(TOKEN)));
+        tokio::spawn(async move { axum::serve(listener, router).await.unwrap() });
+        (url, state)
+    }
+
+    #[tokio::test]
+    async fn test_devices_and_parameters() {
+        let (url, state) = serve().await;
+        let client = client();
+
+        let list: Value = client
+            .get(format!("{url}/devices?q=routerx"))
+            .send()
+            .await
+            .unwrap()
+            .json()
+            .await
+            .unwrap();
+        assert_eq!(list["total"], 1);
+        assert_eq!(list["devices"][0]["id"], "001A2B-RouterX-123456789");
+        assert_eq!(list["devices"][0]["software_version"], "1.2.3");
+        let list: Value = client
+            .get(format!("{url}/devices?oui=00D09E"))
+            .send()
+            .await
+            .unwrap()
+            .json()
+            .await
+            .unwrap();
+        assert_eq!(list["total"], 0);
+
+        let device = format!("{url}/devices/001A2B-RouterX-123456789");
+        let parameters: Value = client
+            .get(format!("{device}/parameters?path=Device.DeviceInfo."))
+            .send()
+            .await
+            .unwrap()
+            .json()
+            .await
+            .unwrap();
+        assert_eq!(parameters.as_object().unwrap().len(), 1);
+        assert_eq!(
+            parameters["Device.DeviceInfo.SoftwareVersion"]["value"],
+            "1.2.3"
+        );
+        let parameters: Value = client
+            .get(format!("{device}/parameters?path=Device.ManagementServer."))
+            .send()
+            .await
+            .unwrap()
+            .json()
+            .await
+            .unwrap();
+        let password = &parameters["Device.ManagementServer.ConnectionRequestPassword"];
+        assert_eq!(password["xsi_type"], "xsd:string");
+        assert!(password["value"].is_null());
+
+        let res = client
+            .get(format!("{url}/devices/bogus"))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+        let credentials = json!({ "username": "cpe1", "password": "pw1" });
+        let res = client
+            .put(format!("{device}/credentials"))
+            .json(&credentials)
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(res.status(), StatusCode::NO_CONTENT);
+        let id = "001A2B-RouterX-123456789".parse().unwrap();
+        assert_eq!(state.devices.find_by_cwmp_username("cpe1"), Some(id));
+        let res = client
+            .put(format!(
+                "{url}/devices/001A2B-RouterX-987654321/credentials"
+            ))
+            .json(&credentials)
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(res.status(), StatusCode::NOT_FOUND);
+
+        let res = client.delete(&device).send().await.unwrap();
+        assert_eq!(res.status(), StatusCode::NO_CONTENT);
+        let res = client.get(&device).send().await.unwrap();
+        assert_eq!(res.status(), StatusCode::NOT_FOUND);
+        let body: Value = res.json().await.unwrap();
+        assert!(body["error"].as_str().unwrap().contains("not found"));
+
+        let document: Value = client
+            .get(format!("{url}/openapi.json"))
+            .send()
+            .await
+            .unwrap()
+            .json()
+            .await
+            .unwrap();
+        assert!(document["paths"]["/api/v1/devices/{id}/tasks"]["post"].is_object());
+        assert!(document["components"]["schemas"]["Operation"].is_object());
+    }
+
+    #[tokio::test]
+    async fn test_api_token() {
+        let (url, _) = serve().await;
+
+        let res = reqwest::get(format!("{url}/devices")).await.unwrap();
+        assert_eq!(res.status(), StatusCode::UNAUTHORIZED);
+        assert_eq!(res.headers()[header::WWW_AUTHENTICATE], "Bearer");
+        let res = reqwest::Client::new()
+            .get(format!("{url}/devices"))
+            .bearer_auth("nbi-test-tokem")
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(res.status(), StatusCode::UNAUTHORIZED);
+        let res = client().get(format!("{url}/devices")).send().await.unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/api/v1", listener.local_addr().unwrap());
+        let router = router(AcsState::default(), None);
+        tokio::spawn(async move { axum::serve(listener, router).await.unwrap() });
+        let res = client().get(format!("{url}/devices")).send().await.unwrap();
+        assert_eq!(res.status(), StatusCode::UNAUTHORIZED);
+    }
+
+    #[tokio::test]
+    async fn test_tasks_and_faults() {
+        let (url, state) = serve().await;
+        let client = client();
+        let tasks = format!("{url}/devices/001A2B-RouterX-123456789/tasks");
+        let reboot = json!({ "operation": { "type": "reboot", "command_key": "nbi" } });
+
+        let res = client.post(&tasks).json(&reboot).send().await.unwrap();
+        assert_eq!(res.status(), StatusCode::CREATED);
+        let task: Value = res.json().await.unwrap();
+        assert_eq!(task["status"], "pending");
+        let id = task["id"].as_u64().unwrap();
+
+        // The interval is reported as xsd:unsignedInt.
+        for (xsi_type, value) in [("xsd:unsignedInt", "often"), ("xsd:int", "60")] {
+            let set = json!({ "operation": {
+                "type": "set_parameter_values",
+                "parameters": [{
+                    "name": "Device.ManagementServer.PeriodicInformInterval",
+                    "xsi_type": xsi_type,
+                    "value": value,
+                }],
+            } });
+            let res = client.post(&tasks).json(&set).send().await.unwrap();
+            assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+        }
+
+        // Passwords the ACS sets are not shown back, nor sent in events.
+        let set = json!({ "operation": {
+            "type": "set_parameter_values",
+            "parameters": [{
+                "name": "Device.ManagementServer.ConnectionRequestPassword",
+                "xsi_type": "xsd:string",
+                "value": "s3cret",
+            }],
+        } });
+        let res = client.post(&tasks).json(&set).send().await.unwrap();
+        assert_eq!(res.status(), StatusCode::CREATED);
+        let created = res.text().await.unwrap();
+        assert!(!created.contains("s3cret"), "{created}");
+        let set_id = serde_json::from_str::<Value>(&created).unwrap()["id"]
+            .as_u64()
+            .unwrap();
+        let shown = client
+            .get(format!("{url}/tasks/{set_id}"))
+            .send()
+            .await
+            .unwrap()
+            .text()
+            .await
+            .unwrap();
+        assert!(!shown.contains("s3cret"), "{shown}");
+        let task = state.tasks.complete(set_id, TaskResult::Done).unwrap();
+        let Operation::SetParameterValues { parameters, .. } = &task.operation else {
+            panic!("not a SetParameterValues");
+        };
+        assert_eq!(parameters[0].value, "s3cret");
+        let event = Event {
+            time: Utc::now(),
+            device_id: task.device_id.clone(),
+            kind: EventKind::TaskCompleted { task },
+        };
+        assert!(!serde_json::to_string(&event).unwrap().contains("s3cret"));
+
+        state.tasks.complete(
+            id,
+            TaskResult::Fault {
+                code: 9001,
+                message: String::from("Request denied"),
+            },
+        );
+
+        // The CPE runs the next task while the client waits for it.
+        let queue = state.tasks.clone();
+        tokio::spawn(async move {
+            loop {
+                tokio::time::sleep(Duration::from_millis(50)).await;
+                let pending = queue
+                    .list()
+                    .into_iter()
+                    .find(|task| task.status == TaskStatus::Pending);
+                if let Some(task) = pending {
+                    queue.complete(task.id, TaskResult::Done);
+                    break;
+                }
+            }
+        });
+        let res = client
+            .post(format!("{tasks}?wait=5"))
+            .json(&json!({ "operation": { "type": "factory_reset" } }))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+        let waited: Value = res.json().await.unwrap();
+        assert_eq!(waited["status"], "completed");
+
+        let faults: Value = client
+            .get(format!("{url}/faults?device_id=001A2B-RouterX-123456789"))
+            .send()
+            .await
+            .unwrap()
+            .json()
+            .await
+            .unwrap();
+        assert_eq!(faults.as_array().unwrap().len(), 1);
+        assert_eq!(faults[0]["result"]["code"], 9001);
+
+        let res = client
+            .post(format!("{url}/faults/{id}/retry"))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+        assert_eq!(state.tasks.get(id).unwrap().status, TaskStatus::Pending);
+        // Only faulted tasks can be retried or deleted as faults.
+        let res = client
+            .delete(format!("{url}/faults/{id}"))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(res.status(), StatusCode::CONFLICT);
+
+        // The fault history outlives the retry.
+        let device_id = "001A2B-RouterX-123456789".parse().unwrap();
+        let fault = FaultRecord {
+            device_id,
+            time: Utc::now(),
+            code: 9001,
+            message: String::from("Request denied"),
+        };
+        state.devices.storage().add_fault(&fault).unwrap();
+        let history: Value = client
+            .get(format!("{url}/devices/001A2B-RouterX-123456789/faults"))
+            .send()
+            .await
+            .unwrap()
+            .json()
+            .await
+            .unwrap();
+        assert_eq!(history.as_array().unwrap().len(), 1);
+        assert_eq!(history[0]["message"], "Request denied");
+
+        let res = client
+            .delete(format!("{url}/tasks/{id}"))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(res.status(), StatusCode::NO_CONTENT);
+        let listed: Value = client
+            .get(&tasks)
+            .send()
+            .await
+            .unwrap()
+            .json()
+            .await
+            .unwrap();
+        assert_eq!(listed.as_array().unwrap().len(), 2);
+        assert!(!listed.to_string().contains("s3cret"));
+
+        let res = client
+            .post(format!("{url}/devices/001A2B-RouterX-987654321/tasks"))
+            .json(&reboot)
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(res.status(), StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn test_event_stream() {
+        use tokio_tungstenite::tungstenite::{Error, Message};
+
+        let (url, state) = serve().await;
+        let url = url.replacen("http", "ws", 1);
+        let (mut stream, _) = tokio_tungstenite::connect_async(ws_request(&format!(
+            "{url}/events?product_class=RouterX&type=session_ended,task_failed"
+        )))
+        .await
+        .unwrap();
+
+        let router = "001A2B-RouterX-123456789".parse().unwrap();
+        let modem = "00D09E-ModemY-1".parse().unwrap();
+        let task = state
+            .tasks
+            .enqueue(
+                router,
+                Operation::Reboot {
+                    command_key: String::from("nbi"),
+                },
+                None,
+            )
+            .unwrap();
+        let router = &task.device_id;
+        state.events.publish(router, EventKind::SessionStarted);
+        state.events.publish(
+            &modem,
+            EventKind::SessionEnded {
+                reason: SessionEnd::Closed,
+            },
+        );
+        state
+            .events
+            .publish(router, EventKind::TaskFailed { task: task.clone() });
+
+        let Some(Ok(Message::Text(text))) = stream.next().await else {
+            panic!("no event");
+        };
+        let event: Value = serde_json::from_str(&text).unwrap();
+        assert_eq!(event["type"], "task_failed");
+        assert_eq!(event["device_id"], "001A2B-RouterX-123456789");
+        assert_eq!(event["task"]["id"], task.id);
+
+        let refused =
+            tokio_tungstenite::connect_async(ws_request(&format!("{url}/events?type=bogus"))).await;
+        let Err(Error::Http(response)) = refused else {
+            panic!("an unknown event type was accepted");
+        };
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    }
+}
@@ -0,0 +1,170 @@
+use super::{blocking, parse_device_id, ApiError, ErrorBody};
+use crate::{
+    startup::AcsState,
+    task::{Operation, Task, TaskStatus},
+};
+use axum::{
+    extract::{Path, Query, State},
+    http::StatusCode,
+    Json,
+};
+use chrono::{DateTime, Utc};
+use serde::Deserialize;
+use std::time::{Duration, Instant};
+use utoipa::{IntoParams, ToSchema};
+
+/// Longest a client may wait for a task to finish.
+const MAX_WAIT: Duration = Duration::from_secs(300);
+/// How often a waiting request checks the task.
+const WAIT_POLL_INTERVAL: Duration = Duration::from_millis(200);
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct NewTask {
+    pub operation: Operation,
+    /// The task is not sent after this time.
+    #[serde(default)]
+    pub expires: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct EnqueueQuery {
+    /// Send a Connection Request so the CPE runs the task now, rather than at
+    /// its next periodic Inform.
+    #[serde(default)]
+    connection_request: bool,
+    /// Seconds to wait for the task to finish, at most 300.
+    wait: Option<u64>,
+}
+
+/// Tasks of a device, in the order they were queued.
+#[utoipa::path(
+    get,
+    path = "/api/v1/devices/{id}/tasks",
+    tag = "tasks",
+    params(("id" = String, Path, description = "OUI-ProductClass-SerialNumber")),
+    responses(
+        (status = 200, body = Vec<Task>),
+        (status = 404, body = ErrorBody),
+    )
+)]
+pub async fn list_device_tasks(
+    State(state): State<AcsState>,
+    Path(id): Path<String>,
+) -> Result<Json<Vec<Task>>, ApiError> {
+    let id = parse_device_id(&id)?;
+    if state.devices.get(&id).is_none() {
+        return Err(ApiError::NotFound(format!("device {id}")));
+    }
+    Ok(Json(state.tasks.tasks_for(&id)))
+}
+
+/// Queue an operation for the device's next session.
+///
+/// With `wait`, the response is held until the task finished (200) or the wait
+/// ran out (202), and carries the task as it is then. Values to set must parse
+/// as their xsi:type, which must be the type the device reported for the Parameter.
+#[utoipa::path(
+    post,
+    path = "/api/v1/devices/{id}/tasks",
+    tag = "tasks",
+    params(("id" = String, Path, description = "OUI-ProductClass-SerialNumber"), EnqueueQuery),
+    request_body = NewTask,
+    responses(
+        (status = 201, description = "Queued", body = Task),
+        (status = 200, description = "Finished while waiting", body = Task),
+        (status = 202, description = "Still pending after waiting", body = Task),
+        (status = 400, description = "Invalid value to set", body = ErrorBody),
+        (status = 404, body = ErrorBody),
+    )
+)]
+pub async fn create_task(
+    State(state): State<AcsState>,
+    Path(id): Path<String>,
+    Query(query): Query<EnqueueQuery>,
+    Json(new_task): Json<NewTask>,
+) -> Result<(StatusCode, Json<Task>), ApiError> {
+    let id = parse_device_id(&id)?;
+    let Some(device) = state.devices.get(&id) else {
+        return Err(ApiError::NotFound(format!("device {id}")));
+    };
+    new_task
+        .operation
+        .check_values(&device)
+        .map_err(|err| ApiError::BadRequest(err.to_string()))?;
+    let enqueuer = state.clone();
+    let task = blocking(move || {
+        let NewTask { operation, expires } = new_task;
+        Ok(if query.connection_request {
+            enqueuer.enqueue_task(id, operation, expires)?
+        } else {
+            enqueuer.tasks.enqueue(id, operation, expires)?
+        })
+    })
+    .await?;
+    let Some(wait) = query.wait else {
+        return Ok((StatusCode::CREATED, Json(task)));
+    };
+    let task = wait_for(&state, task, Duration::from_secs(wait).min(MAX_WAIT)).await;
+    let status = match task.status {
+        TaskStatus::Pending => StatusCode::ACCEPTED,
+        _ => StatusCode::OK,
+    };
+    Ok((status, Json(task)))
+}
+
+// The task once it is no longer pending, or as it is after `wait`.
+async fn wait_for(state: &AcsState, mut task: Task, wait: Duration) -> Task {
+    let start = Instant::now();
+    while task.status == TaskStatus::Pending && start.elapsed() < wait {
+        tokio::time::sleep(WAIT_POLL_INTERVAL).await;
+        match state.tasks.get(task.id) {
+            Some(current) => task = current,
+            // Deleted meanwhile.
+            None => break,
+        }
+    }
+    task
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/tasks/{task_id}",
+    tag = "tasks",
+    params(("task_id" = u64, Path)),
+    responses(
+        (status = 200, body = Task),
+        (status = 404, body = ErrorBody),
+    )
+)]
+pub async fn get_task(
+    State(state): State<AcsState>,
+    Path(task_id): Path<u64>,
+) -> Result<Json<Task>, ApiError> {
+    state
+        .tasks
+        .get(task_id)
+        .map(Json)
+        .ok_or_else(|| ApiError::NotFound(format!("task {task_id}")))
+}
+
+/// Delete a task, finished or not. A pending task is not sent.
+#[utoipa::path(
+    delete,
+    path = "/api/v1/tasks/{task_id}",
+    tag = "tasks",
+    params(("task_id" = u64, Path)),
+    responses(
+        (status = 204, description = "Deleted"),
+        (status = 404, body = ErrorBody),
+    )
+)]
+pub async fn delete_task(
+    State(state): State<AcsState>,
+    Path(task_id): Path<u64>,
+) -> Result<StatusCode, ApiError> {
+    match blocking(move || Ok(state.tasks.cancel(task_id)?)).await? {
+        Some(_) => Ok(StatusCode::NO_CONTENT),
+        None => Err(ApiError::NotFound(format!("task {task_id}"))),
+    }
+}
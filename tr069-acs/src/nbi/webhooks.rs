@@ -0,0 +1,98 @@
+use super::{ApiError, ErrorBody};
+use crate::{
+    startup::AcsState,
+    webhook::{Delivery, DeliveryStatus},
+};
+use axum::{
+    extract::{Path, Query, State},
+    Json,
+};
+use serde::Deserialize;
+use utoipa::IntoParams;
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct DeliveryQuery {
+    /// Only deliveries to the webhook of this name.
+    webhook: Option<String>,
+    /// `dead` lists the dead-letter queue.
+    status: Option<DeliveryStatus>,
+}
+
+/// Delivery history, oldest first.
+#[utoipa::path(
+    get,
+    path = "/api/v1/webhooks/deliveries",
+    tag = "webhooks",
+    params(DeliveryQuery),
+    responses((status = 200, body = Vec<Delivery>))
+)]
+pub async fn list_deliveries(
+    State(state): State<AcsState>,
+    Query(query): Query<DeliveryQuery>,
+) -> Json<Vec<Delivery>> {
+    let deliveries = state
+        .webhooks
+        .list()
+        .into_iter()
+        .filter(|delivery| {
+            query
+                .webhook
+                .as_ref()
+                .is_none_or(|name| &delivery.webhook == name)
+        })
+        .filter(|delivery| query.status.is_none_or(|status| delivery.status == status))
+        .collect();
+    Json(deliveries)
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/webhooks/deliveries/{delivery_id}",
+    tag = "webhooks",
+    params(("delivery_id" = u64, Path)),
+    responses(
+        (status = 200, body = Delivery),
+        (status = 404, body = ErrorBody),
+    )
+)]
+pub async fn get_delivery(
+    State(state): State<AcsState>,
+    Path(delivery_id): Path<u64>,
+) -> Result<Json<Delivery>, ApiError> {
+    state
+        .webhooks
+        .get(delivery_id)
+        .map(Json)
+        .ok_or_else(|| ApiError::NotFound(format!("delivery {delivery_id}")))
+}
+
+/// Take a delivery out of the dead-letter queue and attempt it again.
+#[utoipa::path(
+    post,
+    path = "/api/v1/webhooks/deliveries/{delivery_id}/redeliver",
+    tag = "webhooks",
+    params(("delivery_id" = u64, Path)),
+    responses(
+        (status = 200, description = "Pending again", body = Delivery),
+        (status = 404, body = ErrorBody),
+        (status = 409, description = "The delivery is not dead", body = ErrorBody),
+    )
+)]
+pub async fn redeliver(
+    State(state): State<AcsState>,
+    Path(delivery_id): Path<u64>,
+) -> Result<Json<Delivery>, ApiError> {
+    let not_found = || ApiError::NotFound(format!("delivery {delivery_id}"));
+    let delivery = state.webhooks.get(delivery_id).ok_or_else(not_found)?;
+    if delivery.status != DeliveryStatus::Dead {
+        return Err(ApiError::Conflict(format!(
+            "delivery {delivery_id} is not dead"
+        )));
+    }
+    state
+        .webhooks
+        .redeliver(delivery_id)
+        .map(Json)
+        .ok_or_else(not_found)
+}
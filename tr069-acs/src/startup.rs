@@ -1,37 +1,630 @@
-use crate::cwmp_msg::{self, CWMPMsg, Envelope, InformResponse};
-use axum::{response, Router};
-use axum_xml_up::Xml;
+use crate::auth::{AuthError, CpeAuthenticator};
+use crate::connection_request::ConnectionRequestClient;
+use crate::cwmp_msg::session::{
+    CwmpSession, SessionReply, SessionState, SessionTable, DEFAULT_SESSION_TIMEOUT, SESSION_COOKIE,
+};
+use crate::cwmp_msg::{AcsFaultCode, CWMPMsg, CwmpFault, Envelope, EnvelopeError, SoapFault};
+use crate::device::{DeviceId, DeviceRegistry, ParameterChange};
+use crate::events::{EventBus, EventKind, SessionEnd};
+use crate::storage::FaultRecord;
+use crate::storage::StorageError;
+use crate::task::{Operation, Task, TaskQueue, TaskResult, TaskStatus};
+use crate::tls::{ClientCertificate, ReloadableTlsConfig};
+use crate::webhook::Webhooks;
+use axum::{
+    extract::{ConnectInfo, State},
+    http::{header, HeaderMap, HeaderValue, Request, StatusCode, Uri},
+    response::{IntoResponse, Response},
+    routing::post,
+    Extension, Router,
+};
+use chrono::Utc;
+use hyper::{body::Incoming, server::conn::http1, service::service_fn};
+use hyper_util::rt::TokioIo;
+use std::{
+    future::Future,
+    net::SocketAddr,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+    time::{Duration, Instant},
+};
 use tokio::net::TcpListener;
-// use axum::response::S
-use yaserde;
+use tower::Service;
+
+/// Open CWMP sessions. A session spans every POST the CPE makes, on one or more
+/// connections, until the ACS answers with HTTP 204.
+#[derive(Clone)]
+pub struct AcsState {
+    sessions: Arc<Mutex<SessionTable>>,
+    pub devices: DeviceRegistry,
+    pub tasks: TaskQueue,
+    pub connection_requests: ConnectionRequestClient,
+    /// CPEs must authenticate when set, anyone may open a session otherwise.
+    pub cpe_auth: Option<CpeAuthenticator>,
+    /// Used for CPEs whose Inform has no SessionTimeout header.
+    pub session_timeout: Duration,
+    /// Ask CPEs with pending tasks to hold their own requests.
+    pub hold_requests: bool,
+    /// Device, session and task events, streamed by the northbound API.
+    pub events: EventBus,
+    /// Deliveries of events to the configured webhooks.
+    pub webhooks: Webhooks,
+    /// Set at shutdown, new sessions are refused while open ones finish.
+    draining: Arc<AtomicBool>,
+}
+
+/// How often `drain` checks whether the last session has ended.
+const DRAIN_POLL_INTERVAL: Duration = Duration::from_millis(100);
+/// Seconds a CPE refused during shutdown is asked to wait before retrying.
+const DRAIN_RETRY_AFTER: &str = "30";
+
+impl AcsState {
+    pub fn new(
+        devices: DeviceRegistry,
+        tasks: TaskQueue,
+        connection_requests: ConnectionRequestClient,
+    ) -> Self {
+        Self {
+            sessions: Arc::default(),
+            devices,
+            tasks,
+            connection_requests,
+            cpe_auth: None,
+            session_timeout: DEFAULT_SESSION_TIMEOUT,
+            hold_requests: false,
+            events: EventBus::default(),
+            webhooks: Webhooks::default(),
+            draining: Arc::default(),
+        }
+    }
+
+    pub fn with_cpe_auth(mut self, auth: CpeAuthenticator) -> Self {
+        self.cpe_auth = Some(auth);
+        self
+    }
+
+    pub fn with_session_timeout(mut self, timeout: Duration) -> Self {
+        self.session_timeout = timeout;
+        self
+    }
+
+    pub fn with_hold_requests(mut self, hold: bool) -> Self {
+        self.hold_requests = hold;
+        self
+    }
+
+    pub fn with_webhooks(mut self, webhooks: Webhooks) -> Self {
+        self.webhooks = webhooks;
+        self
+    }
+
+    /// Keep at most `max_sessions` sessions open.
+    pub fn with_max_sessions(mut self, max_sessions: usize) -> Self {
+        self.sessions = Arc::new(Mutex::new(SessionTable::with_capacity(max_sessions)));
+        self
+    }
+
+    /// Refuse new sessions and wait up to `deadline` for the open ones to end.
+    /// Sessions still open then are aborted, and their number returned. Their
+    /// tasks stay pending, tasks only finish once the CPE answered them, so they
+    /// are sent again in the CPE's next session.
+    pub fn drain(&self, deadline: Duration) -> impl Future<Output = usize> {
+        self.draining.store(true, Ordering::SeqCst);
+        let sessions = self.sessions.clone();
+        let events = self.events.clone();
+        async move {
+            let start = Instant::now();
+            loop {
+                let ended = {
+                    let mut sessions = sessions.lock().unwrap();
+                    let expired = sessions.remove_expired();
+                    publish_ended(&events, &expired, SessionEnd::TimedOut);
+                    sessions.is_empty()
+                };
+                if ended {
+                    return 0;
+                }
+                if start.elapsed() >= deadline {
+                    break;
+                }
+                tokio::time::sleep(DRAIN_POLL_INTERVAL).await;
+            }
+            let aborted = sessions.lock().unwrap().drain();
+            publish_ended(&events, &aborted, SessionEnd::Shutdown);
+            for session in &aborted {
+                let device = session
+                    .device_id()
+                    .map_or(String::from("unknown CPE"), |id| id.to_string());
+                match session.in_flight_task() {
+                    Some(task) => {
+                        tracing::warn!("Aborted the session of {device}, task {task} stays pending")
+                    }
+                    None => tracing::warn!("Aborted the session of {device}"),
+                }
+            }
+            aborted.len()
+        }
+    }
+
+    pub fn is_draining(&self) -> bool {
+        self.draining.load(Ordering::SeqCst)
+    }
+
+    /// Queue `operation` and send a Connection Request so the CPE runs it now
+    /// rather than at its next periodic Inform.
+    pub fn enqueue_task(
+        &self,
+        device_id: DeviceId,
+        operation: Operation,
+        expires: Option<chrono::DateTime<Utc>>,
+    ) -> Result<Task, StorageError> {
+        let task = self.tasks.enqueue(device_id.clone(), operation, expires)?;
+        self.request_connection(&device_id);
+        Ok(task)
+    }
+
+    /// Send a Connection Request in the background. Queued tasks still run at the
+    /// next Inform if the CPE cannot be reached.
+    pub fn request_connection(&self, device_id: &DeviceId) {
+        if let Some(device) = self.devices.get(device_id) {
+            let client = self.connection_requests.clone();
+            tokio::spawn(async move {
+                if let Err(err) = client.send(&device).await {
+                    tracing::warn!("Connection Request to {} failed: {err}", device.id);
+                }
+            });
+        }
+    }
+}
+
+impl Default for AcsState {
+    fn default() -> Self {
+        let devices = DeviceRegistry::new();
+        let tasks = TaskQueue::new(devices.storage().clone());
+        Self::new(devices, tasks, ConnectionRequestClient::default())
+    }
+}
+
+fn cwmp_router(state: AcsState) -> Router {
+    Router::new()
+        .route("/", post(xml_request_handler))
+        .with_state(state)
+}
 
 #[cfg(feature = "server")]
-pub async fn run(listener: TcpListener) {
-    // dioxus::logger::initialize_default();
-
-    use axum::{middleware, routing::post};
-
-    // use crate::cwmp_msg::session::{cwmp_session_handle, print_request_response};
-    // let server_addr = SocketAddr::new(listener.local_addr());
-    // tracing::info!("{server_addr}");
-    //
-    // //Build a custom router
-    let router = Router::new().route("/", post(xml_request_handler));
-    // .layer(middleware::from_fn(print_request_response));
-    axum::serve(listener, router).await.unwrap();
-}
-
-#[axum::debug_handler]
-pub async fn xml_request_handler(envelope: String) {
-    tracing::info!("Get xml body: {:?}", envelope);
-    // match envelope.get_rpc_type() {
-    //     _ => {
-    //         tracing::info!("Got message type");
-    //     }
-    // }
-    // let response = InformResponse::default();
-    // let envelope_response = Envelope::new(CWMPMsg::InformResponse(response));
-    let xml_payload: Envelope = yaserde::de::from_str(&envelope).unwrap();
-    tracing::info!("response xml {:?}", xml_payload);
-    // Xml(envelope_response)
+pub async fn run(listener: TcpListener, state: AcsState) {
+    let router = cwmp_router(state);
+    if let Err(err) = axum::serve(
+        listener,
+        router.into_make_service_with_connect_info::<SocketAddr>(),
+    )
+    .await
+    {
+        tracing::error!("CWMP server failed: {err}");
+    }
+}
+
+/// Resolves on Ctrl-C, or SIGTERM on Unix.
+pub async fn shutdown_signal() {
+    let interrupt = async {
+        if let Err(err) = tokio::signal::ctrl_c().await {
+            tracing::warn!("Cannot listen for Ctrl-C: {err}");
+            std::future::pending::<()>().await;
+        }
+    };
+    #[cfg(unix)]
+    let terminate = async {
+        use tokio::signal::unix::{signal, SignalKind};
+        match signal(SignalKind::terminate()) {
+            Ok(mut terminate) => {
+                terminate.recv().await;
+            }
+            Err(err) => {
+                tracing::warn!("Cannot listen for SIGTERM: {err}");
+                std::future::pending::<()>().await;
+            }
+        }
+    };
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+    tokio::select! {
+        _ = interrupt => {}
+        _ = terminate => {}
+    }
+}
+
+/// Serve the CWMP endpoint over TLS. A verified client certificate is handed to
+/// the handler, which then identifies the CPE by it instead of HTTP authentication.
+pub async fn run_tls(listener: TcpListener, state: AcsState, tls: ReloadableTlsConfig) {
+    let router = cwmp_router(state);
+    loop {
+        let (socket, peer) = match listener.accept().await {
+            Ok(accepted) => accepted,
+            Err(err) => {
+                tracing::warn!("Failed to accept a CWMP connection: {err}");
+                continue;
+            }
+        };
+        let acceptor = tls.acceptor();
+        let router = router.clone();
+        tokio::spawn(async move {
+            let stream = match acceptor.accept(socket).await {
+                Ok(stream) => stream,
+                Err(err) => {
+                    tracing::info!("TLS handshake with {peer} failed: {err}");
+                    return;
+                }
+            };
+            let certificate = stream
+                .get_ref()
+                .1
+                .peer_certificates()
+                .and_then(|chain| chain.first())
+                .and_then(|leaf| ClientCertificate::from_der(leaf));
+            let service = service_fn(move |mut request: Request<Incoming>| {
+                request.extensions_mut().insert(ConnectInfo(peer));
+                if let Some(certificate) = &certificate {
+                    request.extensions_mut().insert(certificate.clone());
+                }
+                router.clone().call(request)
+            });
+            if let Err(err) = http1::Builder::new()
+                .serve_connection(TokioIo::new(stream), service)
+                .await
+            {
+                tracing::info!("CWMP connection with {peer} failed: {err}");
+            }
+        });
+    }
+}
+
+pub async fn xml_request_handler(
+    State(state): State<AcsState>,
+    ConnectInfo(peer): ConnectInfo<SocketAddr>,
+    certificate: Option<Extension<ClientCertificate>>,
+    uri: Uri,
+    headers: HeaderMap,
+    body: String,
+) -> Response {
+    let envelope = if body.trim().is_empty() {
+        None
+    } else {
+        match Envelope::parse(&body) {
+            Ok(envelope) => Some(envelope),
+            Err(err) => {
+                tracing::warn!("Failed to parse CWMP envelope from {peer}: {err}");
+                let fault = match err {
+                    EnvelopeError::MustUnderstand(header) => {
+                        Envelope::new(CWMPMsg::Fault(SoapFault::must_understand(&header)))
+                    }
+                    EnvelopeError::Malformed(err) => {
+                        let fault =
+                            CwmpFault::acs(AcsFaultCode::InvalidArguments).with_message(err);
+                        Envelope::fault(&fault, None)
+                    }
+                };
+                return xml_response(StatusCode::INTERNAL_SERVER_ERROR, &fault);
+            }
+        }
+    };
+
+    let (key, opening, in_flight_task, device_id, awaiting_inform) = {
+        let mut sessions = state.sessions.lock().unwrap();
+        let expired = sessions.remove_expired();
+        publish_ended(&state.events, &expired, SessionEnd::TimedOut);
+        let (is_inform, inform) = match envelope.as_ref().and_then(Envelope::msg) {
+            Some(CWMPMsg::Inform(inform)) => {
+                (true, DeviceId::from_device_id_struct(inform.device_id()))
+            }
+            _ => (false, None),
+        };
+        let mut key = sessions.find(session_cookie(&headers), peer);
+        // An Inform always opens a new session, whatever the CPE left behind.
+        if is_inform {
+            if let Some(stale) = key.take() {
+                tracing::info!("CPE at {peer} Informed again, dropping its open session");
+                let stale: Vec<_> = sessions.remove(&stale).into_iter().collect();
+                publish_ended(&state.events, &stale, SessionEnd::Replaced);
+            }
+        }
+        // Only the POST opening a session is authenticated.
+        let opening = key.is_none();
+        if opening && state.is_draining() {
+            tracing::info!("Refusing a new session from {peer}, shutting down");
+            return (
+                StatusCode::SERVICE_UNAVAILABLE,
+                [(header::RETRY_AFTER, DRAIN_RETRY_AFTER)],
+            )
+                .into_response();
+        }
+        if let (Some(Extension(certificate)), true) = (&certificate, opening) {
+            if !inform.as_ref().is_some_and(|id| certificate.matches(id)) {
+                tracing::warn!("CPE at {peer} Informed as another device than its certificate");
+                return StatusCode::FORBIDDEN.into_response();
+            }
+        } else if let (Some(auth), true) = (&state.cpe_auth, opening) {
+            let authorization = headers
+                .get(header::AUTHORIZATION)
+                .and_then(|value| value.to_str().ok());
+            let path = uri.path_and_query().map_or("/", |path| path.as_str());
+            if let Err(err) =
+                auth.authenticate(authorization, path, &state.devices, inform.as_ref())
+            {
+                if err != AuthError::Missing {
+                    tracing::warn!("CPE at {peer} failed to authenticate: {err}");
+                }
+                return unauthorized(auth, err == AuthError::StaleNonce);
+            }
+        }
+        let key = key.unwrap_or_else(|| {
+            let mut session = CwmpSession::with_timeout(state.session_timeout);
+            session.hold_cpe_requests(state.hold_requests);
+            sessions.insert(peer, session)
+        });
+        let session = sessions.get_mut(&key).expect("session was just found");
+        let awaiting_inform = session.state() == SessionState::AwaitingInform;
+        (
+            key,
+            opening,
+            session.in_flight_task(),
+            session.device_id().cloned(),
+            awaiting_inform,
+        )
+    };
+
+    // Storage is written without holding the session table, other CPEs' requests
+    // go on meanwhile.
+    let recorder = state.clone();
+    let record = tokio::task::spawn_blocking(move || {
+        let tasks = record_message(
+            &recorder,
+            peer,
+            envelope.as_ref().and_then(Envelope::msg),
+            device_id.as_ref(),
+            in_flight_task,
+            awaiting_inform.then_some(opening),
+        );
+        (envelope, tasks)
+    });
+    let (envelope, tasks) = match record.await {
+        Ok(recorded) => recorded,
+        Err(err) => {
+            tracing::error!("Failed to record the message of {peer}: {err}");
+            return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+        }
+    };
+
+    let (reply, dispatched, cookie) = {
+        let mut sessions = state.sessions.lock().unwrap();
+        let Some(session) = sessions.get_mut(&key) else {
+            tracing::warn!("CWMP session with {peer} ended while its message was recorded");
+            return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+        };
+        for task in tasks {
+            session.enqueue_task(task.id, task.operation.request());
+        }
+        let reply = session.handle(envelope);
+        let dispatched = session
+            .in_flight_task()
+            .filter(|task| Some(*task) != in_flight_task);
+        let closed = reply.is_err() || session.is_closed();
+        if closed {
+            let reason = if reply.is_ok() {
+                SessionEnd::Closed
+            } else {
+                SessionEnd::Aborted
+            };
+            let ended: Vec<_> = sessions.remove(&key).into_iter().collect();
+            publish_ended(&state.events, &ended, reason);
+        }
+        // The cookie is set with the InformResponse and cleared at the end.
+        let cookie = match (opening, closed) {
+            (true, false) => Some(format!("{SESSION_COOKIE}={key}; Path=/; HttpOnly")),
+            (false, true) => Some(format!("{SESSION_COOKIE}=; Path=/; Max-Age=0")),
+            _ => None,
+        };
+        (reply, dispatched, cookie)
+    };
+    // A task stays pending until answered, so it is sent again if the CPE drops
+    // the session before responding.
+    if let Some(task) = dispatched {
+        let tasks = state.tasks.clone();
+        if let Err(err) = tokio::task::spawn_blocking(move || tasks.dispatched(task)).await {
+            tracing::error!("Failed to count the attempt of task {task}: {err}");
+        }
+    }
+
+    let mut response = match reply {
+        Ok(SessionReply::Message(envelope)) => xml_response(StatusCode::OK, &envelope),
+        Ok(SessionReply::Fault(envelope)) => {
+            xml_response(StatusCode::INTERNAL_SERVER_ERROR, &envelope)
+        }
+        Ok(SessionReply::NoContent) => StatusCode::NO_CONTENT.into_response(),
+        Err(err) => {
+            tracing::warn!("CWMP session with {peer} aborted: {err}");
+            xml_response(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                &Envelope::fault(&err.fault(), None),
+            )
+        }
+    };
+    if let Some(cookie) = cookie.and_then(|cookie| HeaderValue::from_str(&cookie).ok()) {
+        response.headers_mut().insert(header::SET_COOKIE, cookie);
+    }
+    response
+}
+
+// Value of the session cookie among the request's cookies.
+fn session_cookie(headers: &HeaderMap) -> Option<&str> {
+    headers
+        .get_all(header::COOKIE)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|cookies| cookies.split(';'))
+        .filter_map(|cookie| cookie.trim().split_once('='))
+        .find_map(|(name, value)| (name == SESSION_COOKIE).then_some(value))
+}
+
+// Record an Inform or a response in storage and publish it. `opening` is set
+// for the session's first Inform, whether it opened the session; the device's
+// pending tasks are returned then.
+fn record_message(
+    state: &AcsState,
+    peer: SocketAddr,
+    msg: Option<&CWMPMsg>,
+    device_id: Option<&DeviceId>,
+    in_flight_task: Option<u64>,
+    opening: Option<bool>,
+) -> Vec<Task> {
+    match msg {
+        Some(CWMPMsg::Inform(inform)) => {
+            let now = Utc::now();
+            let Some((id, changes)) = state.devices.record_inform(inform, now) else {
+                tracing::warn!("Inform from {peer} without OUI or SerialNumber");
+                return Vec::new();
+            };
+            let events = inform.events().map(|event| event.to_string()).collect();
+            state
+                .events
+                .publish(&id, EventKind::DeviceInformed { events });
+            publish_changes(&state.events, &id, changes);
+            match opening {
+                Some(opening) => {
+                    if opening {
+                        state.events.publish(&id, EventKind::SessionStarted);
+                    }
+                    state.tasks.pending(&id, now)
+                }
+                None => Vec::new(),
+            }
+        }
+        Some(msg) => {
+            if let Some(id) = device_id {
+                record_response(state, id, msg);
+            }
+            if let Some(task) = in_flight_task {
+                let task = state.tasks.complete(task, TaskResult::from_response(msg));
+                record_task(state, task);
+            }
+            Vec::new()
+        }
+        None => Vec::new(),
+    }
+}
+
+// Keep the device's cached Parameters and fault history up to date, and publish
+// what the CPE reported.
+fn record_response(state: &AcsState, id: &DeviceId, msg: &CWMPMsg) {
+    let devices = &state.devices;
+    match msg {
+        CWMPMsg::GetParameterValuesResponse(response) => {
+            let changes = devices.update_parameters(id, &response.parameter_list.parameter_struct);
+            publish_changes(&state.events, id, changes);
+        }
+        CWMPMsg::GetParameterNamesResponse(response) => {
+            devices.update_writable(id, &response.parameter_list.parameters)
+        }
+        CWMPMsg::GetParameterAttributesResponse(response) => {
+            devices.update_attributes(id, &response.parameter_list.parameters)
+        }
+        CWMPMsg::Fault(fault) => {
+            let fault = CwmpFault::from(fault);
+            let record = FaultRecord {
+                device_id: id.clone(),
+                time: Utc::now(),
+                code: fault.code.code(),
+                message: fault.message,
+            };
+            if let Err(err) = devices.storage().add_fault(&record) {
+                tracing::error!("Failed to record fault of {id}: {err}");
+            }
+            state.events.publish(
+                id,
+                EventKind::FaultRaised {
+                    code: record.code,
+                    message: record.message,
+                },
+            );
+        }
+        CWMPMsg::TransferComplete(complete) => state.events.publish(
+            id,
+            EventKind::TransferCompleted {
+                command_key: complete.command_key.clone(),
+                fault_code: complete.fault_struct.fault_code,
+                fault_string: complete.fault_struct.fault_string.clone(),
+                start_time: complete.start_time.clone(),
+                complete_time: complete.complete_time.clone(),
+            },
+        ),
+        _ => {}
+    }
+}
+
+// Publish how the task ended. Values the ACS set are cached too, the CPE never
+// reads back passwords such as ConnectionRequestPassword, and a ManagementServer
+// Username/Password set this way is what the CPE authenticates with from then on.
+fn record_task(state: &AcsState, task: Option<Task>) {
+    let Some(task) = task else {
+        return;
+    };
+    if task.status != TaskStatus::Completed {
+        let id = task.device_id.clone();
+        state.events.publish(&id, EventKind::TaskFailed { task });
+        return;
+    }
+    if let Operation::SetParameterValues { parameters, .. } = &task.operation {
+        let parameters: Vec<_> = parameters.iter().map(|p| p.to_struct()).collect();
+        let changes = state
+            .devices
+            .update_parameters(&task.device_id, &parameters);
+        publish_changes(&state.events, &task.device_id, changes);
+        state
+            .devices
+            .update_credentials(&task.device_id, &parameters);
+    }
+    let id = task.device_id.clone();
+    state.events.publish(&id, EventKind::TaskCompleted { task });
+}
+
+fn publish_changes(events: &EventBus, id: &DeviceId, changes: Vec<ParameterChange>) {
+    if !changes.is_empty() {
+        events.publish(id, EventKind::ParametersChanged { changes });
+    }
+}
+
+// Sessions that never got a valid Inform have no device to report.
+fn publish_ended(events: &EventBus, sessions: &[CwmpSession], reason: SessionEnd) {
+    for id in sessions.iter().filter_map(CwmpSession::device_id) {
+        events.publish(id, EventKind::SessionEnded { reason });
+    }
+}
+
+fn unauthorized(auth: &CpeAuthenticator, stale: bool) -> Response {
+    let mut response = StatusCode::UNAUTHORIZED.into_response();
+    for challenge in auth.challenges(stale) {
+        if let Ok(value) = HeaderValue::from_str(&challenge) {
+            response
+                .headers_mut()
+                .append(header::WWW_AUTHENTICATE, value);
+        }
+    }
+    response
+}
+
+// SOAP 1.1 requires HTTP 500 for responses carrying a SOAP Fault.
+fn xml_response(status: StatusCode, envelope: &Envelope) -> Response {
+    match envelope.to_xml() {
+        Ok(xml) => (
+            status,
+            [(header::CONTENT_TYPE, "text/xml; charset=utf-8")],
+            xml,
+        )
+            .into_response(),
+        Err(err) => {
+            tracing::error!("Failed to serialize CWMP envelope: {err}");
+            StatusCode::INTERNAL_SERVER_ERROR.into_response()
+        }
+    }
 }
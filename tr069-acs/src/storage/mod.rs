@@ -0,0 +1,378 @@
+// Persistent ACS state: devices with their cached Parameters, queued tasks, the
+// faults CPEs answered with and webhook deliveries.
+//
+// The device registry keeps its working set in memory and writes every change
+// through a `Storage` backend. `SqliteStorage` keeps the data on disk across
+// restarts, `MemoryStorage` is used by tests and when no database is configured.
+use crate::device::{CachedParameter, Device, DeviceId};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{BTreeMap, HashMap},
+    fmt,
+    sync::Mutex,
+};
+use utoipa::ToSchema;
+
+#[cfg(feature = "server")]
+mod sqlite;
+#[cfg(feature = "server")]
+pub use sqlite::SqliteStorage;
+
+#[derive(Debug)]
+pub enum StorageError {
+    #[cfg(feature = "server")]
+    Sqlite(rusqlite::Error),
+    /// A stored value could not be decoded.
+    Corrupt(String),
+}
+
+impl fmt::Display for StorageError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            #[cfg(feature = "server")]
+            StorageError::Sqlite(err) => write!(f, "sqlite error: {err}"),
+            StorageError::Corrupt(err) => write!(f, "corrupt stored value: {err}"),
+        }
+    }
+}
+
+impl std::error::Error for StorageError {}
+
+#[cfg(feature = "server")]
+impl From<rusqlite::Error> for StorageError {
+    fn from(err: rusqlite::Error) -> Self {
+        StorageError::Sqlite(err)
+    }
+}
+
+/// A task waiting to be sent to a CPE. The payload is opaque to the storage layer.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TaskRecord {
+    /// Assigned by the storage backend in `add_task`.
+    pub id: u64,
+    pub device_id: DeviceId,
+    pub created: DateTime<Utc>,
+    pub payload: String,
+}
+
+/// A CWMP fault a CPE answered an ACS request with.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+pub struct FaultRecord {
+    pub device_id: DeviceId,
+    pub time: DateTime<Utc>,
+    pub code: u32,
+    pub message: String,
+}
+
+/// A webhook delivery. The payload is opaque to the storage layer.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DeliveryRecord {
+    /// Assigned by the storage backend in `add_delivery`.
+    pub id: u64,
+    pub webhook: String,
+    pub created: DateTime<Utc>,
+    pub payload: String,
+}
+
+pub trait Storage: Send + Sync + fmt::Debug {
+    /// Every stored device, with its Parameters.
+    fn load_devices(&self) -> Result<Vec<Device>, StorageError>;
+
+    /// Insert or update the device row. `device.parameters` is not written, see
+    /// `save_parameters`.
+    fn save_device(&self, device: &Device) -> Result<(), StorageError>;
+
+    /// Remove a device with its Parameters, tasks and faults.
+    fn delete_device(&self, id: &DeviceId) -> Result<(), StorageError>;
+
+    /// Insert or update the given Parameters of a device.
+    fn save_parameters(
+        &self,
+        id: &DeviceId,
+        parameters: &[(&str, &CachedParameter)],
+    ) -> Result<(), StorageError>;
+
+    /// Store a new task and return its id.
+    fn add_task(&self, task: &TaskRecord) -> Result<u64, StorageError>;
+
+    fn update_task(&self, task: &TaskRecord) -> Result<(), StorageError>;
+
+    fn delete_task(&self, id: u64) -> Result<(), StorageError>;
+
+    /// Tasks of every device, oldest first.
+    fn load_tasks(&self) -> Result<Vec<TaskRecord>, StorageError>;
+
+    fn add_fault(&self, fault: &FaultRecord) -> Result<(), StorageError>;
+
+    /// Faults of a device, oldest first.
+    fn faults(&self, id: &DeviceId) -> Result<Vec<FaultRecord>, StorageError>;
+
+    /// Store a new webhook delivery and return its id.
+    fn add_delivery(&self, delivery: &DeliveryRecord) -> Result<u64, StorageError>;
+
+    fn update_delivery(&self, delivery: &DeliveryRecord) -> Result<(), StorageError>;
+
+    fn delete_delivery(&self, id: u64) -> Result<(), StorageError>;
+
+    /// Webhook deliveries, oldest first.
+    fn load_deliveries(&self) -> Result<Vec<DeliveryRecord>, StorageError>;
+
+    /// Make every change so far durable, called before the process exits.
+    fn flush(&self) -> Result<(), StorageError> {
+        Ok(())
+    }
+}
+
+#[derive(Debug, Default)]
+struct MemoryState {
+    devices: HashMap<DeviceId, Device>,
+    tasks: BTreeMap<u64, TaskRecord>,
+    next_task: u64,
+    faults: Vec<FaultRecord>,
+    deliveries: BTreeMap<u64, DeliveryRecord>,
+    next_delivery: u64,
+}
+
+/// Storage that lives as long as the process.
+#[derive(Debug, Default)]
+pub struct MemoryStorage {
+    state: Mutex<MemoryState>,
+}
+
+impl MemoryStorage {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl Storage for MemoryStorage {
+    fn load_devices(&self) -> Result<Vec<Device>, StorageError> {
+        Ok(self
+            .state
+            .lock()
+            .unwrap()
+            .devices
+            .values()
+            .cloned()
+            .collect())
+    }
+
+    fn save_device(&self, device: &Device) -> Result<(), StorageError> {
+        let mut state = self.state.lock().unwrap();
+        let parameters = state
+            .devices
+            .remove(&device.id)
+            .map(|stored| stored.parameters)
+            .unwrap_or_default();
+        let mut device = device.clone();
+        device.parameters = parameters;
+        state.devices.insert(device.id.clone(), device);
+        Ok(())
+    }
+
+    fn delete_device(&self, id: &DeviceId) -> Result<(), StorageError> {
+        let mut state = self.state.lock().unwrap();
+        state.devices.remove(id);
+        state.tasks.retain(|_, task| &task.device_id != id);
+        state.faults.retain(|fault| &fault.device_id != id);
+        Ok(())
+    }
+
+    fn save_parameters(
+        &self,
+        id: &DeviceId,
+        parameters: &[(&str, &CachedParameter)],
+    ) -> Result<(), StorageError> {
+        let mut state = self.state.lock().unwrap();
+        let device = state
+            .devices
+            .get_mut(id)
+            .ok_or_else(|| StorageError::Corrupt(format!("unknown device {id}")))?;
+        for (name, parameter) in parameters {
+            device
+                .parameters
+                .insert(name.to_string(), (*parameter).clone());
+        }
+        Ok(())
+    }
+
+    fn add_task(&self, task: &TaskRecord) -> Result<u64, StorageError> {
+        let mut state = self.state.lock().unwrap();
+        state.next_task += 1;
+        let id = state.next_task;
+        state.tasks.insert(id, TaskRecord { id, ..task.clone() });
+        Ok(id)
+    }
+
+    fn update_task(&self, task: &TaskRecord) -> Result<(), StorageError> {
+        let mut state = self.state.lock().unwrap();
+        match state.tasks.get_mut(&task.id) {
+            Some(stored) => *stored = task.clone(),
+            None => return Err(StorageError::Corrupt(format!("unknown task {}", task.id))),
+        }
+        Ok(())
+    }
+
+    fn delete_task(&self, id: u64) -> Result<(), StorageError> {
+        self.state.lock().unwrap().tasks.remove(&id);
+        Ok(())
+    }
+
+    fn load_tasks(&self) -> Result<Vec<TaskRecord>, StorageError> {
+        Ok(self.state.lock().unwrap().tasks.values().cloned().collect())
+    }
+
+    fn add_fault(&self, fault: &FaultRecord) -> Result<(), StorageError> {
+        self.state.lock().unwrap().faults.push(fault.clone());
+        Ok(())
+    }
+
+    fn faults(&self, id: &DeviceId) -> Result<Vec<FaultRecord>, StorageError> {
+        let state = self.state.lock().unwrap();
+        Ok(state
+            .faults
+            .iter()
+            .filter(|fault| &fault.device_id == id)
+            .cloned()
+            .collect())
+    }
+
+    fn add_delivery(&self, delivery: &DeliveryRecord) -> Result<u64, StorageError> {
+        let mut state = self.state.lock().unwrap();
+        state.next_delivery += 1;
+        let id = state.next_delivery;
+        state.deliveries.insert(
+            id,
+            DeliveryRecord {
+                id,
+                ..delivery.clone()
+            },
+        );
+        Ok(id)
+    }
+
+    fn update_delivery(&self, delivery: &DeliveryRecord) -> Result<(), StorageError> {
+        let mut state = self.state.lock().unwrap();
+        match state.deliveries.get_mut(&delivery.id) {
+            Some(stored) => *stored = delivery.clone(),
+            None => {
+                return Err(StorageError::Corrupt(format!(
+                    "unknown delivery {}",
+                    delivery.id
+                )))
+            }
+        }
+        Ok(())
+    }
+
+    fn delete_delivery(&self, id: u64) -> Result<(), StorageError> {
+        self.state.lock().unwrap().deliveries.remove(&id);
+        Ok(())
+    }
+
+    fn load_deliveries(&self) -> Result<Vec<DeliveryRecord>, StorageError> {
+        Ok(self
+            .state
+            .lock()
+            .unwrap()
+            .deliveries
+            .values()
+            .cloned()
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Run the same checks against every backend.
+    pub(super) fn exercise(storage: &dyn Storage) {
+        let now: DateTime<Utc> = "2025-10-07T10:00:00Z".parse().unwrap();
+        let id: DeviceId = "001A2B-RouterX-SN1".parse().unwrap();
+        let mut device = Device::new(id.clone(), now);
+        device.manufacturer = Some(String::from("ExampleCo"));
+        device.last_events = vec![String::from("0 BOOTSTRAP")];
+        storage.save_device(&device).unwrap();
+
+        let parameter = CachedParameter {
+            xsi_type: Some(String::from("xsd:string")),
+            value: Some(String::from("1.0")),
+            writable: Some(false),
+            notification: Some(2),
+            access_list: vec![String::from("Subscriber")],
+            updated: now,
+        };
+        storage
+            .save_parameters(&id, &[("Device.DeviceInfo.SoftwareVersion", &parameter)])
+            .unwrap();
+        device.software_version = Some(String::from("1.0"));
+        device.credentials = Some(crate::connection_request::Credentials {
+            username: String::from("cpe1"),
+            password: String::from("pw1"),
+        });
+        device.stun_password = Some(String::from("stun1"));
+        storage.save_device(&device).unwrap();
+
+        let devices = storage.load_devices().unwrap();
+        assert_eq!(devices.len(), 1);
+        assert_eq!(devices[0].id, id);
+        assert_eq!(devices[0].manufacturer.as_deref(), Some("ExampleCo"));
+        assert_eq!(devices[0].software_version.as_deref(), Some("1.0"));
+        assert_eq!(devices[0].last_events, ["0 BOOTSTRAP"]);
+        assert_eq!(devices[0].first_inform, now);
+        assert_eq!(devices[0].credentials, device.credentials);
+        assert_eq!(devices[0].stun_password, device.stun_password);
+        assert_eq!(
+            devices[0]
+                .parameters
+                .get("Device.DeviceInfo.SoftwareVersion"),
+            Some(&parameter)
+        );
+
+        let mut task = TaskRecord {
+            id: 0,
+            device_id: id.clone(),
+            created: now,
+            payload: String::from("{}"),
+        };
+        task.id = storage.add_task(&task).unwrap();
+        task.payload = String::from("{\"retries\":1}");
+        storage.update_task(&task).unwrap();
+        assert_eq!(storage.load_tasks().unwrap(), [task.clone()]);
+
+        let fault = FaultRecord {
+            device_id: id.clone(),
+            time: now,
+            code: 9005,
+            message: String::from("Invalid parameter name"),
+        };
+        storage.add_fault(&fault).unwrap();
+        assert_eq!(storage.faults(&id).unwrap(), [fault]);
+
+        let mut delivery = DeliveryRecord {
+            id: 0,
+            webhook: String::from("oss"),
+            created: now,
+            payload: String::from("{}"),
+        };
+        delivery.id = storage.add_delivery(&delivery).unwrap();
+        delivery.payload = String::from("{\"status\":\"dead\"}");
+        storage.update_delivery(&delivery).unwrap();
+        assert_eq!(storage.load_deliveries().unwrap(), [delivery.clone()]);
+        storage.delete_delivery(delivery.id).unwrap();
+        assert!(storage.load_deliveries().unwrap().is_empty());
+
+        storage.delete_task(task.id).unwrap();
+        assert!(storage.load_tasks().unwrap().is_empty());
+        storage.delete_device(&id).unwrap();
+        assert!(storage.load_devices().unwrap().is_empty());
+        assert!(storage.faults(&id).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_memory_storage() {
+        exercise(&MemoryStorage::new());
+    }
+}
@@ -0,0 +1,458 @@
+// SQLite backend. One connection behind a mutex is plenty for the write rates of a
+// CWMP fleet; WAL mode keeps readers from blocking on the writer.
+use super::{DeliveryRecord, FaultRecord, Storage, StorageError, TaskRecord};
+use crate::connection_request::Credentials;
+use crate::device::{CachedParameter, Device, DeviceId};
+use rusqlite::{params, Connection, Row};
+use std::{collections::HashMap, path::Path, sync::Mutex};
+
+const SCHEMA: &str = "
+PRAGMA journal_mode = WAL;
+PRAGMA foreign_keys = ON;
+
+CREATE TABLE IF NOT EXISTS devices (
+    id                     TEXT PRIMARY KEY,
+    oui                    TEXT NOT NULL,
+    product_class          TEXT NOT NULL,
+    serial_number          TEXT NOT NULL,
+    manufacturer           TEXT,
+    first_inform           TEXT NOT NULL,
+    last_inform            TEXT NOT NULL,
+    last_events            TEXT NOT NULL,
+    connection_request_url TEXT,
+    software_version       TEXT,
+    udp_connection_request_address TEXT,
+    connection_request_jabber_id   TEXT,
+    cwmp_username          TEXT,
+    cwmp_password          TEXT,
+    stun_password          TEXT
+);
+
+CREATE TABLE IF NOT EXISTS parameters (
+    device_id    TEXT NOT NULL REFERENCES devices(id) ON DELETE CASCADE,
+    name         TEXT NOT NULL,
+    xsi_type     TEXT,
+    value        TEXT,
+    writable     INTEGER,
+    notification INTEGER,
+    access_list  TEXT NOT NULL,
+    updated      TEXT NOT NULL,
+    PRIMARY KEY (device_id, name)
+);
+
+CREATE TABLE IF NOT EXISTS tasks (
+    id        INTEGER PRIMARY KEY AUTOINCREMENT,
+    device_id TEXT NOT NULL REFERENCES devices(id) ON DELETE CASCADE,
+    created   TEXT NOT NULL,
+    payload   TEXT NOT NULL
+);
+
+CREATE TABLE IF NOT EXISTS faults (
+    id        INTEGER PRIMARY KEY AUTOINCREMENT,
+    device_id TEXT NOT NULL REFERENCES devices(id) ON DELETE CASCADE,
+    time      TEXT NOT NULL,
+    code      INTEGER NOT NULL,
+    message   TEXT NOT NULL
+);
+
+CREATE TABLE IF NOT EXISTS webhook_deliveries (
+    id      INTEGER PRIMARY KEY AUTOINCREMENT,
+    webhook TEXT NOT NULL,
+    created TEXT NOT NULL,
+    payload TEXT NOT NULL
+);
+";
+
+// Changes made to SCHEMA after its first release, applied in order and tracked with
+// `PRAGMA user_version`, each in one transaction with its version. Only ever
+// append to this list.
+const MIGRATIONS: &[&str] = &[
+    // Device ids percent-encode '-' and '%' in their fields.
+    "PRAGMA defer_foreign_keys = ON;
+     CREATE TEMP TABLE device_ids AS
+         SELECT id AS old,
+                replace(replace(oui, '%', '%25'), '-', '%2D') || '-' ||
+                replace(replace(product_class, '%', '%25'), '-', '%2D') || '-' ||
+                replace(replace(serial_number, '%', '%25'), '-', '%2D') AS new
+         FROM devices;
+     UPDATE parameters SET device_id = (SELECT new FROM device_ids WHERE old = device_id);
+     UPDATE tasks SET device_id = (SELECT new FROM device_ids WHERE old = device_id);
+     UPDATE faults SET device_id = (SELECT new FROM device_ids WHERE old = device_id);
+     UPDATE devices SET id = (SELECT new FROM device_ids WHERE old = id);
+     DROP TABLE device_ids;",
+];
+
+#[derive(Debug)]
+pub struct SqliteStorage {
+    connection: Mutex<Connection>,
+}
+
+impl SqliteStorage {
+    /// Open or create the database at `path`.
+    pub fn open(path: impl AsRef<Path>) -> Result<Self, StorageError> {
+        Self::init(Connection::open(path)?)
+    }
+
+    fn init(mut connection: Connection) -> Result<Self, StorageError> {
+        connection.execute_batch(SCHEMA)?;
+        let version: i64 = connection.query_row("PRAGMA user_version", [], |row| row.get(0))?;
+        for (version, migration) in MIGRATIONS.iter().enumerate().skip(version as usize) {
+            let transaction = connection.transaction()?;
+            transaction.execute_batch(migration)?;
+            transaction.pragma_update(None, "user_version", version as i64 + 1)?;
+            transaction.commit()?;
+        }
+        Ok(Self {
+            connection: Mutex::new(connection),
+        })
+    }
+}
+
+fn to_json<T: serde::Serialize>(value: &T) -> Result<String, StorageError> {
+    serde_json::to_string(value).map_err(|err| StorageError::Corrupt(err.to_string()))
+}
+
+fn from_json<T: serde::de::DeserializeOwned>(json: &str) -> Result<T, StorageError> {
+    serde_json::from_str(json).map_err(|err| StorageError::Corrupt(err.to_string()))
+}
+
+// A `devices` row, with the JSON encoded `last_events` left for the caller to decode.
+fn device_row(row: &Row<'_>) -> rusqlite::Result<(Device, String)> {
+    let device = Device {
+        id: DeviceId {
+            oui: row.get("oui")?,
+            product_class: row.get("product_class")?,
+            serial_number: row.get("serial_number")?,
+        },
+        manufacturer: row.get("manufacturer")?,
+        first_inform: row.get("first_inform")?,
+        last_inform: row.get("last_inform")?,
+        last_events: Vec::new(),
+        connection_request_url: row.get("connection_request_url")?,
+        udp_connection_request_address: row.get("udp_connection_request_address")?,
+        connection_request_jabber_id: row.get("connection_request_jabber_id")?,
+        software_version: row.get("software_version")?,
+        credentials: match (row.get("cwmp_username")?, row.get("cwmp_password")?) {
+            (Some(username), Some(password)) => Some(Credentials { username, password }),
+            _ => None,
+        },
+        stun_password: row.get("stun_password")?,
+        parameters: Default::default(),
+    };
+    Ok((device, row.get("last_events")?))
+}
+
+impl Storage for SqliteStorage {
+    fn load_devices(&self) -> Result<Vec<Device>, StorageError> {
+        let connection = self.connection.lock().unwrap();
+        let mut devices = HashMap::new();
+        let mut statement = connection.prepare("SELECT * FROM devices")?;
+        for row in statement.query_map([], device_row)? {
+            let (mut device, last_events) = row?;
+            device.last_events = from_json(&last_events)?;
+            devices.insert(device.id.to_string(), device);
+        }
+
+        let mut statement = connection.prepare(
+            "SELECT device_id, name, xsi_type, value, writable, notification, access_list, updated
+             FROM parameters",
+        )?;
+        let rows = statement.query_map([], |row| {
+            let access_list: String = row.get(6)?;
+            let parameter = CachedParameter {
+                xsi_type: row.get(2)?,
+                value: row.get(3)?,
+                writable: row.get(4)?,
+                notification: row.get(5)?,
+                access_list: Vec::new(),
+                updated: row.get(7)?,
+            };
+            Ok((
+                row.get::<_, String>(0)?,
+                row.get::<_, String>(1)?,
+                parameter,
+                access_list,
+            ))
+        })?;
+        for row in rows {
+            let (device_id, name, mut parameter, access_list) = row?;
+            parameter.access_list = from_json(&access_list)?;
+            let device = devices
+                .get_mut(&device_id)
+                .ok_or_else(|| StorageError::Corrupt(format!("unknown device {device_id}")))?;
+            device.parameters.insert(name, parameter);
+        }
+        Ok(devices.into_values().collect())
+    }
+
+    fn save_device(&self, device: &Device) -> Result<(), StorageError> {
+        let last_events = to_json(&device.last_events)?;
+        self.connection.lock().unwrap().execute(
+            "INSERT INTO devices (id, oui, product_class, serial_number, manufacturer,
+                                  first_inform, last_inform, last_events,
+                                  connection_request_url, software_version,
+                                  udp_connection_request_address,
+                                  connection_request_jabber_id, cwmp_username,
+                                  cwmp_password, stun_password)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15)
+             ON CONFLICT (id) DO UPDATE SET
+                 manufacturer = excluded.manufacturer,
+                 last_inform = excluded.last_inform,
+                 last_events = excluded.last_events,
+                 connection_request_url = excluded.connection_request_url,
+                 software_version = excluded.software_version,
+                 udp_connection_request_address = excluded.udp_connection_request_address,
+                 connection_request_jabber_id = excluded.connection_request_jabber_id,
+                 cwmp_username = excluded.cwmp_username,
+                 cwmp_password = excluded.cwmp_password,
+                 stun_password = excluded.stun_password",
+            params![
+                device.id.to_string(),
+                device.id.oui,
+                device.id.product_class,
+                device.id.serial_number,
+                device.manufacturer,
+                device.first_inform,
+                device.last_inform,
+                last_events,
+                device.connection_request_url,
+                device.software_version,
+                device.udp_connection_request_address,
+                device.connection_request_jabber_id,
+                device.credentials.as_ref().map(|c| &c.username),
+                device.credentials.as_ref().map(|c| &c.password),
+                device.stun_password,
+            ],
+        )?;
+        Ok(())
+    }
+
+    fn delete_device(&self, id: &DeviceId) -> Result<(), StorageError> {
+        self.connection
+            .lock()
+            .unwrap()
+            .execute("DELETE FROM devices WHERE id = ?1", [id.to_string()])?;
+        Ok(())
+    }
+
+    fn save_parameters(
+        &self,
+        id: &DeviceId,
+        parameters: &[(&str, &CachedParameter)],
+    ) -> Result<(), StorageError> {
+        let mut connection = self.connection.lock().unwrap();
+        let transaction = connection.transaction()?;
+        {
+            let mut statement = transaction.prepare(
+                "INSERT OR REPLACE INTO parameters
+                     (device_id, name, xsi_type, value, writable, notification, access_list, updated)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)",
+            )?;
+            for (name, parameter) in parameters {
+                statement.execute(params![
+                    id.to_string(),
+                    name,
+                    parameter.xsi_type,
+                    parameter.value,
+                    parameter.writable,
+                    parameter.notification,
+                    to_json(&parameter.access_list)?,
+                    parameter.updated,
+                ])?;
+            }
+        }
+        transaction.commit()?;
+        Ok(())
+    }
+
+    fn add_task(&self, task: &TaskRecord) -> Result<u64, StorageError> {
+        let connection = self.connection.lock().unwrap();
+        connection.execute(
+            "INSERT INTO tasks (device_id, created, payload) VALUES (?1, ?2, ?3)",
+            params![task.device_id.to_string(), task.created, task.payload],
+        )?;
+        Ok(connection.last_insert_rowid() as u64)
+    }
+
+    fn update_task(&self, task: &TaskRecord) -> Result<(), StorageError> {
+        self.connection.lock().unwrap().execute(
+            "UPDATE tasks SET payload = ?2 WHERE id = ?1",
+            params![task.id as i64, task.payload],
+        )?;
+        Ok(())
+    }
+
+    fn delete_task(&self, id: u64) -> Result<(), StorageError> {
+        self.connection
+            .lock()
+            .unwrap()
+            .execute("DELETE FROM tasks WHERE id = ?1", [id as i64])?;
+        Ok(())
+    }
+
+    fn load_tasks(&self) -> Result<Vec<TaskRecord>, StorageError> {
+        let connection = self.connection.lock().unwrap();
+        let mut statement = connection.prepare(
+            "SELECT tasks.id, devices.oui, devices.product_class, devices.serial_number,
+                    tasks.created, tasks.payload
+             FROM tasks JOIN devices ON devices.id = tasks.device_id
+             ORDER BY tasks.id",
+        )?;
+        let rows = statement.query_map([], |row| {
+            Ok(TaskRecord {
+                id: row.get::<_, i64>(0)? as u64,
+                device_id: DeviceId {
+                    oui: row.get(1)?,
+                    product_class: row.get(2)?,
+                    serial_number: row.get(3)?,
+                },
+                created: row.get(4)?,
+                payload: row.get(5)?,
+            })
+        })?;
+        Ok(rows.collect::<Result<_, _>>()?)
+    }
+
+    fn add_fault(&self, fault: &FaultRecord) -> Result<(), StorageError> {
+        self.connection.lock().unwrap().execute(
+            "INSERT INTO faults (device_id, time, code, message) VALUES (?1, ?2, ?3, ?4)",
+            params![
+                fault.device_id.to_string(),
+                fault.time,
+                fault.code,
+                fault.message
+            ],
+        )?;
+        Ok(())
+    }
+
+    fn faults(&self, id: &DeviceId) -> Result<Vec<FaultRecord>, StorageError> {
+        let connection = self.connection.lock().unwrap();
+        let mut statement = connection
+            .prepare("SELECT time, code, message FROM faults WHERE device_id = ?1 ORDER BY id")?;
+        let rows = statement.query_map([id.to_string()], |row| {
+            Ok(FaultRecord {
+                device_id: id.clone(),
+                time: row.get(0)?,
+                code: row.get(1)?,
+                message: row.get(2)?,
+            })
+        })?;
+        let faults = rows.collect::<Result<Vec<_>, _>>()?;
+        Ok(faults)
+    }
+
+    fn add_delivery(&self, delivery: &DeliveryRecord) -> Result<u64, StorageError> {
+        let connection = self.connection.lock().unwrap();
+        connection.execute(
+            "INSERT INTO webhook_deliveries (webhook, created, payload) VALUES (?1, ?2, ?3)",
+            params![delivery.webhook, delivery.created, delivery.payload],
+        )?;
+        Ok(connection.last_insert_rowid() as u64)
+    }
+
+    fn update_delivery(&self, delivery: &DeliveryRecord) -> Result<(), StorageError> {
+        self.connection.lock().unwrap().execute(
+            "UPDATE webhook_deliveries SET payload = ?2 WHERE id = ?1",
+            params![delivery.id as i64, delivery.payload],
+        )?;
+        Ok(())
+    }
+
+    fn delete_delivery(&self, id: u64) -> Result<(), StorageError> {
+        self.connection
+            .lock()
+            .unwrap()
+            .execute("DELETE FROM webhook_deliveries WHERE id = ?1", [id as i64])?;
+        Ok(())
+    }
+
+    fn load_deliveries(&self) -> Result<Vec<DeliveryRecord>, StorageError> {
+        let connection = self.connection.lock().unwrap();
+        let mut statement = connection
+            .prepare("SELECT id, webhook, created, payload FROM webhook_deliveries ORDER BY id")?;
+        let rows = statement.query_map([], |row| {
+            Ok(DeliveryRecord {
+                id: row.get::<_, i64>(0)? as u64,
+                webhook: row.get(1)?,
+                created: row.get(2)?,
+                payload: row.get(3)?,
+            })
+        })?;
+        let deliveries = rows.collect::<Result<Vec<_>, _>>()?;
+        Ok(deliveries)
+    }
+
+    // Move the write-ahead log into the database file, so it is complete on its own.
+    fn flush(&self) -> Result<(), StorageError> {
+        let connection = self.connection.lock().unwrap();
+        connection.query_row("PRAGMA wal_checkpoint(TRUNCATE)", [], |_| Ok(()))?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sqlite_storage() {
+        let storage = SqliteStorage::init(Connection::open_in_memory().unwrap()).unwrap();
+        super::super::tests::exercise(&storage);
+    }
+
+    #[test]
+    fn test_sqlite_storage_survives_reopen() {
+        let path = std::env::temp_dir().join(format!("tr069-acs-{}.db", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let id: DeviceId = "001A2B-RouterX-SN1".parse().unwrap();
+        {
+            let storage = SqliteStorage::open(&path).unwrap();
+            storage
+                .save_device(&Device::new(id.clone(), chrono::Utc::now()))
+                .unwrap();
+            storage.flush().unwrap();
+            let wal = path.with_extension("db-wal");
+            assert_eq!(std::fs::metadata(wal).map_or(0, |wal| wal.len()), 0);
+        }
+        let storage = SqliteStorage::open(&path).unwrap();
+        let devices = storage.load_devices().unwrap();
+        assert_eq!(devices.len(), 1);
+        assert_eq!(devices[0].id, id);
+        drop(storage);
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[test]
+    fn test_migrate_device_ids() {
+        let connection = Connection::open_in_memory().unwrap();
+        connection.execute_batch(SCHEMA).unwrap();
+        connection
+            .execute_batch(
+                "INSERT INTO devices (id, oui, product_class, serial_number, first_inform,
+                                      last_inform, last_events)
+                 VALUES ('001A2B-Router-X-SN1', '001A2B', 'Router-X', 'SN1',
+                         '2025-10-07T10:00:00Z', '2025-10-07T10:00:00Z', '[]');
+                 INSERT INTO tasks (device_id, created, payload)
+                 VALUES ('001A2B-Router-X-SN1', '2025-10-07T10:00:00Z', '{}');",
+            )
+            .unwrap();
+
+        let storage = SqliteStorage::init(connection).unwrap();
+        let id: DeviceId = "001A2B-Router%2DX-SN1".parse().unwrap();
+        assert_eq!(id.product_class, "Router-X");
+        let devices = storage.load_devices().unwrap();
+        assert_eq!(devices.len(), 1);
+        assert_eq!(devices[0].id, id);
+        assert_eq!(storage.load_tasks().unwrap()[0].device_id, id);
+        storage.delete_device(&id).unwrap();
+        assert!(storage.load_tasks().unwrap().is_empty());
+        let version: i64 = storage
+            .connection
+            .lock()
+            .unwrap()
+            .query_row("PRAGMA user_version", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(version, MIGRATIONS.len() as i64);
+    }
+}
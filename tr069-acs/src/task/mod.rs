@@ -0,0 +1,798 @@
+// Operations queued against a device and sent during its next CWMP session.
+//
+// Pending tasks are loaded into the session when the CPE Informs and sent in order
+// after its empty POST. A task is only finished by the CPE's response (or fault) to
+// it: if the CPE drops the session first, the task stays pending and is sent again
+// in the next session, up to `max_attempts` sessions; after that it faults as
+// unanswered. Tasks past their expiry are never sent. Finished tasks are kept for
+// their results until the retention period is over.
+use crate::cwmp_msg::{
+    AddObject, CWMPMsg, CwmpFault, DeleteObject, Download, FactoryReset, GetParameterAttributes,
+    GetParameterNames, GetParameterValues, ParameterNames, ParameterValueStruct, Reboot,
+    ScheduleDownload, ScheduleInform, SetParameterAttributes, SetParameterAttributesStruct,
+    SetParameterValues, TimeWindowStruct, Upload, ValueError,
+};
+use crate::device::{is_secret, serialize_redacted, Device, DeviceId, Redact};
+use crate::storage::{Storage, StorageError, TaskRecord};
+use chrono::{DateTime, TimeDelta, Utc};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::BTreeMap,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+use utoipa::ToSchema;
+
+/// Sessions a task's request is sent in before it faults as unanswered.
+pub const DEFAULT_MAX_ATTEMPTS: u32 = 5;
+/// Finished tasks are removed this long after they finished.
+pub const DEFAULT_RETENTION: TimeDelta = TimeDelta::days(30);
+/// How often finished tasks past their retention are looked for.
+const PURGE_INTERVAL: Duration = Duration::from_secs(3600);
+
+/// A Parameter value to set, `xsi_type` as in `xsd:unsignedInt`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+pub struct ParameterValue {
+    pub name: String,
+    pub xsi_type: String,
+    pub value: String,
+}
+
+impl ParameterValue {
+    pub fn to_struct(&self) -> ParameterValueStruct {
+        ParameterValueStruct::new(&self.name, &self.xsi_type, &self.value)
+    }
+}
+
+impl Redact for ParameterValue {
+    fn redacted(&self) -> Self {
+        let mut parameter = self.clone();
+        if is_secret(&self.name) {
+            parameter.value.clear();
+        }
+        parameter
+    }
+}
+
+/// Attributes to change on a Parameter, those left out are kept.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+pub struct ParameterAttribute {
+    pub name: String,
+    /// 0 off, 1 passive, 2 active notification.
+    #[serde(default)]
+    pub notification: Option<i32>,
+    /// Who besides the ACS may write the Parameter, e.g. `["Subscriber"]`.
+    #[serde(default)]
+    pub access_list: Option<Vec<String>>,
+}
+
+impl ParameterAttribute {
+    pub fn to_struct(&self) -> SetParameterAttributesStruct {
+        SetParameterAttributesStruct {
+            name: self.name.clone(),
+            notification_change: self.notification.is_some(),
+            notification: self.notification.unwrap_or_default(),
+            access_list_change: self.access_list.is_some(),
+            access_list: ParameterNames::new(self.access_list.clone().unwrap_or_default()),
+        }
+    }
+}
+
+/// When the CPE may carry out a ScheduleDownload, in seconds from its reception.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+pub struct TimeWindow {
+    pub window_start: u32,
+    pub window_end: u32,
+    /// "1 At Any Time", "2 Immediately", "3 When Idle" or "4 Confirmation Needed".
+    pub window_mode: String,
+    #[serde(default)]
+    pub user_message: String,
+    /// -1 lets the CPE pick its own retry policy.
+    #[serde(default = "any_retries")]
+    pub max_retries: i32,
+}
+
+fn any_retries() -> i32 {
+    -1
+}
+
+impl TimeWindow {
+    pub fn to_struct(&self) -> TimeWindowStruct {
+        TimeWindowStruct {
+            window_start: self.window_start,
+            window_end: self.window_end,
+            window_mode: self.window_mode.clone(),
+            user_message: self.user_message.clone(),
+            max_retries: self.max_retries,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Operation {
+    /// Names and writability below `parameter_path`, a partial path ending with a dot.
+    GetParameterNames {
+        parameter_path: String,
+        /// Only the immediate children of `parameter_path`.
+        #[serde(default)]
+        next_level: bool,
+    },
+    GetParameterValues {
+        names: Vec<String>,
+    },
+    SetParameterValues {
+        parameters: Vec<ParameterValue>,
+        #[serde(default)]
+        parameter_key: String,
+    },
+    /// The attributes are cached on the device like those of GetParameterNames.
+    GetParameterAttributes {
+        names: Vec<String>,
+    },
+    SetParameterAttributes {
+        attributes: Vec<ParameterAttribute>,
+    },
+    AddObject {
+        object: String,
+        #[serde(default)]
+        parameter_key: String,
+    },
+    DeleteObject {
+        object: String,
+        #[serde(default)]
+        parameter_key: String,
+    },
+    Reboot {
+        #[serde(default)]
+        command_key: String,
+    },
+    Download {
+        /// As in "1 Firmware Upgrade Image".
+        file_type: String,
+        url: String,
+        #[serde(default)]
+        command_key: String,
+    },
+    Upload {
+        /// As in "1 Vendor Configuration File".
+        file_type: String,
+        url: String,
+        #[serde(default)]
+        command_key: String,
+    },
+    ScheduleDownload {
+        file_type: String,
+        url: String,
+        /// One or two windows, in increasing order of start.
+        time_windows: Vec<TimeWindow>,
+        #[serde(default)]
+        command_key: String,
+    },
+    FactoryReset,
+    /// Have the CPE Inform again in `delay_seconds`.
+    ScheduleInform {
+        delay_seconds: u32,
+        #[serde(default)]
+        command_key: String,
+    },
+}
+
+impl Operation {
+    /// Check the values to set parse as their xsi:type, and that it is the type
+    /// `device` reported for the Parameter, if it did.
+    pub fn check_values(&self, device: &Device) -> Result<(), ValueError> {
+        let Operation::SetParameterValues { parameters, .. } = self else {
+            return Ok(());
+        };
+        for parameter in parameters {
+            let checked = ParameterValueStruct::checked(
+                &parameter.name,
+                &parameter.xsi_type,
+                &parameter.value,
+            )?;
+            let declared = device
+                .parameters
+                .get(&parameter.name)
+                .and_then(|cached| cached.xsi_type.as_deref());
+            if let Some(declared) = declared {
+                checked.typed_value()?.ensure_type(declared)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// The ACS request that carries out this operation.
+    pub fn request(&self) -> CWMPMsg {
+        match self {
+            Operation::GetParameterNames {
+                parameter_path,
+                next_level,
+            } => CWMPMsg::GetParameterNames(GetParameterNames::new(parameter_path, *next_level)),
+            Operation::GetParameterValues { names } => {
+                CWMPMsg::GetParameterValues(GetParameterValues::new(names.clone()))
+            }
+            Operation::SetParameterValues {
+                parameters,
+                parameter_key,
+            } => {
+                let parameters = parameters.iter().map(ParameterValue::to_struct).collect();
+                CWMPMsg::SetParameterValues(SetParameterValues::new(parameters, parameter_key))
+            }
+            Operation::GetParameterAttributes { names } => {
+                CWMPMsg::GetParameterAttributes(GetParameterAttributes::new(names.clone()))
+            }
+            Operation::SetParameterAttributes { attributes } => {
+                let attributes = attributes
+                    .iter()
+                    .map(ParameterAttribute::to_struct)
+                    .collect();
+                CWMPMsg::SetParameterAttributes(SetParameterAttributes::new(attributes))
+            }
+            Operation::AddObject {
+                object,
+                parameter_key,
+            } => CWMPMsg::AddObject(AddObject::new(object, parameter_key)),
+            Operation::DeleteObject {
+                object,
+                parameter_key,
+            } => CWMPMsg::DeleteObject(DeleteObject::new(object, parameter_key)),
+            Operation::Reboot { command_key } => CWMPMsg::Reboot(Reboot::new(command_key)),
+            Operation::Download {
+                file_type,
+                url,
+                command_key,
+            } => CWMPMsg::Download(Download::new(command_key, file_type, url)),
+            Operation::Upload {
+                file_type,
+                url,
+                command_key,
+            } => CWMPMsg::Upload(Upload::new(command_key, file_type, url)),
+            Operation::ScheduleDownload {
+                file_type,
+                url,
+                time_windows,
+                command_key,
+            } => {
+                let windows = time_windows.iter().map(TimeWindow::to_struct).collect();
+                CWMPMsg::ScheduleDownload(ScheduleDownload::new(
+                    command_key,
+                    file_type,
+                    url,
+                    windows,
+                ))
+            }
+            Operation::FactoryReset => CWMPMsg::FactoryReset(FactoryReset {}),
+            Operation::ScheduleInform {
+                delay_seconds,
+                command_key,
+            } => CWMPMsg::ScheduleInform(ScheduleInform::new(*delay_seconds, command_key)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum TaskStatus {
+    Pending,
+    Completed,
+    Faulted,
+    Expired,
+}
+
+/// What the CPE answered.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum TaskResult {
+    ParameterValues {
+        parameters: Vec<ParameterValue>,
+    },
+    ObjectAdded {
+        instance_number: u32,
+        status: i32,
+    },
+    /// Status of SetParameterValues, DeleteObject, Download or Upload: 0 applied,
+    /// 1 pending.
+    Status {
+        status: i32,
+    },
+    Done,
+    Fault {
+        code: u32,
+        message: String,
+    },
+    /// The CPE never answered, the request was sent in this many sessions.
+    Unanswered {
+        attempts: u32,
+    },
+}
+
+impl TaskResult {
+    pub fn from_response(msg: &CWMPMsg) -> Self {
+        match msg {
+            CWMPMsg::GetParameterValuesResponse(response) => TaskResult::ParameterValues {
+                parameters: response
+                    .parameter_list
+                    .parameter_struct
+                    .iter()
+                    .map(|parameter| {
+                        let value = parameter.value.clone().unwrap_or_default();
+                        ParameterValue {
+                            name: parameter.name.clone().unwrap_or_default(),
+                            xsi_type: value.xsi_type.unwrap_or_default(),
+                            value: value.value.unwrap_or_default(),
+                        }
+                    })
+                    .collect(),
+            },
+            CWMPMsg::AddObjectResponse(response) => TaskResult::ObjectAdded {
+                instance_number: response.instance_number,
+                status: response.status,
+            },
+            CWMPMsg::SetParameterValuesResponse(response) => TaskResult::Status {
+                status: response.status,
+            },
+            CWMPMsg::DeleteObjectResponse(response) => TaskResult::Status {
+                status: response.status,
+            },
+            CWMPMsg::DownloadResponse(response) => TaskResult::Status {
+                status: response.status,
+            },
+            CWMPMsg::UploadResponse(response) => TaskResult::Status {
+                status: response.status,
+            },
+            CWMPMsg::Fault(fault) => {
+                let fault = CwmpFault::from(fault);
+                TaskResult::Fault {
+                    code: fault.code.code(),
+                    message: fault.message,
+                }
+            }
+            _ => TaskResult::Done,
+        }
+    }
+}
+
+impl Redact for Operation {
+    fn redacted(&self) -> Self {
+        match self {
+            Operation::SetParameterValues {
+                parameters,
+                parameter_key,
+            } => Operation::SetParameterValues {
+                parameters: parameters.redacted(),
+                parameter_key: parameter_key.clone(),
+            },
+            operation => operation.clone(),
+        }
+    }
+}
+
+impl Redact for TaskResult {
+    fn redacted(&self) -> Self {
+        match self {
+            TaskResult::ParameterValues { parameters } => TaskResult::ParameterValues {
+                parameters: parameters.redacted(),
+            },
+            result => result.clone(),
+        }
+    }
+}
+
+/// A queued operation. Serialized, as in the northbound API and events, the values
+/// of secret Parameters are blanked; the stored `TaskPayload` keeps them.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+pub struct Task {
+    pub id: u64,
+    pub device_id: DeviceId,
+    #[serde(serialize_with = "serialize_redacted")]
+    pub operation: Operation,
+    pub created: DateTime<Utc>,
+    /// Not sent after this time.
+    pub expires: Option<DateTime<Utc>>,
+    pub status: TaskStatus,
+    /// Sessions the request was sent in.
+    pub attempts: u32,
+    #[serde(serialize_with = "serialize_redacted")]
+    pub result: Option<TaskResult>,
+    /// When the task stopped being pending.
+    pub finished: Option<DateTime<Utc>>,
+}
+
+// Stored as the payload of a `TaskRecord`, which holds the id, device and creation time.
+#[derive(Serialize, Deserialize)]
+struct TaskPayload {
+    operation: Operation,
+    expires: Option<DateTime<Utc>>,
+    status: TaskStatus,
+    attempts: u32,
+    result: Option<TaskResult>,
+    #[serde(default)]
+    finished: Option<DateTime<Utc>>,
+}
+
+impl Task {
+    fn to_record(&self) -> Result<TaskRecord, StorageError> {
+        let payload = TaskPayload {
+            operation: self.operation.clone(),
+            expires: self.expires,
+            status: self.status,
+            attempts: self.attempts,
+            result: self.result.clone(),
+            finished: self.finished,
+        };
+        Ok(TaskRecord {
+            id: self.id,
+            device_id: self.device_id.clone(),
+            created: self.created,
+            payload: serde_json::to_string(&payload)
+                .map_err(|err| StorageError::Corrupt(err.to_string()))?,
+        })
+    }
+
+    fn from_record(record: TaskRecord) -> Result<Self, StorageError> {
+        let payload: TaskPayload = serde_json::from_str(&record.payload)
+            .map_err(|err| StorageError::Corrupt(err.to_string()))?;
+        Ok(Self {
+            id: record.id,
+            device_id: record.device_id,
+            operation: payload.operation,
+            created: record.created,
+            expires: payload.expires,
+            status: payload.status,
+            attempts: payload.attempts,
+            result: payload.result,
+            finished: payload.finished,
+        })
+    }
+
+    pub fn is_expired(&self, now: DateTime<Utc>) -> bool {
+        self.expires.is_some_and(|expires| expires <= now)
+    }
+}
+
+/// Every task, finished ones included so their results can be read back.
+#[derive(Debug, Clone)]
+pub struct TaskQueue {
+    tasks: Arc<Mutex<BTreeMap<u64, Task>>>,
+    storage: Arc<dyn Storage>,
+    max_attempts: u32,
+    /// Finished tasks are kept forever when `None`.
+    retention: Option<TimeDelta>,
+}
+
+impl TaskQueue {
+    /// An empty queue backed by `storage`.
+    pub fn new(storage: Arc<dyn Storage>) -> Self {
+        Self {
+            tasks: Arc::default(),
+            storage,
+            max_attempts: DEFAULT_MAX_ATTEMPTS,
+            retention: Some(DEFAULT_RETENTION),
+        }
+    }
+
+    /// Queue backed by `storage`, starting with the tasks it already holds.
+    pub fn load(storage: Arc<dyn Storage>) -> Result<Self, StorageError> {
+        let tasks = storage
+            .load_tasks()?
+            .into_iter()
+            .map(|record| Task::from_record(record).map(|task| (task.id, task)))
+            .collect::<Result<_, _>>()?;
+        Ok(Self {
+            tasks: Arc::new(Mutex::new(tasks)),
+            ..Self::new(storage)
+        })
+    }
+
+    pub fn with_max_attempts(mut self, max_attempts: u32) -> Self {
+        self.max_attempts = max_attempts;
+        self
+    }
+
+    pub fn with_retention(mut self, retention: Option<TimeDelta>) -> Self {
+        self.retention = retention;
+        self
+    }
+
+    pub fn enqueue(
+        &self,
+        device_id: DeviceId,
+        operation: Operation,
+        expires: Option<DateTime<Utc>>,
+    ) -> Result<Task, StorageError> {
+        let mut task = Task {
+            id: 0,
+            device_id,
+            operation,
+            created: Utc::now(),
+            expires,
+            status: TaskStatus::Pending,
+            attempts: 0,
+            result: None,
+            finished: None,
+        };
+        task.id = self.storage.add_task(&task.to_record()?)?;
+        self.tasks.lock().unwrap().insert(task.id, task.clone());
+        Ok(task)
+    }
+
+    pub fn get(&self, id: u64) -> Option<Task> {
+        self.tasks.lock().unwrap().get(&id).cloned()
+    }
+
+    /// Every task, in the order they were queued.
+    pub fn list(&self) -> Vec<Task> {
+        self.tasks.lock().unwrap().values().cloned().collect()
+    }
+
+    /// Tasks of a device in the order they were queued.
+    pub fn tasks_for(&self, device_id: &DeviceId) -> Vec<Task> {
+        let tasks = self.tasks.lock().unwrap();
+        tasks
+            .values()
+            .filter(|task| &task.device_id == device_id)
+            .cloned()
+            .collect()
+    }
+
+    /// Pending tasks of a device to send in a new session. Tasks that expired
+    /// meanwhile, or were sent `max_attempts` times already, finish and are left out.
+    pub fn pending(&self, device_id: &DeviceId, now: DateTime<Utc>) -> Vec<Task> {
+        let mut pending = Vec::new();
+        for task in self.tasks_for(device_id) {
+            if task.status != TaskStatus::Pending {
+                continue;
+            }
+            if task.is_expired(now) {
+                tracing::info!("Task {} for {device_id} expired", task.id);
+                self.update(task.id, |task| {
+                    task.status = TaskStatus::Expired;
+                    task.finished = Some(now);
+                });
+            } else if task.attempts >= self.max_attempts {
+                tracing::warn!(
+                    "Task {} for {device_id} was not answered in {} sessions",
+                    task.id,
+                    task.attempts
+                );
+                self.update(task.id, |task| {
+                    task.status = TaskStatus::Faulted;
+                    task.result = Some(TaskResult::Unanswered {
+                        attempts: task.attempts,
+                    });
+                    task.finished = Some(now);
+                });
+            } else {
+                pending.push(task);
+            }
+        }
+        pending
+    }
+
+    /// The task's request was sent to the CPE.
+    pub fn dispatched(&self, id: u64) {
+        self.update(id, |task| task.attempts += 1);
+    }
+
+    /// Record the CPE's answer and finish the task.
+    pub fn complete(&self, id: u64, result: TaskResult) -> Option<Task> {
+        self.update(id, |task| {
+            task.status = match result {
+                TaskResult::Fault { .. } => TaskStatus::Faulted,
+                _ => TaskStatus::Completed,
+            };
+            task.result = Some(result);
+            task.finished = Some(Utc::now());
+        })
+    }
+
+    /// Queue a finished task again, e.g. after the cause of its fault was fixed.
+    pub fn retry(&self, id: u64) -> Option<Task> {
+        self.update(id, |task| {
+            task.status = TaskStatus::Pending;
+            task.attempts = 0;
+            task.result = None;
+            task.finished = None;
+        })
+    }
+
+    /// Remove the tasks that finished longer than the retention period before
+    /// `now`, returning how many. Tasks stored without a finish time count from
+    /// their creation.
+    pub fn purge(&self, now: DateTime<Utc>) -> Result<usize, StorageError> {
+        let Some(retention) = self.retention else {
+            return Ok(0);
+        };
+        let mut tasks = self.tasks.lock().unwrap();
+        let done: Vec<u64> = tasks
+            .values()
+            .filter(|task| task.status != TaskStatus::Pending)
+            .filter(|task| task.finished.unwrap_or(task.created) + retention <= now)
+            .map(|task| task.id)
+            .collect();
+        for id in &done {
+            self.storage.delete_task(*id)?;
+            tasks.remove(id);
+        }
+        Ok(done.len())
+    }
+
+    /// Purge finished tasks every hour, until the runtime shuts down.
+    pub async fn run_retention(self) {
+        let mut interval = tokio::time::interval(PURGE_INTERVAL);
+        loop {
+            interval.tick().await;
+            let queue = self.clone();
+            match tokio::task::spawn_blocking(move || queue.purge(Utc::now())).await {
+                Ok(Ok(0)) => {}
+                Ok(Ok(purged)) => tracing::info!("Removed {purged} finished tasks"),
+                Ok(Err(err)) => tracing::error!("Failed to remove finished tasks: {err}"),
+                Err(err) => tracing::error!("Failed to remove finished tasks: {err}"),
+            }
+        }
+    }
+
+    /// Remove a task, finished or not.
+    pub fn cancel(&self, id: u64) -> Result<Option<Task>, StorageError> {
+        self.storage.delete_task(id)?;
+        Ok(self.tasks.lock().unwrap().remove(&id))
+    }
+
+    fn update(&self, id: u64, update: impl FnOnce(&mut Task)) -> Option<Task> {
+        let mut tasks = self.tasks.lock().unwrap();
+        let task = tasks.get_mut(&id)?;
+        update(task);
+        let result = task
+            .to_record()
+            .and_then(|record| self.storage.update_task(&record));
+        if let Err(err) = result {
+            tracing::error!("Failed to persist task {id}: {err}");
+        }
+        Some(task.clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::MemoryStorage;
+
+    #[test]
+    fn test_task_queue() {
+        let storage: Arc<dyn Storage> = Arc::new(MemoryStorage::new());
+        let queue = TaskQueue::load(storage.clone()).unwrap();
+        let id: DeviceId = "001A2B-RouterX-SN1".parse().unwrap();
+        let now = Utc::now();
+
+        let reboot = queue
+            .enqueue(
+                id.clone(),
+                Operation::Reboot {
+                    command_key: String::from("reboot-1"),
+                },
+                None,
+            )
+            .unwrap();
+        let get = queue
+            .enqueue(
+                id.clone(),
+                Operation::GetParameterValues {
+                    names: vec![String::from("Device.DeviceInfo.")],
+                },
+                Some(now + chrono::Duration::hours(1)),
+            )
+            .unwrap();
+        let stale = queue
+            .enqueue(
+                id.clone(),
+                Operation::FactoryReset,
+                Some(now - chrono::Duration::seconds(1)),
+            )
+            .unwrap();
+        assert!(matches!(reboot.operation.request(), CWMPMsg::Reboot(_)));
+        let names = Operation::GetParameterNames {
+            parameter_path: String::from("Device.WiFi."),
+            next_level: false,
+        };
+        let CWMPMsg::GetParameterNames(request) = names.request() else {
+            panic!("not a GetParameterNames");
+        };
+        assert_eq!(request.parameter_path, "Device.WiFi.");
+        assert!(!request.next_level);
+        let attributes: Operation = serde_json::from_str(
+            r#"{"type": "set_parameter_attributes",
+                "attributes": [{"name": "Device.DeviceInfo.SoftwareVersion", "notification": 2}]}"#,
+        )
+        .unwrap();
+        let CWMPMsg::SetParameterAttributes(request) = attributes.request() else {
+            panic!("not a SetParameterAttributes");
+        };
+        let attribute = &request.parameter_list.attributes[0];
+        assert!(attribute.notification_change && !attribute.access_list_change);
+        assert_eq!(attribute.notification, 2);
+        let download: Operation = serde_json::from_str(
+            r#"{"type": "schedule_download", "file_type": "1 Firmware Upgrade Image",
+                "url": "http://files.example.com/fw.bin",
+                "time_windows": [{"window_start": 0, "window_end": 3600, "window_mode": "3 When Idle"}]}"#,
+        )
+        .unwrap();
+        let CWMPMsg::ScheduleDownload(request) = download.request() else {
+            panic!("not a ScheduleDownload");
+        };
+        assert_eq!(request.time_window_list.windows[0].max_retries, -1);
+
+        let pending = queue.pending(&id, now);
+        assert_eq!(
+            pending.iter().map(|task| task.id).collect::<Vec<_>>(),
+            [reboot.id, get.id]
+        );
+        assert_eq!(queue.get(stale.id).unwrap().status, TaskStatus::Expired);
+
+        // The CPE dropped the session after the first request: it is sent again.
+        queue.dispatched(reboot.id);
+        assert_eq!(queue.pending(&id, now).len(), 2);
+
+        queue.complete(reboot.id, TaskResult::Done);
+        queue.complete(
+            get.id,
+            TaskResult::Fault {
+                code: 9005,
+                message: String::from("Invalid parameter name"),
+            },
+        );
+        assert!(queue.pending(&id, now).is_empty());
+
+        // Everything survives a restart.
+        let reloaded = TaskQueue::load(storage).unwrap();
+        let reboot = reloaded.get(reboot.id).unwrap();
+        assert_eq!(reboot.status, TaskStatus::Completed);
+        assert_eq!(reboot.attempts, 1);
+        assert_eq!(reloaded.get(get.id).unwrap().status, TaskStatus::Faulted);
+
+        // A retried task is sent in the next session again.
+        let retried = reloaded.retry(get.id).unwrap();
+        assert_eq!(retried.status, TaskStatus::Pending);
+        assert_eq!(retried.result, None);
+        assert_eq!(reloaded.pending(&id, now).len(), 1);
+        assert_eq!(reloaded.list().len(), 3);
+    }
+
+    #[test]
+    fn test_attempts_and_retention() {
+        let storage: Arc<dyn Storage> = Arc::new(MemoryStorage::new());
+        let queue = TaskQueue::new(storage.clone())
+            .with_max_attempts(2)
+            .with_retention(Some(TimeDelta::days(1)));
+        let id: DeviceId = "001A2B-RouterX-SN1".parse().unwrap();
+        let now = Utc::now();
+        let unanswered = queue
+            .enqueue(id.clone(), Operation::FactoryReset, None)
+            .unwrap();
+        let done = queue
+            .enqueue(id.clone(), Operation::FactoryReset, None)
+            .unwrap();
+
+        // Sent in two sessions the CPE dropped before answering.
+        for _ in 0..2 {
+            assert_eq!(queue.pending(&id, now).len(), 2);
+            queue.dispatched(unanswered.id);
+        }
+        queue.complete(done.id, TaskResult::Done);
+        assert!(queue.pending(&id, now).is_empty());
+        let unanswered = queue.get(unanswered.id).unwrap();
+        assert_eq!(unanswered.status, TaskStatus::Faulted);
+        assert_eq!(
+            unanswered.result,
+            Some(TaskResult::Unanswered { attempts: 2 })
+        );
+        assert_eq!(unanswered.finished, Some(now));
+
+        assert_eq!(queue.purge(now).unwrap(), 0);
+        let retried = queue.retry(unanswered.id).unwrap();
+        assert_eq!(retried.attempts, 0);
+        assert_eq!(queue.purge(now + TimeDelta::days(2)).unwrap(), 1);
+        assert_eq!(queue.get(done.id), None);
+        assert_eq!(storage.load_tasks().unwrap().len(), 1);
+    }
+}
@@ -8,11 +8,10 @@ use tracing_subscriber::{layer::SubscriberExt, Registry};
 pub fn get_subscriber(name: String, directive: Directive) -> impl Subscriber + Send + Sync {
     let env_filter = EnvFilter::from_default_env().add_directive(directive);
     let formatting_layer = BunyanFormattingLayer::new(name, std::io::stdout);
-    let global_subscriber = Registry::default()
+    Registry::default()
         .with(env_filter)
         .with(formatting_layer)
-        .with(JsonStorageLayer);
-    return global_subscriber;
+        .with(JsonStorageLayer)
 }
 
 pub fn init_subscriber(subscriber: impl Subscriber + Send + Sync) {
@@ -0,0 +1,287 @@
+// TLS for the CWMP endpoint (TR-069 Amendment 6, section 3.4.1).
+//
+// The server certificate and key are read from PEM files and can be swapped at
+// runtime with `reload`, e.g. on SIGHUP after a renewal; connections already open
+// keep the certificate they were accepted with. With a client CA configured, CPEs
+// may present a certificate, which then identifies the device through its subject
+// CommonName or serialNumber, either being OUI-SerialNumber or
+// OUI-ProductClass-SerialNumber. A bare serial number is not enough, serial
+// numbers are only unique within a manufacturer.
+use crate::device::DeviceId;
+use rustls::{
+    crypto::{ring, CryptoProvider},
+    pki_types::{pem::PemObject, CertificateDer, PrivateKeyDer},
+    server::{danger::ClientCertVerifier, VerifierBuilderError, WebPkiClientVerifier},
+    RootCertStore, ServerConfig,
+};
+use serde::Deserialize;
+use std::{
+    fmt,
+    path::{Path, PathBuf},
+    sync::{Arc, RwLock},
+};
+use tokio_rustls::TlsAcceptor;
+use x509_parser::oid_registry::OID_X509_SERIALNUMBER;
+
+#[derive(Debug)]
+pub enum TlsError {
+    /// A PEM file could not be read or parsed.
+    Pem {
+        path: PathBuf,
+        error: rustls::pki_types::pem::Error,
+    },
+    /// The file holds no certificate.
+    NoCertificate(PathBuf),
+    ClientVerifier(VerifierBuilderError),
+    Rustls(rustls::Error),
+}
+
+impl fmt::Display for TlsError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TlsError::Pem { path, error } => write!(f, "{}: {error}", path.display()),
+            TlsError::NoCertificate(path) => write!(f, "{}: no certificate", path.display()),
+            TlsError::ClientVerifier(err) => write!(f, "client certificate verifier: {err}"),
+            TlsError::Rustls(err) => write!(f, "TLS error: {err}"),
+        }
+    }
+}
+
+impl std::error::Error for TlsError {}
+
+impl From<rustls::Error> for TlsError {
+    fn from(err: rustls::Error) -> Self {
+        TlsError::Rustls(err)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct TlsSettings {
+    /// PEM certificate chain, leaf first.
+    pub cert: PathBuf,
+    /// PEM private key of the leaf certificate.
+    pub key: PathBuf,
+    /// PEM CA certificates CPE certificates are checked against. Client
+    /// certificates are not asked for without it.
+    #[serde(default)]
+    pub client_ca: Option<PathBuf>,
+    /// Refuse CPEs that present no certificate, rather than falling back to
+    /// HTTP authentication.
+    #[serde(default)]
+    pub require_client_cert: bool,
+}
+
+pub(crate) fn certificates(path: &Path) -> Result<Vec<CertificateDer<'static>>, TlsError> {
+    let pem = |error| TlsError::Pem {
+        path: path.to_path_buf(),
+        error,
+    };
+    let certificates = CertificateDer::pem_file_iter(path)
+        .map_err(pem)?
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(pem)?;
+    if certificates.is_empty() {
+        return Err(TlsError::NoCertificate(path.to_path_buf()));
+    }
+    Ok(certificates)
+}
+
+impl TlsSettings {
+    fn server_config(&self) -> Result<ServerConfig, TlsError> {
+        let provider = Arc::new(ring::default_provider());
+        let chain = certificates(&self.cert)?;
+        let key = PrivateKeyDer::from_pem_file(&self.key).map_err(|error| TlsError::Pem {
+            path: self.key.clone(),
+            error,
+        })?;
+        let builder = ServerConfig::builder_with_provider(provider.clone())
+            .with_safe_default_protocol_versions()?;
+        let config = match &self.client_ca {
+            Some(client_ca) => builder
+                .with_client_cert_verifier(self.client_verifier(client_ca, provider)?)
+                .with_single_cert(chain, key)?,
+            None => builder.with_no_client_auth().with_single_cert(chain, key)?,
+        };
+        Ok(config)
+    }
+
+    fn client_verifier(
+        &self,
+        client_ca: &Path,
+        provider: Arc<CryptoProvider>,
+    ) -> Result<Arc<dyn ClientCertVerifier>, TlsError> {
+        let mut roots = RootCertStore::empty();
+        for certificate in certificates(client_ca)? {
+            roots.add(certificate)?;
+        }
+        let mut builder = WebPkiClientVerifier::builder_with_provider(Arc::new(roots), provider);
+        if !self.require_client_cert {
+            builder = builder.allow_unauthenticated();
+        }
+        builder.build().map_err(TlsError::ClientVerifier)
+    }
+}
+
+/// The server side TLS configuration, replaceable while the listener runs.
+#[derive(Debug, Clone)]
+pub struct ReloadableTlsConfig {
+    settings: TlsSettings,
+    current: Arc<RwLock<Arc<ServerConfig>>>,
+}
+
+impl ReloadableTlsConfig {
+    pub fn load(settings: TlsSettings) -> Result<Self, TlsError> {
+        let config = settings.server_config()?;
+        Ok(Self {
+            settings,
+            current: Arc::new(RwLock::new(Arc::new(config))),
+        })
+    }
+
+    /// Read the files again. The previous configuration stays in use on error.
+    pub fn reload(&self) -> Result<(), TlsError> {
+        let config = self.settings.server_config()?;
+        *self.current.write().unwrap() = Arc::new(config);
+        Ok(())
+    }
+
+    /// Acceptor for the next connection.
+    pub fn acceptor(&self) -> TlsAcceptor {
+        TlsAcceptor::from(self.current.read().unwrap().clone())
+    }
+}
+
+/// Reload `config` whenever the process receives SIGHUP.
+#[cfg(unix)]
+pub async fn reload_on_sighup(config: ReloadableTlsConfig) {
+    use tokio::signal::unix::{signal, SignalKind};
+
+    let Ok(mut hangups) = signal(SignalKind::hangup()) else {
+        tracing::warn!("Cannot listen for SIGHUP, TLS certificates will not be reloaded");
+        return;
+    };
+    while hangups.recv().await.is_some() {
+        match config.reload() {
+            Ok(()) => tracing::info!("Reloaded TLS certificates"),
+            Err(err) => tracing::error!("Failed to reload TLS certificates: {err}"),
+        }
+    }
+}
+
+/// Subject of the verified certificate a CPE presented.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ClientCertificate {
+    pub common_name: Option<String>,
+    pub serial_number: Option<String>,
+}
+
+impl ClientCertificate {
+    pub fn from_der(der: &[u8]) -> Option<Self> {
+        let (_, certificate) = x509_parser::parse_x509_certificate(der).ok()?;
+        let subject = certificate.subject();
+        let common_name = subject
+            .iter_common_name()
+            .find_map(|attribute| attribute.as_str().ok())
+            .map(str::to_string);
+        let serial_number = subject
+            .iter_by_oid(&OID_X509_SERIALNUMBER)
+            .find_map(|attribute| attribute.as_str().ok())
+            .map(str::to_string);
+        Some(Self {
+            common_name,
+            serial_number,
+        })
+    }
+
+    /// Whether the certificate was issued to the device `id`.
+    pub fn matches(&self, id: &DeviceId) -> bool {
+        let names = [id.to_string(), format!("{}-{}", id.oui, id.serial_number)];
+        [&self.common_name, &self.serial_number]
+            .into_iter()
+            .flatten()
+            .any(|subject| names.contains(subject))
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use rcgen::{
+        BasicConstraints, CertificateParams, DnType, ExtendedKeyUsagePurpose, IsCa, KeyPair,
+    };
+
+    /// PEM files of a CA, a server certificate for localhost signed by it, and a
+    /// client certificate for the CPE 001A2B-RouterX-123456789, written to `dir`.
+    pub fn write_certificates(dir: &Path) -> TlsSettings {
+        std::fs::create_dir_all(dir).unwrap();
+        let ca_key = KeyPair::generate().unwrap();
+        let mut ca = CertificateParams::new(Vec::<String>::new()).unwrap();
+        ca.is_ca = IsCa::Ca(BasicConstraints::Unconstrained);
+        ca.distinguished_name.push(DnType::CommonName, "Test CA");
+        let ca = ca.self_signed(&ca_key).unwrap();
+
+        let issue = |names: Vec<String>, common_name: &str, usage, file: &str| {
+            let key = KeyPair::generate().unwrap();
+            let mut params = CertificateParams::new(names).unwrap();
+            params
+                .distinguished_name
+                .push(DnType::CommonName, common_name);
+            params.extended_key_usages = vec![usage];
+            let certificate = params.signed_by(&key, &ca, &ca_key).unwrap();
+            std::fs::write(dir.join(format!("{file}.pem")), certificate.pem()).unwrap();
+            std::fs::write(dir.join(format!("{file}.key")), key.serialize_pem()).unwrap();
+        };
+        issue(
+            vec![String::from("localhost")],
+            "localhost",
+            ExtendedKeyUsagePurpose::ServerAuth,
+            "server",
+        );
+        issue(
+            Vec::new(),
+            "001A2B-123456789",
+            ExtendedKeyUsagePurpose::ClientAuth,
+            "client",
+        );
+        std::fs::write(dir.join("ca.pem"), ca.pem()).unwrap();
+
+        TlsSettings {
+            cert: dir.join("server.pem"),
+            key: dir.join("server.key"),
+            client_ca: Some(dir.join("ca.pem")),
+            require_client_cert: false,
+        }
+    }
+
+    #[test]
+    fn test_load_and_reload() {
+        let dir = std::env::temp_dir().join(format!("tr069-acs-tls-{}", std::process::id()));
+        let settings = write_certificates(&dir);
+        let config = ReloadableTlsConfig::load(settings.clone()).unwrap();
+
+        let der = CertificateDer::from_pem_file(dir.join("client.pem")).unwrap();
+        let certificate = ClientCertificate::from_der(&der).unwrap();
+        assert_eq!(certificate.common_name.as_deref(), Some("001A2B-123456789"));
+        assert!(certificate.matches(&"001A2B-RouterX-123456789".parse().unwrap()));
+        assert!(!certificate.matches(&"001A2B-RouterX-987654321".parse().unwrap()));
+        // The serial number of another manufacturer's device.
+        let certificate = ClientCertificate {
+            common_name: Some(String::from("cpe")),
+            serial_number: Some(String::from("123456789")),
+        };
+        assert!(!certificate.matches(&"00D09E-ModemY-123456789".parse().unwrap()));
+        let certificate = ClientCertificate {
+            serial_number: Some(String::from("00D09E-123456789")),
+            ..certificate
+        };
+        assert!(certificate.matches(&"00D09E-ModemY-123456789".parse().unwrap()));
+
+        // A broken renewal keeps the running configuration.
+        std::fs::write(&settings.cert, "not a certificate").unwrap();
+        assert!(matches!(config.reload(), Err(TlsError::NoCertificate(_))));
+        write_certificates(&dir);
+        config.reload().unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
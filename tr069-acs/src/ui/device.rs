@@ -0,0 +1,640 @@
+use super::Route;
+#[cfg(feature = "server")]
+use crate::{
+    device::{Device, DeviceId},
+    startup::AcsState,
+    task::{Operation, ParameterValue},
+};
+use chrono::{DateTime, Utc};
+use dioxus::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::{collections::BTreeSet, fmt};
+
+/// A cached Parameter, or an object when its name ends with a dot.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ParameterRow {
+    pub name: String,
+    /// Left out for passwords and keys.
+    pub value: Option<String>,
+    pub xsi_type: Option<String>,
+    pub writable: Option<bool>,
+    /// When the CPE last reported any of the above.
+    pub updated: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DeviceDetails {
+    /// `OUI-ProductClass-SerialNumber`.
+    pub id: String,
+    pub manufacturer: Option<String>,
+    pub software_version: Option<String>,
+    pub ip_address: Option<String>,
+    pub first_inform: DateTime<Utc>,
+    pub last_inform: DateTime<Utc>,
+    pub last_events: Vec<String>,
+    pub online: bool,
+    /// Tasks waiting for the next session.
+    pub pending_tasks: usize,
+    /// Ordered by name.
+    pub parameters: Vec<ParameterRow>,
+}
+
+#[cfg(feature = "server")]
+impl DeviceDetails {
+    fn new(device: &Device, pending_tasks: usize, now: DateTime<Utc>) -> Self {
+        Self {
+            id: device.id.to_string(),
+            manufacturer: device.manufacturer.clone(),
+            software_version: device.software_version.clone(),
+            ip_address: device.ip_address().map(str::to_string),
+            first_inform: device.first_inform,
+            last_inform: device.last_inform,
+            last_events: device.last_events.clone(),
+            online: device.is_online(now),
+            pending_tasks,
+            parameters: device
+                .parameters
+                .iter()
+                .map(|(name, parameter)| (name, parameter.redacted(name)))
+                .map(|(name, parameter)| ParameterRow {
+                    name: name.clone(),
+                    value: parameter.value,
+                    xsi_type: parameter.xsi_type.clone(),
+                    writable: parameter.writable,
+                    updated: parameter.updated,
+                })
+                .collect(),
+        }
+    }
+}
+
+/// A node of the data model, built from the dotted Parameter names.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct ParameterNode {
+    /// Full name, ending with a dot for objects. Empty for the root.
+    pub path: String,
+    /// Last segment of the path, e.g. `SSID` or the instance number `2`.
+    pub name: String,
+    /// What the CPE reported for this very path, if anything: objects only
+    /// appear in GetParameterNames responses.
+    pub parameter: Option<ParameterRow>,
+    /// Sub-objects and Parameters by name, instances by number.
+    pub children: Vec<ParameterNode>,
+}
+
+impl ParameterNode {
+    /// The tree of `parameters` under a nameless root.
+    pub fn tree(parameters: &[ParameterRow]) -> Self {
+        let mut root = Self::default();
+        for parameter in parameters {
+            root.insert(parameter.clone());
+        }
+        root.sort();
+        root
+    }
+
+    // Adds the intermediate objects a name implies but the cache may not hold.
+    fn insert(&mut self, parameter: ParameterRow) {
+        let name = parameter.name.clone();
+        let object = name.ends_with('.');
+        let segments: Vec<&str> = name.split('.').filter(|s| !s.is_empty()).collect();
+        if segments.is_empty() {
+            return;
+        }
+        let mut node = self;
+        for (i, segment) in segments.iter().enumerate() {
+            let path = if i + 1 == segments.len() && !object {
+                format!("{}{segment}", node.path)
+            } else {
+                format!("{}{segment}.", node.path)
+            };
+            let index = match node.children.iter().position(|child| child.path == path) {
+                Some(index) => index,
+                None => {
+                    node.children.push(Self {
+                        path,
+                        name: segment.to_string(),
+                        ..Self::default()
+                    });
+                    node.children.len() - 1
+                }
+            };
+            node = &mut node.children[index];
+        }
+        node.parameter = Some(parameter);
+    }
+
+    fn sort(&mut self) {
+        self.children
+            .sort_by(|a, b| a.sort_key().cmp(&b.sort_key()));
+        for child in &mut self.children {
+            child.sort();
+        }
+    }
+
+    // Instances after named children, by number rather than as text.
+    fn sort_key(&self) -> (Option<u32>, &str) {
+        (self.name.parse().ok(), &self.name)
+    }
+
+    pub fn is_object(&self) -> bool {
+        self.path.ends_with('.')
+    }
+
+    /// An instance of a multi-instance object, e.g. `Device.WiFi.SSID.2.`.
+    pub fn is_instance(&self) -> bool {
+        self.is_object() && self.name.parse::<u32>().is_ok()
+    }
+
+    pub fn writable(&self) -> Option<bool> {
+        self.parameter
+            .as_ref()
+            .and_then(|parameter| parameter.writable)
+    }
+
+    /// A multi-instance object taking AddObject: a writable object in
+    /// GetParameterNames terms, or, before one reported it, an object holding
+    /// nothing but instances.
+    pub fn can_add_instance(&self) -> bool {
+        self.is_object()
+            && !self.is_instance()
+            && self.writable().unwrap_or_else(|| {
+                !self.children.is_empty() && self.children.iter().all(Self::is_instance)
+            })
+    }
+
+    pub fn can_delete(&self) -> bool {
+        self.is_instance() && self.writable() != Some(false)
+    }
+}
+
+/// What the parameter tree asks of the CPE.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ParameterAction {
+    Set {
+        name: String,
+        xsi_type: String,
+        value: String,
+    },
+    /// Reload the names, writability and values below `path`.
+    Refresh {
+        path: String,
+    },
+    AddInstance {
+        object: String,
+    },
+    DeleteInstance {
+        object: String,
+    },
+}
+
+impl fmt::Display for ParameterAction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ParameterAction::Set { name, value, .. } => write!(f, "setting {name} to {value:?}"),
+            ParameterAction::Refresh { path } => write!(f, "refreshing {path}"),
+            ParameterAction::AddInstance { object } => write!(f, "adding an instance to {object}"),
+            ParameterAction::DeleteInstance { object } => write!(f, "deleting {object}"),
+        }
+    }
+}
+
+#[cfg(feature = "server")]
+impl ParameterAction {
+    fn operations(self) -> Vec<Operation> {
+        match self {
+            ParameterAction::Set {
+                name,
+                xsi_type,
+                value,
+            } => vec![Operation::SetParameterValues {
+                parameters: vec![ParameterValue {
+                    name,
+                    xsi_type,
+                    value,
+                }],
+                parameter_key: String::new(),
+            }],
+            ParameterAction::Refresh { path } => vec![
+                Operation::GetParameterNames {
+                    parameter_path: path.clone(),
+                    next_level: false,
+                },
+                Operation::GetParameterValues { names: vec![path] },
+            ],
+            ParameterAction::AddInstance { object } => vec![Operation::AddObject {
+                object,
+                parameter_key: String::new(),
+            }],
+            ParameterAction::DeleteInstance { object } => vec![Operation::DeleteObject {
+                object,
+                parameter_key: String::new(),
+            }],
+        }
+    }
+}
+
+#[cfg(feature = "server")]
+async fn known_device(id: &str) -> Result<(AcsState, DeviceId), ServerFnError> {
+    use axum::Extension;
+
+    let Extension(state): Extension<AcsState> = extract().await?;
+    let id: DeviceId = id.parse().map_err(ServerFnError::new)?;
+    if state.devices.get(&id).is_none() {
+        return Err(ServerFnError::new(format!("device {id} not found")));
+    }
+    Ok((state, id))
+}
+
+#[server]
+pub async fn get_device(id: String) -> Result<DeviceDetails, ServerFnError> {
+    let (state, id) = known_device(&id).await?;
+    let device = state
+        .devices
+        .get(&id)
+        .ok_or_else(|| ServerFnError::new(format!("device {id} not found")))?;
+    let now = Utc::now();
+    let pending_tasks = state.tasks.pending(&id, now).len();
+    Ok(DeviceDetails::new(&device, pending_tasks, now))
+}
+
+/// Queue the tasks of `action` and ask the CPE to connect, returning their ids.
+#[server]
+pub async fn queue_action(id: String, action: ParameterAction) -> Result<Vec<u64>, ServerFnError> {
+    let (state, id) = known_device(&id).await?;
+    let mut tasks = Vec::new();
+    for operation in action.operations() {
+        let task = state
+            .tasks
+            .enqueue(id.clone(), operation, None)
+            .map_err(ServerFnError::new)?;
+        tasks.push(task.id);
+    }
+    state.request_connection(&id);
+    Ok(tasks)
+}
+
+/// A device and its cached data model, browsable and editable.
+#[component]
+pub fn DeviceDetail(id: ReadOnlySignal<String>) -> Element {
+    let mut device = use_resource(move || get_device(id()));
+    let tree = use_memo(move || match &*device.read() {
+        Some(Ok(details)) => Some(ParameterNode::tree(&details.parameters)),
+        _ => None,
+    });
+    let mut message = use_signal(|| None::<String>);
+    // Paths opened or closed by hand; top-level objects start open.
+    let toggled = use_signal(BTreeSet::<String>::new);
+    let act = use_callback(move |action: ParameterAction| {
+        spawn(async move {
+            let label = action.to_string();
+            let text = match queue_action(id(), action).await {
+                Ok(tasks) => {
+                    let tasks: Vec<String> = tasks.iter().map(|task| format!("#{task}")).collect();
+                    format!("Queued {label}: task {}", tasks.join(", "))
+                }
+                Err(err) => format!("Failed to queue {label}: {err}"),
+            };
+            message.set(Some(text));
+            device.restart();
+        });
+    });
+
+    rsx! {
+        section { id: "device",
+            Link { class: "back", to: Route::DeviceList {}, "← Devices" }
+            h1 { "{id}" }
+            match &*device.read() {
+                Some(Ok(details)) => rsx! {
+                    DeviceSummary { details: details.clone() }
+                },
+                Some(Err(err)) => rsx! {
+                    p { class: "error", "Failed to load the device: {err}" }
+                },
+                None => rsx! {
+                    p { class: "loading", "Loading device…" }
+                },
+            }
+            if let Some(text) = message() {
+                p { class: "message", "{text}" }
+            }
+            if let Some(root) = tree() {
+                table { class: "parameters",
+                    thead {
+                        tr {
+                            th { "Name" }
+                            th { "Value" }
+                            th { "Type" }
+                            th { "Writable" }
+                            th { "Refreshed" }
+                            th {}
+                        }
+                    }
+                    tbody {
+                        for node in root.children.iter() {
+                            TreeRows {
+                                key: "{node.path}",
+                                node: node.clone(),
+                                depth: 0,
+                                toggled,
+                                act,
+                            }
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[component]
+fn DeviceSummary(details: DeviceDetails) -> Element {
+    let optional = |value: &Option<String>| value.clone().unwrap_or_else(|| String::from("—"));
+    let events = details.last_events.join(", ");
+    rsx! {
+        dl { class: "summary",
+            dt { "Manufacturer" }
+            dd { {optional(&details.manufacturer)} }
+            dt { "Software version" }
+            dd { {optional(&details.software_version)} }
+            dt { "IP address" }
+            dd { {optional(&details.ip_address)} }
+            dt { "Status" }
+            dd {
+                if details.online {
+                    span { class: "status online", "Online" }
+                } else {
+                    span { class: "status offline", "Offline" }
+                }
+            }
+            dt { "Last Inform" }
+            dd {
+                {details.last_inform.format("%Y-%m-%d %H:%M:%S UTC").to_string()}
+                if !events.is_empty() {
+                    " ({events})"
+                }
+            }
+            dt { "First Inform" }
+            dd { {details.first_inform.format("%Y-%m-%d %H:%M:%S UTC").to_string()} }
+            dt { "Pending tasks" }
+            dd { "{details.pending_tasks}" }
+        }
+    }
+}
+
+// The row of `node`, followed by those of its children when it is open.
+#[component]
+fn TreeRows(
+    node: ParameterNode,
+    depth: usize,
+    toggled: Signal<BTreeSet<String>>,
+    act: Callback<ParameterAction>,
+) -> Element {
+    let open = (depth == 0) != toggled.read().contains(&node.path);
+    let parameter = node.parameter.clone();
+    let writable = match node.writable() {
+        Some(true) => "yes",
+        Some(false) => "no",
+        None => "",
+    };
+    rsx! {
+        tr { class: if node.is_object() { "object" },
+            td { class: "name", style: "padding-left: {depth * 18 + 10}px",
+                if node.is_object() {
+                    button {
+                        class: "toggle",
+                        onclick: {
+                            let path = node.path.clone();
+                            move |_| {
+                                toggled
+                                    .with_mut(|toggled| {
+                                        if !toggled.remove(&path) {
+                                            toggled.insert(path.clone());
+                                        }
+                                    })
+                            }
+                        },
+                        if open {
+                            "▾"
+                        } else {
+                            "▸"
+                        }
+                    }
+                }
+                span { title: "{node.path}", "{node.name}" }
+            }
+            match parameter.clone() {
+                Some(parameter) if !node.is_object() => rsx! {
+                    ValueCell { parameter, act }
+                },
+                _ => rsx! {
+                    td {}
+                },
+            }
+            td { {parameter.as_ref().and_then(|parameter| parameter.xsi_type.clone())} }
+            td { "{writable}" }
+            td {
+                {
+                    parameter
+                        .as_ref()
+                        .map(|parameter| parameter.updated.format("%Y-%m-%d %H:%M:%S").to_string())
+                }
+            }
+            td { class: "actions",
+                button {
+                    title: "GetParameterNames and GetParameterValues of this subtree",
+                    onclick: {
+                        let path = node.path.clone();
+                        move |_| act.call(ParameterAction::Refresh { path: path.clone() })
+                    },
+                    "Refresh"
+                }
+                if node.can_add_instance() {
+                    button {
+                        onclick: {
+                            let object = node.path.clone();
+                            move |_| act.call(ParameterAction::AddInstance { object: object.clone() })
+                        },
+                        "Add instance"
+                    }
+                }
+                if node.can_delete() {
+                    button {
+                        class: "danger",
+                        onclick: {
+                            let object = node.path.clone();
+                            move |_| act.call(ParameterAction::DeleteInstance { object: object.clone() })
+                        },
+                        "Delete"
+                    }
+                }
+            }
+        }
+        if open {
+            for child in node.children.iter() {
+                TreeRows {
+                    key: "{child.path}",
+                    node: child.clone(),
+                    depth: depth + 1,
+                    toggled,
+                    act,
+                }
+            }
+        }
+    }
+}
+
+// The value of a Parameter, edited in place into a SetParameterValues.
+#[component]
+fn ValueCell(parameter: ParameterRow, act: Callback<ParameterAction>) -> Element {
+    let mut draft = use_signal(|| None::<String>);
+    let save = use_callback({
+        let name = parameter.name.clone();
+        // Values reported without a type are strings.
+        let xsi_type = parameter
+            .xsi_type
+            .clone()
+            .unwrap_or_else(|| String::from("xsd:string"));
+        move |()| {
+            if let Some(value) = draft.take() {
+                act.call(ParameterAction::Set {
+                    name: name.clone(),
+                    xsi_type: xsi_type.clone(),
+                    value,
+                });
+            }
+        }
+    });
+
+    let value = parameter.value.clone().unwrap_or_default();
+    let Some(text) = draft() else {
+        let current = value.clone();
+        return rsx! {
+            td { class: "value",
+                span { "{current}" }
+                // Writability is unknown until a refresh, the CPE faults if it is not.
+                if parameter.writable != Some(false) {
+                    button {
+                        class: "edit",
+                        onclick: move |_| draft.set(Some(value.clone())),
+                        "Edit"
+                    }
+                }
+            }
+        };
+    };
+    rsx! {
+        td { class: "value editing",
+            input {
+                value: "{text}",
+                autofocus: true,
+                oninput: move |event| draft.set(Some(event.value())),
+                onkeydown: move |event| match event.key() {
+                    Key::Enter => save.call(()),
+                    Key::Escape => draft.set(None),
+                    _ => {}
+                },
+            }
+            button { onclick: move |_| save.call(()), "Save" }
+            button { onclick: move |_| draft.set(None), "Cancel" }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn row(name: &str, writable: Option<bool>) -> ParameterRow {
+        ParameterRow {
+            name: name.to_string(),
+            value: (!name.ends_with('.')).then(|| String::from("value")),
+            xsi_type: None,
+            writable,
+            updated: Utc::now(),
+        }
+    }
+
+    #[test]
+    fn test_parameter_tree() {
+        let root = ParameterNode::tree(&[
+            row("Device.DeviceInfo.SoftwareVersion", Some(false)),
+            row("Device.WiFi.SSID.", None),
+            row("Device.WiFi.SSID.10.SSID", Some(true)),
+            row("Device.WiFi.SSID.2.", Some(true)),
+            row("Device.WiFi.SSID.2.SSID", Some(true)),
+            row("Device.WiFi.SSIDNumberOfEntries", Some(false)),
+        ]);
+        let names = |node: &ParameterNode| -> Vec<String> {
+            node.children
+                .iter()
+                .map(|child| child.name.clone())
+                .collect()
+        };
+        assert_eq!(names(&root), ["Device"]);
+        let device = &root.children[0];
+        assert_eq!(device.path, "Device.");
+        assert!(device.parameter.is_none());
+        assert_eq!(names(device), ["DeviceInfo", "WiFi"]);
+
+        let info = &device.children[0];
+        let version = &info.children[0];
+        assert_eq!(version.path, "Device.DeviceInfo.SoftwareVersion");
+        assert!(!version.is_object());
+        assert_eq!(version.writable(), Some(false));
+        assert!(!info.can_add_instance());
+
+        let wifi = &device.children[1];
+        assert_eq!(names(wifi), ["SSID", "SSIDNumberOfEntries"]);
+        // Instances by number, an intermediate one created for 10.
+        let ssid = &wifi.children[0];
+        assert_eq!(names(ssid), ["2", "10"]);
+        assert!(ssid.can_add_instance());
+        assert!(!ssid.can_delete());
+        assert_eq!(ssid.children[0].path, "Device.WiFi.SSID.2.");
+        assert!(ssid.children[0].can_delete());
+        assert!(ssid.children[1].parameter.is_none());
+        assert!(ssid.children[1].can_delete());
+        assert_eq!(
+            ssid.children[1].children[0].path,
+            "Device.WiFi.SSID.10.SSID"
+        );
+    }
+
+    #[test]
+    fn test_parameter_actions() {
+        let refresh = ParameterAction::Refresh {
+            path: String::from("Device.WiFi."),
+        };
+        assert_eq!(refresh.to_string(), "refreshing Device.WiFi.");
+        assert_eq!(
+            refresh.operations(),
+            [
+                Operation::GetParameterNames {
+                    parameter_path: String::from("Device.WiFi."),
+                    next_level: false,
+                },
+                Operation::GetParameterValues {
+                    names: vec![String::from("Device.WiFi.")],
+                },
+            ]
+        );
+        let set = ParameterAction::Set {
+            name: String::from("Device.WiFi.SSID.1.SSID"),
+            xsi_type: String::from("xsd:string"),
+            value: String::from("guest"),
+        };
+        let operations = set.operations();
+        let [Operation::SetParameterValues { parameters, .. }] = &operations[..] else {
+            panic!("not a single SetParameterValues");
+        };
+        assert_eq!(parameters[0].value, "guest");
+        let delete = ParameterAction::DeleteInstance {
+            object: String::from("Device.WiFi.SSID.2."),
+        };
+        assert!(matches!(
+            &delete.operations()[..],
+            [Operation::DeleteObject { object, .. }] if object == "Device.WiFi.SSID.2."
+        ));
+    }
+}
@@ -0,0 +1,450 @@
+use super::Route;
+#[cfg(feature = "server")]
+use crate::device::Device;
+use chrono::{DateTime, Utc};
+use dioxus::prelude::*;
+use serde::{Deserialize, Serialize};
+
+/// Devices shown per page.
+pub const PAGE_SIZE: usize = 25;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum SortKey {
+    SerialNumber,
+    Oui,
+    ProductClass,
+    SoftwareVersion,
+    IpAddress,
+    #[default]
+    LastInform,
+    Status,
+}
+
+impl SortKey {
+    /// Columns of the table, in order.
+    pub const COLUMNS: [(SortKey, &'static str); 7] = [
+        (SortKey::SerialNumber, "Serial number"),
+        (SortKey::Oui, "OUI"),
+        (SortKey::ProductClass, "Product class"),
+        (SortKey::SoftwareVersion, "Software version"),
+        (SortKey::IpAddress, "IP address"),
+        (SortKey::LastInform, "Last Inform"),
+        (SortKey::Status, "Status"),
+    ];
+}
+
+/// What the device table shows.
+#[derive(Debug, Clone, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub struct DeviceListQuery {
+    /// Case-insensitive text in the id, software version or IP address.
+    pub search: String,
+    pub product_class: Option<String>,
+    pub software_version: Option<String>,
+    /// Only online devices, or only offline ones.
+    pub online: Option<bool>,
+    pub sort: SortKey,
+    pub descending: bool,
+    /// Zero-based.
+    pub page: usize,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DeviceRow {
+    /// `OUI-ProductClass-SerialNumber`.
+    pub id: String,
+    pub serial_number: String,
+    pub oui: String,
+    pub product_class: String,
+    pub software_version: Option<String>,
+    pub ip_address: Option<String>,
+    pub last_inform: DateTime<Utc>,
+    pub online: bool,
+}
+
+#[cfg(feature = "server")]
+impl DeviceRow {
+    fn new(device: &Device, now: DateTime<Utc>) -> Self {
+        Self {
+            id: device.id.to_string(),
+            serial_number: device.id.serial_number.clone(),
+            oui: device.id.oui.clone(),
+            product_class: device.id.product_class.clone(),
+            software_version: device.software_version.clone(),
+            ip_address: device.ip_address().map(str::to_string),
+            last_inform: device.last_inform,
+            online: device.is_online(now),
+        }
+    }
+
+    fn matches(&self, query: &DeviceListQuery, search: &str) -> bool {
+        let contains = |field: &str| field.to_lowercase().contains(search);
+        (search.is_empty()
+            || contains(&self.id)
+            || self.software_version.as_deref().is_some_and(contains)
+            || self.ip_address.as_deref().is_some_and(contains))
+            && query
+                .product_class
+                .as_ref()
+                .is_none_or(|class| &self.product_class == class)
+            && query
+                .software_version
+                .as_ref()
+                .is_none_or(|version| self.software_version.as_ref() == Some(version))
+            && query.online.is_none_or(|online| self.online == online)
+    }
+}
+
+/// One page of the devices matching a query.
+#[derive(Debug, Clone, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub struct DevicePage {
+    pub rows: Vec<DeviceRow>,
+    /// Matching devices, on every page.
+    pub total: usize,
+    /// The page shown, the last one if the query asked for a page past it.
+    pub page: usize,
+    pub pages: usize,
+    /// Values to filter on, among every device.
+    pub product_classes: Vec<String>,
+    pub software_versions: Vec<String>,
+}
+
+#[server]
+pub async fn list_devices(query: DeviceListQuery) -> Result<DevicePage, ServerFnError> {
+    use crate::startup::AcsState;
+    use axum::Extension;
+
+    let Extension(state): Extension<AcsState> = extract().await?;
+    Ok(device_page(&state.devices.list(), &query, Utc::now()))
+}
+
+#[cfg(feature = "server")]
+fn device_page(devices: &[Device], query: &DeviceListQuery, now: DateTime<Utc>) -> DevicePage {
+    use std::{cmp::Ordering, collections::BTreeSet, net::IpAddr};
+
+    let product_classes: BTreeSet<&String> = devices
+        .iter()
+        .map(|device| &device.id.product_class)
+        .collect();
+    let software_versions: BTreeSet<&String> = devices
+        .iter()
+        .filter_map(|device| device.software_version.as_ref())
+        .collect();
+
+    let search = query.search.trim().to_lowercase();
+    let mut rows: Vec<DeviceRow> = devices
+        .iter()
+        .map(|device| DeviceRow::new(device, now))
+        .filter(|row| row.matches(query, &search))
+        .collect();
+    let ip = |row: &DeviceRow| {
+        row.ip_address
+            .as_deref()
+            .and_then(|ip| ip.parse::<IpAddr>().ok())
+    };
+    rows.sort_by(|a, b| {
+        let order: Ordering = match query.sort {
+            SortKey::SerialNumber => a.serial_number.cmp(&b.serial_number),
+            SortKey::Oui => a.oui.cmp(&b.oui),
+            SortKey::ProductClass => a.product_class.cmp(&b.product_class),
+            SortKey::SoftwareVersion => a.software_version.cmp(&b.software_version),
+            SortKey::IpAddress => ip(a).cmp(&ip(b)),
+            SortKey::LastInform => a.last_inform.cmp(&b.last_inform),
+            SortKey::Status => a.online.cmp(&b.online),
+        };
+        let order = if query.descending {
+            order.reverse()
+        } else {
+            order
+        };
+        order.then_with(|| a.id.cmp(&b.id))
+    });
+
+    let total = rows.len();
+    let pages = total.div_ceil(PAGE_SIZE).max(1);
+    let page = query.page.min(pages - 1);
+    DevicePage {
+        rows: rows
+            .into_iter()
+            .skip(page * PAGE_SIZE)
+            .take(PAGE_SIZE)
+            .collect(),
+        total,
+        page,
+        pages,
+        product_classes: product_classes.into_iter().cloned().collect(),
+        software_versions: software_versions.into_iter().cloned().collect(),
+    }
+}
+
+/// The device inventory: a searchable, filterable, sortable and paginated table.
+#[component]
+pub fn DeviceList() -> Element {
+    let mut query = use_signal(DeviceListQuery::default);
+    let devices = use_resource(move || list_devices(query()));
+    // Filters keep their options while the next page loads.
+    let mut filters = use_signal(DevicePage::default);
+    use_effect(move || {
+        if let Some(Ok(page)) = &*devices.read() {
+            filters.set(page.clone());
+        }
+    });
+
+    let current = query.read().clone();
+    let options = filters.read();
+    rsx! {
+        section { id: "devices",
+            h1 { "Devices" }
+            div { class: "filters",
+                input {
+                    r#type: "search",
+                    placeholder: "Search by id, software version or IP address",
+                    value: "{current.search}",
+                    oninput: move |event| {
+                        query
+                            .with_mut(|query| {
+                                query.search = event.value();
+                                query.page = 0;
+                            })
+                    },
+                }
+                select {
+                    onchange: move |event| {
+                        let class = event.value();
+                        query
+                            .with_mut(|query| {
+                                query.product_class = (!class.is_empty()).then_some(class);
+                                query.page = 0;
+                            })
+                    },
+                    option { value: "", "All product classes" }
+                    for class in options.product_classes.iter() {
+                        option {
+                            value: "{class}",
+                            selected: current.product_class.as_ref() == Some(class),
+                            "{class}"
+                        }
+                    }
+                }
+                select {
+                    onchange: move |event| {
+                        let version = event.value();
+                        query
+                            .with_mut(|query| {
+                                query.software_version = (!version.is_empty()).then_some(version);
+                                query.page = 0;
+                            })
+                    },
+                    option { value: "", "All software versions" }
+                    for version in options.software_versions.iter() {
+                        option {
+                            value: "{version}",
+                            selected: current.software_version.as_ref() == Some(version),
+                            "{version}"
+                        }
+                    }
+                }
+                select {
+                    onchange: move |event| {
+                        let online = match event.value().as_str() {
+                            "online" => Some(true),
+                            "offline" => Some(false),
+                            _ => None,
+                        };
+                        query
+                            .with_mut(|query| {
+                                query.online = online;
+                                query.page = 0;
+                            })
+                    },
+                    option { value: "", "Online and offline" }
+                    option { value: "online", selected: current.online == Some(true), "Online" }
+                    option { value: "offline", selected: current.online == Some(false), "Offline" }
+                }
+            }
+            match &*devices.read() {
+                Some(Ok(page)) => rsx! {
+                    DeviceTable { query, page: page.clone() }
+                },
+                Some(Err(err)) => rsx! {
+                    p { class: "error", "Failed to load the devices: {err}" }
+                },
+                None => rsx! {
+                    p { class: "loading", "Loading devices…" }
+                },
+            }
+        }
+    }
+}
+
+#[component]
+fn DeviceTable(query: Signal<DeviceListQuery>, page: DevicePage) -> Element {
+    rsx! {
+        table {
+            thead {
+                tr {
+                    for (sort , label) in SortKey::COLUMNS {
+                        SortHeader { query, sort, label }
+                    }
+                }
+            }
+            tbody {
+                if page.rows.is_empty() {
+                    tr {
+                        td { colspan: "{SortKey::COLUMNS.len()}", class: "empty", "No devices match." }
+                    }
+                }
+                for row in page.rows.iter() {
+                    tr { key: "{row.id}", title: "{row.id}",
+                        td {
+                            Link { to: Route::DeviceDetail { id: row.id.clone() }, "{row.serial_number}" }
+                        }
+                        td { "{row.oui}" }
+                        td { "{row.product_class}" }
+                        td { {row.software_version.as_deref().unwrap_or("—")} }
+                        td { {row.ip_address.as_deref().unwrap_or("—")} }
+                        td { {row.last_inform.format("%Y-%m-%d %H:%M:%S UTC").to_string()} }
+                        td {
+                            if row.online {
+                                span { class: "status online", "Online" }
+                            } else {
+                                span { class: "status offline", "Offline" }
+                            }
+                        }
+                    }
+                }
+            }
+        }
+        Pager { query, page: page.page, pages: page.pages, total: page.total }
+    }
+}
+
+// A column header that sorts by its column, and reverses the order when it
+// already does.
+#[component]
+fn SortHeader(query: Signal<DeviceListQuery>, sort: SortKey, label: &'static str) -> Element {
+    let (sorted, descending) = {
+        let query = query.read();
+        (query.sort == sort, query.descending)
+    };
+    let arrow = match (sorted, descending) {
+        (false, _) => "",
+        (true, false) => " ▲",
+        (true, true) => " ▼",
+    };
+    rsx! {
+        th {
+            class: if sorted { "sorted" },
+            onclick: move |_| {
+                query
+                    .with_mut(|query| {
+                        if query.sort == sort {
+                            query.descending = !query.descending;
+                        } else {
+                            query.sort = sort;
+                            query.descending = false;
+                        }
+                        query.page = 0;
+                    })
+            },
+            "{label}{arrow}"
+        }
+    }
+}
+
+#[component]
+fn Pager(query: Signal<DeviceListQuery>, page: usize, pages: usize, total: usize) -> Element {
+    rsx! {
+        div { class: "pager",
+            button {
+                disabled: page == 0,
+                onclick: move |_| query.with_mut(|query| query.page = page.saturating_sub(1)),
+                "Previous"
+            }
+            span { "Page {page + 1} of {pages}, {total} devices" }
+            button {
+                disabled: page + 1 >= pages,
+                onclick: move |_| query.with_mut(|query| query.page = page + 1),
+                "Next"
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeDelta;
+
+    fn device(id: &str, version: &str, ip: &str, last_inform: DateTime<Utc>) -> Device {
+        let mut device = Device::new(id.parse().unwrap(), last_inform);
+        device.software_version = Some(version.to_string());
+        device.connection_request_url = Some(format!("http://{ip}:7547/cr"));
+        device
+    }
+
+    #[test]
+    fn test_device_page() {
+        let now: DateTime<Utc> = "2025-10-07T10:00:00Z".parse().unwrap();
+        let mut devices: Vec<Device> = (0..30)
+            .map(|n| {
+                device(
+                    &format!("001A2B-RouterX-SN{n:02}"),
+                    "1.0",
+                    &format!("10.0.0.{n}"),
+                    now,
+                )
+            })
+            .collect();
+        devices.push(device(
+            "00D09E-ModemY-M1",
+            "2.1",
+            "192.168.1.9",
+            now - TimeDelta::days(5),
+        ));
+
+        let page = device_page(&devices, &DeviceListQuery::default(), now);
+        assert_eq!(page.total, 31);
+        assert_eq!(page.pages, 2);
+        assert_eq!(page.rows.len(), PAGE_SIZE);
+        assert_eq!(page.product_classes, ["ModemY", "RouterX"]);
+        assert_eq!(page.software_versions, ["1.0", "2.1"]);
+        // Oldest Inform first, ties by id.
+        assert_eq!(page.rows[0].id, "00D09E-ModemY-M1");
+        assert!(!page.rows[0].online);
+        assert_eq!(page.rows[1].ip_address.as_deref(), Some("10.0.0.0"));
+
+        let query = DeviceListQuery {
+            sort: SortKey::IpAddress,
+            descending: true,
+            page: 7,
+            ..DeviceListQuery::default()
+        };
+        let page = device_page(&devices, &query, now);
+        assert_eq!(page.page, 1);
+        assert_eq!(page.rows.len(), 6);
+        // 10.0.0.9 sorts before 10.0.0.10 as an address, not as text.
+        assert_eq!(page.rows[4].ip_address.as_deref(), Some("10.0.0.1"));
+        assert_eq!(page.rows[5].ip_address.as_deref(), Some("10.0.0.0"));
+
+        let query = DeviceListQuery {
+            search: String::from("sn1"),
+            online: Some(true),
+            ..DeviceListQuery::default()
+        };
+        assert_eq!(device_page(&devices, &query, now).total, 10);
+        let query = DeviceListQuery {
+            product_class: Some(String::from("ModemY")),
+            online: Some(true),
+            ..DeviceListQuery::default()
+        };
+        assert_eq!(device_page(&devices, &query, now).total, 0);
+        let query = DeviceListQuery {
+            search: String::from("192.168"),
+            ..DeviceListQuery::default()
+        };
+        assert_eq!(
+            device_page(&devices, &query, now).rows[0].serial_number,
+            "M1"
+        );
+    }
+}
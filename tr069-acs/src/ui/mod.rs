@@ -0,0 +1,21 @@
+// Web UI of the ACS.
+//
+// Components render on the server and hydrate in the browser. Their data comes
+// from Dioxus server functions, which read the `AcsState` the UI router carries
+// as an axum `Extension`; only their bodies are compiled into the server.
+pub mod device;
+pub mod devices;
+
+use device::DeviceDetail;
+use devices::DeviceList;
+use dioxus::prelude::*;
+
+/// Pages of the UI. The northbound API owns `/api`, every other path is ours.
+#[derive(Debug, Clone, PartialEq, Routable)]
+pub enum Route {
+    #[route("/")]
+    DeviceList {},
+    /// `id` is `OUI-ProductClass-SerialNumber`.
+    #[route("/devices/:id")]
+    DeviceDetail { id: String },
+}
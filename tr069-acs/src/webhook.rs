@@ -0,0 +1,575 @@
+// Outbound webhooks: events POSTed as JSON to external URLs.
+//
+// Every `[[webhooks]]` entry subscribes a URL to the events its filter lets
+// through, in the same JSON the /api/v1/events WebSocket streams. A delivery is
+// stored before its first attempt and retried with exponential backoff until the
+// receiver answers 2xx or `max_attempts` ran out. It then stays in the dead-letter
+// queue until it is redelivered through the northbound API. Deliveries still
+// pending at shutdown are resumed at the next start.
+//
+// With a `secret`, the body is signed with HMAC-SHA256 and the signature sent as
+// `X-Webhook-Signature: sha256=<hex>`, so receivers can check the ACS sent it.
+use crate::{
+    events::{Event, EventFilter},
+    storage::{DeliveryRecord, MemoryStorage, Storage, StorageError},
+};
+use chrono::{DateTime, Utc};
+use hmac::{Hmac, Mac};
+use reqwest::header;
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+use std::{
+    collections::BTreeMap,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+use tokio::sync::broadcast::{error::RecvError, Receiver};
+use utoipa::ToSchema;
+
+pub const SIGNATURE_HEADER: &str = "x-webhook-signature";
+/// `type` of the event in the body.
+pub const EVENT_HEADER: &str = "x-webhook-event";
+/// Id of the delivery, the same on every attempt so receivers can drop duplicates.
+pub const DELIVERY_HEADER: &str = "x-webhook-delivery";
+
+/// Longest wait between two attempts, however many failed.
+const MAX_BACKOFF: Duration = Duration::from_secs(3600);
+/// Delivered deliveries kept as history. Pending and dead ones are always kept.
+const HISTORY: usize = 1000;
+
+/// One `[[webhooks]]` entry of the configuration.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct WebhookConfig {
+    /// Names the webhook in the delivery history.
+    pub name: String,
+    pub url: String,
+    /// Bodies are signed with this key when set.
+    #[serde(default)]
+    pub secret: Option<String>,
+    /// Event types to deliver, all of them when empty.
+    #[serde(default)]
+    pub types: Vec<String>,
+    /// Only Informs carrying one of these EventCodes, e.g. `0 BOOTSTRAP`.
+    #[serde(default)]
+    pub event_codes: Vec<String>,
+    #[serde(default = "default_max_attempts")]
+    pub max_attempts: u32,
+    /// Seconds before the first retry, doubled for every retry after it.
+    #[serde(default = "default_retry_interval")]
+    pub retry_interval: u64,
+    /// Seconds to wait for the receiver to answer.
+    #[serde(default = "default_timeout")]
+    pub timeout: u64,
+}
+
+fn default_max_attempts() -> u32 {
+    8
+}
+
+fn default_retry_interval() -> u64 {
+    10
+}
+
+fn default_timeout() -> u64 {
+    10
+}
+
+impl WebhookConfig {
+    pub fn filter(&self) -> EventFilter {
+        EventFilter {
+            types: self.types.clone(),
+            event_codes: self.event_codes.clone(),
+            ..EventFilter::default()
+        }
+    }
+
+    /// Wait before the attempt following `failed` failed ones.
+    pub fn backoff(&self, failed: u32) -> Duration {
+        let factor = 1u32 << failed.saturating_sub(1).min(31);
+        Duration::from_secs(self.retry_interval)
+            .saturating_mul(factor)
+            .min(MAX_BACKOFF)
+    }
+}
+
+/// `sha256=<hex>` HMAC of `body` with `secret`.
+pub fn sign(secret: &str, body: &str) -> String {
+    let mut mac =
+        Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("HMAC accepts any key length");
+    mac.update(body.as_bytes());
+    let digest: String = mac
+        .finalize()
+        .into_bytes()
+        .iter()
+        .map(|byte| format!("{byte:02x}"))
+        .collect();
+    format!("sha256={digest}")
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum DeliveryStatus {
+    Pending,
+    Delivered,
+    /// Every attempt failed, the delivery waits in the dead-letter queue.
+    Dead,
+}
+
+/// One POST of a delivery.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+pub struct Attempt {
+    pub time: DateTime<Utc>,
+    /// HTTP status of the answer, `None` when none arrived.
+    pub status: Option<u16>,
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+pub struct Delivery {
+    pub id: u64,
+    pub webhook: String,
+    pub created: DateTime<Utc>,
+    /// `type` of the event.
+    pub event: String,
+    /// The event as JSON, exactly as it is signed and sent.
+    pub body: String,
+    pub status: DeliveryStatus,
+    /// Attempts since the delivery was queued or last redelivered.
+    pub attempts: Vec<Attempt>,
+}
+
+// Stored as the payload of a `DeliveryRecord`, which holds the id, webhook and
+// creation time.
+#[derive(Serialize, Deserialize)]
+struct DeliveryPayload {
+    event: String,
+    body: String,
+    status: DeliveryStatus,
+    attempts: Vec<Attempt>,
+}
+
+impl Delivery {
+    fn to_record(&self) -> Result<DeliveryRecord, StorageError> {
+        let payload = DeliveryPayload {
+            event: self.event.clone(),
+            body: self.body.clone(),
+            status: self.status,
+            attempts: self.attempts.clone(),
+        };
+        Ok(DeliveryRecord {
+            id: self.id,
+            webhook: self.webhook.clone(),
+            created: self.created,
+            payload: serde_json::to_string(&payload)
+                .map_err(|err| StorageError::Corrupt(err.to_string()))?,
+        })
+    }
+
+    fn from_record(record: DeliveryRecord) -> Result<Self, StorageError> {
+        let payload: DeliveryPayload = serde_json::from_str(&record.payload)
+            .map_err(|err| StorageError::Corrupt(err.to_string()))?;
+        Ok(Self {
+            id: record.id,
+            webhook: record.webhook,
+            created: record.created,
+            event: payload.event,
+            body: payload.body,
+            status: payload.status,
+            attempts: payload.attempts,
+        })
+    }
+}
+
+/// The configured webhooks and every delivery to them. Cloning shares both.
+#[derive(Debug, Clone)]
+pub struct Webhooks {
+    hooks: Arc<Vec<WebhookConfig>>,
+    deliveries: Arc<Mutex<BTreeMap<u64, Delivery>>>,
+    storage: Arc<dyn Storage>,
+    http: reqwest::Client,
+}
+
+impl Default for Webhooks {
+    fn default() -> Self {
+        Self {
+            hooks: Arc::default(),
+            deliveries: Arc::default(),
+            storage: Arc::new(MemoryStorage::new()),
+            http: reqwest::Client::new(),
+        }
+    }
+}
+
+impl Webhooks {
+    /// `hooks` backed by `storage`, starting with the deliveries it already holds.
+    pub fn load(
+        hooks: Vec<WebhookConfig>,
+        storage: Arc<dyn Storage>,
+    ) -> Result<Self, StorageError> {
+        let deliveries = storage
+            .load_deliveries()?
+            .into_iter()
+            .map(|record| Delivery::from_record(record).map(|delivery| (delivery.id, delivery)))
+            .collect::<Result<_, _>>()?;
+        Ok(Self {
+            hooks: Arc::new(hooks),
+            deliveries: Arc::new(Mutex::new(deliveries)),
+            storage,
+            http: reqwest::Client::new(),
+        })
+    }
+
+    pub fn get(&self, id: u64) -> Option<Delivery> {
+        self.deliveries.lock().unwrap().get(&id).cloned()
+    }
+
+    /// Every delivery, oldest first.
+    pub fn list(&self) -> Vec<Delivery> {
+        self.deliveries.lock().unwrap().values().cloned().collect()
+    }
+
+    /// Deliver the events `events` receives until the bus closes, after resuming
+    /// the deliveries left pending by the last run.
+    pub async fn run(self, mut events: Receiver<Event>) {
+        for delivery in self.list() {
+            if delivery.status == DeliveryStatus::Pending {
+                self.spawn(delivery.id);
+            }
+        }
+        loop {
+            match events.recv().await {
+                Ok(event) => self.dispatch(&event),
+                Err(RecvError::Lagged(missed)) => {
+                    tracing::warn!("Webhooks fell behind, {missed} events were not delivered")
+                }
+                Err(RecvError::Closed) => break,
+            }
+        }
+    }
+
+    /// Queue a finished delivery again, with a fresh set of attempts. A pending
+    /// one is left as it is.
+    pub fn redeliver(&self, id: u64) -> Option<Delivery> {
+        let delivery = self.get(id)?;
+        if delivery.status == DeliveryStatus::Pending {
+            return Some(delivery);
+        }
+        let delivery = self.update(id, |delivery| {
+            delivery.status = DeliveryStatus::Pending;
+            delivery.attempts.clear();
+        })?;
+        self.spawn(id);
+        Some(delivery)
+    }
+
+    fn dispatch(&self, event: &Event) {
+        for hook in self.hooks.iter() {
+            if !hook.filter().matches(event) {
+                continue;
+            }
+            match self.enqueue(hook, event) {
+                Ok(delivery) => self.spawn(delivery.id),
+                Err(err) => tracing::error!("Failed to queue a delivery to {}: {err}", hook.name),
+            }
+        }
+    }
+
+    fn enqueue(&self, hook: &WebhookConfig, event: &Event) -> Result<Delivery, StorageError> {
+        let mut delivery = Delivery {
+            id: 0,
+            webhook: hook.name.clone(),
+            created: Utc::now(),
+            event: event.kind.name().to_string(),
+            body: serde_json::to_string(event)
+                .map_err(|err| StorageError::Corrupt(err.to_string()))?,
+            status: DeliveryStatus::Pending,
+            attempts: Vec::new(),
+        };
+        delivery.id = self.storage.add_delivery(&delivery.to_record()?)?;
+        self.deliveries
+            .lock()
+            .unwrap()
+            .insert(delivery.id, delivery.clone());
+        Ok(delivery)
+    }
+
+    fn spawn(&self, id: u64) {
+        let webhooks = self.clone();
+        tokio::spawn(async move { webhooks.deliver(id).await });
+    }
+
+    // Attempt the delivery until it is delivered or dead.
+    async fn deliver(&self, id: u64) {
+        loop {
+            let Some(delivery) = self
+                .get(id)
+                .filter(|delivery| delivery.status == DeliveryStatus::Pending)
+            else {
+                return;
+            };
+            let Some(hook) = self.hooks.iter().find(|hook| hook.name == delivery.webhook) else {
+                tracing::warn!(
+                    "Delivery {id} is for webhook {:?}, which is no longer configured",
+                    delivery.webhook
+                );
+                self.update(id, |delivery| delivery.status = DeliveryStatus::Dead);
+                return;
+            };
+            let failed = delivery.attempts.len() as u32;
+            if failed > 0 {
+                tokio::time::sleep(hook.backoff(failed)).await;
+            }
+            let attempt = self.attempt(hook, &delivery).await;
+            let delivered = attempt
+                .status
+                .is_some_and(|status| (200..300).contains(&status));
+            let Some(delivery) = self.update(id, |delivery| {
+                delivery.attempts.push(attempt);
+                if delivered {
+                    delivery.status = DeliveryStatus::Delivered;
+                } else if delivery.attempts.len() as u32 >= hook.max_attempts {
+                    delivery.status = DeliveryStatus::Dead;
+                }
+            }) else {
+                return;
+            };
+            match delivery.status {
+                DeliveryStatus::Pending => {}
+                DeliveryStatus::Delivered => {
+                    self.prune();
+                    return;
+                }
+                DeliveryStatus::Dead => {
+                    tracing::warn!(
+                        "Giving up on delivery {id} to {} after {} attempts",
+                        hook.name,
+                        delivery.attempts.len()
+                    );
+                    return;
+                }
+            }
+        }
+    }
+
+    async fn attempt(&self, hook: &WebhookConfig, delivery: &Delivery) -> Attempt {
+        let mut request = self
+            .http
+            .post(&hook.url)
+            .timeout(Duration::from_secs(hook.timeout))
+            .header(header::CONTENT_TYPE, "application/json")
+            .header(EVENT_HEADER, &delivery.event)
+            .header(DELIVERY_HEADER, delivery.id.to_string())
+            .body(delivery.body.clone());
+        if let Some(secret) = &hook.secret {
+            request = request.header(SIGNATURE_HEADER, sign(secret, &delivery.body));
+        }
+        let time = Utc::now();
+        match request.send().await {
+            Ok(response) => {
+                let status = response.status();
+                Attempt {
+                    time,
+                    status: Some(status.as_u16()),
+                    error: (!status.is_success()).then(|| format!("receiver answered {status}")),
+                }
+            }
+            Err(err) => Attempt {
+                time,
+                status: None,
+                error: Some(err.to_string()),
+            },
+        }
+    }
+
+    fn update(&self, id: u64, update: impl FnOnce(&mut Delivery)) -> Option<Delivery> {
+        let mut deliveries = self.deliveries.lock().unwrap();
+        let delivery = deliveries.get_mut(&id)?;
+        update(delivery);
+        let result = delivery
+            .to_record()
+            .and_then(|record| self.storage.update_delivery(&record));
+        if let Err(err) = result {
+            tracing::error!("Failed to persist delivery {id}: {err}");
+        }
+        Some(delivery.clone())
+    }
+
+    // Forget the oldest delivered deliveries beyond `HISTORY`.
+    fn prune(&self) {
+        let mut deliveries = self.deliveries.lock().unwrap();
+        let delivered: Vec<u64> = deliveries
+            .values()
+            .filter(|delivery| delivery.status == DeliveryStatus::Delivered)
+            .map(|delivery| delivery.id)
+            .collect();
+        for id in delivered
+            .iter()
+            .take(delivered.len().saturating_sub(HISTORY))
+        {
+            if let Err(err) = self.storage.delete_delivery(*id) {
+                tracing::error!("Failed to delete delivery {id}: {err}");
+                break;
+            }
+            deliveries.remove(id);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::events::{EventBus, EventKind};
+    use axum::{extract::State, http::HeaderMap, http::StatusCode, routing::post, Router};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    // Stand-in receiver recording every request, failing the first `failures`.
+    #[derive(Clone, Default)]
+    struct StandIn {
+        requests: Arc<Mutex<Vec<(HeaderMap, String)>>>,
+        failures: Arc<AtomicUsize>,
+    }
+
+    async fn receive(
+        State(receiver): State<StandIn>,
+        headers: HeaderMap,
+        body: String,
+    ) -> StatusCode {
+        receiver.requests.lock().unwrap().push((headers, body));
+        let failing = receiver
+            .failures
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |left| {
+                left.checked_sub(1)
+            })
+            .is_ok();
+        if failing {
+            StatusCode::SERVICE_UNAVAILABLE
+        } else {
+            StatusCode::NO_CONTENT
+        }
+    }
+
+    async fn serve(failures: usize) -> (String, StandIn) {
+        let receiver = StandIn::default();
+        receiver.failures.store(failures, Ordering::SeqCst);
+        let router = Router::new()
+            .route("/hook", post(receive))
+            .with_state(receiver.clone());
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/hook", listener.local_addr().unwrap());
+        tokio::spawn(async move { axum::serve(listener, router).await.unwrap() });
+        (url, receiver)
+    }
+
+    fn hook(name: &str, url: &str) -> WebhookConfig {
+        WebhookConfig {
+            name: name.to_string(),
+            url: url.to_string(),
+            secret: Some(String::from("s3cret")),
+            types: vec![String::from("device_informed")],
+            event_codes: vec![String::from("0 BOOTSTRAP")],
+            max_attempts: 3,
+            // Retry at once, backoff is checked on its own.
+            retry_interval: 0,
+            timeout: 5,
+        }
+    }
+
+    fn informed(code: &str) -> EventKind {
+        EventKind::DeviceInformed {
+            events: vec![code.to_string()],
+        }
+    }
+
+    async fn settled(webhooks: &Webhooks, count: usize) -> Vec<Delivery> {
+        for _ in 0..100 {
+            let deliveries = webhooks.list();
+            if deliveries.len() == count
+                && deliveries
+                    .iter()
+                    .all(|delivery| delivery.status != DeliveryStatus::Pending)
+            {
+                return deliveries;
+            }
+            tokio::time::sleep(Duration::from_millis(50)).await;
+        }
+        panic!("deliveries did not settle: {:?}", webhooks.list());
+    }
+
+    #[test]
+    fn test_backoff_and_signature() {
+        let hook = WebhookConfig {
+            retry_interval: 10,
+            ..hook("oss", "http://127.0.0.1/")
+        };
+        assert_eq!(hook.backoff(1), Duration::from_secs(10));
+        assert_eq!(hook.backoff(3), Duration::from_secs(40));
+        assert_eq!(hook.backoff(30), MAX_BACKOFF);
+        // RFC 4231 test case 2.
+        assert_eq!(
+            sign("Jefe", "what do ya want for nothing?"),
+            "sha256=5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_delivery_retries_and_signs() {
+        let (url, receiver) = serve(1).await;
+        let storage: Arc<dyn Storage> = Arc::new(MemoryStorage::new());
+        let webhooks = Webhooks::load(vec![hook("oss", &url)], storage.clone()).unwrap();
+        let bus = EventBus::default();
+        tokio::spawn(webhooks.clone().run(bus.subscribe()));
+
+        let id = "001A2B-RouterX-1".parse().unwrap();
+        bus.publish(&id, informed("2 PERIODIC"));
+        bus.publish(&id, informed("0 BOOTSTRAP"));
+        let deliveries = settled(&webhooks, 1).await;
+
+        let delivery = &deliveries[0];
+        assert_eq!(delivery.status, DeliveryStatus::Delivered);
+        assert_eq!(delivery.event, "device_informed");
+        assert_eq!(delivery.attempts.len(), 2);
+        assert_eq!(delivery.attempts[0].status, Some(503));
+        assert_eq!(delivery.attempts[1].status, Some(204));
+
+        let requests = receiver.requests.lock().unwrap().clone();
+        assert_eq!(requests.len(), 2);
+        let (headers, body) = &requests[1];
+        assert_eq!(headers[SIGNATURE_HEADER], sign("s3cret", body).as_str());
+        assert_eq!(headers[DELIVERY_HEADER], delivery.id.to_string().as_str());
+        let event: serde_json::Value = serde_json::from_str(body).unwrap();
+        assert_eq!(event["events"][0], "0 BOOTSTRAP");
+
+        // The history survives a restart.
+        let reloaded = Webhooks::load(Vec::new(), storage).unwrap();
+        assert_eq!(reloaded.list(), deliveries);
+    }
+
+    #[tokio::test]
+    async fn test_dead_letters_are_redelivered() {
+        let (url, receiver) = serve(3).await;
+        let storage: Arc<dyn Storage> = Arc::new(MemoryStorage::new());
+        let webhooks = Webhooks::load(vec![hook("oss", &url)], storage.clone()).unwrap();
+        let bus = EventBus::default();
+        tokio::spawn(webhooks.clone().run(bus.subscribe()));
+
+        bus.publish(
+            &"001A2B-RouterX-1".parse().unwrap(),
+            informed("0 BOOTSTRAP"),
+        );
+        let deliveries = settled(&webhooks, 1).await;
+        let dead = &deliveries[0];
+        assert_eq!(dead.status, DeliveryStatus::Dead);
+        assert_eq!(dead.attempts.len(), 3);
+        let stored = Webhooks::load(Vec::new(), storage).unwrap();
+        assert_eq!(stored.get(dead.id).unwrap().status, DeliveryStatus::Dead);
+
+        // The receiver is back.
+        let redelivered = webhooks.redeliver(dead.id).unwrap();
+        assert_eq!(redelivered.status, DeliveryStatus::Pending);
+        let deliveries = settled(&webhooks, 1).await;
+        assert_eq!(deliveries[0].status, DeliveryStatus::Delivered);
+        assert_eq!(deliveries[0].attempts.len(), 1);
+        assert_eq!(receiver.requests.lock().unwrap().len(), 4);
+    }
+}